@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS user_saml_links (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+  provider VARCHAR(255) NOT NULL,
+  name_id VARCHAR(255) NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES users(id),
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  UNIQUE (provider, name_id)
+);
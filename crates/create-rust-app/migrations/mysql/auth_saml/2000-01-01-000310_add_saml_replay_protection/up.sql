@@ -0,0 +1,19 @@
+-- the IDs of the AuthnRequests we sent, which responses must be in response to; `browser_token` is also
+-- in a cookie of the browser which started the login, so that it can't be finished in another one
+CREATE TABLE IF NOT EXISTS saml_requests (
+  provider VARCHAR(255) NOT NULL,
+  request_id VARCHAR(255) NOT NULL,
+  browser_token VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  PRIMARY KEY (provider, request_id),
+  INDEX saml_requests_expires_at (expires_at)
+);
+
+-- the IDs of the assertions already used to log in, kept until they expire so they can't be replayed
+CREATE TABLE IF NOT EXISTS saml_assertions (
+  provider VARCHAR(255) NOT NULL,
+  assertion_id VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  PRIMARY KEY (provider, assertion_id),
+  INDEX saml_assertions_expires_at (expires_at)
+);
@@ -0,0 +1,27 @@
+-- the outbox the mailer queues emails in, and the addresses it doesn't send to anymore
+CREATE TABLE IF NOT EXISTS email_outbox (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+
+  from_address TEXT NOT NULL,
+  recipients TEXT NOT NULL,
+  subject TEXT NOT NULL,
+  message LONGBLOB NOT NULL,
+  status VARCHAR(16) NOT NULL DEFAULT 'pending',
+  attempts INTEGER NOT NULL DEFAULT 0,
+  last_error TEXT,
+  next_attempt_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  sent_at DATETIME,
+  INDEX email_outbox_due (status, next_attempt_at)
+);
+
+CREATE TABLE IF NOT EXISTS email_suppressions (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+
+  email VARCHAR(255) NOT NULL UNIQUE,
+  reason TEXT NOT NULL,
+  details TEXT,
+
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,33 @@
+CREATE TABLE IF NOT EXISTS attachment_blobs (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+
+  `key` VARCHAR(255) NOT NULL,
+  file_name TEXT NOT NULL,
+  content_type TEXT,
+  byte_size BIGINT NOT NULL,
+  checksum TEXT NOT NULL,
+  service_name TEXT NOT NULL,
+
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS attachments (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+
+  name TEXT NOT NULL,
+  record_type TEXT NOT NULL,
+  record_id INTEGER NOT NULL,
+  blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS attachment_blob_variants (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+  blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  variant_blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  name VARCHAR(255) NOT NULL,
+  digest VARCHAR(255) NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (blob_id, name, digest)
+);
@@ -0,0 +1,2 @@
+DROP INDEX attachment_blobs_sha256 ON attachment_blobs;
+ALTER TABLE attachment_blobs DROP COLUMN encryption_key, DROP COLUMN encryption, DROP COLUMN sha256;
@@ -0,0 +1,44 @@
+-- deduplication (by SHA-256) and encryption of the blobs; installations from before these create
+-- `attachment_blobs` without the columns. MySQL can't add a column (or an index) only if it's missing,
+-- so the statements are only prepared when they are
+
+SET @statement = IF(
+  (SELECT COUNT(*) FROM information_schema.columns
+    WHERE table_schema = DATABASE() AND table_name = 'attachment_blobs' AND column_name = 'sha256') = 0,
+  'ALTER TABLE attachment_blobs ADD COLUMN sha256 TEXT',
+  'DO 0'
+);
+PREPARE statement FROM @statement;
+EXECUTE statement;
+DEALLOCATE PREPARE statement;
+
+SET @statement = IF(
+  (SELECT COUNT(*) FROM information_schema.columns
+    WHERE table_schema = DATABASE() AND table_name = 'attachment_blobs' AND column_name = 'encryption') = 0,
+  'ALTER TABLE attachment_blobs ADD COLUMN encryption TEXT',
+  'DO 0'
+);
+PREPARE statement FROM @statement;
+EXECUTE statement;
+DEALLOCATE PREPARE statement;
+
+SET @statement = IF(
+  (SELECT COUNT(*) FROM information_schema.columns
+    WHERE table_schema = DATABASE() AND table_name = 'attachment_blobs' AND column_name = 'encryption_key') = 0,
+  'ALTER TABLE attachment_blobs ADD COLUMN encryption_key TEXT',
+  'DO 0'
+);
+PREPARE statement FROM @statement;
+EXECUTE statement;
+DEALLOCATE PREPARE statement;
+
+-- `AttachmentBlob::find_duplicate` looks blobs up by these on every upload
+SET @statement = IF(
+  (SELECT COUNT(*) FROM information_schema.statistics
+    WHERE table_schema = DATABASE() AND table_name = 'attachment_blobs' AND index_name = 'attachment_blobs_sha256') = 0,
+  'CREATE INDEX attachment_blobs_sha256 ON attachment_blobs (sha256(64), byte_size, service_name(64))',
+  'DO 0'
+);
+PREPARE statement FROM @statement;
+EXECUTE statement;
+DEALLOCATE PREPARE statement;
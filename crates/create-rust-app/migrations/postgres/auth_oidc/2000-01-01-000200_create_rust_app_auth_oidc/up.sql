@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS user_oauth2_links (
+  id SERIAL PRIMARY KEY,
+  provider TEXT NOT NULL,
+
+  -- all attempts at oauth2 will create a record with these properties
+  csrf_token TEXT NOT NULL,
+  nonce TEXT NOT NULL,
+  pkce_secret TEXT NOT NULL,
+
+  -- when oauth2 attempts succeed, either a user is created or the oauth2 attempt is discarded
+  -- depending on whether or not the user ends up linking the account or not
+  refresh_token TEXT,
+  access_token TEXT,
+  subject_id TEXT UNIQUE,
+  user_id INTEGER REFERENCES users(id),
+
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+DROP TRIGGER IF EXISTS set_updated_at ON user_oauth2_links;
+CREATE TRIGGER set_updated_at BEFORE UPDATE ON user_oauth2_links
+  FOR EACH ROW EXECUTE PROCEDURE diesel_set_updated_at();
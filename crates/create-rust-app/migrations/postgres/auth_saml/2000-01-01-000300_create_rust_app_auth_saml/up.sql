@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS user_saml_links (
+  id SERIAL PRIMARY KEY,
+  provider TEXT NOT NULL,
+  name_id TEXT NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES users(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (provider, name_id)
+);
+
+DROP TRIGGER IF EXISTS set_updated_at ON user_saml_links;
+CREATE TRIGGER set_updated_at BEFORE UPDATE ON user_saml_links
+  FOR EACH ROW EXECUTE PROCEDURE diesel_set_updated_at();
@@ -0,0 +1,20 @@
+-- the IDs of the AuthnRequests we sent, which responses must be in response to; `browser_token` is also
+-- in a cookie of the browser which started the login, so that it can't be finished in another one
+CREATE TABLE IF NOT EXISTS saml_requests (
+  provider TEXT NOT NULL,
+  request_id TEXT NOT NULL,
+  browser_token TEXT NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (provider, request_id)
+);
+
+-- the IDs of the assertions already used to log in, kept until they expire so they can't be replayed
+CREATE TABLE IF NOT EXISTS saml_assertions (
+  provider TEXT NOT NULL,
+  assertion_id TEXT NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (provider, assertion_id)
+);
+
+CREATE INDEX IF NOT EXISTS saml_requests_expires_at ON saml_requests (expires_at);
+CREATE INDEX IF NOT EXISTS saml_assertions_expires_at ON saml_assertions (expires_at);
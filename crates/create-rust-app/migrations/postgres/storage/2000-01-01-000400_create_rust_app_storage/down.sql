@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS attachment_blob_variants;
+DROP TABLE IF EXISTS attachments;
+DROP TABLE IF EXISTS attachment_blobs;
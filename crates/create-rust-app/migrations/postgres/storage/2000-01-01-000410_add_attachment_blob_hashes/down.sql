@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS attachment_blobs_sha256;
+ALTER TABLE attachment_blobs DROP COLUMN IF EXISTS encryption_key;
+ALTER TABLE attachment_blobs DROP COLUMN IF EXISTS encryption;
+ALTER TABLE attachment_blobs DROP COLUMN IF EXISTS sha256;
@@ -0,0 +1,8 @@
+-- deduplication (by SHA-256) and encryption of the blobs; installations from before these create
+-- `attachment_blobs` without the columns
+ALTER TABLE attachment_blobs ADD COLUMN IF NOT EXISTS sha256 TEXT;
+ALTER TABLE attachment_blobs ADD COLUMN IF NOT EXISTS encryption TEXT;
+ALTER TABLE attachment_blobs ADD COLUMN IF NOT EXISTS encryption_key TEXT;
+
+-- `AttachmentBlob::find_duplicate` looks blobs up by these on every upload
+CREATE INDEX IF NOT EXISTS attachment_blobs_sha256 ON attachment_blobs (sha256, byte_size, service_name);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS fang_tasks;
+DROP TYPE IF EXISTS fang_task_state;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS user_saml_links (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  provider TEXT NOT NULL,
+  name_id TEXT NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES users(id),
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (provider, name_id)
+);
@@ -0,0 +1,12 @@
+-- deduplication (by SHA-256) and encryption of the blobs; installations from before these create
+-- `attachment_blobs` without the columns
+--
+-- SQLite can't add a column only if it's missing: `Database::run_migrations` records this migration
+-- as applied without running it when the table already has them (it was created by the app's own
+-- migration, like in apps generated by the CLI)
+ALTER TABLE attachment_blobs ADD COLUMN sha256 TEXT;
+ALTER TABLE attachment_blobs ADD COLUMN encryption TEXT;
+ALTER TABLE attachment_blobs ADD COLUMN encryption_key TEXT;
+
+-- `AttachmentBlob::find_duplicate` looks blobs up by these on every upload
+CREATE INDEX IF NOT EXISTS attachment_blobs_sha256 ON attachment_blobs (sha256, byte_size, service_name);
@@ -4,10 +4,10 @@ use crate::auth::{
     AccessTokenClaims, Auth, PaginationParams, Permission, Role, User, UserChangeset, UserSession,
     UserSessionChangeset, UserSessionJson, UserSessionResponse, ID,
 };
-use crate::{Connection, Database, Mailer};
+use crate::{Connection, Database, MailError, Mailer};
 
 use lazy_static::lazy_static;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 pub const COOKIE_NAME: &str = "refresh_token";
 
@@ -31,13 +31,13 @@ type Message = &'static str;
 /// Rust struct representing the Json body of
 /// POST requests to the .../login endpoint
 pub struct LoginInput {
-    email: String,
-    password: String,
-    device: Option<String>,
+    pub(super) email: String,
+    pub(super) password: String,
+    pub(super) device: Option<String>,
     #[cfg(not(debug_assertions))]
-    ttl: Option<Seconds>, // Seconds
+    pub(super) ttl: Option<Seconds>, // Seconds
     #[cfg(debug_assertions)]
-    ttl: Option<i64>, // Seconds
+    pub(super) ttl: Option<i64>, // Seconds
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -53,8 +53,8 @@ pub struct RefreshTokenClaims {
 /// Rust struct representing the Json body of
 /// POST requests to the .../register endpoint
 pub struct RegisterInput {
-    email: String,
-    password: String,
+    pub(super) email: String,
+    pub(super) password: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,7 +70,7 @@ pub struct RegistrationClaims {
 /// Rust struct representing the Json body of
 /// GET requests to the .../activate endpoint
 pub struct ActivationInput {
-    activation_token: String,
+    pub(super) activation_token: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -78,7 +78,7 @@ pub struct ActivationInput {
 /// Rust struct representing the Json body of
 /// POST requests to the /forgot endpoint
 pub struct ForgotInput {
-    email: String,
+    pub(super) email: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -94,8 +94,8 @@ pub struct ResetTokenClaims {
 /// Rust struct representing the Json body of
 /// POST requests to the /change endpoint
 pub struct ChangeInput {
-    old_password: String,
-    new_password: String,
+    pub(super) old_password: String,
+    pub(super) new_password: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -103,8 +103,8 @@ pub struct ChangeInput {
 /// Rust struct representing the Json body of
 /// POST requests to the /reset endpoint
 pub struct ResetInput {
-    reset_token: String,
-    new_password: String,
+    pub(super) reset_token: String,
+    pub(super) new_password: String,
 }
 
 /// /sessions
@@ -131,7 +131,7 @@ pub fn get_sessions(
     auth: &Auth,
     info: &PaginationParams,
 ) -> Result<UserSessionResponse, (StatusCode, Message)> {
-    let mut db = db.get_connection().unwrap();
+    let mut db = db.get_read_connection().unwrap();
 
     let Ok(sessions) = UserSession::read_all(&mut db, info, auth.user_id) else {
         return Err((500, "Could not fetch sessions."));
@@ -143,7 +143,7 @@ pub fn get_sessions(
             id: s.id,
             device: s.device.clone(),
             created_at: s.created_at,
-            #[cfg(not(feature = "database_sqlite"))]
+            #[cfg(not(any(feature = "database_sqlite", feature = "database_any")))]
             updated_at: s.updated_at,
         })
         .collect();
@@ -216,8 +216,8 @@ pub fn destroy_sessions(db: &Database, auth: &Auth) -> Result<(), (StatusCode, M
     Ok(())
 }
 
-type AccessToken = String;
-type RefreshToken = String;
+pub(super) type AccessToken = String;
+pub(super) type RefreshToken = String;
 
 /// /login
 ///
@@ -246,14 +246,7 @@ pub fn login(
 ) -> Result<(AccessToken, RefreshToken), (StatusCode, Message)> {
     let mut db = db.get_connection().unwrap();
 
-    // verify device
-    let device = match item.device {
-        Some(ref device) if device.len() > 256 => {
-            return Err((400, "'device' cannot be longer than 256 characters."));
-        }
-        Some(ref device) => Some(device.clone()),
-        None => None,
-    };
+    let device = validate_device(item.device.clone())?;
 
     let user = match User::find_by_email(&mut db, item.email.clone()) {
         Ok(user) if user.activated => user,
@@ -261,15 +254,7 @@ pub fn login(
         Err(_) => return Err((401, "Invalid credentials.")),
     };
 
-    let is_valid = argon2::verify_encoded_ext(
-        &user.hash_password,
-        item.password.as_bytes(),
-        ARGON_CONFIG.secret,
-        ARGON_CONFIG.ad,
-    )
-    .unwrap();
-
-    if !is_valid {
+    if !verify_password(&user.hash_password, &item.password) {
         return Err((401, "Invalid credentials."));
     }
 
@@ -295,14 +280,7 @@ pub fn create_user_session(
     ttl: Option<i64>,
     user_id: i32,
 ) -> Result<(AccessToken, RefreshToken), (StatusCode, Message)> {
-    // verify device
-    let device = match device_type {
-        Some(device) if device.len() > 256 => {
-            return Err((400, "'device' cannot be longer than 256 characters."));
-        }
-        Some(device) => Some(device),
-        None => None,
-    };
+    let device = validate_device(device_type)?;
 
     let Ok(permissions) = Permission::fetch_all(db, user_id) else {
         return Err((500, "An internal server error occurred."));
@@ -312,39 +290,8 @@ pub fn create_user_session(
         return Err((500, "An internal server error occurred."));
     };
 
-    let access_token_duration = chrono::Duration::seconds(
-        ttl.map_or_else(|| /* 15 minutes */ 15 * 60, |tt| std::cmp::max(tt, 1)),
-    );
-
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    let access_token_claims = AccessTokenClaims {
-        exp: (chrono::Utc::now() + access_token_duration).timestamp() as usize,
-        sub: user_id,
-        token_type: "access_token".to_string(),
-        roles,
-        permissions,
-    };
-
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    let refresh_token_claims = RefreshTokenClaims {
-        exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
-        sub: user_id,
-        token_type: "refresh_token".to_string(),
-    };
-
-    let access_token = encode(
-        &Header::default(),
-        &access_token_claims,
-        &EncodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-    )
-    .unwrap();
-
-    let refresh_token = encode(
-        &Header::default(),
-        &refresh_token_claims,
-        &EncodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-    )
-    .unwrap();
+    let (access_token, refresh_token) =
+        session_tokens(user_id, access_token_duration(ttl), roles, permissions);
 
     UserSession::create(
         db,
@@ -418,21 +365,9 @@ pub fn refresh(
         return Err((401, "Invalid session."));
     };
 
-    let _refresh_token = match decode::<RefreshTokenClaims>(
-        refresh_token_str,
-        &DecodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-        &Validation::default(),
-    ) {
-        Ok(token)
-            if token
-                .claims
-                .token_type
-                .eq_ignore_ascii_case("refresh_token") =>
-        {
-            token
-        }
-        _ => return Err((401, "Invalid token.")),
-    };
+    if !is_valid_refresh_token(refresh_token_str) {
+        return Err((401, "Invalid token."));
+    }
 
     let Ok(session) = UserSession::find_by_refresh_token(&mut db, refresh_token_str) else {
         return Err((401, "Invalid session."));
@@ -446,35 +381,12 @@ pub fn refresh(
         return Err((500, "An internal server error occurred."));
     };
 
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    let access_token_claims = AccessTokenClaims {
-        exp: (chrono::Utc::now() + chrono::Duration::minutes(15)).timestamp() as usize,
-        sub: session.user_id,
-        token_type: "access_token".to_string(),
+    let (access_token, refresh_token_str) = session_tokens(
+        session.user_id,
+        chrono::Duration::minutes(15),
         roles,
         permissions,
-    };
-
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    let refresh_token_claims = RefreshTokenClaims {
-        exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
-        sub: session.user_id,
-        token_type: "refresh_token".to_string(),
-    };
-
-    let access_token = encode(
-        &Header::default(),
-        &access_token_claims,
-        &EncodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-    )
-    .unwrap();
-
-    let refresh_token_str = encode(
-        &Header::default(),
-        &refresh_token_claims,
-        &EncodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-    )
-    .unwrap();
+    );
 
     // update session with the new refresh token
     UserSession::update(
@@ -495,64 +407,59 @@ pub fn refresh(
 ///
 /// creates a new User with the information in [`item`](`RegisterInput`)
 ///
-/// sends an email, using [`mailer`](`Mailer`), to the email address in [`item`](`RegisterInput`)
+/// queues an email, using [`mailer`](`Mailer`), to the email address in [`item`](`RegisterInput`)
 /// that contains a unique link that allows the recipient to activate the account associated with
 /// that email address
 ///
+/// the email is written to the outbox (see [`Mailer::enqueue`]) in the transaction creating the user,
+/// and delivered by the outbox worker; the other endpoints sending emails do the same
+///
+/// emails are sent in `locale` (like `fr-CA`) when there are templates for it, here and in the other
+/// endpoints sending emails; see [`MailTemplates`](`crate::MailTemplates`)
+///
 /// # Errors
 /// - 400: Already registered
+/// - 500: Could not register
 ///
 /// # Panics
 /// - could not connect to database
 /// - could not get `SECRET_KEY` from environment
-/// - any of the database operations fail
 ///
 /// TODO: don't panic if db connection fails, just return an error
 pub fn register(
     db: &Database,
     item: &RegisterInput,
     mailer: &Mailer,
+    locale: Option<&str>,
 ) -> Result<(), (StatusCode, Message)> {
     let mut db = db.get_connection().unwrap();
 
-    match User::find_by_email(&mut db, item.email.to_string()) {
+    let unactivated_user_id = match User::find_by_email(&mut db, item.email.to_string()) {
         Ok(user) if user.activated => return Err((400, "Already registered.")),
-        Ok(user) => {
-            User::delete(&mut db, user.id).unwrap();
-        }
-        Err(_) => (),
-    }
-
-    let salt = generate_salt();
-    let hash = argon2::hash_encoded(item.password.as_bytes(), &salt, &ARGON_CONFIG).unwrap();
+        Ok(user) => Some(user.id),
+        Err(_) => None,
+    };
 
-    let user = User::create(
-        &mut db,
-        &UserChangeset {
-            activated: false,
-            email: item.email.clone(),
-            hash_password: hash,
-        },
-    )
-    .unwrap();
+    let hash = hash_password(&item.password);
 
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    let registration_claims = RegistrationClaims {
-        exp: (chrono::Utc::now() + chrono::Duration::days(30)).timestamp() as usize,
-        sub: user.id,
-        token_type: "activation_token".to_string(),
-    };
+    diesel::connection::Connection::transaction::<_, MailError, _>(&mut db, |db| {
+        if let Some(user_id) = unactivated_user_id {
+            User::delete(db, user_id)?;
+        }
 
-    let token = encode(
-        &Header::default(),
-        &registration_claims,
-        &EncodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-    )
-    .unwrap();
+        let user = User::create(
+            db,
+            &UserChangeset {
+                activated: false,
+                email: item.email.clone(),
+                hash_password: hash,
+            },
+        )?;
 
-    mailer
-        .templates
-        .send_register(mailer, &user.email, &format!("activate?token={token}"));
+        let link = format!("activate?token={}", activation_token(user.id));
+        mailer.enqueue(db, &mailer.templates.register(&user.email, &link, locale)?)
+    })
+    .map_err(|_| (500, "Could not register."))?;
 
     Ok(())
 }
@@ -577,44 +484,35 @@ pub fn activate(
     db: &Database,
     item: &ActivationInput,
     mailer: &Mailer,
+    locale: Option<&str>,
 ) -> Result<(), (StatusCode, Message)> {
     let mut db = db.get_connection().unwrap();
 
-    let token = match decode::<RegistrationClaims>(
-        &item.activation_token,
-        &DecodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-        &Validation::default(),
-    ) {
-        Ok(token)
-            if token
-                .claims
-                .token_type
-                .eq_ignore_ascii_case("activation_token") =>
-        {
-            token
-        }
-        _ => return Err((401, "Invalid token.")),
+    let Some(user_id) = activation_token_user_id(&item.activation_token) else {
+        return Err((401, "Invalid token."));
     };
 
-    let user = match User::read(&mut db, token.claims.sub) {
+    let user = match User::read(&mut db, user_id) {
         Ok(user) if !user.activated => user,
         Ok(_) => return Err((200, "Already activated!")),
         Err(_) => return Err((400, "Invalid token.")),
     };
 
-    User::update(
-        &mut db,
-        user.id,
-        &UserChangeset {
-            activated: true,
-            email: user.email.clone(),
-            hash_password: user.hash_password,
-        },
-    )
+    diesel::connection::Connection::transaction::<_, MailError, _>(&mut db, |db| {
+        User::update(
+            db,
+            user.id,
+            &UserChangeset {
+                activated: true,
+                email: user.email.clone(),
+                hash_password: user.hash_password,
+            },
+        )?;
+
+        mailer.enqueue(db, &mailer.templates.activated(&user.email, locale)?)
+    })
     .map_err(|_| (500, "Could not activate user."))?;
 
-    mailer.templates.send_activated(mailer, &user.email);
-
     Ok(())
 }
 
@@ -622,13 +520,13 @@ pub fn activate(
 /// sends an email to the email in the [`ForgotInput`] Json in the request body
 /// that will allow the user associated with that email to change their password
 ///
-/// sends an email, using [`mailer`](`Mailer`), to the email address in [`item`](`RegisterInput`)
+/// queues an email, using [`mailer`](`Mailer`), to the email address in [`item`](`RegisterInput`)
 /// that contains a unique link that allows the recipient to reset the password
 /// of the account associated with that email address (or create a new account if there is
 /// no accound accosiated with the email address)
 ///
 /// # Errors
-/// - None
+/// - 500: Could not queue the email
 ///
 /// # Panics
 /// - could not connect to database
@@ -640,40 +538,32 @@ pub fn forgot_password(
     db: &Database,
     item: &ForgotInput,
     mailer: &Mailer,
+    locale: Option<&str>,
 ) -> Result<(), (StatusCode, Message)> {
     let mut db = db.get_connection().unwrap();
 
     let user_result = User::find_by_email(&mut db, item.email.clone());
 
-    if let Ok(user) = user_result {
+    let email = if let Ok(user) = user_result {
         // if !user.activated {
         //   return Ok(HttpResponse::build(400).body(" has not been activate"))
         // }
 
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let reset_token_claims = ResetTokenClaims {
-            exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
-            sub: user.id,
-            token_type: "reset_token".to_string(),
-        };
-
-        let reset_token = encode(
-            &Header::default(),
-            &reset_token_claims,
-            &EncodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-        )
-        .unwrap();
+        let reset_token = reset_token(user.id);
 
         let link = &format!("reset?token={reset_token}");
         mailer
             .templates
-            .send_recover_existent_account(mailer, &user.email, link);
+            .recover_existent_account(&user.email, link, locale)
     } else {
-        let link = &"register".to_string();
         mailer
             .templates
-            .send_recover_nonexistent_account(mailer, &item.email, link);
-    }
+            .recover_nonexistent_account(&item.email, "register", locale)
+    };
+
+    email
+        .and_then(|email| mailer.enqueue(&mut db, &email))
+        .map_err(|_| (500, "Could not queue the email."))?;
 
     Ok(())
 }
@@ -701,6 +591,7 @@ pub fn change_password(
     item: &ChangeInput,
     auth: &Auth,
     mailer: &Mailer,
+    locale: Option<&str>,
 ) -> Result<(), (StatusCode, Message)> {
     if item.old_password.is_empty() || item.new_password.is_empty() {
         return Err((400, "Missing password"));
@@ -718,35 +609,27 @@ pub fn change_password(
         Err(_) => return Err((500, "Could not find user")),
     };
 
-    let is_old_password_valid = argon2::verify_encoded_ext(
-        &user.hash_password,
-        item.old_password.as_bytes(),
-        ARGON_CONFIG.secret,
-        ARGON_CONFIG.ad,
-    )
-    .unwrap();
-
-    if !is_old_password_valid {
+    if !verify_password(&user.hash_password, &item.old_password) {
         return Err((401, "Invalid credentials"));
     }
 
-    let salt = generate_salt();
-    let new_hash =
-        argon2::hash_encoded(item.new_password.as_bytes(), &salt, &ARGON_CONFIG).unwrap();
-
-    User::update(
-        &mut db,
-        auth.user_id,
-        &UserChangeset {
-            email: user.email.clone(),
-            hash_password: new_hash,
-            activated: user.activated,
-        },
-    )
+    let new_hash = hash_password(&item.new_password);
+
+    diesel::connection::Connection::transaction::<_, MailError, _>(&mut db, |db| {
+        User::update(
+            db,
+            auth.user_id,
+            &UserChangeset {
+                email: user.email.clone(),
+                hash_password: new_hash,
+                activated: user.activated,
+            },
+        )?;
+
+        mailer.enqueue(db, &mailer.templates.password_changed(&user.email, locale)?)
+    })
     .map_err(|_| (500, "Could not update password"))?;
 
-    mailer.templates.send_password_changed(mailer, &user.email);
-
     Ok(())
 }
 
@@ -777,6 +660,7 @@ pub fn reset_password(
     db: &Database,
     item: &ResetInput,
     mailer: &Mailer,
+    locale: Option<&str>,
 ) -> Result<(), (StatusCode, Message)> {
     let mut db = db.get_connection().unwrap();
 
@@ -784,39 +668,166 @@ pub fn reset_password(
         return Err((400, "Missing password"));
     }
 
-    let token = match decode::<ResetTokenClaims>(
-        &item.reset_token,
-        &DecodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
-        &Validation::default(),
-    ) {
-        Ok(token) if token.claims.token_type.eq_ignore_ascii_case("reset_token") => token,
-        _ => return Err((401, "Invalid token.")),
+    let Some(user_id) = reset_token_user_id(&item.reset_token) else {
+        return Err((401, "Invalid token."));
     };
 
-    let user = match User::read(&mut db, token.claims.sub) {
+    let user = match User::read(&mut db, user_id) {
         Ok(user) if user.activated => user,
         Ok(_) => return Err((400, "Account has not been activated")),
         Err(_) => return Err((400, "Invalid token.")),
     };
 
+    let new_hash = hash_password(&item.new_password);
+
+    diesel::connection::Connection::transaction::<_, MailError, _>(&mut db, |db| {
+        User::update(
+            db,
+            user.id,
+            &UserChangeset {
+                email: user.email.clone(),
+                hash_password: new_hash,
+                activated: user.activated,
+            },
+        )?;
+
+        mailer.enqueue(db, &mailer.templates.password_reset(&user.email, locale)?)
+    })
+    .map_err(|_| (500, "Could not update password"))?;
+
+    Ok(())
+}
+
+/// `device` if it's at most 256 characters long
+pub(super) fn validate_device(
+    device: Option<String>,
+) -> Result<Option<String>, (StatusCode, Message)> {
+    match device {
+        Some(device) if device.len() > 256 => {
+            Err((400, "'device' cannot be longer than 256 characters."))
+        }
+        device => Ok(device),
+    }
+}
+
+/// whether `password` matches a [`User`]'s `hash_password`
+///
+/// # Panics
+/// - `hash` is not an argon2 hash
+pub(super) fn verify_password(hash: &str, password: &str) -> bool {
+    argon2::verify_encoded_ext(
+        hash,
+        password.as_bytes(),
+        ARGON_CONFIG.secret,
+        ARGON_CONFIG.ad,
+    )
+    .unwrap()
+}
+
+/// the `hash_password` of a [`User`] with `password`
+pub(super) fn hash_password(password: &str) -> String {
     let salt = generate_salt();
-    let new_hash =
-        argon2::hash_encoded(item.new_password.as_bytes(), &salt, &ARGON_CONFIG).unwrap();
+    argon2::hash_encoded(password.as_bytes(), &salt, &ARGON_CONFIG).unwrap()
+}
 
-    User::update(
-        &mut db,
-        user.id,
-        &UserChangeset {
-            email: user.email.clone(),
-            hash_password: new_hash,
-            activated: user.activated,
-        },
+/// how long the access tokens of a session created with `ttl` are valid (default: 15 minutes)
+pub(super) fn access_token_duration(ttl: Option<i64>) -> chrono::Duration {
+    chrono::Duration::seconds(
+        ttl.map_or_else(|| /* 15 minutes */ 15 * 60, |tt| std::cmp::max(tt, 1)),
     )
-    .map_err(|_| (500, "Could not update password"))?;
+}
+
+/// the access token, with `roles` and `permissions`, and the refresh token of a session of the
+/// User whose id is [`user_id`](`ID`)
+pub(super) fn session_tokens(
+    user_id: ID,
+    access_token_duration: chrono::Duration,
+    roles: Vec<String>,
+    permissions: Vec<Permission>,
+) -> (AccessToken, RefreshToken) {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let access_token_claims = AccessTokenClaims {
+        exp: (chrono::Utc::now() + access_token_duration).timestamp() as usize,
+        sub: user_id,
+        token_type: "access_token".to_string(),
+        roles,
+        permissions,
+    };
 
-    mailer.templates.send_password_reset(mailer, &user.email);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let refresh_token_claims = RefreshTokenClaims {
+        exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
+        sub: user_id,
+        token_type: "refresh_token".to_string(),
+    };
 
-    Ok(())
+    (
+        encode_token(&access_token_claims),
+        encode_token(&refresh_token_claims),
+    )
+}
+
+/// whether `token` is an unexpired refresh token
+pub(super) fn is_valid_refresh_token(token: &str) -> bool {
+    decode_token::<RefreshTokenClaims>(token)
+        .is_some_and(|claims| claims.token_type.eq_ignore_ascii_case("refresh_token"))
+}
+
+/// the token in the link of the registration email
+pub(super) fn activation_token(user_id: ID) -> String {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let registration_claims = RegistrationClaims {
+        exp: (chrono::Utc::now() + chrono::Duration::days(30)).timestamp() as usize,
+        sub: user_id,
+        token_type: "activation_token".to_string(),
+    };
+
+    encode_token(&registration_claims)
+}
+
+/// the id of the User to activate, if `token` is a valid [`activation_token`]
+pub(super) fn activation_token_user_id(token: &str) -> Option<ID> {
+    decode_token::<RegistrationClaims>(token)
+        .filter(|claims| claims.token_type.eq_ignore_ascii_case("activation_token"))
+        .map(|claims| claims.sub)
+}
+
+/// the token in the link of the password recovery email
+pub(super) fn reset_token(user_id: ID) -> String {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let reset_token_claims = ResetTokenClaims {
+        exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
+        sub: user_id,
+        token_type: "reset_token".to_string(),
+    };
+
+    encode_token(&reset_token_claims)
+}
+
+/// the id of the User whose password to reset, if `token` is a valid [`reset_token`]
+pub(super) fn reset_token_user_id(token: &str) -> Option<ID> {
+    decode_token::<ResetTokenClaims>(token)
+        .filter(|claims| claims.token_type.eq_ignore_ascii_case("reset_token"))
+        .map(|claims| claims.sub)
+}
+
+fn encode_token(claims: &impl Serialize) -> String {
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
+    )
+    .unwrap()
+}
+
+fn decode_token<T: DeserializeOwned>(token: &str) -> Option<T> {
+    decode::<T>(
+        token,
+        &DecodingKey::from_secret(std::env::var("SECRET_KEY").unwrap().as_ref()),
+        &Validation::default(),
+    )
+    .ok()
+    .map(|token| token.claims)
 }
 
 #[must_use]
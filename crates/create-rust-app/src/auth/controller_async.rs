@@ -0,0 +1,578 @@
+//! the functions of [`controller`](`super::controller`) on the `database_async` pool
+//!
+//! they don't block the runtime's threads: the queries use [`Database::get_async_connection`],
+//! hashing the passwords runs with [`tokio::task::spawn_blocking`], and the emails are queued in the
+//! outbox with [`Mailer::enqueue_async`].
+//! Unlike the blocking ones, they don't panic when no connection can be had.
+
+use crate::auth::controller::{
+    access_token_duration, activation_token, activation_token_user_id, hash_password,
+    is_valid_refresh_token, reset_token, reset_token_user_id, session_tokens, validate_device,
+    verify_password, AccessToken, ActivationInput, ChangeInput, ForgotInput, LoginInput,
+    RefreshToken, RegisterInput, ResetInput,
+};
+use crate::auth::{
+    Auth, PaginationParams, Permission, Role, User, UserChangeset, UserSession,
+    UserSessionChangeset, UserSessionJson, UserSessionResponse, ID,
+};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::AsyncConnection as _;
+
+use crate::{AsyncConnection, Database, MailError, Mailer};
+
+type StatusCode = u16;
+type Message = &'static str;
+
+async fn connection(db: &Database) -> Result<AsyncConnection, (StatusCode, Message)> {
+    db.get_async_connection()
+        .await
+        .map_err(|_| (500, "Could not connect to the database."))
+}
+
+/// runs `f`, which hashes a password, on tokio's blocking threads
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, (StatusCode, Message)> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|_| (500, "An internal server error occurred."))
+}
+
+/// like [`controller::get_sessions`](`super::controller::get_sessions`)
+///
+/// # Errors
+/// - 500: Could not connect to the database
+/// - 500: Could not fetch sessions
+pub async fn get_sessions(
+    db: &Database,
+    auth: &Auth,
+    info: &PaginationParams,
+) -> Result<UserSessionResponse, (StatusCode, Message)> {
+    let mut db = connection(db).await?;
+
+    let Ok(sessions) = UserSession::read_all_async(&mut db, info, auth.user_id).await else {
+        return Err((500, "Could not fetch sessions."));
+    };
+
+    let sessions_json: Vec<UserSessionJson> = sessions
+        .iter()
+        .map(|s| UserSessionJson {
+            id: s.id,
+            device: s.device.clone(),
+            created_at: s.created_at,
+            #[cfg(not(feature = "database_sqlite"))]
+            updated_at: s.updated_at,
+        })
+        .collect();
+
+    let Ok(num_sessions) = UserSession::count_all_async(&mut db, auth.user_id).await else {
+        return Err((500, "Could not fetch sessions."));
+    };
+
+    let num_pages = (num_sessions / info.page_size) + i64::from(num_sessions % info.page_size != 0);
+
+    Ok(UserSessionResponse {
+        sessions: sessions_json,
+        num_pages,
+    })
+}
+
+/// like [`controller::destroy_session`](`super::controller::destroy_session`)
+///
+/// # Errors
+/// - 404: Session not found
+/// - 500: Could not connect to the database
+/// - 500: Internal error
+/// - 500: Could not delete session
+pub async fn destroy_session(
+    db: &Database,
+    auth: &Auth,
+    item_id: ID,
+) -> Result<(), (StatusCode, Message)> {
+    let mut db = connection(db).await?;
+
+    let user_session = match UserSession::read_async(&mut db, item_id).await {
+        Ok(user_session) if user_session.user_id == auth.user_id => user_session,
+        Ok(_) => return Err((404, "Session not found.")),
+        Err(_) => return Err((500, "Internal error.")),
+    };
+
+    UserSession::delete_async(&mut db, user_session.id)
+        .await
+        .map_err(|_| (500, "Could not delete session."))?;
+
+    Ok(())
+}
+
+/// like [`controller::destroy_sessions`](`super::controller::destroy_sessions`)
+///
+/// # Errors
+/// - 500: Could not connect to the database
+/// - 500: Could not delete sessions
+pub async fn destroy_sessions(db: &Database, auth: &Auth) -> Result<(), (StatusCode, Message)> {
+    let mut db = connection(db).await?;
+
+    UserSession::delete_all_for_user_async(&mut db, auth.user_id)
+        .await
+        .map_err(|_| (500, "Could not delete sessions."))?;
+
+    Ok(())
+}
+
+/// like [`controller::login`](`super::controller::login`)
+///
+/// # Errors
+/// - 400: 'device' cannot be longer than 256 characters.
+/// - 400: Account has not been activated.
+/// - 401: Invalid credentials.
+/// - 500: Could not connect to the database
+///
+/// # Panics
+/// - verifying the password hash fails
+pub async fn login(
+    db: &Database,
+    item: &LoginInput,
+) -> Result<(AccessToken, RefreshToken), (StatusCode, Message)> {
+    let device = validate_device(item.device.clone())?;
+
+    let mut db = connection(db).await?;
+
+    let user = match User::find_by_email_async(&mut db, item.email.clone()).await {
+        Ok(user) if user.activated => user,
+        Ok(_) => return Err((400, "Account has not been activated.")),
+        Err(_) => return Err((401, "Invalid credentials.")),
+    };
+
+    let hash = user.hash_password;
+    let password = item.password.clone();
+    if !blocking(move || verify_password(&hash, &password)).await? {
+        return Err((401, "Invalid credentials."));
+    }
+
+    create_user_session(&mut db, device, None, user.id).await
+}
+
+/// like [`controller::create_user_session`](`super::controller::create_user_session`)
+///
+/// # Errors
+/// - 400: 'device' cannot be longer than 256 characters.
+/// - 500: An internal server error occurred.
+/// - 500: Could not create session.
+///
+/// # Panics
+/// - could not get `SECRET_KEY` from environment
+pub async fn create_user_session(
+    db: &mut AsyncConnection,
+    device_type: Option<String>,
+    ttl: Option<i64>,
+    user_id: ID,
+) -> Result<(AccessToken, RefreshToken), (StatusCode, Message)> {
+    let device = validate_device(device_type)?;
+
+    let Ok(permissions) = Permission::fetch_all_async(db, user_id).await else {
+        return Err((500, "An internal server error occurred."));
+    };
+
+    let Ok(roles) = Role::fetch_all_async(db, user_id).await else {
+        return Err((500, "An internal server error occurred."));
+    };
+
+    let (access_token, refresh_token) =
+        session_tokens(user_id, access_token_duration(ttl), roles, permissions);
+
+    UserSession::create_async(
+        db,
+        &UserSessionChangeset {
+            user_id,
+            refresh_token: refresh_token.clone(),
+            device,
+        },
+    )
+    .await
+    .map_err(|_| (500, "Could not create session."))?;
+
+    Ok((access_token, refresh_token))
+}
+
+/// like [`controller::logout`](`super::controller::logout`)
+///
+/// # Errors
+/// - 401: Invalid session
+/// - 500: Could not connect to the database
+/// - 500: Could not delete session
+pub async fn logout(
+    db: &Database,
+    refresh_token: Option<&'_ str>,
+) -> Result<(), (StatusCode, Message)> {
+    let Some(refresh_token) = refresh_token else {
+        return Err((401, "Invalid session."));
+    };
+
+    let mut db = connection(db).await?;
+
+    let Ok(session) = UserSession::find_by_refresh_token_async(&mut db, refresh_token).await else {
+        return Err((401, "Invalid session."));
+    };
+
+    UserSession::delete_async(&mut db, session.id)
+        .await
+        .map_err(|_| (500, "Could not delete session."))?;
+
+    Ok(())
+}
+
+/// like [`controller::refresh`](`super::controller::refresh`)
+///
+/// # Errors
+/// - 401: Invalid session
+/// - 401: Invalid token
+/// - 500: Could not connect to the database
+/// - 500: Could not update session
+/// - 500: An internal server error occurred
+///
+/// # Panics
+/// - could not get `SECRET_KEY` from environment
+pub async fn refresh(
+    db: &Database,
+    refresh_token_str: Option<&'_ str>,
+) -> Result<(AccessToken, RefreshToken), (StatusCode, Message)> {
+    let Some(refresh_token_str) = refresh_token_str else {
+        return Err((401, "Invalid session."));
+    };
+
+    if !is_valid_refresh_token(refresh_token_str) {
+        return Err((401, "Invalid token."));
+    }
+
+    let mut db = connection(db).await?;
+
+    let Ok(session) = UserSession::find_by_refresh_token_async(&mut db, refresh_token_str).await
+    else {
+        return Err((401, "Invalid session."));
+    };
+
+    let Ok(permissions) = Permission::fetch_all_async(&mut db, session.user_id).await else {
+        return Err((500, "An internal server error occurred."));
+    };
+
+    let Ok(roles) = Role::fetch_all_async(&mut db, session.user_id).await else {
+        return Err((500, "An internal server error occurred."));
+    };
+
+    let (access_token, refresh_token_str) = session_tokens(
+        session.user_id,
+        chrono::Duration::minutes(15),
+        roles,
+        permissions,
+    );
+
+    // update session with the new refresh token
+    UserSession::update_async(
+        &mut db,
+        session.id,
+        &UserSessionChangeset {
+            user_id: session.user_id,
+            refresh_token: refresh_token_str.clone(),
+            device: session.device,
+        },
+    )
+    .await
+    .map_err(|_| (500, "Could not update session."))?;
+
+    Ok((access_token, refresh_token_str))
+}
+
+/// like [`controller::register`](`super::controller::register`)
+///
+/// # Errors
+/// - 400: Already registered
+/// - 500: Could not connect to the database
+/// - 500: Could not register
+///
+/// # Panics
+/// - could not get `SECRET_KEY` from environment
+pub async fn register(
+    db: &Database,
+    item: &RegisterInput,
+    mailer: &Mailer,
+    locale: Option<&str>,
+) -> Result<(), (StatusCode, Message)> {
+    let mut db = connection(db).await?;
+
+    let unactivated_user_id = match User::find_by_email_async(&mut db, item.email.clone()).await {
+        Ok(user) if user.activated => return Err((400, "Already registered.")),
+        Ok(user) => Some(user.id),
+        Err(_) => None,
+    };
+
+    let password = item.password.clone();
+    let hash = blocking(move || hash_password(&password)).await?;
+
+    // the transaction's future can't borrow from the caller
+    let (mailer, email, locale) = (
+        mailer.clone(),
+        item.email.clone(),
+        locale.map(ToString::to_string),
+    );
+    db.transaction::<_, MailError, _>(|db| {
+        async move {
+            if let Some(user_id) = unactivated_user_id {
+                User::delete_async(db, user_id).await?;
+            }
+
+            let user = User::create_async(
+                db,
+                &UserChangeset {
+                    activated: false,
+                    email,
+                    hash_password: hash,
+                },
+            )
+            .await?;
+
+            let link = format!("activate?token={}", activation_token(user.id));
+            let email = mailer
+                .templates
+                .register(&user.email, &link, locale.as_deref())?;
+            mailer.enqueue_async(db, &email).await
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|_| (500, "Could not register."))?;
+
+    Ok(())
+}
+
+/// like [`controller::activate`](`super::controller::activate`)
+///
+/// # Errors
+/// - 401: Invalid token
+/// - 400: Invalid token
+/// - 200: Already activated!
+/// - 500: Could not connect to the database
+/// - 500: Could not activate user
+///
+/// # Panics
+/// - could not get `SECRET_KEY` from environment
+pub async fn activate(
+    db: &Database,
+    item: &ActivationInput,
+    mailer: &Mailer,
+    locale: Option<&str>,
+) -> Result<(), (StatusCode, Message)> {
+    let Some(user_id) = activation_token_user_id(&item.activation_token) else {
+        return Err((401, "Invalid token."));
+    };
+
+    let mut db = connection(db).await?;
+
+    let user = match User::read_async(&mut db, user_id).await {
+        Ok(user) if !user.activated => user,
+        Ok(_) => return Err((200, "Already activated!")),
+        Err(_) => return Err((400, "Invalid token.")),
+    };
+
+    let (mailer, locale) = (mailer.clone(), locale.map(ToString::to_string));
+    db.transaction::<_, MailError, _>(|db| {
+        async move {
+            User::update_async(
+                db,
+                user.id,
+                &UserChangeset {
+                    activated: true,
+                    email: user.email.clone(),
+                    hash_password: user.hash_password,
+                },
+            )
+            .await?;
+
+            let email = mailer.templates.activated(&user.email, locale.as_deref())?;
+            mailer.enqueue_async(db, &email).await
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|_| (500, "Could not activate user."))?;
+
+    Ok(())
+}
+
+/// like [`controller::forgot_password`](`super::controller::forgot_password`)
+///
+/// # Errors
+/// - 500: Could not connect to the database
+/// - 500: Could not queue the email
+///
+/// # Panics
+/// - could not get `SECRET_KEY` from environment
+pub async fn forgot_password(
+    db: &Database,
+    item: &ForgotInput,
+    mailer: &Mailer,
+    locale: Option<&str>,
+) -> Result<(), (StatusCode, Message)> {
+    let mut db = connection(db).await?;
+
+    let email = match User::find_by_email_async(&mut db, item.email.clone()).await {
+        Ok(user) => {
+            let link = format!("reset?token={}", reset_token(user.id));
+            mailer
+                .templates
+                .recover_existent_account(&user.email, &link, locale)
+        }
+        Err(_) => mailer
+            .templates
+            .recover_nonexistent_account(&item.email, "register", locale),
+    }
+    .map_err(|_| (500, "Could not queue the email."))?;
+
+    mailer
+        .enqueue_async(&mut db, &email)
+        .await
+        .map_err(|_| (500, "Could not queue the email."))?;
+
+    Ok(())
+}
+
+/// like [`controller::change_password`](`super::controller::change_password`)
+///
+/// # Errors
+/// - 400: Missing password
+/// - 400: The new password must be different
+/// - 400: Account has not been activated
+/// - 401: Invalid credentials
+/// - 500: Could not connect to the database
+/// - 500: Could not update password
+/// - 500: Could not find user
+///
+/// # Panics
+/// - verifying the password hash fails
+pub async fn change_password(
+    db: &Database,
+    item: &ChangeInput,
+    auth: &Auth,
+    mailer: &Mailer,
+    locale: Option<&str>,
+) -> Result<(), (StatusCode, Message)> {
+    if item.old_password.is_empty() || item.new_password.is_empty() {
+        return Err((400, "Missing password"));
+    }
+
+    if item.old_password.eq(&item.new_password) {
+        return Err((400, "The new password must be different"));
+    }
+
+    let mut db = connection(db).await?;
+
+    let user = match User::read_async(&mut db, auth.user_id).await {
+        Ok(user) if user.activated => user,
+        Ok(_) => return Err((400, "Account has not been activated")),
+        Err(_) => return Err((500, "Could not find user")),
+    };
+
+    let hash = user.hash_password.clone();
+    let old_password = item.old_password.clone();
+    let new_password = item.new_password.clone();
+    let Some(new_hash) = blocking(move || {
+        verify_password(&hash, &old_password).then(|| hash_password(&new_password))
+    })
+    .await?
+    else {
+        return Err((401, "Invalid credentials"));
+    };
+
+    let (mailer, locale, user_id) = (
+        mailer.clone(),
+        locale.map(ToString::to_string),
+        auth.user_id,
+    );
+    db.transaction::<_, MailError, _>(|db| {
+        async move {
+            User::update_async(
+                db,
+                user_id,
+                &UserChangeset {
+                    email: user.email.clone(),
+                    hash_password: new_hash,
+                    activated: user.activated,
+                },
+            )
+            .await?;
+
+            let email = mailer
+                .templates
+                .password_changed(&user.email, locale.as_deref())?;
+            mailer.enqueue_async(db, &email).await
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|_| (500, "Could not update password"))?;
+
+    Ok(())
+}
+
+/// like [`controller::reset_password`](`super::controller::reset_password`)
+///
+/// # Errors
+/// - 400: Missing password
+/// - 401: Invalid token
+/// - 400: Invalid token
+/// - 400: Account has not been activated
+/// - 500: Could not connect to the database
+/// - 500: Could not update password
+///
+/// # Panics
+/// - could not get `SECRET_KEY` from environment
+pub async fn reset_password(
+    db: &Database,
+    item: &ResetInput,
+    mailer: &Mailer,
+    locale: Option<&str>,
+) -> Result<(), (StatusCode, Message)> {
+    if item.new_password.is_empty() {
+        return Err((400, "Missing password"));
+    }
+
+    let Some(user_id) = reset_token_user_id(&item.reset_token) else {
+        return Err((401, "Invalid token."));
+    };
+
+    let mut db = connection(db).await?;
+
+    let user = match User::read_async(&mut db, user_id).await {
+        Ok(user) if user.activated => user,
+        Ok(_) => return Err((400, "Account has not been activated")),
+        Err(_) => return Err((400, "Invalid token.")),
+    };
+
+    let new_password = item.new_password.clone();
+    let new_hash = blocking(move || hash_password(&new_password)).await?;
+
+    let (mailer, locale) = (mailer.clone(), locale.map(ToString::to_string));
+    db.transaction::<_, MailError, _>(|db| {
+        async move {
+            User::update_async(
+                db,
+                user.id,
+                &UserChangeset {
+                    email: user.email.clone(),
+                    hash_password: new_hash,
+                    activated: user.activated,
+                },
+            )
+            .await?;
+
+            let email = mailer
+                .templates
+                .password_reset(&user.email, locale.as_deref())?;
+            mailer.enqueue_async(db, &email).await
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|_| (500, "Could not update password"))?;
+
+    Ok(())
+}
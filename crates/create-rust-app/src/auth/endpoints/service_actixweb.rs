@@ -21,7 +21,24 @@ use crate::auth::{
     },
     Auth, PaginationParams, ID,
 };
-use crate::{auth::AuthConfig, AppConfig, Database, Mailer};
+use crate::{auth::AuthConfig, preferred_locale, AppConfig, Database, Mailer};
+
+/// the locale the user prefers, from the `Accept-Language` header; emails are sent in it
+fn locale(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(actix_web::http::header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(preferred_locale)
+}
+
+/// where to send the user when an OIDC or SAML login fails
+#[cfg(any(feature = "plugin_auth-oidc", feature = "plugin_auth-saml"))]
+fn error_redirect_uri(error_uri: &str, status_code: u16, message: &str) -> String {
+    format!(
+        "{error_uri}?status_code={status_code}&message={message}",
+        message = crate::auth::url_encode(message)
+    )
+}
 
 /// handler for GET requests at the .../sessions endpoint,
 ///
@@ -284,9 +301,10 @@ async fn oidc_login(
         }
         Err((status_code, message)) => response.headers_mut().append(
             LOCATION,
-            HeaderValue::from_str(&format!(
-                "{}?status_code={}&message={}",
-                provider.error_uri, status_code, message
+            HeaderValue::from_str(&error_redirect_uri(
+                &provider.error_uri,
+                status_code,
+                &message,
             ))
             .expect("Invalid URL"),
         ),
@@ -295,6 +313,145 @@ async fn oidc_login(
     response
 }
 
+#[cfg(feature = "plugin_auth-saml")]
+#[get("/saml/{provider}/metadata")]
+async fn saml_metadata(
+    app_config: Data<AppConfig>,
+    auth_config: Data<AuthConfig>,
+    provider: Path<String>,
+) -> HttpResponse {
+    match crate::auth::saml::controller::saml_metadata(&app_config, &auth_config, &provider) {
+        Some(metadata) => HttpResponse::Ok()
+            .content_type("application/samlmetadata+xml")
+            .body(metadata),
+        None => HttpResponse::NotImplemented().finish(),
+    }
+}
+
+#[cfg(feature = "plugin_auth-saml")]
+#[get("/saml/{provider}")]
+async fn saml_login_redirect(
+    db: Data<Database>,
+    app_config: Data<AppConfig>,
+    auth_config: Data<AuthConfig>,
+    provider: Path<String>,
+) -> Result<HttpResponse, AWError> {
+    use actix_web::http::header::LOCATION;
+
+    use crate::auth::saml::controller::{AUTHN_REQUEST_LIFETIME, BROWSER_TOKEN_COOKIE_NAME};
+
+    let cookie_path = format!("/api/auth/saml/{provider}");
+    let url = web::block(move || {
+        crate::auth::saml::controller::saml_login_url(&db, &app_config, &auth_config, &provider)
+    })
+    .await?;
+
+    Ok(match url {
+        // the IdP posts the response cross-site, so the cookie can't be `SameSite=Strict` (or `Lax`)
+        Ok(Some((url, browser_token))) => HttpResponse::SeeOther()
+            .insert_header((LOCATION, url))
+            .cookie(
+                Cookie::build(BROWSER_TOKEN_COOKIE_NAME, browser_token)
+                    .secure(true)
+                    .http_only(true)
+                    .same_site(SameSite::None)
+                    .path(cookie_path)
+                    .max_age(actix_web::cookie::time::Duration::seconds(
+                        AUTHN_REQUEST_LIFETIME.num_seconds(),
+                    ))
+                    .finish(),
+            )
+            .finish(),
+        Ok(None) => HttpResponse::NotImplemented().finish(),
+        Err(error) => {
+            log::error!("Could not start SAML login: {error}");
+            HttpResponse::InternalServerError().finish()
+        }
+    })
+}
+
+#[cfg(feature = "plugin_auth-saml")]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SAMLResponseForm {
+    #[serde(rename = "SAMLResponse")]
+    saml_response: String,
+}
+
+#[cfg(feature = "plugin_auth-saml")]
+#[post("/saml/{provider}/acs")]
+async fn saml_login(
+    db: Data<Database>,
+    app_config: Data<AppConfig>,
+    auth_config: Data<AuthConfig>,
+    path_params: Path<String>,
+    form: web::Form<SAMLResponseForm>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AWError> {
+    use crate::auth::saml::controller::BROWSER_TOKEN_COOKIE_NAME;
+    use actix_web::http::header::LOCATION;
+    let provider_name = path_params.into_inner();
+
+    let browser_token = req
+        .cookie(BROWSER_TOKEN_COOKIE_NAME)
+        .map(|cookie| String::from(cookie.value()));
+    // the request it was bound to is used up either way
+    let mut browser_token_removal = Cookie::build(BROWSER_TOKEN_COOKIE_NAME, "")
+        .path(format!("/api/auth/saml/{provider_name}"))
+        .finish();
+    browser_token_removal.make_removal();
+
+    let Some(provider) = auth_config
+        .saml_providers
+        .iter()
+        .find(|p| p.name.eq(&provider_name))
+        .cloned()
+    else {
+        return Ok(HttpResponse::NotImplemented().json(json!({
+            "success": false,
+            "message": "Provider not configured",
+            "provider": &provider_name
+        })));
+    };
+
+    let resp = web::block(move || {
+        crate::auth::saml::controller::saml_login(
+            &db,
+            &app_config,
+            &auth_config,
+            &provider_name,
+            &form.saml_response,
+            browser_token.as_deref(),
+        )
+    })
+    .await?;
+
+    match resp {
+        Ok((access_token, refresh_token)) => Ok(HttpResponse::SeeOther()
+            .insert_header((
+                LOCATION,
+                format!("{}?access_token={}", provider.success_uri, access_token),
+            ))
+            .cookie(
+                Cookie::build(COOKIE_NAME, refresh_token)
+                    .secure(true)
+                    .http_only(true)
+                    .same_site(SameSite::Strict)
+                    .path("/")
+                    .finish(),
+            )
+            .cookie(browser_token_removal)
+            .finish()),
+        Err((status_code, message)) => Ok(HttpResponse::SeeOther()
+            .insert_header((
+                LOCATION,
+                error_redirect_uri(&provider.error_uri, status_code, &message),
+            ))
+            .cookie(browser_token_removal)
+            .finish()),
+    }
+}
+
 /// handler for POST requests to the .../logout endpount
 ///
 /// If this is successful, delete the cookie storing the refresh token
@@ -400,8 +557,9 @@ async fn register(
     db: Data<Database>,
     Json(item): Json<RegisterInput>,
     mailer: Data<Mailer>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AWError> {
-    let result = controller::register(&db, &item, &mailer);
+    let result = controller::register(&db, &item, &mailer, locale(&req).as_deref());
 
     match result {
         Ok(()) => Ok(HttpResponse::build(StatusCode::OK)
@@ -433,8 +591,9 @@ async fn activate(
     db: Data<Database>,
     Query(item): Query<ActivationInput>,
     mailer: Data<Mailer>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AWError> {
-    let result = controller::activate(&db, &item, &mailer);
+    let result = controller::activate(&db, &item, &mailer, locale(&req).as_deref());
 
     match result {
         Ok(()) => Ok(HttpResponse::build(StatusCode::OK).body("{ \"message\": \"Activated!\" }")),
@@ -467,8 +626,9 @@ async fn forgot_password(
     db: Data<Database>,
     Json(item): Json<ForgotInput>,
     mailer: Data<Mailer>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AWError> {
-    let result = controller::forgot_password(&db, &item, &mailer);
+    let result = controller::forgot_password(&db, &item, &mailer, locale(&req).as_deref());
 
     match result {
         Ok(()) => Ok(HttpResponse::build(StatusCode::OK)
@@ -507,8 +667,9 @@ async fn change_password(
     Json(item): Json<ChangeInput>,
     auth: Auth,
     mailer: Data<Mailer>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AWError> {
-    let result = controller::change_password(&db, &item, &auth, &mailer);
+    let result = controller::change_password(&db, &item, &auth, &mailer, locale(&req).as_deref());
 
     match result {
         Ok(()) => Ok(HttpResponse::build(StatusCode::OK)
@@ -559,8 +720,9 @@ async fn reset_password(
     db: Data<Database>,
     Json(item): Json<ResetInput>,
     mailer: Data<Mailer>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AWError> {
-    let result = controller::reset_password(&db, &item, &mailer);
+    let result = controller::reset_password(&db, &item, &mailer, locale(&req).as_deref());
 
     match result {
         Ok(()) => Ok(HttpResponse::build(StatusCode::OK)
@@ -595,6 +757,13 @@ pub fn endpoints(scope: actix_web::Scope) -> actix_web::Scope {
         scope = scope.service(oidc_login);
     }
 
+    #[cfg(feature = "plugin_auth-saml")]
+    {
+        scope = scope.service(saml_metadata);
+        scope = scope.service(saml_login_redirect);
+        scope = scope.service(saml_login);
+    }
+
     scope
 }
 
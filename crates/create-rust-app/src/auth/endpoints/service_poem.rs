@@ -6,7 +6,7 @@ use poem::{
         cookie::{Cookie, CookieJar, SameSite},
         Data, Json, Path, Query,
     },
-    Error, IntoResponse, Response, Result, Route,
+    Error, IntoResponse, Request, Response, Result, Route,
 };
 use serde_json::json;
 
@@ -14,7 +14,13 @@ use crate::auth::controller::{
     ActivationInput, ChangeInput, ForgotInput, LoginInput, RegisterInput, ResetInput, COOKIE_NAME,
 };
 use crate::auth::{controller, Auth, PaginationParams, ID};
-use crate::{Database, Mailer};
+use crate::{preferred_locale, Database, Mailer};
+
+/// the locale the user prefers, from the `Accept-Language` header; emails are sent in it
+fn locale(req: &Request) -> Option<String> {
+    req.header(poem::http::header::ACCEPT_LANGUAGE)
+        .and_then(preferred_locale)
+}
 
 fn error_response(status_code: u16, message: &'static str) -> Error {
     Error::from_string(
@@ -231,8 +237,9 @@ async fn register(
     db: Data<&Database>,
     Json(item): Json<RegisterInput>,
     mailer: Data<&Mailer>,
+    req: &Request,
 ) -> Result<impl IntoResponse> {
-    let result = controller::register(db.0, &item, mailer.0);
+    let result = controller::register(db.0, &item, mailer.0, locale(req).as_deref());
 
     match result {
         Ok(_) => Ok(Response::builder()
@@ -260,8 +267,9 @@ async fn activate(
     db: Data<&Database>,
     Query(item): Query<ActivationInput>,
     mailer: Data<&Mailer>,
+    req: &Request,
 ) -> Result<impl IntoResponse> {
-    let result = controller::activate(db.0, &item, mailer.0);
+    let result = controller::activate(db.0, &item, mailer.0, locale(req).as_deref());
 
     match result {
         Ok(_) => Ok(Response::builder()
@@ -288,8 +296,9 @@ async fn forgot_password(
     db: Data<&Database>,
     Json(item): Json<ForgotInput>,
     mailer: Data<&Mailer>,
+    req: &Request,
 ) -> Result<impl IntoResponse> {
-    let result = controller::forgot_password(db.0, &item, mailer.0);
+    let result = controller::forgot_password(db.0, &item, mailer.0, locale(req).as_deref());
 
     match result {
         Ok(_) => Ok(Response::builder()
@@ -324,8 +333,9 @@ async fn change_password(
     Json(item): Json<ChangeInput>,
     auth: Auth,
     mailer: Data<&Mailer>,
+    req: &Request,
 ) -> Result<impl IntoResponse> {
-    let result = controller::change_password(db.0, &item, &auth, mailer.0);
+    let result = controller::change_password(db.0, &item, &auth, mailer.0, locale(req).as_deref());
 
     match result {
         Ok(_) => Ok(Response::builder()
@@ -355,8 +365,9 @@ async fn reset_password(
     db: Data<&Database>,
     Json(item): Json<ResetInput>,
     mailer: Data<&Mailer>,
+    req: &Request,
 ) -> Result<impl IntoResponse> {
-    let result = controller::reset_password(db.0, &item, mailer.0);
+    let result = controller::reset_password(db.0, &item, mailer.0, locale(req).as_deref());
 
     match result {
         Ok(_) => Ok(Response::builder()
@@ -1,7 +1,54 @@
 // Mail
-pub mod auth_activated;
-pub mod auth_password_changed;
-pub mod auth_password_reset;
-pub mod auth_recover_existent_account;
-pub mod auth_recover_nonexistent_account;
-pub mod auth_register;
+
+/// the built-in templates of the auth emails, which the files in the mail templates folder override;
+/// see [`MailTemplates`](`crate::MailTemplates`)
+pub const TEMPLATES: [(&str, &str); 18] = [
+    (
+        "activated.subject.txt",
+        include_str!("activated.subject.txt"),
+    ),
+    ("activated.txt", include_str!("activated.txt")),
+    ("activated.html", include_str!("activated.html")),
+    (
+        "password_changed.subject.txt",
+        include_str!("password_changed.subject.txt"),
+    ),
+    ("password_changed.txt", include_str!("password_changed.txt")),
+    (
+        "password_changed.html",
+        include_str!("password_changed.html"),
+    ),
+    (
+        "password_reset.subject.txt",
+        include_str!("password_reset.subject.txt"),
+    ),
+    ("password_reset.txt", include_str!("password_reset.txt")),
+    ("password_reset.html", include_str!("password_reset.html")),
+    (
+        "recover_existent_account.subject.txt",
+        include_str!("recover_existent_account.subject.txt"),
+    ),
+    (
+        "recover_existent_account.txt",
+        include_str!("recover_existent_account.txt"),
+    ),
+    (
+        "recover_existent_account.html",
+        include_str!("recover_existent_account.html"),
+    ),
+    (
+        "recover_nonexistent_account.subject.txt",
+        include_str!("recover_nonexistent_account.subject.txt"),
+    ),
+    (
+        "recover_nonexistent_account.txt",
+        include_str!("recover_nonexistent_account.txt"),
+    ),
+    (
+        "recover_nonexistent_account.html",
+        include_str!("recover_nonexistent_account.html"),
+    ),
+    ("register.subject.txt", include_str!("register.subject.txt")),
+    ("register.txt", include_str!("register.txt")),
+    ("register.html", include_str!("register.html")),
+];
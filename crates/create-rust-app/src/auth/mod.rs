@@ -12,12 +12,17 @@ pub use extractors::*;
 
 // api endpoint definitions
 pub mod controller;
+#[cfg(feature = "database_async")]
+pub mod controller_async;
 mod endpoints;
 pub use endpoints::*;
 
 #[cfg(feature = "plugin_auth-oidc")]
 pub mod oidc;
 
+#[cfg(feature = "plugin_auth-saml")]
+pub mod saml;
+
 pub(crate) mod mail;
 mod permissions;
 mod schema;
@@ -35,9 +40,9 @@ pub use user_session::{UserSession, UserSessionChangeset};
 type ID = i32;
 
 #[tsync::tsync]
-#[cfg(not(feature = "database_sqlite"))]
+#[cfg(feature = "database_postgres")]
 type Utc = chrono::DateTime<chrono::Utc>;
-#[cfg(feature = "database_sqlite")]
+#[cfg(not(feature = "database_postgres"))]
 type Utc = chrono::NaiveDateTime;
 
 #[tsync::tsync]
@@ -69,7 +74,7 @@ pub struct UserSessionJson {
     pub id: ID,
     pub device: Option<String>,
     pub created_at: Utc,
-    #[cfg(not(feature = "database_sqlite"))]
+    #[cfg(not(any(feature = "database_sqlite", feature = "database_any")))]
     pub updated_at: Utc,
 }
 
@@ -130,9 +135,25 @@ pub struct AuthTokenResponse {
     pub access_token: String,
 }
 
+/// percent-encodes `value` for a URL's query
+#[cfg(any(feature = "plugin_auth-oidc", feature = "plugin_auth-saml"))]
+pub(crate) fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Clone)]
 pub struct AuthConfig {
     #[cfg(feature = "plugin_auth-oidc")]
     pub oidc_providers: Vec<crate::auth::oidc::OIDCProvider>,
+    #[cfg(feature = "plugin_auth-saml")]
+    pub saml_providers: Vec<crate::auth::saml::SAMLProvider>,
 }
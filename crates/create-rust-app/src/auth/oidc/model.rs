@@ -1,7 +1,7 @@
 /* This file is generated and managed by dsync */
 
 use crate::auth::oidc::schema::user_oauth2_links;
-use crate::auth::User;
+use crate::auth::{User, Utc};
 use crate::diesel::{
     insert_into, AsChangeset, Associations, ExpressionMethods, Identifiable, Insertable, QueryDsl,
     Queryable, RunQueryDsl, Selectable,
@@ -35,8 +35,8 @@ pub struct UserOauth2Link {
     pub access_token: Option<String>,
     pub subject_id: Option<String>,
     pub user_id: Option<i32>,
-    pub created_at: chrono::DateTime<chrono::Utc>,
-    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: Utc,
+    pub updated_at: Utc,
 }
 
 #[tsync::tsync]
@@ -69,17 +69,24 @@ pub struct UpdateUserOauth2Link {
     pub subject_id: Option<Option<String>>,
     #[allow(clippy::option_option)]
     pub user_id: Option<Option<i32>>,
-    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
-    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: Option<Utc>,
+    pub updated_at: Option<Utc>,
 }
 
 impl UserOauth2Link {
     pub fn create(db: &mut Connection, item: &CreateUserOauth2Link) -> QueryResult<Self> {
         use crate::auth::oidc::schema::user_oauth2_links::dsl::user_oauth2_links;
 
-        insert_into(user_oauth2_links)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(user_oauth2_links)
             .values(item)
-            .get_result::<Self>(db)
+            .get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(user_oauth2_links).values(item).execute(db)?;
+            Self::read(db, crate::database::last_insert_id(db)?)
+        }
     }
 
     pub fn read(db: &mut Connection, param_id: i32) -> QueryResult<Self> {
@@ -118,9 +125,18 @@ impl UserOauth2Link {
     ) -> QueryResult<Self> {
         use crate::auth::oidc::schema::user_oauth2_links::dsl::{id, user_oauth2_links};
 
-        diesel::update(user_oauth2_links.filter(id.eq(param_id)))
+        #[cfg(not(feature = "database_mysql"))]
+        return diesel::update(user_oauth2_links.filter(id.eq(param_id)))
             .set(item)
-            .get_result(db)
+            .get_result(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            diesel::update(user_oauth2_links.filter(id.eq(param_id)))
+                .set(item)
+                .execute(db)?;
+            Self::read(db, param_id)
+        }
     }
 
     pub fn delete(db: &mut Connection, param_id: i32) -> QueryResult<usize> {
@@ -0,0 +1,25 @@
+table! {
+  users (id) {
+      id -> Integer,
+  }
+}
+
+table! {
+  user_oauth2_links (id) {
+      id -> Integer,
+      provider -> Text,
+      csrf_token -> Text,
+      nonce -> Text,
+      pkce_secret -> Text,
+      refresh_token -> Nullable<Text>,
+      access_token -> Nullable<Text>,
+      subject_id -> Nullable<Text>,
+      user_id -> Nullable<Integer>,
+      created_at -> Datetime,
+      updated_at -> Datetime,
+    }
+}
+
+joinable!(user_oauth2_links -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(users, user_oauth2_links);
@@ -18,6 +18,49 @@ use serde::{Deserialize, Serialize};
 
 use crate::auth::ID;
 
+/// every role assigned to a user
+#[cfg(not(feature = "database_mysql"))]
+const ROLES_QUERY: &str = "SELECT role FROM user_roles WHERE user_id = $1";
+#[cfg(feature = "database_mysql")]
+const ROLES_QUERY: &str = "SELECT role FROM user_roles WHERE user_id = ?";
+
+/// every permission granted to a user, directly or through their roles
+#[cfg(not(feature = "database_mysql"))]
+const PERMISSIONS_QUERY: &str = r"
+      SELECT 
+        permission AS permission,
+        NULL AS from_role
+      FROM user_permissions
+      WHERE user_permissions.user_id = $1
+
+      UNION
+
+      SELECT
+        permission AS permission,
+        user_roles.role AS form_role
+      FROM user_roles
+      INNER JOIN role_permissions ON user_roles.role = role_permissions.role
+      WHERE user_roles.user_id = $1
+      ";
+// MySQL's placeholders are positional, so the user's id is bound twice
+#[cfg(feature = "database_mysql")]
+const PERMISSIONS_QUERY: &str = r"
+      SELECT 
+        permission AS permission,
+        NULL AS from_role
+      FROM user_permissions
+      WHERE user_permissions.user_id = ?
+
+      UNION
+
+      SELECT
+        permission AS permission,
+        user_roles.role AS form_role
+      FROM user_roles
+      INNER JOIN role_permissions ON user_roles.role = role_permissions.role
+      WHERE user_roles.user_id = ?
+      ";
+
 pub struct Role;
 
 #[derive(Debug, Serialize, Deserialize, QueryableByName, Clone)]
@@ -100,7 +143,7 @@ impl Role {
     /// # Errors
     /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
     pub fn fetch_all(db: &mut Connection, user_id: ID) -> Result<Vec<String>> {
-        let roles = sql_query("SELECT role FROM user_roles WHERE user_id = $1");
+        let roles = sql_query(ROLES_QUERY);
 
         let roles = roles
             .bind::<Integer, _>(user_id)
@@ -318,29 +361,301 @@ impl Permission {
     /// # Errors
     /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
     pub fn fetch_all(db: &mut Connection, user_id: ID) -> Result<Vec<Self>> {
-        let permissions = sql_query(
-            r"
-      SELECT 
-        permission AS permission,
-        NULL AS from_role
-      FROM user_permissions
-      WHERE user_permissions.user_id = $1
+        let permissions = sql_query(PERMISSIONS_QUERY).bind::<Integer, _>(user_id);
+        #[cfg(feature = "database_mysql")]
+        let permissions = permissions.bind::<Integer, _>(user_id);
 
-      UNION
+        let permissions = permissions.get_results::<Self>(db)?;
 
-      SELECT
-        permission AS permission,
-        user_roles.role AS form_role
-      FROM user_roles
-      INNER JOIN role_permissions ON user_roles.role = role_permissions.role
-      WHERE user_roles.user_id = $1
-      ",
-        );
+        Ok(permissions)
+    }
+}
 
-        let permissions = permissions
-            .bind::<Integer, _>(user_id)
-            .get_results::<Self>(db)?;
+#[cfg(feature = "database_async")]
+mod database_async {
+    use anyhow::Result;
+    use diesel::{sql_query, sql_types::Integer};
+    use diesel_async::RunQueryDsl;
+
+    use super::{
+        Permission, Role, RolePermission, RolePermissionChangeset, RoleQueryRow, UserPermission,
+        UserPermissionChangeset, UserRole, UserRoleChangeset, PERMISSIONS_QUERY, ROLES_QUERY,
+    };
+    use crate::auth::ID;
+    use crate::database::AsyncConnection;
+
+    /// the functions of [`Role`], on an [`AsyncConnection`]
+    impl Role {
+        /// like [`Role::assign`]
+        ///
+        /// # Errors
+        /// * infallible
+        pub async fn assign_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            role: &str,
+        ) -> Result<bool> {
+            let assigned = UserRole::create_async(
+                db,
+                &UserRoleChangeset {
+                    user_id,
+                    role: role.to_string(),
+                },
+            )
+            .await;
+
+            Ok(assigned.is_ok())
+        }
+
+        /// like [`Role::assign_many`]
+        ///
+        /// # Errors
+        /// * infallible
+        pub async fn assign_many_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            roles: Vec<String>,
+        ) -> Result<bool> {
+            let assigned = UserRole::create_many_async(
+                db,
+                roles
+                    .into_iter()
+                    .map(|role| UserRoleChangeset { user_id, role })
+                    .collect(),
+            )
+            .await;
+
+            Ok(assigned.is_ok())
+        }
+
+        /// like [`Role::unassign`]
+        ///
+        /// # Errors
+        /// * infallible
+        pub async fn unassign_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            role: &str,
+        ) -> Result<bool> {
+            let unassigned = UserRole::delete_async(db, user_id, role.to_string()).await;
+
+            Ok(unassigned.is_ok())
+        }
+
+        /// like [`Role::unassign_many`]
+        ///
+        /// # Errors
+        /// * infallible
+        pub async fn unassign_many_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            roles: Vec<String>,
+        ) -> Result<bool> {
+            let unassigned = UserRole::delete_many_async(db, user_id, roles).await;
+
+            Ok(unassigned.is_ok())
+        }
+
+        /// like [`Role::fetch_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn fetch_all_async(db: &mut AsyncConnection, user_id: ID) -> Result<Vec<String>> {
+            let roles = sql_query(ROLES_QUERY)
+                .bind::<Integer, _>(user_id)
+                .get_results::<RoleQueryRow>(db)
+                .await?;
+
+            Ok(roles.into_iter().map(|r| r.role).collect())
+        }
+    }
 
-        Ok(permissions)
+    /// the functions of [`Permission`], on an [`AsyncConnection`]
+    impl Permission {
+        /// like [`Permission::grant_to_user`]
+        ///
+        /// # Errors
+        /// * if `UserPermission::create_async` fails, returns the error
+        pub async fn grant_to_user_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            permission: &str,
+        ) -> Result<()> {
+            UserPermission::create_async(
+                db,
+                &UserPermissionChangeset {
+                    permission: permission.to_string(),
+                    user_id,
+                },
+            )
+            .await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::grant_to_role`]
+        ///
+        /// # Errors
+        /// * if `RolePermission::create_async` fails, returns the error
+        pub async fn grant_to_role_async(
+            db: &mut AsyncConnection,
+            role: &str,
+            permission: &str,
+        ) -> Result<()> {
+            RolePermission::create_async(
+                db,
+                &RolePermissionChangeset {
+                    permission: permission.to_string(),
+                    role: role.to_string(),
+                },
+            )
+            .await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::grant_many_to_role`]
+        ///
+        /// # Errors
+        /// * if `RolePermission::create_many_async` fails, returns the error
+        pub async fn grant_many_to_role_async(
+            db: &mut AsyncConnection,
+            role: String,
+            permissions: Vec<String>,
+        ) -> Result<()> {
+            RolePermission::create_many_async(
+                db,
+                permissions
+                    .into_iter()
+                    .map(|permission| RolePermissionChangeset {
+                        permission,
+                        role: role.clone(),
+                    })
+                    .collect(),
+            )
+            .await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::grant_many_to_user`]
+        ///
+        /// # Errors
+        /// * if `UserPermission::create_many_async` fails, returns the error
+        pub async fn grant_many_to_user_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            permissions: Vec<String>,
+        ) -> Result<()> {
+            UserPermission::create_many_async(
+                db,
+                permissions
+                    .into_iter()
+                    .map(|permission| UserPermissionChangeset {
+                        user_id,
+                        permission,
+                    })
+                    .collect(),
+            )
+            .await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::revoke_from_user`]
+        ///
+        /// # Errors
+        /// * if `UserPermission::delete_async` fails, returns the error
+        pub async fn revoke_from_user_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            permission: &str,
+        ) -> Result<()> {
+            UserPermission::delete_async(db, user_id, permission.to_string()).await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::revoke_from_role`]
+        ///
+        /// # Errors
+        /// * if `RolePermission::delete_async` fails, returns the error
+        pub async fn revoke_from_role_async(
+            db: &mut AsyncConnection,
+            role: String,
+            permission: String,
+        ) -> Result<()> {
+            RolePermission::delete_async(db, role, permission).await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::revoke_many_from_user`]
+        ///
+        /// # Errors
+        /// * if `UserPermission::delete_many_async` fails, returns the error
+        pub async fn revoke_many_from_user_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+            permissions: Vec<String>,
+        ) -> Result<()> {
+            UserPermission::delete_many_async(db, user_id, permissions).await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::revoke_many_from_role`]
+        ///
+        /// # Errors
+        /// * if `RolePermission::delete_many_async` fails, returns the error
+        pub async fn revoke_many_from_role_async(
+            db: &mut AsyncConnection,
+            role: String,
+            permissions: Vec<String>,
+        ) -> Result<()> {
+            RolePermission::delete_many_async(db, role, permissions).await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::revoke_all_from_role`]
+        ///
+        /// # Errors
+        /// * if `RolePermission::delete_all_async` fails, returns the error
+        pub async fn revoke_all_from_role_async(
+            db: &mut AsyncConnection,
+            role: &str,
+        ) -> Result<()> {
+            RolePermission::delete_all_async(db, role).await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::revoke_all_from_user`]
+        ///
+        /// # Errors
+        /// * if `UserPermission::delete_all_async` fails, returns the error
+        pub async fn revoke_all_from_user_async(
+            db: &mut AsyncConnection,
+            user_id: ID,
+        ) -> Result<()> {
+            UserPermission::delete_all_async(db, user_id).await?;
+
+            Ok(())
+        }
+
+        /// like [`Permission::fetch_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn fetch_all_async(db: &mut AsyncConnection, user_id: ID) -> Result<Vec<Self>> {
+            let permissions = sql_query(PERMISSIONS_QUERY).bind::<Integer, _>(user_id);
+            #[cfg(feature = "database_mysql")]
+            let permissions = permissions.bind::<Integer, _>(user_id);
+
+            let permissions = permissions.get_results::<Self>(db).await?;
+
+            Ok(permissions)
+        }
     }
 }
@@ -44,12 +44,23 @@ impl RolePermission {
     pub fn create(db: &mut Connection, item: &RolePermissionChangeset) -> QueryResult<Self> {
         use crate::auth::schema::role_permissions::dsl::role_permissions;
 
-        insert_into(role_permissions)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(role_permissions)
             .values(item)
-            .get_result::<Self>(db)
+            .get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(role_permissions).values(item).execute(db)?;
+            Self::read(db, item.role.clone(), item.permission.clone())
+        }
     }
 
-    #[cfg(feature = "database_sqlite")]
+    #[cfg(any(
+        feature = "database_sqlite",
+        feature = "database_mysql",
+        feature = "database_any"
+    ))]
     /// Create an entry in [`db`](`Connection`)'s role_permissions table for each [element](`RolePermissionChangeset`) in `items`
     ///
     /// # Errors
@@ -60,10 +71,25 @@ impl RolePermission {
     ) -> QueryResult<usize> {
         use crate::auth::schema::role_permissions::dsl::*;
 
-        insert_into(role_permissions).values(items).execute(db)
+        #[cfg(not(feature = "database_any"))]
+        return insert_into(role_permissions).values(items).execute(db);
+
+        // `database_any` can't insert several rows in one statement on SQLite
+        #[cfg(feature = "database_any")]
+        {
+            let mut created = 0;
+            for item in items {
+                created += insert_into(role_permissions).values(item).execute(db)?;
+            }
+            Ok(created)
+        }
     }
 
-    #[cfg(not(feature = "database_sqlite"))]
+    #[cfg(not(any(
+        feature = "database_sqlite",
+        feature = "database_mysql",
+        feature = "database_any"
+    )))]
     /// Create an entry in [`db`](`Connection`)'s `role_permissions` table for each [element](`RolePermissionChangeset`) in `items`
     ///
     /// # Errors
@@ -159,3 +185,166 @@ impl RolePermission {
         diesel::delete(role_permissions.filter(role.eq(item_role))).execute(db)
     }
 }
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::{insert_into, BoolExpressionMethods, ExpressionMethods, QueryDsl, QueryResult};
+    use diesel_async::RunQueryDsl;
+
+    use super::{RolePermission, RolePermissionChangeset};
+    use crate::database::AsyncConnection;
+
+    /// the queries of [`RolePermission`], on an [`AsyncConnection`]
+    impl RolePermission {
+        /// like [`RolePermission::create`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn create_async(
+            db: &mut AsyncConnection,
+            item: &RolePermissionChangeset,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::role_permissions::dsl::role_permissions;
+
+            #[cfg(not(feature = "database_mysql"))]
+            return insert_into(role_permissions)
+                .values(item)
+                .get_result::<Self>(db)
+                .await;
+
+            #[cfg(feature = "database_mysql")]
+            {
+                insert_into(role_permissions)
+                    .values(item)
+                    .execute(db)
+                    .await?;
+                Self::read_async(db, item.role.clone(), item.permission.clone()).await
+            }
+        }
+
+        /// like [`RolePermission::create_many`], but returns how many entries were created, on every backend
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        #[cfg(not(feature = "database_sqlite"))]
+        pub async fn create_many_async(
+            db: &mut AsyncConnection,
+            items: Vec<RolePermissionChangeset>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::role_permissions::dsl::role_permissions;
+
+            insert_into(role_permissions)
+                .values(items)
+                .execute(db)
+                .await
+        }
+
+        /// like [`RolePermission::create_many`], but returns how many entries were created, on every backend
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        #[cfg(feature = "database_sqlite")]
+        pub async fn create_many_async(
+            db: &mut AsyncConnection,
+            items: Vec<RolePermissionChangeset>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::role_permissions::dsl::role_permissions;
+
+            // the async sqlite connection can't insert several rows in one statement
+            let mut created = 0;
+            for item in items {
+                created += insert_into(role_permissions)
+                    .values(item)
+                    .execute(db)
+                    .await?;
+            }
+            Ok(created)
+        }
+
+        /// like [`RolePermission::read`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_async(
+            db: &mut AsyncConnection,
+            item_role: String,
+            item_permission: String,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::role_permissions::dsl::{permission, role, role_permissions};
+
+            role_permissions
+                .filter(role.eq(item_role).and(permission.eq(item_permission)))
+                .first::<Self>(db)
+                .await
+        }
+
+        /// like [`RolePermission::read_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_all_async(
+            db: &mut AsyncConnection,
+            item_role: String,
+        ) -> QueryResult<Vec<Self>> {
+            use crate::auth::schema::role_permissions::dsl::{created_at, role, role_permissions};
+
+            role_permissions
+                .filter(role.eq(item_role))
+                .order(created_at)
+                .load::<Self>(db)
+                .await
+        }
+
+        /// like [`RolePermission::delete`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_async(
+            db: &mut AsyncConnection,
+            item_role: String,
+            item_permission: String,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::role_permissions::dsl::{permission, role, role_permissions};
+
+            diesel::delete(
+                role_permissions.filter(role.eq(item_role).and(permission.eq(item_permission))),
+            )
+            .execute(db)
+            .await
+        }
+
+        /// like [`RolePermission::delete_many`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_many_async(
+            db: &mut AsyncConnection,
+            item_role: String,
+            item_permissions: Vec<String>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::role_permissions::dsl::{permission, role, role_permissions};
+
+            diesel::delete(
+                role_permissions
+                    .filter(role.eq(item_role).and(permission.eq_any(item_permissions))),
+            )
+            .execute(db)
+            .await
+        }
+
+        /// like [`RolePermission::delete_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_all_async(
+            db: &mut AsyncConnection,
+            item_role: &str,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::role_permissions::dsl::{role, role_permissions};
+
+            diesel::delete(role_permissions.filter(role.eq(item_role)))
+                .execute(db)
+                .await
+        }
+    }
+}
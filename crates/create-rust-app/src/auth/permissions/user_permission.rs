@@ -50,12 +50,23 @@ impl UserPermission {
     pub fn create(db: &mut Connection, item: &UserPermissionChangeset) -> QueryResult<Self> {
         use crate::auth::schema::user_permissions::dsl::user_permissions;
 
-        insert_into(user_permissions)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(user_permissions)
             .values(item)
-            .get_result::<Self>(db)
+            .get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(user_permissions).values(item).execute(db)?;
+            Self::read(db, item.user_id, item.permission.clone())
+        }
     }
 
-    #[cfg(feature = "database_sqlite")]
+    #[cfg(any(
+        feature = "database_sqlite",
+        feature = "database_mysql",
+        feature = "database_any"
+    ))]
     /// Create an entry in [`db`](`Connection`)'s user_permissions table for each [element](`UserPermissionChangeset`) in `items`
     ///
     /// # Errors
@@ -66,10 +77,25 @@ impl UserPermission {
     ) -> QueryResult<usize> {
         use crate::auth::schema::user_permissions::dsl::*;
 
-        insert_into(user_permissions).values(items).execute(db)
+        #[cfg(not(feature = "database_any"))]
+        return insert_into(user_permissions).values(items).execute(db);
+
+        // `database_any` can't insert several rows in one statement on SQLite
+        #[cfg(feature = "database_any")]
+        {
+            let mut created = 0;
+            for item in items {
+                created += insert_into(user_permissions).values(item).execute(db)?;
+            }
+            Ok(created)
+        }
     }
 
-    #[cfg(not(feature = "database_sqlite"))]
+    #[cfg(not(any(
+        feature = "database_sqlite",
+        feature = "database_mysql",
+        feature = "database_any"
+    )))]
     /// Create an entry in [`db`](`Connection`)'s `user_permissions` table for each [element](`UserPermissionChangeset`) in `items`
     ///
     /// # Errors
@@ -165,3 +191,179 @@ impl UserPermission {
         diesel::delete(user_permissions.filter(user_id.eq(item_user_id))).execute(db)
     }
 }
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::{insert_into, BoolExpressionMethods, ExpressionMethods, QueryDsl, QueryResult};
+    use diesel_async::RunQueryDsl;
+
+    use super::{UserPermission, UserPermissionChangeset};
+    use crate::auth::ID;
+    use crate::database::AsyncConnection;
+
+    /// the queries of [`UserPermission`], on an [`AsyncConnection`]
+    impl UserPermission {
+        /// like [`UserPermission::create`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn create_async(
+            db: &mut AsyncConnection,
+            item: &UserPermissionChangeset,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::user_permissions::dsl::user_permissions;
+
+            #[cfg(not(feature = "database_mysql"))]
+            return insert_into(user_permissions)
+                .values(item)
+                .get_result::<Self>(db)
+                .await;
+
+            #[cfg(feature = "database_mysql")]
+            {
+                insert_into(user_permissions)
+                    .values(item)
+                    .execute(db)
+                    .await?;
+                Self::read_async(db, item.user_id, item.permission.clone()).await
+            }
+        }
+
+        /// like [`UserPermission::create_many`], but returns how many entries were created, on every backend
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        #[cfg(not(feature = "database_sqlite"))]
+        pub async fn create_many_async(
+            db: &mut AsyncConnection,
+            items: Vec<UserPermissionChangeset>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_permissions::dsl::user_permissions;
+
+            insert_into(user_permissions)
+                .values(items)
+                .execute(db)
+                .await
+        }
+
+        /// like [`UserPermission::create_many`], but returns how many entries were created, on every backend
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        #[cfg(feature = "database_sqlite")]
+        pub async fn create_many_async(
+            db: &mut AsyncConnection,
+            items: Vec<UserPermissionChangeset>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_permissions::dsl::user_permissions;
+
+            // the async sqlite connection can't insert several rows in one statement
+            let mut created = 0;
+            for item in items {
+                created += insert_into(user_permissions)
+                    .values(item)
+                    .execute(db)
+                    .await?;
+            }
+            Ok(created)
+        }
+
+        /// like [`UserPermission::read`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+            item_permission: String,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::user_permissions::dsl::{
+                permission, user_id, user_permissions,
+            };
+
+            user_permissions
+                .filter(user_id.eq(item_user_id).and(permission.eq(item_permission)))
+                .first::<Self>(db)
+                .await
+        }
+
+        /// like [`UserPermission::read_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_all_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+        ) -> QueryResult<Vec<Self>> {
+            use crate::auth::schema::user_permissions::dsl::{
+                created_at, user_id, user_permissions,
+            };
+
+            user_permissions
+                .filter(user_id.eq(item_user_id))
+                .order(created_at)
+                .load::<Self>(db)
+                .await
+        }
+
+        /// like [`UserPermission::delete`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+            item_permission: String,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_permissions::dsl::{
+                permission, user_id, user_permissions,
+            };
+
+            diesel::delete(
+                user_permissions
+                    .filter(user_id.eq(item_user_id).and(permission.eq(item_permission))),
+            )
+            .execute(db)
+            .await
+        }
+
+        /// like [`UserPermission::delete_many`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_many_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+            item_permissions: Vec<String>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_permissions::dsl::{
+                permission, user_id, user_permissions,
+            };
+
+            diesel::delete(
+                user_permissions.filter(
+                    user_id
+                        .eq(item_user_id)
+                        .and(permission.eq_any(item_permissions)),
+                ),
+            )
+            .execute(db)
+            .await
+        }
+
+        /// like [`UserPermission::delete_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_all_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_permissions::dsl::{user_id, user_permissions};
+
+            diesel::delete(user_permissions.filter(user_id.eq(item_user_id)))
+                .execute(db)
+                .await
+        }
+    }
+}
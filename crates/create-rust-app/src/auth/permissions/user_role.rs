@@ -49,10 +49,21 @@ impl UserRole {
     pub fn create(db: &mut Connection, item: &UserRoleChangeset) -> QueryResult<Self> {
         use crate::auth::schema::user_roles::dsl::user_roles;
 
-        insert_into(user_roles).values(item).get_result::<Self>(db)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(user_roles).values(item).get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(user_roles).values(item).execute(db)?;
+            Self::read(db, item.user_id, item.role.clone())
+        }
     }
 
-    #[cfg(feature = "database_sqlite")]
+    #[cfg(any(
+        feature = "database_sqlite",
+        feature = "database_mysql",
+        feature = "database_any"
+    ))]
     /// Create an entry in [`db`](`Connection`)'s user_roles table for each [element](`UserRoleChangeset`) in `items`
     ///
     /// # Errors
@@ -60,10 +71,25 @@ impl UserRole {
     pub fn create_many(db: &mut Connection, items: Vec<UserRoleChangeset>) -> QueryResult<usize> {
         use crate::auth::schema::user_roles::dsl::*;
 
-        insert_into(user_roles).values(items).execute(db)
+        #[cfg(not(feature = "database_any"))]
+        return insert_into(user_roles).values(items).execute(db);
+
+        // `database_any` can't insert several rows in one statement on SQLite
+        #[cfg(feature = "database_any")]
+        {
+            let mut created = 0;
+            for item in items {
+                created += insert_into(user_roles).values(item).execute(db)?;
+            }
+            Ok(created)
+        }
     }
 
-    #[cfg(not(feature = "database_sqlite"))]
+    #[cfg(not(any(
+        feature = "database_sqlite",
+        feature = "database_mysql",
+        feature = "database_any"
+    )))]
     /// Create an entry in [`db`](`Connection`)'s `user_roles` table for each [element](`UserRoleChangeset`) in `items`
     ///
     /// # Errors
@@ -134,3 +160,138 @@ impl UserRole {
             .execute(db)
     }
 }
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::{insert_into, BoolExpressionMethods, ExpressionMethods, QueryDsl, QueryResult};
+    use diesel_async::RunQueryDsl;
+
+    use super::{UserRole, UserRoleChangeset};
+    use crate::auth::ID;
+    use crate::database::AsyncConnection;
+
+    /// the queries of [`UserRole`], on an [`AsyncConnection`]
+    impl UserRole {
+        /// like [`UserRole::create`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn create_async(
+            db: &mut AsyncConnection,
+            item: &UserRoleChangeset,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::user_roles::dsl::user_roles;
+
+            #[cfg(not(feature = "database_mysql"))]
+            return insert_into(user_roles)
+                .values(item)
+                .get_result::<Self>(db)
+                .await;
+
+            #[cfg(feature = "database_mysql")]
+            {
+                insert_into(user_roles).values(item).execute(db).await?;
+                Self::read_async(db, item.user_id, item.role.clone()).await
+            }
+        }
+
+        /// like [`UserRole::create_many`], but returns how many entries were created, on every backend
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        #[cfg(not(feature = "database_sqlite"))]
+        pub async fn create_many_async(
+            db: &mut AsyncConnection,
+            items: Vec<UserRoleChangeset>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_roles::dsl::user_roles;
+
+            insert_into(user_roles).values(items).execute(db).await
+        }
+
+        /// like [`UserRole::create_many`], but returns how many entries were created, on every backend
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        #[cfg(feature = "database_sqlite")]
+        pub async fn create_many_async(
+            db: &mut AsyncConnection,
+            items: Vec<UserRoleChangeset>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_roles::dsl::user_roles;
+
+            // the async sqlite connection can't insert several rows in one statement
+            let mut created = 0;
+            for item in items {
+                created += insert_into(user_roles).values(item).execute(db).await?;
+            }
+            Ok(created)
+        }
+
+        /// like [`UserRole::read`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+            item_role: String,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::user_roles::dsl::{role, user_id, user_roles};
+
+            user_roles
+                .filter(user_id.eq(item_user_id).and(role.eq(item_role)))
+                .first::<Self>(db)
+                .await
+        }
+
+        /// like [`UserRole::read_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_all_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+        ) -> QueryResult<Vec<Self>> {
+            use crate::auth::schema::user_roles::dsl::{created_at, user_id, user_roles};
+
+            user_roles
+                .filter(user_id.eq(item_user_id))
+                .order(created_at)
+                .load::<Self>(db)
+                .await
+        }
+
+        /// like [`UserRole::delete`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+            item_role: String,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_roles::dsl::{role, user_id, user_roles};
+
+            diesel::delete(user_roles.filter(user_id.eq(item_user_id).and(role.eq(item_role))))
+                .execute(db)
+                .await
+        }
+
+        /// like [`UserRole::delete_many`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_many_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+            item_roles: Vec<String>,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_roles::dsl::{role, user_id, user_roles};
+
+            diesel::delete(user_roles.filter(user_id.eq(item_user_id).and(role.eq_any(item_roles))))
+                .execute(db)
+                .await
+        }
+    }
+}
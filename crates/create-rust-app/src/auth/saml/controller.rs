@@ -0,0 +1,300 @@
+use crate::{
+    auth::{
+        controller::{create_user_session, generate_salt, ARGON_CONFIG},
+        AuthConfig, User, UserChangeset, Utc,
+    },
+    AppConfig, Database,
+};
+use anyhow::Result;
+use diesel::{
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection as _, OptionalExtension,
+};
+use rand::{distributions::Alphanumeric, Rng};
+
+use super::{
+    model::{ConsumedSamlAssertion, CreateUserSamlLink, SamlRequest, UserSamlLink},
+    response::{parse_response, ExpectedRecipient},
+    SAMLProvider, SamlAssertion,
+};
+
+const EMAIL_NAME_ID_FORMAT: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+
+/// how long the user has to log in at the IdP before the `AuthnRequest` is forgotten
+pub const AUTHN_REQUEST_LIFETIME: chrono::Duration = chrono::Duration::minutes(15);
+
+/// the cookie binding an `AuthnRequest` to the browser which started the login
+pub const BROWSER_TOKEN_COOKIE_NAME: &str = "saml_request";
+
+type LoginUrl = String;
+type BrowserToken = String;
+type RefreshToken = String;
+type AccessToken = String;
+type StatusCode = u16;
+type Message = String;
+
+fn find_provider<'a>(auth_config: &'a AuthConfig, provider_name: &str) -> Option<&'a SAMLProvider> {
+    auth_config
+        .saml_providers
+        .iter()
+        .find(|provider_config| provider_config.name.eq(provider_name))
+}
+
+/// returns the SP metadata XML for the given provider, or `None` if it isn't configured
+#[must_use]
+pub fn saml_metadata(
+    app_config: &AppConfig,
+    auth_config: &AuthConfig,
+    provider_name: &str,
+) -> Option<String> {
+    find_provider(auth_config, provider_name)
+        .map(|provider| provider.sp_metadata(&app_config.app_url))
+}
+
+/// the timestamp in the type of the auth tables' columns
+fn db_time(time: chrono::DateTime<chrono::Utc>) -> Utc {
+    #[cfg(feature = "database_postgres")]
+    return time;
+    #[cfg(not(feature = "database_postgres"))]
+    return time.naive_utc();
+}
+
+/// returns the IdP URL to redirect the user to, or `None` if the provider isn't configured
+/// or only supports IdP-initiated login
+///
+/// the ID of the `AuthnRequest` is stored, the response has to be in response to it; so is the
+/// returned browser token, which has to be set in the [`BROWSER_TOKEN_COOKIE_NAME`] cookie and
+/// given back to [`saml_login`]
+///
+/// # Errors
+/// * could not encode the `AuthnRequest`, or store its ID
+pub fn saml_login_url(
+    db: &Database,
+    app_config: &AppConfig,
+    auth_config: &AuthConfig,
+    provider_name: &str,
+) -> Result<Option<(LoginUrl, BrowserToken)>> {
+    let Some(provider) = find_provider(auth_config, provider_name) else {
+        return Ok(None);
+    };
+
+    let request_id = super::new_request_id();
+    let Some(url) = provider.authn_request_url(&app_config.app_url, &request_id, None)? else {
+        return Ok(None);
+    };
+
+    let browser_token = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect::<String>();
+
+    let mut db = db.get_connection()?;
+    let now = chrono::Utc::now();
+    SamlRequest::delete_expired(&mut db, db_time(now))?;
+    SamlRequest::create(
+        &mut db,
+        &SamlRequest {
+            provider: provider.name.clone(),
+            request_id,
+            browser_token: browser_token.clone(),
+            expires_at: db_time(now + AUTHN_REQUEST_LIFETIME),
+        },
+    )?;
+
+    Ok(Some((url, browser_token)))
+}
+
+/// handles an HTTP-POST binding `SAMLResponse` posted to the assertion consumer service
+///
+/// `browser_token` is the value of the [`BROWSER_TOKEN_COOKIE_NAME`] cookie, if the browser has it
+///
+/// # Errors
+/// * 501 - This SAML provider is not supported
+/// * 401 - Invalid SAML response (also when it's unsolicited or replayed)
+/// * 400 - No email returned / Email already registered
+/// * 500 - Internal server error (could be a lot of things)
+pub fn saml_login(
+    db: &Database,
+    app_config: &AppConfig,
+    auth_config: &AuthConfig,
+    provider_name: &str,
+    saml_response: &str,
+    browser_token: Option<&str>,
+) -> Result<(AccessToken, RefreshToken), (StatusCode, Message)> {
+    let Ok(mut db) = db.get_connection() else {
+        return Err((500, "Internal server error".into()));
+    };
+
+    // 1. Make sure this provider is setup
+    let Some(provider) = find_provider(auth_config, provider_name) else {
+        return Err((501, "This SAML provider is not supported".into()));
+    };
+
+    // 2. verify the response and extract the assertion
+    let assertion = match parse_response(
+        saml_response,
+        &provider.idp,
+        &ExpectedRecipient {
+            audience: &provider.entity_id(&app_config.app_url),
+            acs_url: &provider.acs_url(&app_config.app_url),
+        },
+        chrono::Utc::now(),
+    ) {
+        Ok(assertion) => assertion,
+        Err(error) => {
+            log::warn!("Rejected SAML response from '{provider_name}': {error}");
+            return Err((401, "Invalid SAML response".into()));
+        }
+    };
+
+    // 3. make sure it answers one of our requests and wasn't used before,
+    //    then find or create the user linked to the NameID
+    let user = db
+        .transaction::<_, SamlLoginError, _>(|db| {
+            consume_assertion(db, provider, &assertion, browser_token)?;
+            find_or_create_user(db, provider, &assertion)
+        })
+        .map_err(|error| match error {
+            SamlLoginError::Invalid(reason) => {
+                log::warn!("Rejected SAML response from '{provider_name}': {reason}");
+                (401, "Invalid SAML response".to_string())
+            }
+            SamlLoginError::Rejected(message) => (400, message.to_string()),
+            SamlLoginError::Internal(error) => {
+                log::error!("Could not complete SAML login: {error}");
+                (500, "Internal server error".to_string())
+            }
+        })?;
+
+    create_user_session(
+        &mut db,
+        Some(format!("SAML - {}", &provider.name)),
+        None,
+        user.id,
+    )
+    .map_err(|error| (error.0, error.1.to_string()))
+}
+
+enum SamlLoginError {
+    Invalid(&'static str),
+    Rejected(&'static str),
+    Internal(anyhow::Error),
+}
+
+impl From<diesel::result::Error> for SamlLoginError {
+    fn from(error: diesel::result::Error) -> Self {
+        Self::Internal(error.into())
+    }
+}
+
+/// checks the assertion answers an `AuthnRequest` this browser sent (unless IdP-initiated login is
+/// allowed) and remembers its ID until it expires, so that it can't be used again
+fn consume_assertion(
+    db: &mut crate::Connection,
+    provider: &SAMLProvider,
+    assertion: &SamlAssertion,
+    browser_token: Option<&str>,
+) -> Result<(), SamlLoginError> {
+    let now = db_time(chrono::Utc::now());
+    SamlRequest::delete_expired(db, now)?;
+    ConsumedSamlAssertion::delete_expired(db, now)?;
+
+    if let Some(request_id) = &assertion.in_response_to {
+        let Some(browser_token) = browser_token else {
+            return Err(SamlLoginError::Invalid(
+                "the login was not started in this browser",
+            ));
+        };
+        if !SamlRequest::consume(
+            db,
+            provider.name.clone(),
+            request_id.clone(),
+            browser_token.to_string(),
+        )? {
+            return Err(SamlLoginError::Invalid(
+                "InResponseTo does not match an AuthnRequest pending in this browser",
+            ));
+        }
+    } else if !provider.allow_idp_initiated {
+        return Err(SamlLoginError::Invalid("unsolicited response"));
+    }
+
+    match ConsumedSamlAssertion::create(
+        db,
+        &ConsumedSamlAssertion {
+            provider: provider.name.clone(),
+            assertion_id: assertion.id.clone(),
+            expires_at: db_time(assertion.expires_at),
+        },
+    ) {
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(SamlLoginError::Invalid("assertion was already used"))
+        }
+        result => result.map(|_| ()).map_err(Into::into),
+    }
+}
+
+/// returns the email address carried by the assertion, if any
+fn assertion_email(provider: &SAMLProvider, assertion: &SamlAssertion) -> Option<String> {
+    assertion
+        .attribute(&provider.email_attribute)
+        .map(str::to_string)
+        .or_else(|| {
+            (assertion.name_id_format.as_deref() == Some(EMAIL_NAME_ID_FORMAT))
+                .then(|| assertion.name_id.clone())
+        })
+}
+
+fn find_or_create_user(
+    db: &mut crate::Connection,
+    provider: &SAMLProvider,
+    assertion: &SamlAssertion,
+) -> Result<User, SamlLoginError> {
+    // the NameID is already linked to a user
+    if let Some(link) =
+        UserSamlLink::read_by_name_id(db, provider.name.clone(), assertion.name_id.clone())
+            .optional()?
+    {
+        return Ok(User::read(db, link.user_id)?);
+    }
+
+    // otherwise link it to a new user (unless the email is already claimed by a local account)
+    let Some(email) = assertion_email(provider, assertion) else {
+        return Err(SamlLoginError::Rejected("No email returned"));
+    };
+
+    if User::find_by_email(db, email.clone()).optional()?.is_some() {
+        return Err(SamlLoginError::Rejected("Email already registered"));
+    }
+
+    // create a random password
+    let salt = generate_salt();
+    let random_password = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect::<String>();
+    let hash = argon2::hash_encoded(random_password.as_bytes(), &salt, &ARGON_CONFIG)
+        .map_err(|error| SamlLoginError::Internal(error.into()))?;
+
+    let user = User::create(
+        db,
+        &UserChangeset {
+            email,
+            activated: false, // do not activate the account because it should not be allowed to login locally
+            hash_password: hash,
+        },
+    )?;
+
+    UserSamlLink::create(
+        db,
+        &CreateUserSamlLink {
+            provider: provider.name.clone(),
+            name_id: assertion.name_id.clone(),
+            user_id: user.id,
+        },
+    )?;
+
+    Ok(user)
+}
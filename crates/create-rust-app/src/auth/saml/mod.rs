@@ -0,0 +1,218 @@
+pub mod controller;
+mod model;
+mod response;
+mod schema;
+mod xmldsig;
+
+#[cfg(test)]
+mod test_idp;
+
+pub use response::SamlAssertion;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use super::url_encode;
+
+const METADATA_NS: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+const HTTP_REDIRECT_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
+const HTTP_POST_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+
+#[derive(Clone)]
+pub struct SAMLProvider {
+    pub name: String,
+    // parsed from the metadata XML published by the identity provider
+    pub idp: IdpMetadata,
+    // name of the assertion attribute holding the user's email;
+    // falls back to the NameID when it's an email address
+    pub email_attribute: String,
+    // URI to redirect to upon successful login
+    pub success_uri: String,
+    // URI to redirect to when login fails
+    pub error_uri: String,
+    // accept responses which don't answer one of our `AuthnRequest`s (IdP-initiated login);
+    // off by default, their assertions are still only accepted once
+    pub allow_idp_initiated: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct IdpMetadata {
+    pub entity_id: String,
+    // where to send AuthnRequests (HTTP-Redirect binding); `None` if only IdP-initiated login is possible,
+    // see `SAMLProvider::allow_idp_initiated`
+    pub sso_url: Option<String>,
+    // DER-encoded X.509 certificates the IdP signs responses with
+    pub signing_certificates: Vec<Vec<u8>>,
+}
+
+impl IdpMetadata {
+    /// parses an `<EntityDescriptor>` metadata document published by an identity provider
+    ///
+    /// # Errors
+    /// * the XML is malformed, or doesn't describe an IdP with at least one signing certificate
+    pub fn from_xml(xml: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(xml)?;
+        let entity = document
+            .descendants()
+            .find(|n| xmldsig::is_element(*n, METADATA_NS, "EntityDescriptor"))
+            .ok_or_else(|| anyhow!("missing EntityDescriptor"))?;
+        let idp = xmldsig::child(entity, METADATA_NS, "IDPSSODescriptor")
+            .ok_or_else(|| anyhow!("missing IDPSSODescriptor"))?;
+
+        let sso_url = idp
+            .children()
+            .filter(|n| xmldsig::is_element(*n, METADATA_NS, "SingleSignOnService"))
+            .find(|n| n.attribute("Binding") == Some(HTTP_REDIRECT_BINDING))
+            .and_then(|n| n.attribute("Location"))
+            .map(str::to_string);
+
+        let signing_certificates = idp
+            .children()
+            .filter(|n| xmldsig::is_element(*n, METADATA_NS, "KeyDescriptor"))
+            .filter(|n| n.attribute("use").is_none_or(|usage| usage == "signing"))
+            .flat_map(|n| n.descendants())
+            .filter(|n| xmldsig::is_element(*n, xmldsig::DSIG_NS, "X509Certificate"))
+            .map(|n| xmldsig::decode_base64_text(Some(n)))
+            .collect::<Result<Vec<_>>>()?;
+
+        if signing_certificates.is_empty() {
+            return Err(anyhow!(
+                "IdP metadata does not contain a signing certificate"
+            ));
+        }
+
+        Ok(Self {
+            entity_id: entity
+                .attribute("entityID")
+                .ok_or_else(|| anyhow!("missing entityID"))?
+                .to_string(),
+            sso_url,
+            signing_certificates,
+        })
+    }
+}
+
+impl SAMLProvider {
+    /// creates a provider from the identity provider's metadata XML
+    ///
+    /// # Errors
+    /// * see [`IdpMetadata::from_xml`]
+    pub fn new(
+        name: impl Into<String>,
+        idp_metadata_xml: &str,
+        success_uri: impl Into<String>,
+        error_uri: impl Into<String>,
+    ) -> Result<Self> {
+        Ok(Self {
+            name: name.into(),
+            idp: IdpMetadata::from_xml(idp_metadata_xml)?,
+            email_attribute: "email".to_string(),
+            success_uri: success_uri.into(),
+            error_uri: error_uri.into(),
+            allow_idp_initiated: false,
+        })
+    }
+
+    /// our entity ID; this is also where the SP metadata is served
+    #[must_use]
+    pub fn entity_id(&self, api_url: impl AsRef<str>) -> String {
+        format!(
+            "{api_url}/api/auth/saml/{provider_name}/metadata",
+            api_url = api_url.as_ref(),
+            provider_name = self.name
+        )
+    }
+
+    /// the assertion consumer service URL the IdP posts responses to
+    #[must_use]
+    pub fn acs_url(&self, api_url: impl AsRef<str>) -> String {
+        format!(
+            "{api_url}/api/auth/saml/{provider_name}/acs",
+            api_url = api_url.as_ref(),
+            provider_name = self.name
+        )
+    }
+
+    /// SP metadata describing our entity ID and ACS endpoint, to be registered with the IdP
+    #[must_use]
+    pub fn sp_metadata(&self, api_url: impl AsRef<str>) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<md:EntityDescriptor xmlns:md="{METADATA_NS}" entityID="{entity_id}">
+  <md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">
+    <md:NameIDFormat>urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress</md:NameIDFormat>
+    <md:AssertionConsumerService Binding="{HTTP_POST_BINDING}" Location="{acs_url}" index="0" isDefault="true"/>
+  </md:SPSSODescriptor>
+</md:EntityDescriptor>
+"#,
+            entity_id = xml_escape(&self.entity_id(&api_url)),
+            acs_url = xml_escape(&self.acs_url(&api_url)),
+        )
+    }
+
+    /// builds the IdP URL which starts an SP-initiated login (HTTP-Redirect binding)
+    /// with an `AuthnRequest` identified by `request_id`
+    ///
+    /// returns `None` if the IdP doesn't advertise a redirect endpoint
+    ///
+    /// # Errors
+    /// * the `AuthnRequest` could not be deflated
+    pub fn authn_request_url(
+        &self,
+        api_url: impl AsRef<str>,
+        request_id: &str,
+        relay_state: Option<&str>,
+    ) -> Result<Option<String>> {
+        use flate2::{write::DeflateEncoder, Compression};
+        use std::io::Write;
+
+        let Some(sso_url) = &self.idp.sso_url else {
+            return Ok(None);
+        };
+
+        let request = format!(
+            r#"<samlp:AuthnRequest xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="{id}" Version="2.0" IssueInstant="{now}" Destination="{destination}" AssertionConsumerServiceURL="{acs_url}" ProtocolBinding="{HTTP_POST_BINDING}"><saml:Issuer>{entity_id}</saml:Issuer></samlp:AuthnRequest>"#,
+            id = xml_escape(request_id),
+            now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            destination = xml_escape(sso_url),
+            acs_url = xml_escape(&self.acs_url(&api_url)),
+            entity_id = xml_escape(&self.entity_id(&api_url)),
+        );
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(request.as_bytes())?;
+        let deflated = encoder.finish()?;
+
+        let mut url = format!(
+            "{sso_url}{separator}SAMLRequest={request}",
+            separator = if sso_url.contains('?') { '&' } else { '?' },
+            request = url_encode(&BASE64.encode(deflated)),
+        );
+        if let Some(relay_state) = relay_state {
+            url.push_str(&format!("&RelayState={}", url_encode(relay_state)));
+        }
+
+        Ok(Some(url))
+    }
+}
+
+/// a new `AuthnRequest` ID (XML IDs can't start with a digit)
+fn new_request_id() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+
+    let id = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect::<String>();
+
+    format!("_{id}")
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,153 @@
+/* This file is generated and managed by dsync */
+
+use crate::auth::saml::schema::{saml_assertions, saml_requests, user_saml_links};
+use crate::auth::{User, Utc, ID};
+use crate::diesel::{
+    insert_into, AsChangeset, Associations, ExpressionMethods, Identifiable, Insertable, QueryDsl,
+    Queryable, RunQueryDsl, Selectable,
+};
+use diesel::QueryResult;
+use serde::{Deserialize, Serialize};
+
+type Connection = crate::Connection;
+
+#[tsync::tsync]
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    Queryable,
+    Insertable,
+    AsChangeset,
+    Identifiable,
+    Associations,
+    Selectable,
+)]
+#[diesel(table_name=user_saml_links, primary_key(id), belongs_to(User, foreign_key=user_id))]
+pub struct UserSamlLink {
+    pub id: ID,
+    pub provider: String,
+    pub name_id: String,
+    pub user_id: ID,
+    pub created_at: Utc,
+    #[cfg(not(any(feature = "database_sqlite", feature = "database_any")))]
+    pub updated_at: Utc,
+}
+
+#[tsync::tsync]
+#[derive(Debug, Serialize, Deserialize, Clone, Insertable, AsChangeset)]
+#[diesel(table_name=user_saml_links)]
+pub struct CreateUserSamlLink {
+    pub provider: String,
+    pub name_id: String,
+    pub user_id: ID,
+}
+
+impl UserSamlLink {
+    pub fn create(db: &mut Connection, item: &CreateUserSamlLink) -> QueryResult<Self> {
+        use crate::auth::saml::schema::user_saml_links::dsl::user_saml_links;
+
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(user_saml_links)
+            .values(item)
+            .get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(user_saml_links).values(item).execute(db)?;
+            Self::read_by_name_id(db, item.provider.clone(), item.name_id.clone())
+        }
+    }
+
+    pub fn read_by_name_id(
+        db: &mut Connection,
+        param_provider: String,
+        param_name_id: String,
+    ) -> QueryResult<Self> {
+        use crate::auth::saml::schema::user_saml_links::dsl::{name_id, provider, user_saml_links};
+
+        user_saml_links
+            .filter(provider.eq(param_provider))
+            .filter(name_id.eq(param_name_id))
+            .first::<Self>(db)
+    }
+
+    pub fn delete(db: &mut Connection, param_id: ID) -> QueryResult<usize> {
+        use crate::auth::saml::schema::user_saml_links::dsl::{id, user_saml_links};
+
+        diesel::delete(user_saml_links.filter(id.eq(param_id))).execute(db)
+    }
+}
+
+/// an `AuthnRequest` we sent, which the IdP's response has to be in response to
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name=saml_requests)]
+pub struct SamlRequest {
+    pub provider: String,
+    pub request_id: String,
+    /// also in a cookie of the browser which started the login
+    pub browser_token: String,
+    pub expires_at: Utc,
+}
+
+impl SamlRequest {
+    pub fn create(db: &mut Connection, item: &Self) -> QueryResult<usize> {
+        use crate::auth::saml::schema::saml_requests::dsl::saml_requests;
+
+        insert_into(saml_requests).values(item).execute(db)
+    }
+
+    /// deletes the request, so that only one response can be in response to it
+    ///
+    /// returns `false` if there was no such request started by the browser with `param_browser_token`
+    pub fn consume(
+        db: &mut Connection,
+        param_provider: String,
+        param_request_id: String,
+        param_browser_token: String,
+    ) -> QueryResult<bool> {
+        use crate::auth::saml::schema::saml_requests::dsl::{
+            browser_token, provider, request_id, saml_requests,
+        };
+
+        diesel::delete(
+            saml_requests
+                .filter(provider.eq(param_provider))
+                .filter(request_id.eq(param_request_id))
+                .filter(browser_token.eq(param_browser_token)),
+        )
+        .execute(db)
+        .map(|deleted| deleted > 0)
+    }
+
+    pub fn delete_expired(db: &mut Connection, now: Utc) -> QueryResult<usize> {
+        use crate::auth::saml::schema::saml_requests::dsl::{expires_at, saml_requests};
+
+        diesel::delete(saml_requests.filter(expires_at.le(now))).execute(db)
+    }
+}
+
+/// an assertion which was already used to log in, kept until it expires
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name=saml_assertions)]
+pub struct ConsumedSamlAssertion {
+    pub provider: String,
+    pub assertion_id: String,
+    pub expires_at: Utc,
+}
+
+impl ConsumedSamlAssertion {
+    /// fails with a unique violation if the assertion was already consumed
+    pub fn create(db: &mut Connection, item: &Self) -> QueryResult<usize> {
+        use crate::auth::saml::schema::saml_assertions::dsl::saml_assertions;
+
+        insert_into(saml_assertions).values(item).execute(db)
+    }
+
+    pub fn delete_expired(db: &mut Connection, now: Utc) -> QueryResult<usize> {
+        use crate::auth::saml::schema::saml_assertions::dsl::{expires_at, saml_assertions};
+
+        diesel::delete(saml_assertions.filter(expires_at.le(now))).execute(db)
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use roxmltree::{Document, Node};
+
+use super::xmldsig::{child, is_element, verify_enveloped_signature};
+use super::IdpMetadata;
+
+const PROTOCOL_NS: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const ASSERTION_NS: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+
+/// how far the IdP's clock is allowed to drift from ours when checking validity windows
+const ALLOWED_CLOCK_SKEW: chrono::Duration = chrono::Duration::minutes(3);
+
+/// The parts of a verified SAML assertion we care about
+#[derive(Debug, Clone)]
+pub struct SamlAssertion {
+    /// the assertion's `ID`, remembered until `expires_at` so it can't be replayed
+    pub id: String,
+    /// the ID of the `AuthnRequest` this is a response to, as far as the signature covers it;
+    /// `None` for IdP-initiated logins
+    pub in_response_to: Option<String>,
+    /// when the assertion stops being accepted (its earliest `NotOnOrAfter`, plus the allowed clock skew)
+    pub expires_at: DateTime<Utc>,
+    pub name_id: String,
+    pub name_id_format: Option<String>,
+    pub attributes: HashMap<String, Vec<String>>,
+}
+
+impl SamlAssertion {
+    /// first value of the attribute with the given `Name` (or `FriendlyName`)
+    #[must_use]
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .get(name)
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+}
+
+/// What the assertion has to be addressed to
+pub struct ExpectedRecipient<'a> {
+    /// our entity ID, checked against the `AudienceRestriction`
+    pub audience: &'a str,
+    /// our assertion consumer service URL, checked against the `Recipient`/`Destination`
+    pub acs_url: &'a str,
+}
+
+/// Decodes the base64 `SAMLResponse` form field of an HTTP-POST binding,
+/// verifies it was signed by `idp` and returns the assertion it carries.
+///
+/// Either the `<Response>` or its single `<Assertion>` must carry a valid signature.
+/// Whether the response was solicited and the assertion is used only once is up to the caller,
+/// see [`SamlAssertion::in_response_to`] and [`SamlAssertion::id`].
+///
+/// # Errors
+/// * the response is malformed, unsigned, expired, unsuccessful or addressed to someone else
+/// * the assertion has no `AudienceRestriction` or no `NotOnOrAfter`
+pub fn parse_response(
+    saml_response: &str,
+    idp: &IdpMetadata,
+    expected: &ExpectedRecipient,
+    now: DateTime<Utc>,
+) -> Result<SamlAssertion> {
+    let xml = String::from_utf8(BASE64.decode(saml_response.trim())?)?;
+    let document = Document::parse(&xml)?;
+
+    let response = document.root_element();
+    if !is_element(response, PROTOCOL_NS, "Response") {
+        bail!("not a SAML response");
+    }
+
+    if let Some(destination) = response.attribute("Destination") {
+        if destination != expected.acs_url {
+            bail!("response destination '{destination}' does not match");
+        }
+    }
+
+    let status = child(response, PROTOCOL_NS, "Status")
+        .and_then(|status| child(status, PROTOCOL_NS, "StatusCode"))
+        .and_then(|code| code.attribute("Value"));
+    if status != Some(STATUS_SUCCESS) {
+        bail!("IdP returned status '{}'", status.unwrap_or("missing"));
+    }
+
+    if child(response, ASSERTION_NS, "EncryptedAssertion").is_some() {
+        bail!("encrypted assertions are not supported");
+    }
+
+    // refuse responses with several assertions: only one of them could be covered by the signature
+    let assertions = response
+        .children()
+        .filter(|n| is_element(*n, ASSERTION_NS, "Assertion"))
+        .collect::<Vec<_>>();
+    let [assertion] = assertions.as_slice() else {
+        bail!("expected exactly one assertion");
+    };
+    let assertion = *assertion;
+
+    let response_signed = child(response, super::xmldsig::DSIG_NS, "Signature").is_some();
+    if response_signed {
+        verify_enveloped_signature(response, &idp.signing_certificates)?;
+    } else {
+        verify_enveloped_signature(assertion, &idp.signing_certificates)?;
+    }
+
+    let issuer = child(assertion, ASSERTION_NS, "Issuer").and_then(|n| n.text());
+    if issuer.map(str::trim) != Some(idp.entity_id.as_str()) {
+        bail!("unexpected assertion issuer");
+    }
+
+    let id = assertion
+        .attribute("ID")
+        .ok_or_else(|| anyhow!("missing assertion ID"))?;
+
+    let conditions_expiry = check_conditions(assertion, expected.audience, now)?;
+
+    let subject =
+        child(assertion, ASSERTION_NS, "Subject").ok_or_else(|| anyhow!("missing Subject"))?;
+    let confirmation = check_subject_confirmation(
+        subject,
+        expected.acs_url,
+        response.attribute("InResponseTo"),
+        now,
+    )?;
+
+    // the `<Response>` can be forged around a signed assertion, so its `InResponseTo` is only
+    // trusted when the signature covers it; otherwise the subject confirmation's has to say
+    let in_response_to = if response_signed {
+        response
+            .attribute("InResponseTo")
+            .or_else(|| confirmation.attribute("InResponseTo"))
+    } else {
+        confirmation.attribute("InResponseTo")
+    };
+
+    let expires_at = [
+        conditions_expiry,
+        parse_time(confirmation.attribute("NotOnOrAfter"))?,
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+    .ok_or_else(|| anyhow!("assertion has no NotOnOrAfter"))?;
+
+    let name_id =
+        child(subject, ASSERTION_NS, "NameID").ok_or_else(|| anyhow!("missing NameID"))?;
+
+    Ok(SamlAssertion {
+        id: id.to_string(),
+        in_response_to: in_response_to.map(str::to_string),
+        expires_at: expires_at + ALLOWED_CLOCK_SKEW,
+        name_id: name_id.text().unwrap_or_default().trim().to_string(),
+        name_id_format: name_id.attribute("Format").map(str::to_string),
+        attributes: attributes(assertion),
+    })
+}
+
+/// returns the `NotOnOrAfter` of the conditions, if any
+fn check_conditions(
+    assertion: Node,
+    audience: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>> {
+    let conditions = child(assertion, ASSERTION_NS, "Conditions")
+        .ok_or_else(|| anyhow!("missing Conditions"))?;
+
+    check_validity_window(
+        conditions.attribute("NotBefore"),
+        conditions.attribute("NotOnOrAfter"),
+        now,
+    )?;
+
+    let restrictions = conditions
+        .children()
+        .filter(|n| is_element(*n, ASSERTION_NS, "AudienceRestriction"))
+        .collect::<Vec<_>>();
+    if restrictions.is_empty() {
+        bail!("missing AudienceRestriction");
+    }
+
+    for restriction in restrictions {
+        let matches = restriction
+            .children()
+            .filter(|n| is_element(*n, ASSERTION_NS, "Audience"))
+            .any(|n| n.text().map(str::trim) == Some(audience));
+        if !matches {
+            bail!("assertion is not intended for this service provider");
+        }
+    }
+
+    parse_time(conditions.attribute("NotOnOrAfter"))
+}
+
+/// returns the `SubjectConfirmationData` of the bearer confirmation that is valid now;
+/// its `InResponseTo` has to agree with the response's
+fn check_subject_confirmation<'a, 'input>(
+    subject: Node<'a, 'input>,
+    acs_url: &str,
+    in_response_to: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Node<'a, 'input>> {
+    subject
+        .children()
+        .filter(|n| is_element(*n, ASSERTION_NS, "SubjectConfirmation"))
+        .filter(|n| n.attribute("Method") == Some(BEARER))
+        .filter_map(|n| child(n, ASSERTION_NS, "SubjectConfirmationData"))
+        .find(|data| {
+            data.attribute("Recipient").is_none_or(|r| r == acs_url)
+                && in_response_to.is_none_or(|id| {
+                    data.attribute("InResponseTo")
+                        .is_none_or(|data_id| data_id == id)
+                })
+                && check_validity_window(
+                    data.attribute("NotBefore"),
+                    data.attribute("NotOnOrAfter"),
+                    now,
+                )
+                .is_ok()
+        })
+        .ok_or_else(|| anyhow!("no valid bearer subject confirmation"))
+}
+
+fn parse_time(time: Option<&str>) -> Result<Option<DateTime<Utc>>> {
+    time.map(|time| Ok(DateTime::parse_from_rfc3339(time)?.with_timezone(&Utc)))
+        .transpose()
+}
+
+fn check_validity_window(
+    not_before: Option<&str>,
+    not_on_or_after: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    if let Some(not_before) = not_before {
+        if now + ALLOWED_CLOCK_SKEW < DateTime::parse_from_rfc3339(not_before)? {
+            bail!("assertion is not yet valid");
+        }
+    }
+    if let Some(not_on_or_after) = not_on_or_after {
+        if now - ALLOWED_CLOCK_SKEW >= DateTime::parse_from_rfc3339(not_on_or_after)? {
+            bail!("assertion has expired");
+        }
+    }
+    Ok(())
+}
+
+fn attributes(assertion: Node) -> HashMap<String, Vec<String>> {
+    let mut attributes: HashMap<String, Vec<String>> = HashMap::new();
+
+    for attribute in assertion
+        .children()
+        .filter(|n| is_element(*n, ASSERTION_NS, "AttributeStatement"))
+        .flat_map(|statement| statement.children())
+        .filter(|n| is_element(*n, ASSERTION_NS, "Attribute"))
+    {
+        let values = attribute
+            .children()
+            .filter(|n| is_element(*n, ASSERTION_NS, "AttributeValue"))
+            .map(|n| n.text().unwrap_or_default().trim().to_string())
+            .collect::<Vec<_>>();
+
+        for name in [
+            attribute.attribute("Name"),
+            attribute.attribute("FriendlyName"),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            attributes
+                .entry(name.to_string())
+                .or_default()
+                .extend(values.clone());
+        }
+    }
+
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_idp::{self, Assertion, APP_URL};
+    use super::*;
+
+    fn idp() -> IdpMetadata {
+        IdpMetadata::from_xml(&test_idp::metadata()).unwrap()
+    }
+
+    fn parse(assertion: &str) -> Result<SamlAssertion> {
+        parse_response_to(None, assertion)
+    }
+
+    fn parse_response_to(request_id: Option<&str>, assertion: &str) -> Result<SamlAssertion> {
+        parse_response(
+            &test_idp::response_to(request_id, assertion),
+            &idp(),
+            &ExpectedRecipient {
+                audience: &format!("{APP_URL}/api/auth/saml/test/metadata"),
+                acs_url: &format!("{APP_URL}/api/auth/saml/test/acs"),
+            },
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn accepts_signed_assertion() {
+        let assertion = parse(&Assertion::default().signed()).unwrap();
+
+        assert_eq!(assertion.id, "_assertion1");
+        assert_eq!(assertion.in_response_to, None);
+        assert_eq!(assertion.name_id, "jane@example.com");
+        assert_eq!(assertion.attribute("email"), Some("jane@example.com"));
+    }
+
+    #[test]
+    fn expires_with_the_earliest_not_on_or_after() {
+        let not_on_or_after = Utc::now() + chrono::Duration::minutes(5);
+        let assertion = parse(
+            &Assertion {
+                not_on_or_after,
+                ..Assertion::default()
+            }
+            .signed(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            assertion.expires_at.timestamp(),
+            (not_on_or_after + ALLOWED_CLOCK_SKEW).timestamp()
+        );
+    }
+
+    #[test]
+    fn rejects_missing_conditions_and_audience() {
+        let without_conditions = Assertion {
+            without_conditions: true,
+            ..Assertion::default()
+        };
+        let without_audience = Assertion {
+            audience: None,
+            ..Assertion::default()
+        };
+
+        assert!(parse(&without_conditions.signed()).is_err());
+        assert!(parse(&without_audience.signed()).is_err());
+    }
+
+    #[test]
+    fn reads_and_checks_in_response_to() {
+        let answering = |request_id: &str| Assertion {
+            in_response_to: Some(request_id.to_string()),
+            ..Assertion::default()
+        };
+
+        // only the assertion is signed, so the response's InResponseTo could have been forged
+        let unsigned_response =
+            parse_response_to(Some("_request1"), &Assertion::default().signed());
+        let from_confirmation = parse(&answering("_request1").signed());
+        let matching = parse_response_to(Some("_request1"), &answering("_request1").signed());
+        let mismatched = parse_response_to(Some("_request1"), &answering("_request2").signed());
+
+        assert_eq!(unsigned_response.unwrap().in_response_to, None);
+        assert_eq!(
+            from_confirmation.unwrap().in_response_to.as_deref(),
+            Some("_request1")
+        );
+        assert_eq!(
+            matching.unwrap().in_response_to.as_deref(),
+            Some("_request1")
+        );
+        assert!(mismatched.is_err());
+    }
+
+    #[test]
+    fn rejects_unsigned_assertion() {
+        let signed = Assertion::default().signed();
+        let start = signed.find("<ds:Signature").unwrap();
+        let end = signed.find("</ds:Signature>").unwrap() + "</ds:Signature>".len();
+        let unsigned = format!("{}{}", &signed[..start], &signed[end..]);
+
+        assert!(parse(&unsigned).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_assertion() {
+        let tampered = Assertion::default()
+            .signed()
+            .replace(">jane@example.com<", ">admin@example.com<");
+
+        assert!(parse(&tampered).is_err());
+    }
+
+    #[test]
+    fn rejects_wrapped_assertion() {
+        // a validly signed assertion next to an attacker-controlled one
+        let forged = Assertion {
+            id: "_forged".to_string(),
+            name_id: "admin@example.com".to_string(),
+            ..Assertion::default()
+        }
+        .signed()
+        .replace("<ds:SignatureValue>", "<ds:SignatureValue>AAAA");
+        let wrapped = format!("{forged}{}", Assertion::default().signed());
+
+        assert!(parse(&wrapped).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_audience_issuer_and_expired() {
+        let wrong_audience = Assertion {
+            audience: Some("https://someone-else.test".to_string()),
+            ..Assertion::default()
+        };
+        let wrong_issuer = Assertion {
+            issuer: "https://evil.test".to_string(),
+            ..Assertion::default()
+        };
+        let expired = Assertion {
+            not_on_or_after: Utc::now() - chrono::Duration::hours(1),
+            ..Assertion::default()
+        };
+
+        assert!(parse(&wrong_audience.signed()).is_err());
+        assert!(parse(&wrong_issuer.signed()).is_err());
+        assert!(parse(&expired.signed()).is_err());
+    }
+
+    #[test]
+    fn rejects_untrusted_certificate() {
+        let mut other_idp = idp();
+        other_idp.signing_certificates = vec![];
+
+        let result = parse_response(
+            &test_idp::response(&Assertion::default().signed()),
+            &other_idp,
+            &ExpectedRecipient {
+                audience: &format!("{APP_URL}/api/auth/saml/test/metadata"),
+                acs_url: &format!("{APP_URL}/api/auth/saml/test/acs"),
+            },
+            Utc::now(),
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,15 @@
+// `database_any` uses the SQLite schema, whose types Postgres has too
+#[cfg(any(feature = "database_sqlite", feature = "database_any"))]
+mod sqlite;
+#[cfg(any(feature = "database_sqlite", feature = "database_any"))]
+pub use sqlite::*;
+
+#[cfg(feature = "database_postgres")]
+mod postgres;
+#[cfg(feature = "database_postgres")]
+pub use postgres::*;
+
+#[cfg(feature = "database_mysql")]
+mod mysql;
+#[cfg(feature = "database_mysql")]
+pub use mysql::*;
@@ -0,0 +1,37 @@
+table! {
+  users (id) {
+      id -> Integer,
+  }
+}
+
+table! {
+  user_saml_links (id) {
+      id -> Integer,
+      provider -> Text,
+      name_id -> Text,
+      user_id -> Integer,
+      created_at -> Datetime,
+      updated_at -> Datetime,
+    }
+}
+
+joinable!(user_saml_links -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(users, user_saml_links);
+
+table! {
+  saml_requests (provider, request_id) {
+      provider -> Text,
+      request_id -> Text,
+      browser_token -> Text,
+      expires_at -> Datetime,
+    }
+}
+
+table! {
+  saml_assertions (provider, assertion_id) {
+      provider -> Text,
+      assertion_id -> Text,
+      expires_at -> Datetime,
+    }
+}
@@ -0,0 +1,37 @@
+table! {
+  users (id) {
+      id -> Int4,
+  }
+}
+
+table! {
+  user_saml_links (id) {
+      id -> Int4,
+      provider -> Text,
+      name_id -> Text,
+      user_id -> Int4,
+      created_at -> Timestamptz,
+      updated_at -> Timestamptz,
+    }
+}
+
+joinable!(user_saml_links -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(users, user_saml_links);
+
+table! {
+  saml_requests (provider, request_id) {
+      provider -> Text,
+      request_id -> Text,
+      browser_token -> Text,
+      expires_at -> Timestamptz,
+    }
+}
+
+table! {
+  saml_assertions (provider, assertion_id) {
+      provider -> Text,
+      assertion_id -> Text,
+      expires_at -> Timestamptz,
+    }
+}
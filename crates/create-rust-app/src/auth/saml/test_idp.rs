@@ -0,0 +1,194 @@
+//! A local SAML identity provider used to exercise the service-provider flow in tests.
+//!
+//! The key pair is a throwaway self-signed certificate generated for these tests only.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::RsaPrivateKey;
+
+use super::xmldsig::{canonicalize, digest};
+
+pub const ENTITY_ID: &str = "https://idp.test/metadata";
+pub const SSO_URL: &str = "https://idp.test/sso";
+pub const APP_URL: &str = "https://app.test";
+
+const PRIVATE_KEY: &str = concat!(
+    "MIIEvgIBADANBgkqhkiG9w0BAQEFAASCBKgwggSkAgEAAoIBAQCxh97ai1k0lQol4dqWTK91Vxsc",
+    "9YUoTD4OG2GVzjw5z2Mr2vV5jndm13wKIgvmETczrA0omGxbjD/kcljfQjbulqUGN1RgLBabzBAt",
+    "+0htm7QHXvJGADozwBl+mUXaTw/vGzvEVlyURmdROj4U9AT2LcKlal4Rv/o7F/r8WKx+PkOrGJ4B",
+    "As7CjinaXDZsiNhI5MNozONa0xZTSf5DfOqZDH7V1SN2bI8UPdFyBzDVqH6WrIVyCfKPXxi+T6Bf",
+    "S8WcH/S3z0nK0Gn9V1cnC3jLuUQusC7J7+0RuJ5K2DM2XRHj7+9JwFqa5AYd0UmqefZnau0TsEjn",
+    "PZI4lh4uyhOjAgMBAAECggEAID1R6bNDpGpqeN2uofvUI0WJzXUC1QIfhNVRH/DffAoe8uBlEsme",
+    "AmWgh4COU54NvcsBar1fdDKgib6ASC8QpbxixKG4p2xqQKnKyGbpTMppY6tI6RBygWJh3t0707zm",
+    "4l+6N1oNnvm4GoauroRwADroLb0Gz0djTGb68+ewj442DpUODPobmYASGjEdDLgQhQ2sj/jdKUUg",
+    "dy1UrijD+AUwhpE4w73telYe5PQIjJsrdDTdc0cAlp/hC04b+RqXgLbWM+NsPt63tHdQ294zzFF+",
+    "EppyxOG3p9TtGckwwdAFKuAooZz9YLKnLRPS4wjoH2IGIhLSjAfmiYumUVFvjQKBgQDyb6S8sKYE",
+    "ry0gGGwxckHrtdS5L03BQRq9c9wlgp0JBwCrBqHbnr8dEQw2YUP4jHMuY0XXCfjY4E8z77aqJ0vF",
+    "XU3ifComgjJk3uRfQwjfTv3RDuyU09ja5C8he3B4Q/jRyL2TjcLtWX/EPlZttiBtEBnP6Mdtk3A2",
+    "e967lMoxPwKBgQC7dpo6utgB07eENS7VegWX63QRt7ffmYlVDFib1m25sh0yFOtYK1YLwm+lY32+",
+    "K9pxhbOsLVtHs5cyuP+O2S7Y4u8L/YLToFBbXqJY/j16tMUe6SknAVyRlHXL84ZZQnTKvV/RwNZ7",
+    "ukFLsrJCOfBOhBupWHJucop7jSzrDN4gnQKBgQCw05+LhSjCN+cpBsc7VYQhYVGT9J24/Dx9vJjw",
+    "vxC8+d+R3teFsYbhs4J903nZOs/0ILJbA0n/HbSHRCuuddT9Um2xaTDxP3t0OWLG3MUG472bk1AZ",
+    "yV7dYRvDRx6kiaddlBY6Hmbz6ZiYUYOGqBWElv7Mt5NmJ+jrxHOTzBWhzwKBgBkOqV7gSq8z1Xve",
+    "f/wY61Z7KnCT1ggVRb4QgGeUKeaAYFapZSvL3xyDSNkai1w4EGLMNE/3YBQddUBaag52CUUEUF+R",
+    "60LOUu9jnt+2XBFiyFZxZAbKr8zlwzkXvAWCVIok9gHbfM/c/Y1YvWsMw4o7Lldccy9AMDKDlFV2",
+    "ytohAoGBALgexMxlvIbi1dggiYOcc0w+4GRdB/QSBLTfJO/vkiQDuSxTNJh+qz/hngEc8/CxIzvE",
+    "bHyi0Y0QX1EyykFtelhTsgewYDa/HVvFhx/OP4l/EliUVqUfaCC13JqIIrTYy5Halvzos+gdMTkl",
+    "hKPB2z+f9l2OLLGM5FWCLOmtmeLV",
+);
+
+const CERTIFICATE: &str = concat!(
+    "MIIDKTCCAhGgAwIBAgIUJfekQ5yDIWzSvE+UmvVYsipfRAAwDQYJKoZIhvcNAQELBQAwIzEhMB8G",
+    "A1UEAwwYY3JlYXRlLXJ1c3QtYXBwIHRlc3QgaWRwMCAXDTI2MTAxODIyMjE1M1oYDzIxMjYwOTI0",
+    "MjIyMTUzWjAjMSEwHwYDVQQDDBhjcmVhdGUtcnVzdC1hcHAgdGVzdCBpZHAwggEiMA0GCSqGSIb3",
+    "DQEBAQUAA4IBDwAwggEKAoIBAQCxh97ai1k0lQol4dqWTK91Vxsc9YUoTD4OG2GVzjw5z2Mr2vV5",
+    "jndm13wKIgvmETczrA0omGxbjD/kcljfQjbulqUGN1RgLBabzBAt+0htm7QHXvJGADozwBl+mUXa",
+    "Tw/vGzvEVlyURmdROj4U9AT2LcKlal4Rv/o7F/r8WKx+PkOrGJ4BAs7CjinaXDZsiNhI5MNozONa",
+    "0xZTSf5DfOqZDH7V1SN2bI8UPdFyBzDVqH6WrIVyCfKPXxi+T6BfS8WcH/S3z0nK0Gn9V1cnC3jL",
+    "uUQusC7J7+0RuJ5K2DM2XRHj7+9JwFqa5AYd0UmqefZnau0TsEjnPZI4lh4uyhOjAgMBAAGjUzBR",
+    "MB0GA1UdDgQWBBRTCcXAMCBig+E40KWUJ4kaFiRolDAfBgNVHSMEGDAWgBRTCcXAMCBig+E40KWU",
+    "J4kaFiRolDAPBgNVHRMBAf8EBTADAQH/MA0GCSqGSIb3DQEBCwUAA4IBAQCI22EmymgOvXZAqkfo",
+    "aKXJmkKmOv9nYSoDzNCYKjI7D2B7phn38xepjPQqzKZPOPlGP/3WKSDoaBTQRIecb2gbecqnYujo",
+    "lRu8py/QhK7m0PfLZri0/izNo7MibE9JzcRejR6Pc3/XCNPcM7aYFIbyxNDYTqu4aXKZV8bVaEJ8",
+    "NPifgVP0uwlPGRVhcLV2dc3Z0JvpciqwEhLopukYN4y48LwvdsIxqWVHbsOuOGSoyWxKhaiwrLHf",
+    "nFumZqISF/awdP8lVhCxPY0QgHm7q0j3Cx9nRgt7ooJgaWA/nlbw7o1+i0oE573lFB7nQRz0PX/V",
+    "1LMtkoDdGnRS0+9YtlrA",
+);
+
+pub struct Assertion {
+    pub id: String,
+    pub issuer: String,
+    pub name_id: String,
+    pub email: Option<String>,
+    // `None` leaves out the `AudienceRestriction`
+    pub audience: Option<String>,
+    // leaves out the `Conditions` altogether
+    pub without_conditions: bool,
+    pub recipient: String,
+    // the `InResponseTo` of the bearer `SubjectConfirmationData`
+    pub in_response_to: Option<String>,
+    pub issue_instant: chrono::DateTime<chrono::Utc>,
+    pub not_before: chrono::DateTime<chrono::Utc>,
+    pub not_on_or_after: chrono::DateTime<chrono::Utc>,
+}
+
+impl Default for Assertion {
+    fn default() -> Self {
+        let now = chrono::Utc::now();
+
+        Self {
+            id: "_assertion1".to_string(),
+            issuer: ENTITY_ID.to_string(),
+            name_id: "jane@example.com".to_string(),
+            email: Some("jane@example.com".to_string()),
+            audience: Some(format!("{APP_URL}/api/auth/saml/test/metadata")),
+            without_conditions: false,
+            recipient: format!("{APP_URL}/api/auth/saml/test/acs"),
+            in_response_to: None,
+            issue_instant: now,
+            not_before: now - chrono::Duration::minutes(1),
+            not_on_or_after: now + chrono::Duration::minutes(5),
+        }
+    }
+}
+
+impl Assertion {
+    fn to_xml(&self, signature: &str) -> String {
+        let time =
+            |t: chrono::DateTime<chrono::Utc>| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let attributes = self.email.as_ref().map_or_else(String::new, |email| {
+            format!(
+                r#"<saml:AttributeStatement><saml:Attribute Name="email"><saml:AttributeValue xmlns:xs="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="xs:string">{email}</saml:AttributeValue></saml:Attribute></saml:AttributeStatement>"#
+            )
+        });
+        let audience_restriction = self.audience.as_ref().map_or_else(String::new, |audience| {
+            format!("<saml:AudienceRestriction><saml:Audience>{audience}</saml:Audience></saml:AudienceRestriction>")
+        });
+        let conditions = if self.without_conditions {
+            String::new()
+        } else {
+            format!(
+                r#"<saml:Conditions NotBefore="{not_before}" NotOnOrAfter="{not_on_or_after}">{audience_restriction}</saml:Conditions>"#,
+                not_before = time(self.not_before),
+                not_on_or_after = time(self.not_on_or_after),
+            )
+        };
+        let in_response_to = self
+            .in_response_to
+            .as_ref()
+            .map_or_else(String::new, |id| format!(r#" InResponseTo="{id}""#));
+
+        format!(
+            r#"<saml:Assertion xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="{id}" Version="2.0" IssueInstant="{now}"><saml:Issuer>{issuer}</saml:Issuer>{signature}<saml:Subject><saml:NameID Format="urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress">{name_id}</saml:NameID><saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer"><saml:SubjectConfirmationData NotOnOrAfter="{not_on_or_after}" Recipient="{recipient}"{in_response_to}/></saml:SubjectConfirmation></saml:Subject>{conditions}{attributes}</saml:Assertion>"#,
+            id = self.id,
+            now = time(self.issue_instant),
+            issuer = self.issuer,
+            name_id = self.name_id,
+            recipient = self.recipient,
+            not_on_or_after = time(self.not_on_or_after),
+        )
+    }
+
+    /// the assertion with an enveloped signature made with the test IdP's key
+    pub fn signed(&self) -> String {
+        let unsigned = self.to_xml("");
+        let document = roxmltree::Document::parse(&unsigned).unwrap();
+        let digest_value = BASE64.encode(
+            digest(
+                "http://www.w3.org/2001/04/xmlenc#sha256",
+                canonicalize(document.root_element(), None, &["xs".to_string()], false).as_bytes(),
+            )
+            .unwrap(),
+        );
+
+        let signed_info = format!(
+            r##"<ds:SignedInfo xmlns:ds="http://www.w3.org/2000/09/xmldsig#"><ds:CanonicalizationMethod Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#"/><ds:SignatureMethod Algorithm="http://www.w3.org/2001/04/xmldsig-more#rsa-sha256"/><ds:Reference URI="#{id}"><ds:Transforms><ds:Transform Algorithm="http://www.w3.org/2000/09/xmldsig#enveloped-signature"/><ds:Transform Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#"><ec:InclusiveNamespaces xmlns:ec="http://www.w3.org/2001/10/xml-exc-c14n#" PrefixList="xs"/></ds:Transform></ds:Transforms><ds:DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/><ds:DigestValue>{digest_value}</ds:DigestValue></ds:Reference></ds:SignedInfo>"##,
+            id = self.id,
+        );
+        let signed_info_document = roxmltree::Document::parse(&signed_info).unwrap();
+        let signed_info_c14n = canonicalize(signed_info_document.root_element(), None, &[], false);
+        let signature_value = BASE64.encode(sign(signed_info_c14n.as_bytes()));
+
+        self.to_xml(&format!(
+            r#"<ds:Signature xmlns:ds="http://www.w3.org/2000/09/xmldsig#">{signed_info}<ds:SignatureValue>{signature_value}</ds:SignatureValue></ds:Signature>"#
+        ))
+    }
+}
+
+fn sign(data: &[u8]) -> Vec<u8> {
+    let key = RsaPrivateKey::from_pkcs8_der(&BASE64.decode(PRIVATE_KEY).unwrap()).unwrap();
+    SigningKey::<sha2::Sha256>::new(key).sign(data).to_vec()
+}
+
+/// the IdP metadata a real deployment would download from the identity provider
+pub fn metadata() -> String {
+    format!(
+        r#"<md:EntityDescriptor xmlns:md="urn:oasis:names:tc:SAML:2.0:metadata" entityID="{ENTITY_ID}">
+  <md:IDPSSODescriptor protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">
+    <md:KeyDescriptor use="signing">
+      <ds:KeyInfo xmlns:ds="http://www.w3.org/2000/09/xmldsig#">
+        <ds:X509Data><ds:X509Certificate>{CERTIFICATE}</ds:X509Certificate></ds:X509Data>
+      </ds:KeyInfo>
+    </md:KeyDescriptor>
+    <md:SingleSignOnService Binding="urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect" Location="{SSO_URL}"/>
+  </md:IDPSSODescriptor>
+</md:EntityDescriptor>"#
+    )
+}
+
+/// wraps `assertion` in a successful `<samlp:Response>`, base64-encoded like the HTTP-POST binding
+pub fn response(assertion: &str) -> String {
+    response_to(None, assertion)
+}
+
+/// like [`response`], in response to the `AuthnRequest` with the ID `request_id`
+pub fn response_to(request_id: Option<&str>, assertion: &str) -> String {
+    let in_response_to =
+        request_id.map_or_else(String::new, |id| format!(r#" InResponseTo="{id}""#));
+
+    BASE64.encode(format!(
+        r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" ID="_response1" Version="2.0" Destination="{APP_URL}/api/auth/saml/test/acs"{in_response_to}><samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>{assertion}</samlp:Response>"#
+    ))
+}
@@ -0,0 +1,441 @@
+//! Minimal XML-DSig verification for SAML responses.
+//!
+//! Only what SAML identity providers actually emit is supported:
+//! exclusive canonicalization (with or without comments), the enveloped-signature
+//! transform, SHA-1/SHA-256 digests and RSA PKCS#1 v1.5 signatures.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use roxmltree::{Node, NodeId, NodeType};
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::signature::Verifier;
+use rsa::RsaPublicKey;
+use sha2::Digest;
+
+pub const DSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const EXC_C14N_WITH_COMMENTS: &str = "http://www.w3.org/2001/10/xml-exc-c14n#WithComments";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+const DIGEST_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#sha1";
+const DIGEST_SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+const SIGNATURE_RSA_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#rsa-sha1";
+const SIGNATURE_RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+
+/// Verifies the enveloped `<ds:Signature>` which is a direct child of `signed`.
+///
+/// The signature's single reference must point at `signed` itself (by its `ID` attribute)
+/// so that a valid signature somewhere else in the document can't vouch for `signed`.
+///
+/// # Errors
+/// * `signed` has no signature, or the signature is malformed
+/// * the digest or signature value doesn't match any of the `certificates`
+pub fn verify_enveloped_signature(signed: Node, certificates: &[Vec<u8>]) -> Result<()> {
+    let signature =
+        child(signed, DSIG_NS, "Signature").ok_or_else(|| anyhow!("element is not signed"))?;
+    let signed_info =
+        child(signature, DSIG_NS, "SignedInfo").ok_or_else(|| anyhow!("missing SignedInfo"))?;
+
+    let c14n_method = algorithm(child(signed_info, DSIG_NS, "CanonicalizationMethod"))?;
+    let with_comments = match c14n_method {
+        EXC_C14N => false,
+        EXC_C14N_WITH_COMMENTS => true,
+        other => bail!("unsupported canonicalization method '{other}'"),
+    };
+    let signature_method = algorithm(child(signed_info, DSIG_NS, "SignatureMethod"))?;
+
+    let references = signed_info
+        .children()
+        .filter(|n| is_element(*n, DSIG_NS, "Reference"))
+        .collect::<Vec<_>>();
+    let [reference] = references.as_slice() else {
+        bail!("expected exactly one signature reference");
+    };
+
+    // 1. the reference must point at the signed element, and its ID must be unique
+    let id = signed
+        .attribute("ID")
+        .ok_or_else(|| anyhow!("signed element has no ID"))?;
+    if reference.attribute("URI") != Some(&format!("#{id}")) {
+        bail!("signature reference does not point at the signed element");
+    }
+    let id_count = signed
+        .document()
+        .descendants()
+        .filter(|n| n.attribute("ID") == Some(id))
+        .count();
+    if id_count != 1 {
+        bail!("duplicate ID '{id}' in document");
+    }
+
+    // 2. check the transforms and compute the digest of the signed element
+    let mut inclusive_prefixes = vec![];
+    let mut reference_with_comments = false;
+    if let Some(transforms) = child(*reference, DSIG_NS, "Transforms") {
+        for transform in transforms
+            .children()
+            .filter(|n| is_element(*n, DSIG_NS, "Transform"))
+        {
+            match transform.attribute("Algorithm") {
+                Some(ENVELOPED_SIGNATURE) => {}
+                Some(EXC_C14N | EXC_C14N_WITH_COMMENTS) => {
+                    reference_with_comments =
+                        transform.attribute("Algorithm") == Some(EXC_C14N_WITH_COMMENTS);
+                    inclusive_prefixes = inclusive_namespaces(transform);
+                }
+                other => bail!("unsupported transform '{}'", other.unwrap_or_default()),
+            }
+        }
+    }
+
+    let canonical = canonicalize(
+        signed,
+        Some(signature.id()),
+        &inclusive_prefixes,
+        reference_with_comments,
+    );
+    let digest_method = algorithm(child(*reference, DSIG_NS, "DigestMethod"))?;
+    let expected_digest = decode_base64_text(child(*reference, DSIG_NS, "DigestValue"))?;
+    if digest(digest_method, canonical.as_bytes())? != expected_digest {
+        bail!("digest mismatch");
+    }
+
+    // 3. verify the signature over the canonicalized SignedInfo
+    let c14n_prefixes = child(signed_info, DSIG_NS, "CanonicalizationMethod")
+        .map(inclusive_namespaces)
+        .unwrap_or_default();
+    let signed_info_bytes = canonicalize(signed_info, None, &c14n_prefixes, with_comments);
+    let signature_value = decode_base64_text(child(signature, DSIG_NS, "SignatureValue"))?;
+
+    for certificate in certificates {
+        let public_key = public_key_from_certificate(certificate)?;
+        if verify(
+            signature_method,
+            &public_key,
+            signed_info_bytes.as_bytes(),
+            &signature_value,
+        )? {
+            return Ok(());
+        }
+    }
+
+    bail!("signature does not match any trusted certificate")
+}
+
+/// Serializes `node` using exclusive XML canonicalization,
+/// leaving out the subtree rooted at `exclude` (the enveloped signature).
+pub fn canonicalize(
+    node: Node,
+    exclude: Option<NodeId>,
+    inclusive_prefixes: &[String],
+    with_comments: bool,
+) -> String {
+    let mut out = String::new();
+    write_node(
+        node,
+        exclude,
+        inclusive_prefixes,
+        with_comments,
+        &BTreeMap::new(),
+        &mut out,
+    );
+    out
+}
+
+fn write_node(
+    node: Node,
+    exclude: Option<NodeId>,
+    inclusive_prefixes: &[String],
+    with_comments: bool,
+    rendered: &BTreeMap<String, String>,
+    out: &mut String,
+) {
+    if Some(node.id()) == exclude {
+        return;
+    }
+
+    match node.node_type() {
+        NodeType::Text => out.push_str(&escape_text(node.text().unwrap_or_default())),
+        NodeType::Comment if with_comments => {
+            out.push_str("<!--");
+            out.push_str(node.text().unwrap_or_default());
+            out.push_str("-->");
+        }
+        NodeType::PI => {
+            if let Some(pi) = node.pi() {
+                out.push_str("<?");
+                out.push_str(pi.target);
+                if let Some(value) = pi.value {
+                    out.push(' ');
+                    out.push_str(value);
+                }
+                out.push_str("?>");
+            }
+        }
+        NodeType::Element => {
+            write_element(
+                node,
+                exclude,
+                inclusive_prefixes,
+                with_comments,
+                rendered,
+                out,
+            );
+        }
+        NodeType::Root | NodeType::Comment => {}
+    }
+}
+
+fn write_element(
+    node: Node,
+    exclude: Option<NodeId>,
+    inclusive_prefixes: &[String],
+    with_comments: bool,
+    rendered: &BTreeMap<String, String>,
+    out: &mut String,
+) {
+    let prefix = element_prefix(node);
+
+    // namespaces which are "visibly utilized" by this element (or explicitly included)
+    let mut utilized = vec![prefix.clone()];
+    for attribute in node.attributes() {
+        if let Some(uri) = attribute.namespace() {
+            if uri != XML_NS {
+                utilized.push(prefix_for(node, uri));
+            }
+        }
+    }
+    for prefix in inclusive_prefixes {
+        let prefix = if prefix == "#default" { "" } else { prefix };
+        utilized.push(prefix.to_string());
+    }
+
+    let mut declarations = BTreeMap::new();
+    for prefix in utilized {
+        let uri = node
+            .namespaces()
+            .find(|ns| ns.name().unwrap_or_default() == prefix)
+            .map(|ns| ns.uri().to_string())
+            .unwrap_or_default();
+        let already_rendered = rendered.get(&prefix).map_or(
+            // an empty default namespace doesn't need to be declared unless it was overridden
+            prefix.is_empty() && uri.is_empty(),
+            |rendered_uri| *rendered_uri == uri,
+        );
+        if !already_rendered && (!uri.is_empty() || prefix.is_empty()) {
+            declarations.insert(prefix, uri);
+        }
+    }
+
+    let mut attributes = node
+        .attributes()
+        .map(|attribute| {
+            let uri = attribute.namespace().unwrap_or_default();
+            let name = match attribute.namespace() {
+                Some(XML_NS) => format!("xml:{}", attribute.name()),
+                Some(uri) => format!("{}:{}", prefix_for(node, uri), attribute.name()),
+                None => attribute.name().to_string(),
+            };
+            ((uri, attribute.name()), name, attribute.value())
+        })
+        .collect::<Vec<_>>();
+    attributes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let qname = if prefix.is_empty() {
+        node.tag_name().name().to_string()
+    } else {
+        format!("{prefix}:{}", node.tag_name().name())
+    };
+
+    out.push('<');
+    out.push_str(&qname);
+    for (prefix, uri) in &declarations {
+        if prefix.is_empty() {
+            out.push_str(&format!(" xmlns=\"{}\"", escape_attribute(uri)));
+        } else {
+            out.push_str(&format!(" xmlns:{prefix}=\"{}\"", escape_attribute(uri)));
+        }
+    }
+    for (_, name, value) in attributes {
+        out.push_str(&format!(" {name}=\"{}\"", escape_attribute(value)));
+    }
+    out.push('>');
+
+    let mut rendered = rendered.clone();
+    rendered.extend(declarations);
+    for child in node.children() {
+        write_node(
+            child,
+            exclude,
+            inclusive_prefixes,
+            with_comments,
+            &rendered,
+            out,
+        );
+    }
+
+    out.push_str("</");
+    out.push_str(&qname);
+    out.push('>');
+}
+
+/// roxmltree resolves namespaces but doesn't keep the prefix an element was written with,
+/// so we read it back from the source text.
+fn element_prefix(node: Node) -> String {
+    let source = &node.document().input_text()[node.range()];
+    let qname = source
+        .trim_start_matches('<')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or_default();
+
+    qname
+        .split_once(':')
+        .map(|(prefix, _)| prefix.to_string())
+        .unwrap_or_default()
+}
+
+fn prefix_for(node: Node, uri: &str) -> String {
+    node.namespaces()
+        .find(|ns| ns.uri() == uri && ns.name().is_some())
+        .and_then(|ns| ns.name())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn inclusive_namespaces(transform: Node) -> Vec<String> {
+    transform
+        .children()
+        .find(|n| n.is_element() && n.tag_name().name() == "InclusiveNamespaces")
+        .and_then(|n| n.attribute("PrefixList"))
+        .map(|list| list.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\r', "&#xD;")
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+        .replace('\t', "&#x9;")
+        .replace('\n', "&#xA;")
+        .replace('\r', "&#xD;")
+}
+
+pub fn digest(method: &str, data: &[u8]) -> Result<Vec<u8>> {
+    match method {
+        DIGEST_SHA1 => Ok(sha1::Sha1::digest(data).to_vec()),
+        DIGEST_SHA256 => Ok(sha2::Sha256::digest(data).to_vec()),
+        other => bail!("unsupported digest method '{other}'"),
+    }
+}
+
+fn verify(method: &str, key: &RsaPublicKey, data: &[u8], signature: &[u8]) -> Result<bool> {
+    let signature = Signature::try_from(signature)?;
+
+    Ok(match method {
+        SIGNATURE_RSA_SHA1 => VerifyingKey::<sha1::Sha1>::new(key.clone())
+            .verify(data, &signature)
+            .is_ok(),
+        SIGNATURE_RSA_SHA256 => VerifyingKey::<sha2::Sha256>::new(key.clone())
+            .verify(data, &signature)
+            .is_ok(),
+        other => bail!("unsupported signature method '{other}'"),
+    })
+}
+
+/// extracts the RSA public key from a DER-encoded X.509 certificate
+///
+/// # Errors
+/// * the certificate can't be parsed, or doesn't contain an RSA key
+pub fn public_key_from_certificate(der: &[u8]) -> Result<RsaPublicKey> {
+    use x509_cert::der::{Decode, Encode};
+
+    let certificate = x509_cert::Certificate::from_der(der)?;
+    let spki = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()?;
+
+    Ok(RsaPublicKey::from_public_key_der(&spki)?)
+}
+
+/// decodes base64 text content, ignoring any whitespace/line breaks
+///
+/// # Errors
+/// * the node is missing or doesn't contain valid base64
+pub fn decode_base64_text(node: Option<Node>) -> Result<Vec<u8>> {
+    let node = node.ok_or_else(|| anyhow!("missing base64 value"))?;
+    let text = node
+        .text()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+
+    Ok(BASE64.decode(text)?)
+}
+
+fn algorithm<'a>(node: Option<Node<'a, '_>>) -> Result<&'a str> {
+    node.and_then(|n| n.attribute("Algorithm"))
+        .ok_or_else(|| anyhow!("missing Algorithm"))
+}
+
+pub fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| is_element(*n, namespace, name))
+}
+
+pub fn is_element(node: Node, namespace: &str, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().namespace() == Some(namespace)
+        && node.tag_name().name() == name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonicalizes_with_exclusive_namespaces() {
+        let xml = r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="r1"><saml:Assertion   Version="2.0" ID="a1" xmlns:unused="urn:unused"><saml:Issuer>idp &amp; co</saml:Issuer><saml:Subject/></saml:Assertion></samlp:Response>"#;
+        let doc = roxmltree::Document::parse(xml).unwrap();
+        let assertion = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "Assertion")
+            .unwrap();
+
+        assert_eq!(
+            canonicalize(assertion, None, &[], false),
+            r#"<saml:Assertion xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="a1" Version="2.0"><saml:Issuer>idp &amp; co</saml:Issuer><saml:Subject></saml:Subject></saml:Assertion>"#
+        );
+    }
+
+    #[test]
+    fn canonicalizes_inclusive_prefixes_and_excludes_signature() {
+        let xml = r#"<a:root xmlns:a="urn:a" xmlns:xs="urn:xs" xmlns:ds="http://www.w3.org/2000/09/xmldsig#"><ds:Signature><ds:SignedInfo/></ds:Signature><a:value b="1" a="2">x</a:value></a:root>"#;
+        let doc = roxmltree::Document::parse(xml).unwrap();
+        let root = doc.root_element();
+        let signature = child(root, DSIG_NS, "Signature").unwrap();
+
+        assert_eq!(
+            canonicalize(root, Some(signature.id()), &["xs".to_string()], false),
+            r#"<a:root xmlns:a="urn:a" xmlns:xs="urn:xs"><a:value a="2" b="1">x</a:value></a:root>"#
+        );
+    }
+}
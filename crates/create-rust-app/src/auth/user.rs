@@ -26,7 +26,7 @@ pub struct User {
     pub activated: bool,
 
     pub created_at: Utc,
-    #[cfg(not(feature = "database_sqlite"))]
+    #[cfg(not(any(feature = "database_sqlite", feature = "database_any")))]
     pub updated_at: Utc,
 }
 
@@ -53,7 +53,14 @@ impl User {
     pub fn create(db: &mut Connection, item: &UserChangeset) -> QueryResult<Self> {
         use super::schema::users::dsl::users;
 
-        insert_into(users).values(item).get_result::<Self>(db)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(users).values(item).get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(users).values(item).execute(db)?;
+            Self::read(db, crate::database::last_insert_id(db)?)
+        }
     }
 
     /// Read from [`db`](`Connection`), querying for an entry in the `users`
@@ -81,6 +88,8 @@ impl User {
     /// Read from [`db`](`Connection`), return entries of the `users` table,
     /// paginated according to [`pagination`](`PaginationParams`)
     ///
+    /// read-only, so `db` can be a replica's, see [`Database::get_read_connection`](`crate::Database::get_read_connection`)
+    ///
     /// # Errors
     /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
     pub fn read_all(db: &mut Connection, pagination: &PaginationParams) -> QueryResult<Vec<Self>> {
@@ -107,9 +116,18 @@ impl User {
     pub fn update(db: &mut Connection, item_id: ID, item: &UserChangeset) -> QueryResult<Self> {
         use super::schema::users::dsl::{id, users};
 
-        diesel::update(users.filter(id.eq(item_id)))
+        #[cfg(not(feature = "database_mysql"))]
+        return diesel::update(users.filter(id.eq(item_id)))
             .set(item)
-            .get_result(db)
+            .get_result(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            diesel::update(users.filter(id.eq(item_id)))
+                .set(item)
+                .execute(db)?;
+            Self::read(db, item_id)
+        }
     }
 
     /// Delete the entry in [`db`](`Connection`)'s `users` table who's
@@ -123,3 +141,122 @@ impl User {
         diesel::delete(users.filter(id.eq(item_id))).execute(db)
     }
 }
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::{insert_into, ExpressionMethods, QueryDsl, QueryResult};
+    use diesel_async::RunQueryDsl;
+
+    use super::{PaginationParams, User, UserChangeset, ID};
+    use crate::database::AsyncConnection;
+
+    /// the queries of [`User`], on an [`AsyncConnection`]
+    impl User {
+        /// like [`User::create`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn create_async(
+            db: &mut AsyncConnection,
+            item: &UserChangeset,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::users::dsl::users;
+
+            #[cfg(not(feature = "database_mysql"))]
+            return insert_into(users).values(item).get_result::<Self>(db).await;
+
+            #[cfg(feature = "database_mysql")]
+            {
+                insert_into(users).values(item).execute(db).await?;
+                let item_id = crate::database::last_insert_id_async(db).await?;
+                Self::read_async(db, item_id).await
+            }
+        }
+
+        /// like [`User::read`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_async(db: &mut AsyncConnection, item_id: ID) -> QueryResult<Self> {
+            use crate::auth::schema::users::dsl::{id, users};
+
+            users.filter(id.eq(item_id)).first::<Self>(db).await
+        }
+
+        /// like [`User::find_by_email`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn find_by_email_async(
+            db: &mut AsyncConnection,
+            item_email: String,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::users::dsl::{email, users};
+
+            users.filter(email.eq(item_email)).first::<Self>(db).await
+        }
+
+        /// like [`User::read_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_all_async(
+            db: &mut AsyncConnection,
+            pagination: &PaginationParams,
+        ) -> QueryResult<Vec<Self>> {
+            use crate::auth::schema::users::dsl::{created_at, users};
+
+            users
+                .order(created_at)
+                .limit(pagination.page_size)
+                .offset(
+                    pagination.page
+                        * std::cmp::max(
+                            pagination.page_size,
+                            i64::from(PaginationParams::MAX_PAGE_SIZE),
+                        ),
+                )
+                .load::<Self>(db)
+                .await
+        }
+
+        /// like [`User::update`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn update_async(
+            db: &mut AsyncConnection,
+            item_id: ID,
+            item: &UserChangeset,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::users::dsl::{id, users};
+
+            #[cfg(not(feature = "database_mysql"))]
+            return diesel::update(users.filter(id.eq(item_id)))
+                .set(item)
+                .get_result(db)
+                .await;
+
+            #[cfg(feature = "database_mysql")]
+            {
+                diesel::update(users.filter(id.eq(item_id)))
+                    .set(item)
+                    .execute(db)
+                    .await?;
+                Self::read_async(db, item_id).await
+            }
+        }
+
+        /// like [`User::delete`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_async(db: &mut AsyncConnection, item_id: ID) -> QueryResult<usize> {
+            use crate::auth::schema::users::dsl::{id, users};
+
+            diesel::delete(users.filter(id.eq(item_id)))
+                .execute(db)
+                .await
+        }
+    }
+}
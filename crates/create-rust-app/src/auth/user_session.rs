@@ -35,7 +35,7 @@ pub struct UserSession {
     pub device: Option<String>,
 
     pub created_at: Utc,
-    #[cfg(not(feature = "database_sqlite"))]
+    #[cfg(not(any(feature = "database_sqlite", feature = "database_any")))]
     pub updated_at: Utc,
 }
 
@@ -62,9 +62,16 @@ impl UserSession {
     pub fn create(db: &mut Connection, item: &UserSessionChangeset) -> QueryResult<Self> {
         use super::schema::user_sessions::dsl::user_sessions;
 
-        insert_into(user_sessions)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(user_sessions)
             .values(item)
-            .get_result::<Self>(db)
+            .get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(user_sessions).values(item).execute(db)?;
+            Self::read(db, crate::database::last_insert_id(db)?)
+        }
     }
 
     /// Read from [`db`](`Connection`), querying for an entry in the `user_sessions`
@@ -97,6 +104,8 @@ impl UserSession {
     /// Read from [`db`](`Connection`), return entries of the `user_sessions` table,
     /// paginated according to [`pagination`](`PaginationParams`)
     ///
+    /// read-only, so `db` can be a replica's, see [`Database::get_read_connection`](`crate::Database::get_read_connection`)
+    ///
     /// # Errors
     /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
     pub fn read_all(
@@ -146,9 +155,18 @@ impl UserSession {
     ) -> QueryResult<Self> {
         use super::schema::user_sessions::dsl::{id, user_sessions};
 
-        diesel::update(user_sessions.filter(id.eq(item_id)))
+        #[cfg(not(feature = "database_mysql"))]
+        return diesel::update(user_sessions.filter(id.eq(item_id)))
             .set(item)
-            .get_result(db)
+            .get_result(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            diesel::update(user_sessions.filter(id.eq(item_id)))
+                .set(item)
+                .execute(db)?;
+            Self::read(db, item_id)
+        }
     }
 
     /// Delete the entry in [`db`](`Connection`)'s `user_sessions` table who's
@@ -173,3 +191,162 @@ impl UserSession {
         diesel::delete(user_sessions.filter(user_id.eq(item_user_id))).execute(db)
     }
 }
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::{insert_into, ExpressionMethods, QueryDsl, QueryResult};
+    use diesel_async::RunQueryDsl;
+
+    use super::{PaginationParams, UserSession, UserSessionChangeset, ID};
+    use crate::database::AsyncConnection;
+
+    /// the queries of [`UserSession`], on an [`AsyncConnection`]
+    impl UserSession {
+        /// like [`UserSession::create`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn create_async(
+            db: &mut AsyncConnection,
+            item: &UserSessionChangeset,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::user_sessions::dsl::user_sessions;
+
+            #[cfg(not(feature = "database_mysql"))]
+            return insert_into(user_sessions)
+                .values(item)
+                .get_result::<Self>(db)
+                .await;
+
+            #[cfg(feature = "database_mysql")]
+            {
+                insert_into(user_sessions).values(item).execute(db).await?;
+                let item_id = crate::database::last_insert_id_async(db).await?;
+                Self::read_async(db, item_id).await
+            }
+        }
+
+        /// like [`UserSession::read`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_async(db: &mut AsyncConnection, item_id: ID) -> QueryResult<Self> {
+            use crate::auth::schema::user_sessions::dsl::{id, user_sessions};
+
+            user_sessions.filter(id.eq(item_id)).first::<Self>(db).await
+        }
+
+        /// like [`UserSession::find_by_refresh_token`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn find_by_refresh_token_async(
+            db: &mut AsyncConnection,
+            item_refresh_token: &str,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::user_sessions::dsl::{refresh_token, user_sessions};
+
+            user_sessions
+                .filter(refresh_token.eq(item_refresh_token))
+                .first::<Self>(db)
+                .await
+        }
+
+        /// like [`UserSession::read_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn read_all_async(
+            db: &mut AsyncConnection,
+            pagination: &PaginationParams,
+            item_user_id: ID,
+        ) -> QueryResult<Vec<Self>> {
+            use crate::auth::schema::user_sessions::dsl::{created_at, user_id, user_sessions};
+
+            user_sessions
+                .filter(user_id.eq(item_user_id))
+                .order(created_at)
+                .limit(pagination.page_size)
+                .offset(
+                    pagination.page
+                        * std::cmp::min(
+                            pagination.page_size,
+                            i64::from(PaginationParams::MAX_PAGE_SIZE),
+                        ),
+                )
+                .load::<Self>(db)
+                .await
+        }
+
+        /// like [`UserSession::count_all`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn count_all_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+        ) -> QueryResult<i64> {
+            use crate::auth::schema::user_sessions::dsl::{user_id, user_sessions};
+
+            user_sessions
+                .filter(user_id.eq(item_user_id))
+                .count()
+                .get_result(db)
+                .await
+        }
+
+        /// like [`UserSession::update`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn update_async(
+            db: &mut AsyncConnection,
+            item_id: ID,
+            item: &UserSessionChangeset,
+        ) -> QueryResult<Self> {
+            use crate::auth::schema::user_sessions::dsl::{id, user_sessions};
+
+            #[cfg(not(feature = "database_mysql"))]
+            return diesel::update(user_sessions.filter(id.eq(item_id)))
+                .set(item)
+                .get_result(db)
+                .await;
+
+            #[cfg(feature = "database_mysql")]
+            {
+                diesel::update(user_sessions.filter(id.eq(item_id)))
+                    .set(item)
+                    .execute(db)
+                    .await?;
+                Self::read_async(db, item_id).await
+            }
+        }
+
+        /// like [`UserSession::delete`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_async(db: &mut AsyncConnection, item_id: ID) -> QueryResult<usize> {
+            use crate::auth::schema::user_sessions::dsl::{id, user_sessions};
+
+            diesel::delete(user_sessions.filter(id.eq(item_id)))
+                .execute(db)
+                .await
+        }
+
+        /// like [`UserSession::delete_all_for_user`]
+        ///
+        /// # Errors
+        /// * [`diesel::result::Error`](`diesel::result::Error`) if the query fails
+        pub async fn delete_all_for_user_async(
+            db: &mut AsyncConnection,
+            item_user_id: ID,
+        ) -> QueryResult<usize> {
+            use crate::auth::schema::user_sessions::dsl::{user_id, user_sessions};
+
+            diesel::delete(user_sessions.filter(user_id.eq(item_user_id)))
+                .execute(db)
+                .await
+        }
+    }
+}
@@ -0,0 +1,24 @@
+/// The connection of the `database_any` feature, chosen at runtime from the `DATABASE_URL`:
+/// `postgres://` (or `postgresql://`) urls connect to Postgres, anything else is the path of a SQLite database
+///
+/// the models work on both, with the SQLite schemas: `updated_at` columns aren't read, and the timestamps
+/// are `chrono::NaiveDateTime`s (in UTC). Match on the connection for backend-specific queries.
+#[derive(diesel::MultiConnection)]
+pub enum AnyConnection {
+    // diesel tries the variants in order, and SQLite accepts any path, so it goes last
+    Postgresql(diesel::PgConnection),
+    Sqlite(diesel::SqliteConnection),
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::Connection;
+
+    use super::AnyConnection;
+
+    #[test]
+    fn connects_to_sqlite_paths() {
+        let connection = AnyConnection::establish(":memory:").unwrap();
+        assert!(matches!(connection, AnyConnection::Sqlite(_)));
+    }
+}
@@ -0,0 +1,64 @@
+use diesel::ConnectionError;
+use diesel_async::pooled_connection::{
+    AsyncDieselConnectionManager, ManagerConfig, RecyclingMethod,
+};
+use diesel_async::AsyncConnection as _;
+use futures_util::FutureExt;
+
+use super::DatabaseConfig;
+
+#[cfg(feature = "database_postgres")]
+type AsyncDbCon = diesel_async::AsyncPgConnection;
+
+#[cfg(feature = "database_sqlite")]
+type AsyncDbCon =
+    diesel_async::sync_connection_wrapper::SyncConnectionWrapper<diesel::SqliteConnection>;
+
+#[cfg(feature = "database_mysql")]
+type AsyncDbCon = diesel_async::AsyncMysqlConnection;
+
+/// the `database_async` pool, see [`Database::get_async_connection`](`super::Database::get_async_connection`)
+pub type AsyncPool = bb8::Pool<AsyncDieselConnectionManager<AsyncDbCon>>;
+/// a connection for `diesel_async::RunQueryDsl`, like `users.load(&mut db).await`
+pub type AsyncConnection = bb8::PooledConnection<'static, AsyncDieselConnectionManager<AsyncDbCon>>;
+
+/// a pool with the settings of `config`'s primary; it connects in the background, when it's first used
+///
+/// must be called within a tokio runtime
+pub(super) fn build(config: &DatabaseConfig) -> AsyncPool {
+    let statements = config.session_statements();
+
+    let mut manager_config = ManagerConfig::<AsyncDbCon>::default();
+    manager_config.recycling_method = if config.test_on_checkout {
+        RecyclingMethod::Verified
+    } else {
+        RecyclingMethod::Fast
+    };
+    manager_config.custom_setup = Box::new(move |url| {
+        let statements = statements.clone();
+        async move {
+            let mut connection = AsyncDbCon::establish(url).await?;
+            if !statements.is_empty() {
+                use diesel_async::SimpleAsyncConnection;
+                connection
+                    .batch_execute(&statements)
+                    .await
+                    .map_err(ConnectionError::CouldntSetupConfiguration)?;
+            }
+            Ok(connection)
+        }
+        .boxed()
+    });
+
+    bb8::Pool::builder()
+        .max_size(config.max_connections)
+        .min_idle(config.min_connections)
+        .connection_timeout(config.connection_timeout)
+        .idle_timeout(config.idle_timeout)
+        .max_lifetime(config.max_lifetime)
+        .test_on_check_out(config.test_on_checkout)
+        .build_unchecked(AsyncDieselConnectionManager::new_with_config(
+            &config.url,
+            manager_config,
+        ))
+}
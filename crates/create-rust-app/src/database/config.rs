@@ -0,0 +1,370 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(any(feature = "database_postgres", feature = "database_any"))]
+use diesel::connection::SimpleConnection;
+use diesel::r2d2::CustomizeConnection;
+
+use super::query_log::QueryLogger;
+use super::{DatabaseError, DbCon};
+
+/// Settings for the connection pool of a [`Database`](`super::Database`)
+///
+/// Start from [`DatabaseConfig::from_env`] and override what a binary needs:
+///
+/// ```rust,ignore
+/// let db = Database::try_new(&DatabaseConfig {
+///     max_connections: 2,
+///     application_name: Some("queue".to_string()),
+///     ..DatabaseConfig::from_env()?
+/// })?;
+/// ```
+#[derive(Clone)]
+pub struct DatabaseConfig {
+    /// the connection url, `DATABASE_URL`
+    pub url: String,
+    /// the read replicas, for [`Database::get_read_connection`](`super::Database::get_read_connection`);
+    /// each gets a pool with the same settings
+    pub replica_urls: Vec<String>,
+    /// the size of the pool (default: 10)
+    pub max_connections: u32,
+    /// how many idle connections the pool keeps open; `max_connections` if unset
+    pub min_connections: Option<u32>,
+    /// how long getting a connection waits before failing (default: 5s)
+    pub connection_timeout: Duration,
+    /// idle connections are closed after this long (default: 10 minutes); never if unset
+    pub idle_timeout: Option<Duration>,
+    /// connections are closed after this long (default: 30 minutes); never if unset
+    pub max_lifetime: Option<Duration>,
+    /// whether connections are checked before being handed out (default: true)
+    pub test_on_checkout: bool,
+    /// queries running longer are cancelled; Postgres only
+    pub statement_timeout: Option<Duration>,
+    /// identifies the connections in `pg_stat_activity`; Postgres only
+    pub application_name: Option<String>,
+    /// queries running longer are logged as warnings (default: 1s); never if unset
+    pub slow_query_threshold: Option<Duration>,
+    /// whether every query is logged, at the debug level (default: in debug builds)
+    pub log_queries: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            replica_urls: vec![],
+            max_connections: 10,
+            min_connections: None,
+            connection_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            test_on_checkout: true,
+            statement_timeout: None,
+            application_name: None,
+            slow_query_threshold: Some(Duration::from_secs(1)),
+            log_queries: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl std::fmt::Debug for DatabaseConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatabaseConfig")
+            .field("url", &"<redacted>")
+            .field("replicas", &self.replica_urls.len())
+            .field("max_connections", &self.max_connections)
+            .field("min_connections", &self.min_connections)
+            .field("connection_timeout", &self.connection_timeout)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("max_lifetime", &self.max_lifetime)
+            .field("test_on_checkout", &self.test_on_checkout)
+            .field("statement_timeout", &self.statement_timeout)
+            .field("application_name", &self.application_name)
+            .field("slow_query_threshold", &self.slow_query_threshold)
+            .field("log_queries", &self.log_queries)
+            .finish()
+    }
+}
+
+impl DatabaseConfig {
+    /// the defaults, for the database at `url`
+    #[must_use]
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Self::default()
+        }
+    }
+
+    /// reads the environment variables (the defaults are used for the unset ones):
+    ///
+    /// * `DATABASE_URL` (required)
+    /// * `DATABASE_REPLICA_URLS`, comma separated
+    /// * `DATABASE_MAX_CONNECTIONS`, `DATABASE_MIN_CONNECTIONS`
+    /// * `DATABASE_CONNECTION_TIMEOUT`, `DATABASE_IDLE_TIMEOUT`, `DATABASE_MAX_LIFETIME`, in seconds;
+    ///   `0` disables the idle timeout and the max lifetime
+    /// * `DATABASE_TEST_ON_CHECKOUT`, `true` or `false`
+    /// * `DATABASE_STATEMENT_TIMEOUT_MS`, in milliseconds
+    /// * `DATABASE_APPLICATION_NAME`
+    /// * `DATABASE_SLOW_QUERY_MS`, in milliseconds; `0` disables the slow query log
+    /// * `DATABASE_LOG_QUERIES`, `true` or `false`
+    ///
+    /// # Errors
+    /// * [`DatabaseError::Config`] if `DATABASE_URL` is not set, or a variable can't be parsed
+    pub fn from_env() -> Result<Self, DatabaseError> {
+        #[cfg(debug_assertions)]
+        crate::load_env_vars();
+
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, DatabaseError> {
+        let defaults = Self::default();
+        let seconds = |name: &str, default: Option<Duration>| {
+            Ok::<_, DatabaseError>(match parse_var::<u64>(&var, name)? {
+                Some(0) => None,
+                Some(seconds) => Some(Duration::from_secs(seconds)),
+                None => default,
+            })
+        };
+
+        let url = var("DATABASE_URL")
+            .filter(|url| !url.is_empty())
+            .ok_or_else(|| {
+                DatabaseError::Config("No DATABASE_URL environment variable set".to_string())
+            })?;
+
+        let replica_urls = var("DATABASE_REPLICA_URLS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(ToString::to_string)
+            .collect();
+
+        Ok(Self {
+            url,
+            replica_urls,
+            max_connections: parse_var(&var, "DATABASE_MAX_CONNECTIONS")?
+                .unwrap_or(defaults.max_connections),
+            min_connections: parse_var(&var, "DATABASE_MIN_CONNECTIONS")?,
+            connection_timeout: parse_var(&var, "DATABASE_CONNECTION_TIMEOUT")?
+                .map_or(defaults.connection_timeout, Duration::from_secs),
+            idle_timeout: seconds("DATABASE_IDLE_TIMEOUT", defaults.idle_timeout)?,
+            max_lifetime: seconds("DATABASE_MAX_LIFETIME", defaults.max_lifetime)?,
+            test_on_checkout: parse_var(&var, "DATABASE_TEST_ON_CHECKOUT")?
+                .unwrap_or(defaults.test_on_checkout),
+            statement_timeout: parse_var::<u64>(&var, "DATABASE_STATEMENT_TIMEOUT_MS")?
+                .filter(|millis| *millis > 0)
+                .map(Duration::from_millis),
+            application_name: var("DATABASE_APPLICATION_NAME").filter(|name| !name.is_empty()),
+            slow_query_threshold: match parse_var::<u64>(&var, "DATABASE_SLOW_QUERY_MS")? {
+                Some(0) => None,
+                Some(millis) => Some(Duration::from_millis(millis)),
+                None => defaults.slow_query_threshold,
+            },
+            log_queries: parse_var(&var, "DATABASE_LOG_QUERIES")?.unwrap_or(defaults.log_queries),
+        })
+    }
+
+    /// # Errors
+    /// * [`DatabaseError::Config`] if the pool can't be built with these settings
+    pub(super) fn validate(&self) -> Result<(), DatabaseError> {
+        if self.url.is_empty() {
+            return Err(DatabaseError::Config(
+                "The database url is empty".to_string(),
+            ));
+        }
+        if self.max_connections == 0 {
+            return Err(DatabaseError::Config(
+                "The database pool needs at least one connection".to_string(),
+            ));
+        }
+        if self
+            .min_connections
+            .is_some_and(|min| min > self.max_connections)
+        {
+            return Err(DatabaseError::Config(format!(
+                "The database pool's min_connections is larger than its max_connections ({})",
+                self.max_connections
+            )));
+        }
+        if self.connection_timeout.is_zero() {
+            return Err(DatabaseError::Config(
+                "The database connection timeout must be positive".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// the statements run on each new connection, for the settings the pool doesn't handle itself
+    #[cfg(any(feature = "database_postgres", feature = "database_any"))]
+    pub(super) fn session_statements(&self) -> String {
+        let mut statements = String::new();
+        if let Some(timeout) = self.statement_timeout {
+            statements.push_str(&format!("SET statement_timeout = {};", timeout.as_millis()));
+        }
+        if let Some(name) = &self.application_name {
+            statements.push_str(&format!(
+                "SET application_name = '{}';",
+                name.replace('\'', "''")
+            ));
+        }
+        statements
+    }
+
+    #[cfg(not(any(feature = "database_postgres", feature = "database_any")))]
+    #[allow(clippy::unused_self)]
+    pub(super) fn session_statements(&self) -> String {
+        String::new()
+    }
+
+    /// the hook applying [`Self::session_statements`] and setting up the query log, if there's anything to do
+    pub(super) fn customizer(
+        &self,
+    ) -> Option<Box<dyn CustomizeConnection<DbCon, diesel::r2d2::Error>>> {
+        let setup = ConnectionSetup {
+            statements: self.session_statements(),
+            logger: QueryLogger::new(self.log_queries, self.slow_query_threshold),
+        };
+        if setup.statements.is_empty() && setup.logger.is_none() {
+            None
+        } else {
+            Some(Box::new(setup))
+        }
+    }
+}
+
+fn parse_var<T: FromStr>(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+) -> Result<Option<T>, DatabaseError>
+where
+    T::Err: std::fmt::Display,
+{
+    var(name)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .trim()
+                .parse::<T>()
+                .map_err(|err| DatabaseError::Config(format!("Invalid {name} '{value}' ({err})")))
+        })
+        .transpose()
+}
+
+#[derive(Debug)]
+struct ConnectionSetup {
+    #[cfg_attr(
+        not(any(feature = "database_postgres", feature = "database_any")),
+        allow(dead_code)
+    )]
+    statements: String,
+    logger: Option<QueryLogger>,
+}
+
+impl ConnectionSetup {
+    #[cfg(feature = "database_postgres")]
+    fn apply_statements(&self, conn: &mut DbCon) -> Result<(), diesel::r2d2::Error> {
+        if self.statements.is_empty() {
+            return Ok(());
+        }
+        conn.batch_execute(&self.statements)
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+
+    /// the settings are Postgres' own, so SQLite connections are left as they are
+    #[cfg(feature = "database_any")]
+    fn apply_statements(&self, conn: &mut DbCon) -> Result<(), diesel::r2d2::Error> {
+        match conn {
+            DbCon::Postgresql(conn) if !self.statements.is_empty() => conn
+                .batch_execute(&self.statements)
+                .map_err(diesel::r2d2::Error::QueryError),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(any(feature = "database_postgres", feature = "database_any")))]
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
+    fn apply_statements(&self, _conn: &mut DbCon) -> Result<(), diesel::r2d2::Error> {
+        Ok(())
+    }
+}
+
+impl CustomizeConnection<DbCon, diesel::r2d2::Error> for ConnectionSetup {
+    fn on_acquire(&self, conn: &mut DbCon) -> Result<(), diesel::r2d2::Error> {
+        self.apply_statements(conn)?;
+        if let Some(logger) = &self.logger {
+            diesel::Connection::set_instrumentation(conn, logger.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_pool_settings() {
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| (*value).to_string())
+            }
+        };
+
+        assert_eq!(
+            DatabaseConfig::from_vars(vars(&[])).unwrap_err(),
+            DatabaseError::Config("No DATABASE_URL environment variable set".to_string())
+        );
+
+        let config = DatabaseConfig::from_vars(vars(&[
+            ("DATABASE_URL", "postgres://localhost/app"),
+            (
+                "DATABASE_REPLICA_URLS",
+                "postgres://replica-1/app, postgres://replica-2/app",
+            ),
+            ("DATABASE_MAX_CONNECTIONS", "4"),
+            ("DATABASE_IDLE_TIMEOUT", "0"),
+            ("DATABASE_TEST_ON_CHECKOUT", "false"),
+            ("DATABASE_STATEMENT_TIMEOUT_MS", "1500"),
+            ("DATABASE_APPLICATION_NAME", "queue's worker"),
+            ("DATABASE_SLOW_QUERY_MS", "0"),
+            ("DATABASE_LOG_QUERIES", "true"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            config.replica_urls,
+            vec!["postgres://replica-1/app", "postgres://replica-2/app"]
+        );
+        assert_eq!(config.max_connections, 4);
+        assert_eq!(config.connection_timeout, Duration::from_secs(5));
+        assert_eq!(config.idle_timeout, None);
+        assert_eq!(config.max_lifetime, Some(Duration::from_secs(30 * 60)));
+        assert!(!config.test_on_checkout);
+        assert_eq!(config.slow_query_threshold, None);
+        assert!(config.log_queries);
+        assert!(config.validate().is_ok());
+        #[cfg(any(feature = "database_postgres", feature = "database_any"))]
+        assert_eq!(
+            config.session_statements(),
+            "SET statement_timeout = 1500;SET application_name = 'queue''s worker';"
+        );
+
+        assert!(matches!(
+            DatabaseConfig::from_vars(vars(&[
+                ("DATABASE_URL", "postgres://localhost/app"),
+                ("DATABASE_MAX_CONNECTIONS", "many"),
+            ])),
+            Err(DatabaseError::Config(_))
+        ));
+        let config = DatabaseConfig {
+            min_connections: Some(20),
+            ..DatabaseConfig::new("postgres://localhost/app")
+        };
+        assert!(matches!(config.validate(), Err(DatabaseError::Config(_))));
+    }
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+
+/// Why a [`Database`](`super::Database`) could not be created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// the [`DatabaseConfig`](`super::DatabaseConfig`) is invalid, like when `DATABASE_URL` is not set
+    Config(String),
+    /// the pool could not open its connections
+    Connection(String),
+    /// the migrations could not be read, locked or run, see [`Database::run_migrations`](`super::Database::run_migrations`)
+    Migration(String),
+}
+
+impl DatabaseError {
+    /// the description of the error
+    #[must_use]
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Config(message) | Self::Connection(message) | Self::Migration(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for DatabaseError {}
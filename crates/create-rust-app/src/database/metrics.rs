@@ -0,0 +1,166 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::r2d2::event::{CheckoutEvent, TimeoutEvent};
+use diesel::r2d2::HandleEvent;
+
+use super::Pool;
+
+/// The state of a [`Database`](`super::Database`)'s primary pool, see [`Database::pool_metrics`](`super::Database::pool_metrics`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// the size of the pool
+    pub max_connections: u32,
+    /// the open connections, idle or in use
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub in_use_connections: u32,
+    /// how many connections were handed out
+    pub checkouts: u64,
+    /// how many times getting a connection timed out
+    pub timeouts: u64,
+    /// the time spent waiting for the connections that were handed out
+    pub total_wait: Duration,
+    /// the longest wait for a connection
+    pub max_wait: Duration,
+}
+
+impl PoolMetrics {
+    /// the metrics in the Prometheus text format
+    #[must_use]
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, String)]| {
+            let _ = writeln!(text, "# HELP create_rust_app_db_pool_{name} {help}");
+            let _ = writeln!(text, "# TYPE create_rust_app_db_pool_{name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(text, "create_rust_app_db_pool_{name}{labels} {value}");
+            }
+        };
+
+        metric(
+            "max_connections",
+            "gauge",
+            "The size of the database pool.",
+            &[("", self.max_connections.to_string())],
+        );
+        metric(
+            "connections",
+            "gauge",
+            "The open database connections.",
+            &[
+                ("{state=\"idle\"}", self.idle_connections.to_string()),
+                ("{state=\"in_use\"}", self.in_use_connections.to_string()),
+            ],
+        );
+        metric(
+            "checkouts_total",
+            "counter",
+            "The connections handed out by the pool.",
+            &[("", self.checkouts.to_string())],
+        );
+        metric(
+            "timeouts_total",
+            "counter",
+            "The times getting a connection timed out.",
+            &[("", self.timeouts.to_string())],
+        );
+        metric(
+            "wait_seconds_total",
+            "counter",
+            "The time spent waiting for connections.",
+            &[("", self.total_wait.as_secs_f64().to_string())],
+        );
+        metric(
+            "wait_seconds_max",
+            "gauge",
+            "The longest wait for a connection.",
+            &[("", self.max_wait.as_secs_f64().to_string())],
+        );
+
+        text
+    }
+}
+
+/// the counters of a pool, updated by its event handler ([`PoolStats::handler`])
+#[derive(Debug, Default)]
+pub(super) struct PoolStats {
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+    total_wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+impl PoolStats {
+    pub(super) fn handler(self: &Arc<Self>) -> Box<dyn HandleEvent> {
+        Box::new(StatsHandler(Arc::clone(self)))
+    }
+
+    pub(super) fn metrics(&self, pool: &Pool) -> PoolMetrics {
+        let state = pool.state();
+
+        PoolMetrics {
+            max_connections: pool.max_size(),
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            in_use_connections: state.connections - state.idle_connections,
+            checkouts: self.checkouts.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(self.total_wait_micros.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(self.max_wait_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn record_checkout(&self, wait: Duration) {
+        let micros = u64::try_from(wait.as_micros()).unwrap_or(u64::MAX);
+
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.total_wait_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_wait_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+struct StatsHandler(Arc<PoolStats>);
+
+impl HandleEvent for StatsHandler {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        self.0.record_checkout(event.duration());
+    }
+
+    fn handle_timeout(&self, _event: TimeoutEvent) {
+        self.0.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_the_metrics_for_prometheus() {
+        let stats = PoolStats::default();
+        stats.record_checkout(Duration::from_millis(3));
+        stats.record_checkout(Duration::from_millis(1));
+
+        let metrics = PoolMetrics {
+            max_connections: 10,
+            connections: 4,
+            idle_connections: 3,
+            in_use_connections: 1,
+            checkouts: stats.checkouts.load(Ordering::Relaxed),
+            timeouts: 0,
+            total_wait: Duration::from_micros(stats.total_wait_micros.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(stats.max_wait_micros.load(Ordering::Relaxed)),
+        };
+        let text = metrics.to_prometheus();
+
+        assert!(text.contains("create_rust_app_db_pool_connections{state=\"in_use\"} 1\n"));
+        assert!(text.contains("create_rust_app_db_pool_checkouts_total 2\n"));
+        assert!(text.contains("create_rust_app_db_pool_wait_seconds_total 0.004\n"));
+        assert!(text.contains("create_rust_app_db_pool_wait_seconds_max 0.003\n"));
+        assert!(text.contains("# TYPE create_rust_app_db_pool_timeouts_total counter\n"));
+    }
+}
@@ -0,0 +1,507 @@
+use diesel::migration::{MigrationSource, MigrationVersion};
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Serialize;
+
+use super::{Database, DatabaseError, DbCon};
+
+type Backend = <DbCon as diesel::Connection>::Backend;
+type PoolConnection = PooledConnection<ConnectionManager<DbCon>>;
+type Sources = Vec<(&'static str, &'static EmbeddedMigrations)>;
+
+/// the key of the lock held while migrating, so replicas starting together don't race
+#[cfg(not(feature = "database_sqlite"))]
+const LOCK_NAME: &str = "create_rust_app_migrations";
+
+// the mailer's and the plugins' tables are created `IF NOT EXISTS`, so apps generated by the CLI (whose own
+// migrations create them) can run these too
+// the mailer's outbox and suppressions, which every app has
+#[cfg(any(feature = "database_postgres", feature = "database_any"))]
+static POSTGRES_MAILER: EmbeddedMigrations = embed_migrations!("migrations/postgres/mailer");
+#[cfg(all(
+    feature = "plugin_auth",
+    any(feature = "database_postgres", feature = "database_any")
+))]
+static POSTGRES_AUTH: EmbeddedMigrations = embed_migrations!("migrations/postgres/auth");
+#[cfg(all(
+    feature = "plugin_auth-oidc",
+    any(feature = "database_postgres", feature = "database_any")
+))]
+static POSTGRES_AUTH_OIDC: EmbeddedMigrations = embed_migrations!("migrations/postgres/auth_oidc");
+#[cfg(all(
+    feature = "plugin_auth-saml",
+    any(feature = "database_postgres", feature = "database_any")
+))]
+static POSTGRES_AUTH_SAML: EmbeddedMigrations = embed_migrations!("migrations/postgres/auth_saml");
+#[cfg(all(
+    feature = "plugin_storage",
+    any(feature = "database_postgres", feature = "database_any")
+))]
+static POSTGRES_STORAGE: EmbeddedMigrations = embed_migrations!("migrations/postgres/storage");
+#[cfg(all(
+    feature = "plugin_tasks",
+    any(feature = "database_postgres", feature = "database_any")
+))]
+static POSTGRES_TASKS: EmbeddedMigrations = embed_migrations!("migrations/postgres/tasks");
+
+#[cfg(any(feature = "database_sqlite", feature = "database_any"))]
+static SQLITE_MAILER: EmbeddedMigrations = embed_migrations!("migrations/sqlite/mailer");
+#[cfg(all(
+    feature = "plugin_auth",
+    any(feature = "database_sqlite", feature = "database_any")
+))]
+static SQLITE_AUTH: EmbeddedMigrations = embed_migrations!("migrations/sqlite/auth");
+#[cfg(all(
+    feature = "plugin_auth-oidc",
+    any(feature = "database_sqlite", feature = "database_any")
+))]
+static SQLITE_AUTH_OIDC: EmbeddedMigrations = embed_migrations!("migrations/sqlite/auth_oidc");
+#[cfg(all(
+    feature = "plugin_auth-saml",
+    any(feature = "database_sqlite", feature = "database_any")
+))]
+static SQLITE_AUTH_SAML: EmbeddedMigrations = embed_migrations!("migrations/sqlite/auth_saml");
+#[cfg(all(
+    feature = "plugin_storage",
+    any(feature = "database_sqlite", feature = "database_any")
+))]
+static SQLITE_STORAGE: EmbeddedMigrations = embed_migrations!("migrations/sqlite/storage");
+
+/// SQLite can't `ADD COLUMN IF NOT EXISTS`: the plugins' migrations which add columns (by version), with
+/// the table and the columns they add; they're recorded as applied without running when the table
+/// already has the columns, like in apps generated by the CLI
+#[cfg(any(feature = "database_sqlite", feature = "database_any"))]
+const SQLITE_ADDED_COLUMNS: &[(&str, &str, &[&str])] = &[(
+    "20000101000410",
+    "attachment_blobs",
+    &["sha256", "encryption", "encryption_key"],
+)];
+
+#[cfg(feature = "database_mysql")]
+static MYSQL_MAILER: EmbeddedMigrations = embed_migrations!("migrations/mysql/mailer");
+#[cfg(all(feature = "plugin_auth", feature = "database_mysql"))]
+static MYSQL_AUTH: EmbeddedMigrations = embed_migrations!("migrations/mysql/auth");
+#[cfg(all(feature = "plugin_auth-oidc", feature = "database_mysql"))]
+static MYSQL_AUTH_OIDC: EmbeddedMigrations = embed_migrations!("migrations/mysql/auth_oidc");
+#[cfg(all(feature = "plugin_auth-saml", feature = "database_mysql"))]
+static MYSQL_AUTH_SAML: EmbeddedMigrations = embed_migrations!("migrations/mysql/auth_saml");
+#[cfg(all(feature = "plugin_storage", feature = "database_mysql"))]
+static MYSQL_STORAGE: EmbeddedMigrations = embed_migrations!("migrations/mysql/storage");
+
+/// a migration known to [`Database::migration_status`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationState {
+    /// where the migration comes from: `"app"`, `"mailer"`, or the plugin that embeds it, like
+    /// `"plugin_auth"`
+    pub source: &'static str,
+    /// the name of the migration's directory, like `2000-01-01-000100_create_rust_app_auth`
+    pub name: String,
+    pub version: String,
+    pub applied: bool,
+}
+
+impl Database {
+    /// runs the pending migrations: the mailer's and the enabled plugins' first, then the app's (`app`, from
+    /// `diesel_migrations::embed_migrations!`), so the app's can reference the plugins' tables; returns
+    /// the migrations that ran
+    ///
+    /// the migrations run while holding a lock (an advisory lock on Postgres, `GET_LOCK` on MySQL),
+    /// so replicas starting together apply them once; SQLite databases are local to one process,
+    /// and aren't locked
+    ///
+    /// # Errors
+    /// * [`DatabaseError::Connection`] if the pool can't give a connection
+    /// * [`DatabaseError::Migration`] if a migration (or taking the lock) fails; the migrations
+    ///   that ran before it stay applied
+    pub fn run_migrations(
+        &self,
+        app: Option<&'static EmbeddedMigrations>,
+    ) -> Result<Vec<MigrationState>, DatabaseError> {
+        let mut db = self.migration_connection()?;
+
+        lock(&mut db)?;
+        let ran = run_pending(&mut db, app);
+        let unlocked = unlock(&mut db);
+
+        let ran = ran?;
+        unlocked?;
+        Ok(ran)
+    }
+
+    /// every migration of the app (`app`) and of the enabled plugins, in the order
+    /// [`Database::run_migrations`] runs them, and whether it was applied
+    ///
+    /// # Errors
+    /// * [`DatabaseError::Connection`] if the pool can't give a connection
+    /// * [`DatabaseError::Migration`] if the applied migrations can't be read
+    pub fn migration_status(
+        &self,
+        app: Option<&'static EmbeddedMigrations>,
+    ) -> Result<Vec<MigrationState>, DatabaseError> {
+        let mut db = self.migration_connection()?;
+        let applied = applied_versions(&mut db)?;
+
+        let mut states = vec![];
+        for (source, migrations) in sources(&db, app) {
+            for (name, version) in names(migrations)? {
+                states.push(MigrationState {
+                    source,
+                    applied: applied.contains(&version),
+                    name,
+                    version,
+                });
+            }
+        }
+
+        Ok(states)
+    }
+
+    fn migration_connection(&self) -> Result<PoolConnection, DatabaseError> {
+        self.pool.get().map_err(|err| {
+            DatabaseError::Connection(format!("Could not connect to the database ({err})"))
+        })
+    }
+}
+
+fn run_pending(
+    db: &mut PoolConnection,
+    app: Option<&'static EmbeddedMigrations>,
+) -> Result<Vec<MigrationState>, DatabaseError> {
+    let applied = applied_versions(db)?;
+
+    let mut ran = vec![];
+    for (source, migrations) in sources(db, app) {
+        let mut pending = MigrationSource::<Backend>::migrations(migrations)
+            .map_err(migration_error)?
+            .into_iter()
+            .filter(|migration| !applied.contains(&migration.name().version().to_string()))
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|migration| migration.name().version().as_owned());
+
+        for migration in pending {
+            if skip_existing_columns(db, &migration.name().version().to_string())? {
+                continue;
+            }
+
+            db.run_migration(&*migration).map_err(|err| {
+                DatabaseError::Migration(format!(
+                    "Could not run the migration {} ({err})",
+                    migration.name()
+                ))
+            })?;
+            ran.push(MigrationState {
+                source,
+                name: migration.name().to_string(),
+                version: migration.name().version().to_string(),
+                applied: true,
+            });
+        }
+    }
+
+    Ok(ran)
+}
+
+/// records the migration `version` as applied without running it if it adds columns which the
+/// tables already have (see [`SQLITE_ADDED_COLUMNS`]); returns whether it did
+#[allow(unused_variables, clippy::unnecessary_wraps)]
+fn skip_existing_columns(db: &mut PoolConnection, version: &str) -> Result<bool, DatabaseError> {
+    #[cfg(feature = "database_sqlite")]
+    return sqlite_skip_existing_columns(db, version);
+
+    #[cfg(feature = "database_any")]
+    return match &mut **db {
+        DbCon::Sqlite(db) => sqlite_skip_existing_columns(db, version),
+        DbCon::Postgresql(_) => Ok(false),
+    };
+
+    #[cfg(not(any(feature = "database_sqlite", feature = "database_any")))]
+    Ok(false)
+}
+
+#[cfg(any(feature = "database_sqlite", feature = "database_any"))]
+fn sqlite_skip_existing_columns(
+    db: &mut diesel::SqliteConnection,
+    version: &str,
+) -> Result<bool, DatabaseError> {
+    use diesel::sql_types::{BigInt, Text};
+    use diesel::{QueryableByName, RunQueryDsl};
+
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+
+    let Some((_, table, columns)) = SQLITE_ADDED_COLUMNS
+        .iter()
+        .find(|(added_by, _, _)| *added_by == version)
+    else {
+        return Ok(false);
+    };
+
+    for column in *columns {
+        let Count { count } =
+            diesel::sql_query("SELECT COUNT(*) AS count FROM pragma_table_info(?) WHERE name = ?")
+                .bind::<Text, _>(table)
+                .bind::<Text, _>(column)
+                .get_result(db)
+                .map_err(|err| {
+                    DatabaseError::Migration(format!(
+                        "Could not read the columns of {table} ({err})"
+                    ))
+                })?;
+        if count == 0 {
+            return Ok(false);
+        }
+    }
+
+    diesel::sql_query("INSERT INTO __diesel_schema_migrations (version) VALUES (?)")
+        .bind::<Text, _>(version)
+        .execute(db)
+        .map_err(|err| {
+            DatabaseError::Migration(format!("Could not record the migration {version} ({err})"))
+        })?;
+
+    Ok(true)
+}
+
+/// the names and versions of `migrations`, sorted by version
+fn names(migrations: &EmbeddedMigrations) -> Result<Vec<(String, String)>, DatabaseError> {
+    let mut names = MigrationSource::<Backend>::migrations(migrations)
+        .map_err(migration_error)?
+        .iter()
+        .map(|migration| {
+            (
+                migration.name().to_string(),
+                migration.name().version().to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    names.sort_by(|(_, a), (_, b)| a.cmp(b));
+
+    Ok(names)
+}
+
+fn applied_versions(db: &mut PoolConnection) -> Result<Vec<String>, DatabaseError> {
+    Ok(db
+        .applied_migrations()
+        .map_err(migration_error)?
+        .iter()
+        .map(MigrationVersion::to_string)
+        .collect())
+}
+
+fn migration_error(err: Box<dyn std::error::Error + Send + Sync>) -> DatabaseError {
+    DatabaseError::Migration(format!("Could not read the migrations ({err})"))
+}
+
+/// the mailer's and the plugins' migrations, for the backend `db` is connected to, then the app's
+#[allow(unused_variables)]
+fn sources(db: &PoolConnection, app: Option<&'static EmbeddedMigrations>) -> Sources {
+    let mut sources: Sources = vec![];
+
+    #[cfg(feature = "database_postgres")]
+    sources.extend(postgres_sources());
+    #[cfg(feature = "database_sqlite")]
+    sources.extend(sqlite_sources());
+    #[cfg(feature = "database_mysql")]
+    sources.extend(mysql_sources());
+    #[cfg(feature = "database_any")]
+    sources.extend(match **db {
+        DbCon::Postgresql(_) => postgres_sources(),
+        DbCon::Sqlite(_) => sqlite_sources(),
+    });
+    // the app's migrations may reference the plugins' tables (`users`, `attachment_blobs`, ...)
+    sources.extend(app.map(|app| ("app", app)));
+
+    sources
+}
+
+#[cfg(any(feature = "database_postgres", feature = "database_any"))]
+#[allow(clippy::vec_init_then_push, unused_mut)]
+fn postgres_sources() -> Sources {
+    let mut sources: Sources = vec![("mailer", &POSTGRES_MAILER)];
+    #[cfg(feature = "plugin_auth")]
+    sources.push(("plugin_auth", &POSTGRES_AUTH));
+    #[cfg(feature = "plugin_auth-oidc")]
+    sources.push(("plugin_auth-oidc", &POSTGRES_AUTH_OIDC));
+    #[cfg(feature = "plugin_auth-saml")]
+    sources.push(("plugin_auth-saml", &POSTGRES_AUTH_SAML));
+    #[cfg(feature = "plugin_storage")]
+    sources.push(("plugin_storage", &POSTGRES_STORAGE));
+    #[cfg(feature = "plugin_tasks")]
+    sources.push(("plugin_tasks", &POSTGRES_TASKS));
+    sources
+}
+
+#[cfg(any(feature = "database_sqlite", feature = "database_any"))]
+#[allow(clippy::vec_init_then_push, unused_mut)]
+fn sqlite_sources() -> Sources {
+    let mut sources: Sources = vec![("mailer", &SQLITE_MAILER)];
+    #[cfg(feature = "plugin_auth")]
+    sources.push(("plugin_auth", &SQLITE_AUTH));
+    #[cfg(feature = "plugin_auth-oidc")]
+    sources.push(("plugin_auth-oidc", &SQLITE_AUTH_OIDC));
+    #[cfg(feature = "plugin_auth-saml")]
+    sources.push(("plugin_auth-saml", &SQLITE_AUTH_SAML));
+    #[cfg(feature = "plugin_storage")]
+    sources.push(("plugin_storage", &SQLITE_STORAGE));
+    sources
+}
+
+#[cfg(feature = "database_mysql")]
+#[allow(clippy::vec_init_then_push, unused_mut)]
+fn mysql_sources() -> Sources {
+    let mut sources: Sources = vec![("mailer", &MYSQL_MAILER)];
+    #[cfg(feature = "plugin_auth")]
+    sources.push(("plugin_auth", &MYSQL_AUTH));
+    #[cfg(feature = "plugin_auth-oidc")]
+    sources.push(("plugin_auth-oidc", &MYSQL_AUTH_OIDC));
+    #[cfg(feature = "plugin_auth-saml")]
+    sources.push(("plugin_auth-saml", &MYSQL_AUTH_SAML));
+    #[cfg(feature = "plugin_storage")]
+    sources.push(("plugin_storage", &MYSQL_STORAGE));
+    sources
+}
+
+/// waits for the migration lock; it's held by the connection, so [`unlock`] must use the same one
+#[allow(unused_variables, clippy::unnecessary_wraps)]
+fn lock(db: &mut PoolConnection) -> Result<(), DatabaseError> {
+    #[cfg(feature = "database_postgres")]
+    return postgres_lock(db, "pg_advisory_lock");
+
+    #[cfg(feature = "database_mysql")]
+    return mysql_lock(db, &format!("GET_LOCK('{LOCK_NAME}', 600)"));
+
+    #[cfg(feature = "database_any")]
+    return match &mut **db {
+        DbCon::Postgresql(db) => postgres_lock(db, "pg_advisory_lock"),
+        DbCon::Sqlite(_) => Ok(()),
+    };
+
+    #[cfg(feature = "database_sqlite")]
+    Ok(())
+}
+
+#[allow(unused_variables, clippy::unnecessary_wraps)]
+fn unlock(db: &mut PoolConnection) -> Result<(), DatabaseError> {
+    #[cfg(feature = "database_postgres")]
+    return postgres_lock(db, "pg_advisory_unlock");
+
+    #[cfg(feature = "database_mysql")]
+    return mysql_lock(db, &format!("RELEASE_LOCK('{LOCK_NAME}')"));
+
+    #[cfg(feature = "database_any")]
+    return match &mut **db {
+        DbCon::Postgresql(db) => postgres_lock(db, "pg_advisory_unlock"),
+        DbCon::Sqlite(_) => Ok(()),
+    };
+
+    #[cfg(feature = "database_sqlite")]
+    Ok(())
+}
+
+/// calls the advisory lock function `function` with the key of [`LOCK_NAME`]
+#[cfg(any(feature = "database_postgres", feature = "database_any"))]
+fn postgres_lock(db: &mut diesel::PgConnection, function: &str) -> Result<(), DatabaseError> {
+    use diesel::RunQueryDsl;
+
+    diesel::sql_query(format!("SELECT {function}(hashtext('{LOCK_NAME}'))"))
+        .execute(db)
+        .map(|_| ())
+        .map_err(|err| DatabaseError::Migration(format!("Could not lock the migrations ({err})")))
+}
+
+/// runs `call` (`GET_LOCK` or `RELEASE_LOCK`), which returns 1 when it succeeds
+#[cfg(feature = "database_mysql")]
+fn mysql_lock(db: &mut diesel::MysqlConnection, call: &str) -> Result<(), DatabaseError> {
+    use diesel::sql_types::{Integer, Nullable};
+    use diesel::RunQueryDsl;
+
+    match diesel::select(diesel::dsl::sql::<Nullable<Integer>>(call)).get_result(db) {
+        Ok(Some(1)) => Ok(()),
+        Ok(_) => Err(DatabaseError::Migration(format!(
+            "Could not lock the migrations ({call} failed)"
+        ))),
+        Err(err) => Err(DatabaseError::Migration(format!(
+            "Could not lock the migrations ({err})"
+        ))),
+    }
+}
+
+#[cfg(all(test, feature = "database_sqlite"))]
+mod tests {
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use diesel::RunQueryDsl;
+
+    use super::{applied_versions, run_pending, sources, SQLITE_STORAGE};
+
+    #[test]
+    fn runs_the_plugins_migrations_once() {
+        let pool = Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::new(":memory:"))
+            .unwrap();
+        let mut db = pool.get().unwrap();
+
+        let ran = run_pending(&mut db, None).unwrap();
+        assert!(ran
+            .iter()
+            .any(|migration| migration.source == "plugin_auth"));
+        diesel::sql_query("SELECT id, email FROM users")
+            .execute(&mut db)
+            .unwrap();
+        diesel::sql_query("SELECT id, status FROM email_outbox")
+            .execute(&mut db)
+            .unwrap();
+        // added by a later migration than the one creating the table
+        diesel::sql_query("SELECT sha256, encryption_key FROM attachment_blobs")
+            .execute(&mut db)
+            .unwrap();
+
+        assert!(run_pending(&mut db, None).unwrap().is_empty());
+        // the app's migrations come last, since they may reference the plugins' tables
+        let sources = sources(&db, Some(&SQLITE_STORAGE));
+        assert_eq!(sources.first().unwrap().0, "mailer");
+        assert_eq!(sources.last().unwrap().0, "app");
+    }
+
+    #[test]
+    fn skips_adding_columns_the_tables_already_have() {
+        let pool = Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::new(":memory:"))
+            .unwrap();
+        let mut db = pool.get().unwrap();
+
+        // like the CLI's storage migration, which creates the table with the columns
+        diesel::sql_query(
+            "CREATE TABLE attachment_blobs (
+              id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+              key TEXT NOT NULL,
+              file_name TEXT NOT NULL,
+              content_type TEXT,
+              byte_size BIGINT NOT NULL,
+              checksum TEXT NOT NULL,
+              sha256 TEXT,
+              encryption TEXT,
+              encryption_key TEXT,
+              service_name TEXT NOT NULL,
+              created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+        )
+        .execute(&mut db)
+        .unwrap();
+
+        let ran = run_pending(&mut db, None).unwrap();
+        assert!(ran
+            .iter()
+            .any(|migration| migration.version == "20000101000400"));
+        assert!(!ran
+            .iter()
+            .any(|migration| migration.version == "20000101000410"));
+        assert!(applied_versions(&mut db)
+            .unwrap()
+            .contains(&"20000101000410".to_string()));
+    }
+}
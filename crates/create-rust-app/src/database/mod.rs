@@ -1,13 +1,44 @@
+use std::sync::Arc;
+
 use diesel::r2d2::{self, ConnectionManager, PooledConnection};
-use diesel_logger::LoggingConnection;
 use once_cell::sync::OnceCell;
 
+#[cfg(feature = "database_any")]
+mod any;
+#[cfg(feature = "database_async")]
+mod async_pool;
+mod config;
+mod error;
+mod metrics;
+#[cfg(feature = "database_migrations")]
+mod migrations;
+mod query_log;
+mod replica;
+
+#[cfg(feature = "database_any")]
+pub use any::AnyConnection;
+#[cfg(feature = "database_async")]
+pub use async_pool::{AsyncConnection, AsyncPool};
+pub use config::DatabaseConfig;
+pub use error::DatabaseError;
+pub use metrics::PoolMetrics;
+use metrics::PoolStats;
+#[cfg(feature = "database_migrations")]
+pub use migrations::MigrationState;
+use replica::Replica;
+
 #[cfg(feature = "database_postgres")]
 type DbCon = diesel::PgConnection;
 
 #[cfg(feature = "database_sqlite")]
 type DbCon = diesel::SqliteConnection;
 
+#[cfg(feature = "database_mysql")]
+type DbCon = diesel::MysqlConnection;
+
+#[cfg(feature = "database_any")]
+type DbCon = AnyConnection;
+
 #[cfg(all(feature = "database_postgres", debug_assertions))]
 #[allow(dead_code)]
 pub type DieselBackend = diesel::pg::Pg;
@@ -16,13 +47,39 @@ pub type DieselBackend = diesel::pg::Pg;
 #[allow(dead_code)]
 pub type DieselBackend = diesel::sqlite::Sqlite;
 
+#[cfg(all(feature = "database_mysql", debug_assertions))]
+#[allow(dead_code)]
+pub type DieselBackend = diesel::mysql::Mysql;
+
+#[cfg(all(feature = "database_any", debug_assertions))]
+#[allow(dead_code)]
+pub type DieselBackend = any::MultiBackend;
+
 pub type Pool = r2d2::Pool<ConnectionManager<DbCon>>;
-pub type Connection = LoggingConnection<PooledConnection<ConnectionManager<DbCon>>>;
+/// a connection of the pool; its queries are logged as the [`DatabaseConfig`] says (see
+/// [`DatabaseConfig::slow_query_threshold`])
+pub type Connection = PooledConnection<ConnectionManager<DbCon>>;
 
 #[derive(Clone)]
 /// wrapper function for a database pool
 pub struct Database {
-    pub pool: &'static Pool,
+    /// the primary's pool
+    pub pool: Pool,
+    pools: Arc<Pools>,
+}
+
+/// the pools of a [`Database`]
+struct Pools {
+    primary: Pool,
+    /// see [`Database::pool_metrics`]
+    stats: Arc<PoolStats>,
+    /// see [`Database::get_read_connection`]
+    replicas: Vec<Replica>,
+    /// see [`Database::get_async_connection`]
+    #[cfg(feature = "database_async")]
+    async_pool: OnceCell<AsyncPool>,
+    #[cfg(feature = "database_async")]
+    config: DatabaseConfig,
 }
 
 impl Default for Database {
@@ -31,13 +88,46 @@ impl Default for Database {
     }
 }
 
+static POOLS: OnceCell<Arc<Pools>> = OnceCell::new();
+
 impl Database {
-    /// create a new [`Database`]
+    /// create a new [`Database`], sharing the app's pool
+    ///
+    /// the pool is configured with [`DatabaseConfig::from_env`], unless a binary set it up first
+    /// with [`Database::init`]
+    ///
+    /// # Panics
+    /// * if the pool can't be configured from the environment, or can't connect, see [`Database::init`]
     #[must_use]
     pub fn new() -> Self {
-        Self {
-            pool: Self::get_or_init_pool(),
-        }
+        Self::from_pools(Self::get_or_init_pools())
+    }
+
+    /// sets up the app's pool (the one [`Database::new`] shares) with `config`; call this at the start
+    /// of a binary to tune its pool, like a queue worker that needs fewer connections than the web server
+    ///
+    /// # Errors
+    /// * [`DatabaseError::Config`] if `config` is invalid, or the pool was already set up
+    /// * [`DatabaseError::Connection`] if the pool can't connect within the `connection_timeout`
+    pub fn init(config: &DatabaseConfig) -> Result<Self, DatabaseError> {
+        let pools = Self::build_pools(config)?;
+        POOLS.set(Arc::new(pools)).map_err(|_| {
+            DatabaseError::Config("The database pool is already set up".to_string())
+        })?;
+
+        Ok(Self::new())
+    }
+
+    /// create a [`Database`] with its own pool, configured with `config`
+    ///
+    /// the [`Database`] (and its clones) own the pool, which is closed when the last of them is dropped;
+    /// opening a pool is slow, so create one per binary (or per kind of workload), not per request
+    ///
+    /// # Errors
+    /// * [`DatabaseError::Config`] if `config` is invalid
+    /// * [`DatabaseError::Connection`] if the pool can't connect within the `connection_timeout`
+    pub fn try_new(config: &DatabaseConfig) -> Result<Self, DatabaseError> {
+        Ok(Self::from_pools(Arc::new(Self::build_pools(config)?)))
     }
 
     /// get a [`Connection`] to a database
@@ -46,28 +136,176 @@ impl Database {
     ///
     /// * if the pool is unable to get a connection
     pub fn get_connection(&self) -> Result<Connection, anyhow::Error> {
-        Ok(LoggingConnection::new(self.pool.get()?))
+        Ok(self.pool.get()?)
     }
 
-    fn get_or_init_pool() -> &'static Pool {
-        static POOL: OnceCell<Pool> = OnceCell::new();
-        #[cfg(debug_assertions)]
-        crate::load_env_vars();
+    /// get a [`Connection`] for reading, to one of the read replicas (see [`DatabaseConfig::replica_urls`])
+    ///
+    /// the replicas take turns; one that can't give a connection is skipped for a while, and the primary
+    /// is used when none can (or there are none)
+    ///
+    /// replicas may lag behind the primary, so read what was just written with [`Database::get_connection`]
+    ///
+    /// # Errors
+    ///
+    /// * if the primary's pool is unable to get a connection either
+    pub fn get_read_connection(&self) -> Result<Connection, anyhow::Error> {
+        match replica::read_connection(&self.pools.replicas) {
+            Some(connection) => Ok(connection),
+            None => self.get_connection(),
+        }
+    }
+
+    /// the `database_async` pool to the primary, with the same settings as [`Database::pool`]; it's
+    /// created when first used
+    ///
+    /// # Panics
+    /// * if it's first used outside of a tokio runtime
+    #[cfg(feature = "database_async")]
+    #[must_use]
+    pub fn async_pool(&self) -> &AsyncPool {
+        self.pools
+            .async_pool
+            .get_or_init(|| async_pool::build(&self.pools.config))
+    }
+
+    /// get an [`AsyncConnection`] to the primary, for `diesel_async`, so the queries don't block the
+    /// runtime's threads (see the `*_async` functions of the models, and `auth::controller_async`)
+    ///
+    /// # Errors
+    ///
+    /// * if the pool is unable to get a connection
+    #[cfg(feature = "database_async")]
+    pub async fn get_async_connection(&self) -> Result<AsyncConnection, anyhow::Error> {
+        Ok(self.async_pool().get_owned().await?)
+    }
+
+    /// the state of the primary's pool: its connections, and how long getting one took
+    ///
+    /// see [`PoolMetrics::to_prometheus`]
+    #[must_use]
+    pub fn pool_metrics(&self) -> PoolMetrics {
+        self.pools.stats.metrics(&self.pools.primary)
+    }
+
+    fn from_pools(pools: Arc<Pools>) -> Self {
+        Self {
+            pool: pools.primary.clone(),
+            pools,
+        }
+    }
+
+    fn get_or_init_pools() -> Arc<Pools> {
+        Self::try_get_or_init_pools().unwrap_or_else(|err| panic!("ERROR: {err}"))
+    }
 
-        POOL.get_or_init(|| {
-            Pool::builder()
-                .connection_timeout(std::time::Duration::from_secs(5))
-                .build(ConnectionManager::<DbCon>::new(Self::connection_url()))
-                .unwrap()
+    /// like [`Database::new`], returning the error instead of panicking; the app's pool is set up with
+    /// [`Database::init`]'s checks (from the environment), unless a binary already set it up
+    ///
+    /// # Errors
+    /// * like [`Database::init`]
+    pub(crate) fn try_shared() -> Result<Self, DatabaseError> {
+        Ok(Self::from_pools(Self::try_get_or_init_pools()?))
+    }
+
+    fn try_get_or_init_pools() -> Result<Arc<Pools>, DatabaseError> {
+        POOLS
+            .get_or_try_init(|| {
+                DatabaseConfig::from_env()
+                    .and_then(|config| Self::build_pools(&config))
+                    .map(Arc::new)
+            })
+            .map(Arc::clone)
+    }
+
+    /// the primary's pool connects right away, so an unreachable database is reported here; the
+    /// replicas' pools connect in the background instead, so the app starts even when one is down
+    fn build_pools(config: &DatabaseConfig) -> Result<Pools, DatabaseError> {
+        config.validate()?;
+
+        let stats = Arc::new(PoolStats::default());
+        let primary = Self::pool_builder(config)
+            .event_handler(stats.handler())
+            .build(ConnectionManager::<DbCon>::new(&config.url))
+            .map_err(|err| {
+                DatabaseError::Connection(format!("Could not connect to the database ({err})"))
+            })?;
+        let replicas = config
+            .replica_urls
+            .iter()
+            .map(|url| {
+                Replica::new(
+                    Self::pool_builder(config)
+                        .build_unchecked(ConnectionManager::<DbCon>::new(url)),
+                )
+            })
+            .collect();
+
+        Ok(Pools {
+            primary,
+            stats,
+            replicas,
+            #[cfg(feature = "database_async")]
+            async_pool: OnceCell::new(),
+            #[cfg(feature = "database_async")]
+            config: config.clone(),
         })
     }
 
+    fn pool_builder(config: &DatabaseConfig) -> r2d2::Builder<ConnectionManager<DbCon>> {
+        let builder = Pool::builder()
+            .max_size(config.max_connections)
+            .min_idle(config.min_connections)
+            .connection_timeout(config.connection_timeout)
+            .idle_timeout(config.idle_timeout)
+            .max_lifetime(config.max_lifetime)
+            .test_on_check_out(config.test_on_checkout);
+
+        match config.customizer() {
+            Some(customizer) => builder.connection_customizer(customizer),
+            None => builder,
+        }
+    }
+
     /// get the connection url for the database
     ///
     /// # Panics
     /// * if the `DATABASE_URL` environment variable is not set
     #[must_use]
     pub fn connection_url() -> String {
+        #[cfg(debug_assertions)]
+        crate::load_env_vars();
+
         std::env::var("DATABASE_URL").expect("DATABASE_URL environment variable expected.")
     }
 }
+
+/// the id of the row the last `INSERT` on `db` created; MySQL has no `RETURNING`, so the models read
+/// back the rows they insert with it
+///
+/// # Errors
+/// * the query fails
+#[cfg(feature = "database_mysql")]
+pub(crate) fn last_insert_id(db: &mut Connection) -> diesel::QueryResult<i32> {
+    use diesel::RunQueryDsl;
+
+    diesel::select(diesel::dsl::sql::<diesel::sql_types::Integer>(
+        "LAST_INSERT_ID()",
+    ))
+    .get_result(db)
+}
+
+/// like [`last_insert_id`], on an [`AsyncConnection`]
+///
+/// # Errors
+/// * the query fails
+#[cfg(all(feature = "database_mysql", feature = "database_async"))]
+pub(crate) async fn last_insert_id_async(db: &mut AsyncConnection) -> diesel::QueryResult<i32> {
+    use diesel_async::RunQueryDsl;
+
+    diesel::select(diesel::dsl::sql::<diesel::sql_types::Integer>(
+        "LAST_INSERT_ID()",
+    ))
+    .get_result(db)
+    .await
+}
@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+
+/// Logs the queries of a connection (with the `log` crate): the ones slower than `slow_query_threshold`
+/// as warnings, and with `log_queries`, all of them at the debug level
+///
+/// see [`DatabaseConfig::slow_query_threshold`](`super::DatabaseConfig::slow_query_threshold`)
+#[derive(Debug, Clone)]
+pub(super) struct QueryLogger {
+    log_queries: bool,
+    slow_query_threshold: Option<Duration>,
+    /// when the running query started; a connection runs one query at a time
+    started_at: Option<Instant>,
+}
+
+impl QueryLogger {
+    /// `None` if nothing would be logged
+    pub(super) fn new(log_queries: bool, slow_query_threshold: Option<Duration>) -> Option<Self> {
+        (log_queries || slow_query_threshold.is_some()).then_some(Self {
+            log_queries,
+            slow_query_threshold,
+            started_at: None,
+        })
+    }
+
+    fn is_slow(&self, duration: Duration) -> bool {
+        self.slow_query_threshold
+            .is_some_and(|threshold| duration >= threshold)
+    }
+}
+
+impl Instrumentation for QueryLogger {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { .. } => self.started_at = Some(Instant::now()),
+            InstrumentationEvent::FinishQuery { query, .. } => {
+                let Some(duration) = self.started_at.take().map(|started| started.elapsed()) else {
+                    return;
+                };
+
+                if self.is_slow(duration) {
+                    log::warn!("SLOW QUERY [{:.2}s]: {query}", duration.as_secs_f64());
+                } else if self.log_queries {
+                    log::debug!("QUERY [{:.1}ms]: {query}", duration.as_secs_f64() * 1000.0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_the_queries_over_the_threshold() {
+        assert!(QueryLogger::new(false, None).is_none());
+
+        let logger = QueryLogger::new(false, Some(Duration::from_millis(500))).unwrap();
+        assert!(!logger.is_slow(Duration::from_millis(499)));
+        assert!(logger.is_slow(Duration::from_millis(500)));
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+
+use super::{DbCon, Pool};
+
+/// how long a replica that could not give a connection is skipped
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// The pool of a read replica, and whether it's been failing
+pub(super) struct Replica {
+    pool: Pool,
+    /// when getting a connection last failed; `None` while it works
+    failed_at: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    pub(super) const fn new(pool: Pool) -> Self {
+        Self {
+            pool,
+            failed_at: Mutex::new(None),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.failed_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_none_or(|failed_at| failed_at.elapsed() >= RETRY_AFTER)
+    }
+
+    fn set_failed(&self, failed: bool) {
+        *self
+            .failed_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = failed.then(Instant::now);
+    }
+}
+
+/// a connection to one of the healthy `replicas`, taking turns between them; `None` if none of them
+/// could give one
+pub(super) fn read_connection(
+    replicas: &[Replica],
+) -> Option<PooledConnection<ConnectionManager<DbCon>>> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    if replicas.is_empty() {
+        return None;
+    }
+
+    let start = NEXT.fetch_add(1, Ordering::Relaxed);
+    (0..replicas.len())
+        .map(|offset| &replicas[(start + offset) % replicas.len()])
+        .filter(|replica| replica.is_healthy())
+        .find_map(|replica| match replica.pool.get() {
+            Ok(connection) => {
+                replica.set_failed(false);
+                Some(connection)
+            }
+            Err(err) => {
+                println!(
+                    "Warning: skipping a database replica for {}s ({err})",
+                    RETRY_AFTER.as_secs()
+                );
+                replica.set_failed(true);
+                None
+            }
+        })
+}
+
+#[cfg(all(test, feature = "database_postgres"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_the_failing_replicas() {
+        let unreachable = || {
+            Replica::new(
+                Pool::builder()
+                    .connection_timeout(Duration::from_millis(200))
+                    .build_unchecked(ConnectionManager::new("postgres://127.0.0.1:1/app")),
+            )
+        };
+        let replicas = [unreachable(), unreachable()];
+
+        assert!(read_connection(&replicas).is_none());
+        assert!(replicas.iter().all(|replica| !replica.is_healthy()));
+
+        // the failed replicas aren't tried again until RETRY_AFTER
+        let started = Instant::now();
+        assert!(read_connection(&replicas).is_none());
+        assert!(started.elapsed() < Duration::from_millis(200));
+
+        replicas[0].set_failed(false);
+        assert!(replicas[0].is_healthy());
+        assert!(read_connection(&[]).is_none());
+    }
+}
@@ -27,6 +27,12 @@ pub struct HealthCheckResponse {
     pub message: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct DevServerResponse {
+    /// `None` when the app wasn't started by the development server
+    pub port: Option<u16>,
+}
+
 /// /db/query
 ///
 /// # Errors
@@ -35,14 +41,66 @@ pub struct HealthCheckResponse {
 /// # Panics
 /// * cannot connect to the database
 pub fn query_db(db: &Database, body: &MySqlQuery) -> Result<String, diesel::result::Error> {
-    let q = format!("SELECT json_agg(q) as json FROM ({}) q;", body.query);
     let mut db = db.get_connection().unwrap();
 
+    #[cfg(not(feature = "database_mysql"))]
+    let q = format!("SELECT json_agg(q) as json FROM ({}) q;", body.query);
+    #[cfg(feature = "database_mysql")]
+    let q = mysql_json_query(&mut db, &body.query)?;
+
     Ok(sql_query(q.as_str())
         .get_result::<MyQueryResult>(&mut db)?
         .json)
 }
 
+/// MySQL has no `json_agg`, so each row is built with `JSON_OBJECT`, which needs the query's
+/// column names; they're read from a view created (and dropped) for the query
+#[cfg(feature = "database_mysql")]
+fn mysql_json_query(
+    db: &mut crate::Connection,
+    query: &str,
+) -> Result<String, diesel::result::Error> {
+    #[derive(QueryableByName)]
+    struct Column {
+        #[diesel(sql_type = Text)]
+        column_name: String,
+    }
+
+    let query = query.trim().trim_end_matches(';');
+    let view = format!(
+        "create_rust_app_query_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    );
+
+    sql_query(format!("CREATE VIEW `{view}` AS {query}")).execute(db)?;
+    let columns = sql_query(
+        "SELECT column_name AS column_name FROM information_schema.columns \
+         WHERE table_schema = DATABASE() AND table_name = ? ORDER BY ordinal_position",
+    )
+    .bind::<Text, _>(&view)
+    .load::<Column>(db);
+    sql_query(format!("DROP VIEW `{view}`")).execute(db)?;
+
+    let fields = columns?
+        .iter()
+        .map(|column| {
+            format!(
+                "'{}', q.`{}`",
+                column.column_name.replace('\\', "\\\\").replace('\'', "''"),
+                column.column_name.replace('`', "``")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Ok(format!(
+        "SELECT CAST(COALESCE(JSON_ARRAYAGG(JSON_OBJECT({fields})), JSON_ARRAY()) AS CHAR) AS json FROM ({query}) q"
+    ))
+}
+
 /// /db/is-connected
 #[must_use]
 pub fn is_connected(db: &Database) -> bool {
@@ -50,7 +108,7 @@ pub fn is_connected(db: &Database) -> bool {
         return false;
     };
     let is_connected = sql_query("SELECT 1;").execute(&mut db);
-    is_connected.is_err()
+    is_connected.is_ok()
 }
 
 /// # Panics
@@ -63,10 +121,6 @@ pub fn get_migrations(db: &Database) -> Vec<CreateRustAppMigration> {
 
     let source = FileBasedMigrations::find_migrations_directory().unwrap();
 
-    #[cfg(feature = "database_sqlite")]
-    let file_migrations =
-        MigrationSource::<crate::database::DieselBackend>::migrations(&source).unwrap();
-    #[cfg(feature = "database_postgres")]
     let file_migrations =
         MigrationSource::<crate::database::DieselBackend>::migrations(&source).unwrap();
 
@@ -160,3 +214,15 @@ pub fn migrate_db(db: &Database) -> Result<()> {
 
 /// /health
 pub const fn health() {}
+
+/// /dev-server
+///
+/// the port of the development server, whose websocket the admin dashboard listens to (for the captured emails)
+#[must_use]
+pub fn dev_server() -> DevServerResponse {
+    DevServerResponse {
+        port: std::env::var("DEV_SERVER_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok()),
+    }
+}
@@ -4,15 +4,16 @@ use axum::{
     extract::{ws::WebSocket, State, WebSocketUpgrade},
     http::status::StatusCode,
     response::{Html, IntoResponse},
-    routing::get,
-    Router,
+    routing::{get, post},
+    Json, Router,
 };
 use cargo_metadata::CompilerMessage;
 use futures_util::{SinkExt, StreamExt};
-use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::broadcast::{error::RecvError, Receiver, Sender};
 
 use crate::{dev::controller, Database};
 
+use super::mail_catcher::{MailCatcher, MailRequest};
 use super::{CreateRustAppMigration, DevServerEvent};
 
 /// TODO: use a state machine or refactor into an enum
@@ -27,6 +28,7 @@ struct CurrentDevState {
     vite_status: bool,
     features: Vec<String>,
     migrations_pending: (bool, Vec<CreateRustAppMigration>),
+    mail: MailCatcher,
 }
 
 struct AppState {
@@ -34,6 +36,8 @@ struct AppState {
     project_dir: &'static str,
     rx: tokio::sync::Mutex<Receiver<DevServerEvent>>, // this is the original subscribed receiver which hasn't missed a single event :)
     tx: Sender<DevServerEvent>,
+    /// the mail catcher's events; unlike the ones of `rx`, every websocket gets them
+    mail_tx: Sender<DevServerEvent>,
     file_tx: Sender<String>,
     db: Database,
     dev: Mutex<CurrentDevState>,
@@ -56,6 +60,7 @@ pub async fn start(
         project_dir,
         rx: tokio::sync::Mutex::new(dev_server_events_r),
         tx: dev_server_events_s,
+        mail_tx: tokio::sync::broadcast::channel(64).0,
         file_tx: file_events_s,
         db: Database::new(),
         dev: Mutex::new(CurrentDevState {
@@ -67,6 +72,7 @@ pub async fn start(
             vite_status: true,
             features,
             migrations_pending: (false, vec![]),
+            mail: MailCatcher::default(),
         }),
     });
 
@@ -86,6 +92,7 @@ pub async fn start(
         .route("/vitejs-down", get(vitejs_down_handler))
         .route("/vitejs-up", get(vitejs_up_handler))
         .route("/backend-up", get(backend_up_handler))
+        .route("/mail", post(mail_handler))
         .route("/ws", get(ws_handler))
         .with_state(app_state);
 
@@ -120,6 +127,20 @@ async fn vitejs_down_handler(State(state): State<Arc<AppState>>) -> impl IntoRes
     StatusCode::OK.into_response()
 }
 
+/// the app's mailer posts the emails here when `SEND_MAIL` is false
+async fn mail_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<MailRequest>,
+) -> impl IntoResponse {
+    let email = state.dev.lock().unwrap().mail.capture(request);
+    state
+        .mail_tx
+        .send(DevServerEvent::EmailCaptured(email))
+        .ok();
+
+    StatusCode::OK.into_response()
+}
+
 async fn ws_handler(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> impl IntoResponse {
     ws.on_upgrade(|socket| handle_socket(socket, state))
 }
@@ -129,6 +150,7 @@ async fn ws_handler(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) ->
 async fn handle_socket(stream: WebSocket, state: Arc<AppState>) {
     use axum::extract::ws::Message;
     let (mut sender, mut receiver) = stream.split();
+    let mut mail_rx = state.mail_tx.subscribe();
 
     /*
         SECTION: sending initial state
@@ -141,6 +163,7 @@ async fn handle_socket(stream: WebSocket, state: Arc<AppState>) {
     let vite_status = state.dev.lock().unwrap().vite_status;
     let features = state.dev.lock().unwrap().features.clone();
     let migrations_pending = state.dev.lock().unwrap().migrations_pending.clone();
+    let emails = state.dev.lock().unwrap().mail.emails();
     sender
         .send(Message::Text(DevServerEvent::FeaturesList(features).json()))
         .await
@@ -187,10 +210,18 @@ async fn handle_socket(stream: WebSocket, state: Arc<AppState>) {
         ))
         .await
         .unwrap();
+    sender
+        .send(Message::Text(DevServerEvent::CapturedEmails(emails).json()))
+        .await
+        .unwrap();
+
+    // shared with the task forwarding the mail catcher's events
+    let sender = Arc::new(tokio::sync::Mutex::new(sender));
 
     /*
         SECTION: receive dev server events
     */
+    let sender2 = sender.clone();
     let db = state.db.clone();
     let state2 = state.clone();
     let dse_s = state2.tx.clone();
@@ -257,10 +288,18 @@ async fn handle_socket(stream: WebSocket, state: Arc<AppState>) {
                     let mut s = state2.dev.lock().unwrap();
                     s.vite_status = b;
                 }
+                DevServerEvent::CapturedEmails(_) | DevServerEvent::EmailCaptured(_) => {
+                    // these are sent on `mail_tx`
+                }
             };
 
             if send_response {
-                sender.send(Message::Text(e.json())).await.unwrap();
+                sender2
+                    .lock()
+                    .await
+                    .send(Message::Text(e.json()))
+                    .await
+                    .unwrap();
             }
         }
     });
@@ -311,6 +350,12 @@ async fn handle_socket(stream: WebSocket, state: Arc<AppState>) {
                                     .tx
                                     .send(DevServerEvent::MigrationResponse(success, error_message))
                                     .ok();
+                            } else if t.eq_ignore_ascii_case("mail:clear") {
+                                state3.dev.lock().unwrap().mail.clear();
+                                state3
+                                    .mail_tx
+                                    .send(DevServerEvent::CapturedEmails(vec![]))
+                                    .ok();
                             }
                         });
                     }
@@ -323,9 +368,33 @@ async fn handle_socket(stream: WebSocket, state: Arc<AppState>) {
         }
     });
 
-    // If any one of the tasks exit, abort the other.
+    /*
+        SECTION: forward the mail catcher's events
+    */
+    let mut mail_task = tokio::spawn(async move {
+        loop {
+            match mail_rx.recv().await {
+                Ok(e) => {
+                    if sender
+                        .lock()
+                        .await
+                        .send(Message::Text(e.json()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    // If any one of the tasks exit, abort the others.
     tokio::select! {
-        _ = (&mut send_task) => {recv_task.abort()},
-        _ = (&mut recv_task) => {send_task.abort()},
+        _ = (&mut send_task) => {recv_task.abort(); mail_task.abort()},
+        _ = (&mut recv_task) => {send_task.abort(); mail_task.abort()},
+        _ = (&mut mail_task) => {send_task.abort(); recv_task.abort()},
     };
 }
@@ -1,6 +1,6 @@
 use crate::{dev::controller, dev::controller::MySqlQuery, Database};
 use actix_web::{
-    post,
+    get, post,
     web::{Data, Json},
     HttpResponse, Scope,
 };
@@ -13,7 +13,12 @@ async fn query_db(db: Data<Database>, body: Json<MySqlQuery>) -> HttpResponse {
     )
 }
 
+#[get("/dev-server")]
+async fn dev_server() -> HttpResponse {
+    HttpResponse::Ok().json(controller::dev_server())
+}
+
 #[must_use]
 pub fn endpoints(scope: Scope) -> Scope {
-    scope.service(query_db)
+    scope.service(query_db).service(dev_server)
 }
@@ -1,5 +1,5 @@
 use poem::{
-    handler,
+    get, handler,
     http::StatusCode,
     post,
     web::{Data, Json},
@@ -16,6 +16,13 @@ async fn query(db: Data<&Database>, body: Json<MySqlQuery>) -> Result<impl IntoR
         .map_err(|_| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))
 }
 
+#[handler]
+async fn dev_server() -> Json<controller::DevServerResponse> {
+    Json(controller::dev_server())
+}
+
 pub fn api() -> Route {
-    Route::new().at("/db/query", post(query))
+    Route::new()
+        .at("/db/query", post(query))
+        .at("/dev-server", get(dev_server))
 }
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use mail_parser::{MessageParser, MimeHeaders};
+use serde::{Deserialize, Serialize};
+
+/// how many emails are kept; the oldest ones are dropped
+const CAPACITY: usize = 100;
+
+/// what the app's [`Mailer`](`crate::Mailer`) posts to the development server when `SEND_MAIL` is false
+/// (the default body of [`HttpTransport`](`crate::HttpTransport`))
+#[derive(Debug, Deserialize)]
+pub struct MailRequest {
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    /// the formatted message
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CapturedAttachment {
+    file_name: Option<String>,
+    content_type: Option<String>,
+    content_id: Option<String>,
+    size: usize,
+}
+
+/// an email sent by the app, as shown in the admin dashboard
+#[derive(Serialize, Debug, Clone)]
+pub struct CapturedEmail {
+    id: usize,
+    /// milliseconds since the unix epoch
+    received_at: u64,
+    from: Option<String>,
+    to: Vec<String>,
+    subject: Option<String>,
+    text: Option<String>,
+    /// with its inline images (`cid:` urls) replaced by data urls, so it can be previewed as is
+    html: Option<String>,
+    attachments: Vec<CapturedAttachment>,
+    /// the formatted message
+    raw: String,
+}
+
+impl CapturedEmail {
+    fn parse(id: usize, request: MailRequest) -> Self {
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| {
+                u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+            });
+        let mut email = Self {
+            id,
+            received_at,
+            from: request.from,
+            to: request.to,
+            subject: None,
+            text: None,
+            html: None,
+            attachments: vec![],
+            raw: String::new(),
+        };
+
+        let Some(message) = MessageParser::default().parse(request.message.as_bytes()) else {
+            email.raw = request.message;
+            return email;
+        };
+
+        email.subject = message.subject().map(ToString::to_string);
+        // the parser converts between text and html when a message only has one of them
+        email.text = message
+            .text_part(0)
+            .filter(|part| !part.is_text_html())
+            .and_then(|part| part.text_contents())
+            .map(ToString::to_string);
+        email.html = message
+            .html_part(0)
+            .filter(|part| part.is_text_html())
+            .and_then(|part| part.text_contents())
+            .map(ToString::to_string);
+
+        for attachment in message.attachments() {
+            let content_type = attachment.content_type().map(|content_type| {
+                content_type.subtype().map_or_else(
+                    || content_type.ctype().to_string(),
+                    |subtype| format!("{}/{subtype}", content_type.ctype()),
+                )
+            });
+            let content_id = attachment
+                .content_id()
+                .map(|id| id.trim_matches(['<', '>']).to_string());
+
+            if let (Some(html), Some(content_id), Some(content_type)) =
+                (&mut email.html, &content_id, &content_type)
+            {
+                *html = html.replace(
+                    &format!("cid:{content_id}"),
+                    &format!(
+                        "data:{content_type};base64,{}",
+                        STANDARD.encode(attachment.contents())
+                    ),
+                );
+            }
+
+            email.attachments.push(CapturedAttachment {
+                file_name: attachment.attachment_name().map(ToString::to_string),
+                content_type,
+                content_id,
+                size: attachment.len(),
+            });
+        }
+
+        email.raw = request.message;
+        email
+    }
+}
+
+/// The emails captured by the development server, newest last
+#[derive(Debug, Default)]
+pub struct MailCatcher {
+    emails: VecDeque<CapturedEmail>,
+    next_id: usize,
+}
+
+impl MailCatcher {
+    pub fn capture(&mut self, request: MailRequest) -> CapturedEmail {
+        let email = CapturedEmail::parse(self.next_id, request);
+        self.next_id += 1;
+
+        if self.emails.len() == CAPACITY {
+            self.emails.pop_front();
+        }
+        self.emails.push_back(email.clone());
+
+        email
+    }
+
+    #[must_use]
+    pub fn emails(&self) -> Vec<CapturedEmail> {
+        self.emails.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.emails.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Email, EmailAddress, EmailAttachment};
+
+    #[test]
+    fn previews_the_captured_emails() {
+        let email = Email::new(EmailAddress::new("user@example.com").unwrap(), "Welcome")
+            .text("Activate: http://localhost:3000/activate?token=abc")
+            .html(r#"<img src="cid:logo"><a href="http://localhost:3000/activate?token=abc">Activate</a>"#)
+            .attach(EmailAttachment::inline("logo", "image/png", vec![1, 2, 3]))
+            .attach(EmailAttachment::new("terms.pdf", "application/pdf", vec![4, 5]));
+        let message = email
+            .message(&EmailAddress::new("app@example.com").unwrap())
+            .unwrap();
+
+        let mut catcher = MailCatcher::default();
+        let captured = catcher.capture(MailRequest {
+            from: Some("app@example.com".to_string()),
+            to: vec!["user@example.com".to_string()],
+            message: String::from_utf8(message.formatted()).unwrap(),
+        });
+
+        assert_eq!(captured.subject.as_deref(), Some("Welcome"));
+        assert!(captured.text.unwrap().contains("activate?token=abc"));
+        let html = captured.html.unwrap();
+        assert!(html.contains(r#"<img src="data:image/png;base64,AQID">"#));
+        assert!(html.contains(r#"href="http://localhost:3000/activate?token=abc""#));
+        assert_eq!(captured.attachments.len(), 2);
+        assert_eq!(
+            captured.attachments[1].file_name.as_deref(),
+            Some("terms.pdf")
+        );
+
+        for _ in 0..CAPACITY {
+            catcher.capture(MailRequest {
+                from: None,
+                to: vec![],
+                message: "Subject: Hi\r\n\r\nHello".to_string(),
+            });
+        }
+        let emails = catcher.emails();
+        assert_eq!(emails.len(), CAPACITY);
+        assert_eq!(emails[0].id, 1);
+        assert_eq!(emails[0].text.as_deref(), Some("Hello"));
+        assert_eq!(emails[0].html, None);
+
+        catcher.clear();
+        assert!(catcher.emails().is_empty());
+    }
+}
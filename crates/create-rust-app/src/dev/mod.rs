@@ -1,6 +1,7 @@
 mod backend_compiling_server;
 mod dev_server;
 mod frontend_dev_server;
+mod mail_catcher;
 
 pub mod controller;
 use cargo_metadata::CompilerMessage;
@@ -11,6 +12,7 @@ mod endpoints;
 use crate::util::net::find_free_port;
 use async_priority_channel as priority;
 pub use endpoints::*;
+pub use mail_catcher::{CapturedAttachment, CapturedEmail};
 use std::iter::FromIterator;
 use std::path::PathBuf;
 use std::process::exit;
@@ -97,6 +99,9 @@ pub enum DevServerEvent {
     BackendStatus(bool),
     CompileSuccess(bool),
     CompileMessages(Vec<CompilerMessage>),
+    /// all the emails captured so far, see [`CapturedEmail`]
+    CapturedEmails(Vec<CapturedEmail>),
+    EmailCaptured(CapturedEmail),
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -179,6 +184,16 @@ impl DevServerEvent {
                 })
                 .to_string()
             }
+            Self::CapturedEmails(emails) => json!({
+                "type": "mails",
+                "mails": emails
+            })
+            .to_string(),
+            Self::EmailCaptured(email) => json!({
+                "type": "mailCaptured",
+                "mail": email
+            })
+            .to_string(),
         }
     }
 }
@@ -0,0 +1,56 @@
+use actix_web::{
+    get,
+    web::{self, Data},
+    HttpRequest, HttpResponse, Result, Scope,
+};
+use serde_json::json;
+
+use crate::health::{self, HealthReport};
+use crate::Database;
+#[cfg(feature = "plugin_storage")]
+use crate::Storage;
+
+/// handler for GET requests at the .../live endpoint; answers as long as the server runs
+#[get("/live")]
+async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "healthy": true }))
+}
+
+/// handler for GET requests at the .../ready endpoint; `503 Service Unavailable` unless the database
+/// (and the storage, when it's in the app data, and the tasks queue) can be reached
+///
+/// see [`HealthReport`]
+#[get("/ready")]
+#[cfg_attr(not(feature = "plugin_storage"), allow(unused_variables))]
+async fn ready(req: HttpRequest, db: Data<Database>) -> Result<HttpResponse> {
+    #[allow(unused_mut)]
+    let mut checks = web::block(move || health::blocking_checks(&db)).await?;
+
+    #[cfg(feature = "plugin_storage")]
+    if let Some(storage) = req.app_data::<Data<Storage>>() {
+        checks.push(health::check_storage(storage).await);
+    }
+
+    let report = HealthReport::new(checks);
+    Ok(if report.healthy {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    })
+}
+
+/// handler for GET requests at the .../metrics endpoint: the database pool's metrics, for Prometheus
+///
+/// see [`PoolMetrics`](`crate::PoolMetrics`)
+#[get("/metrics")]
+async fn metrics(db: Data<Database>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(db.pool_metrics().to_prometheus())
+}
+
+/// returns the health endpoints: `/live`, `/ready` and `/metrics`
+#[must_use]
+pub fn endpoints(scope: Scope) -> Scope {
+    scope.service(live).service(ready).service(metrics)
+}
@@ -0,0 +1,62 @@
+use poem::{
+    get, handler,
+    http::StatusCode,
+    web::{Data, Json},
+    IntoResponse, Request, Response, Route,
+};
+use serde_json::json;
+
+use crate::health::{self, HealthReport};
+use crate::Database;
+#[cfg(feature = "plugin_storage")]
+use crate::Storage;
+
+/// handler for GET requests at the .../live endpoint; answers as long as the server runs
+#[handler]
+async fn live() -> Json<serde_json::Value> {
+    Json(json!({ "healthy": true }))
+}
+
+/// handler for GET requests at the .../ready endpoint; `503 Service Unavailable` unless the database
+/// (and the storage, when it's in the app data, and the tasks queue) can be reached
+///
+/// see [`HealthReport`]
+#[handler]
+#[cfg_attr(not(feature = "plugin_storage"), allow(unused_variables))]
+async fn ready(req: &Request, db: Data<&Database>) -> Response {
+    #[allow(unused_mut)]
+    let mut checks = health::blocking_checks(db.0);
+
+    #[cfg(feature = "plugin_storage")]
+    if let Some(storage) = req.data::<Storage>() {
+        checks.push(health::check_storage(storage).await);
+    }
+
+    let report = HealthReport::new(checks);
+    let status = if report.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Json(report).with_status(status).into_response()
+}
+
+/// handler for GET requests at the .../metrics endpoint: the database pool's metrics, for Prometheus
+///
+/// see [`PoolMetrics`](`crate::PoolMetrics`)
+#[handler]
+async fn metrics(db: Data<&Database>) -> Response {
+    db.pool_metrics()
+        .to_prometheus()
+        .with_content_type("text/plain; version=0.0.4")
+        .into_response()
+}
+
+/// returns a poem Route with the health endpoints: `/live`, `/ready` and `/metrics`
+#[must_use]
+pub fn api() -> Route {
+    Route::new()
+        .at("/live", get(live))
+        .at("/ready", get(ready))
+        .at("/metrics", get(metrics))
+}
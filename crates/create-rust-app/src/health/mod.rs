@@ -0,0 +1,177 @@
+#[cfg(feature = "plugin_storage")]
+use std::time::Duration;
+use std::time::Instant;
+
+use diesel::RunQueryDsl;
+use serde::Serialize;
+
+use crate::Database;
+#[cfg(feature = "plugin_storage")]
+use crate::Storage;
+
+mod endpoints;
+
+#[cfg(feature = "backend_poem")]
+pub use endpoints::api;
+#[cfg(feature = "backend_actix-web")]
+pub use endpoints::endpoints;
+
+/// how long the storage check waits for the bucket
+#[cfg(feature = "plugin_storage")]
+const STORAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The result of one of the readiness checks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthCheck {
+    /// what was checked: `"database"`, `"storage"` or `"queue"`
+    pub name: &'static str,
+    pub healthy: bool,
+    /// how long the check took, in milliseconds
+    pub duration_ms: u64,
+    /// why the check failed, or what it found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl HealthCheck {
+    fn new(
+        name: &'static str,
+        started_at: Instant,
+        result: Result<Option<String>, String>,
+    ) -> Self {
+        let duration_ms = u64::try_from(started_at.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+        match result {
+            Ok(message) => Self {
+                name,
+                healthy: true,
+                duration_ms,
+                message,
+            },
+            Err(message) => Self {
+                name,
+                healthy: false,
+                duration_ms,
+                message: Some(message),
+            },
+        }
+    }
+}
+
+/// The checks of a readiness probe; the app is ready when all of them pass
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    #[must_use]
+    pub fn new(checks: Vec<HealthCheck>) -> Self {
+        Self {
+            healthy: checks.iter().all(|check| check.healthy),
+            checks,
+        }
+    }
+}
+
+/// checks that the primary database answers a query
+///
+/// this blocks the thread; in async code, run it with `actix_web::web::block` or `tokio::task::spawn_blocking`
+#[must_use]
+pub fn check_database(db: &Database) -> HealthCheck {
+    let started_at = Instant::now();
+    let result = db
+        .get_connection()
+        .map_err(|err| format!("Could not get a connection ({err})"))
+        .and_then(|mut connection| {
+            diesel::sql_query("SELECT 1")
+                .execute(&mut connection)
+                .map_err(|err| format!("The database did not answer ({err})"))
+        })
+        .map(|_| None);
+
+    HealthCheck::new("database", started_at, result)
+}
+
+/// checks that the default storage backend can be reached, by looking up an object that doesn't exist
+#[cfg(feature = "plugin_storage")]
+pub async fn check_storage(storage: &Storage) -> HealthCheck {
+    let started_at = Instant::now();
+    let result = match tokio::time::timeout(
+        STORAGE_TIMEOUT,
+        storage.exists(".create-rust-app-health-check".to_string()),
+    )
+    .await
+    {
+        Ok(Ok(_)) => Ok(None),
+        Ok(Err(err)) => Err(format!("Could not reach the storage ({err})")),
+        Err(_) => Err(format!(
+            "The storage did not answer within {}s",
+            STORAGE_TIMEOUT.as_secs()
+        )),
+    };
+
+    HealthCheck::new("storage", started_at, result)
+}
+
+/// checks that the tasks queue (`fang_tasks`) can be read, and reports how many tasks are waiting
+///
+/// this blocks the thread, like [`check_database`]
+#[cfg(feature = "plugin_tasks")]
+#[must_use]
+pub fn check_queue(db: &Database) -> HealthCheck {
+    #[derive(QueryableByName)]
+    struct Waiting {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+
+    let started_at = Instant::now();
+    let result = db
+        .get_connection()
+        .map_err(|err| format!("Could not get a connection ({err})"))
+        .and_then(|mut connection| {
+            diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM fang_tasks WHERE state IN ('new', 'retried')",
+            )
+            .get_result::<Waiting>(&mut connection)
+            .map_err(|err| format!("Could not read the queue ({err})"))
+        })
+        .map(|waiting| Some(format!("{} tasks waiting", waiting.count)));
+
+    HealthCheck::new("queue", started_at, result)
+}
+
+/// the checks that block (the database's and the queue's)
+#[cfg(any(feature = "backend_actix-web", feature = "backend_poem"))]
+pub(crate) fn blocking_checks(db: &Database) -> Vec<HealthCheck> {
+    vec![
+        check_database(db),
+        #[cfg(feature = "plugin_tasks")]
+        check_queue(db),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_healthy_when_all_the_checks_pass() {
+        let check = |name, result| HealthCheck::new(name, Instant::now(), result);
+
+        assert!(HealthReport::new(vec![check("database", Ok(None))]).healthy);
+        assert!(
+            !HealthReport::new(vec![
+                check("database", Ok(None)),
+                check("storage", Err("unreachable".to_string())),
+            ])
+            .healthy
+        );
+        assert_eq!(
+            serde_json::to_value(check("database", Ok(None))).unwrap()["message"],
+            serde_json::Value::Null
+        );
+    }
+}
@@ -3,9 +3,31 @@ compile_error!(
     "feature \"backend_actix-web\" and feature \"backend_poem\" cannot be enabled at the same time"
 );
 
-#[cfg(all(feature = "database_sqlite", feature = "database_postgres"))]
+#[cfg(any(
+    all(feature = "database_sqlite", feature = "database_postgres"),
+    all(feature = "database_sqlite", feature = "database_mysql"),
+    all(feature = "database_postgres", feature = "database_mysql"),
+    all(
+        feature = "database_any",
+        any(
+            feature = "database_postgres",
+            feature = "database_sqlite",
+            feature = "database_mysql"
+        )
+    ),
+))]
 compile_error!(
-    "feature \"database_sqlite\" and feature \"database_postgres\" cannot be enabled at the same time"
+    "only one of the features \"database_postgres\", \"database_sqlite\", \"database_mysql\" and \"database_any\" can be enabled"
+);
+
+#[cfg(all(feature = "plugin_auth-saml", feature = "backend_poem"))]
+compile_error!(
+    "feature \"plugin_auth-saml\" only has actix-web endpoints; use \"backend_actix-web\""
+);
+
+#[cfg(all(feature = "database_any", feature = "database_async"))]
+compile_error!(
+    "feature \"database_async\" doesn't support \"database_any\"; use \"database_postgres\" or \"database_sqlite\""
 );
 
 // #[cfg(not(any(feature = "backend_poem", feature = "backend_actix-web")))]
@@ -31,7 +53,24 @@ pub mod dev;
 pub use dev::setup_development;
 
 mod database;
-pub use database::{Connection, Database, Pool};
+#[cfg(feature = "database_any")]
+pub use database::AnyConnection;
+#[cfg(feature = "database_migrations")]
+pub use database::MigrationState;
+#[cfg(feature = "database_async")]
+pub use database::{AsyncConnection, AsyncPool};
+pub use database::{Connection, Database, DatabaseConfig, DatabaseError, Pool, PoolMetrics};
+
+mod health;
+#[cfg(feature = "backend_poem")]
+pub use health::api as health_api;
+#[cfg(feature = "plugin_tasks")]
+pub use health::check_queue;
+#[cfg(feature = "plugin_storage")]
+pub use health::check_storage;
+#[cfg(feature = "backend_actix-web")]
+pub use health::endpoints as health_endpoints;
+pub use health::{check_database, HealthCheck, HealthReport};
 
 #[cfg(feature = "backend_poem")]
 mod logger;
@@ -41,11 +80,39 @@ pub use logger::Logger as PoemLogger;
 
 #[cfg(feature = "plugin_storage")]
 mod storage;
+#[cfg(all(feature = "plugin_storage-variants", feature = "plugin_tasks"))]
+pub use storage::ProcessVariants;
+#[cfg(all(feature = "plugin_storage", feature = "plugin_tasks"))]
+pub use storage::ReconcileStorage;
 #[cfg(feature = "plugin_storage")]
-pub use storage::{Attachment, AttachmentBlob, AttachmentData, Storage};
+pub use storage::{
+    sniff_content_type, Attachment, AttachmentBlob, AttachmentData, AttachmentDb, AttachmentRules,
+    ClamAvScanHook, DirectUpload, Encryption, HasAttachments, HasMany, HasOne, LocalBackend,
+    MemoryBackend, ObjectMetadata, ObjectReader, OrphanedObject, ReconcileOptions, ReconcileReport,
+    RetryPolicy, S3Backend, S3Config, S3Credentials, ScanHook, ScanResult, ServerSideEncryption,
+    Storage, StorageBackend, StorageError, UploadSession, UploadedObject, UPLOAD_CHUNK_SIZE,
+};
+#[cfg(feature = "plugin_storage-variants")]
+pub use storage::{ProcessedVariant, Transformation, Variant, VariantFormat};
+// used by `has_attachments!`
+#[cfg(feature = "plugin_storage")]
+#[doc(hidden)]
+pub use {diesel::QueryResult as __QueryResult, paste as __paste};
 
 mod mailer;
-pub use mailer::Mailer;
+#[cfg(feature = "backend_poem")]
+pub use mailer::api as mail_api;
+#[cfg(feature = "backend_actix-web")]
+pub use mailer::endpoints as mail_endpoints;
+#[cfg(feature = "plugin_tasks")]
+pub use mailer::DeliverOutbox;
+pub use mailer::{
+    preferred_locale, Email, EmailAddress, EmailAttachment, EmailSuppression, FileFormat,
+    FileTransport, HttpRequestBody, HttpTransport, MailError, MailNotification, MailTemplates,
+    MailTransport, Mailer, MemoryTransport, OutboxEmail, OutboxOptions, OutboxReport, OutboxStatus,
+    RenderedEmail, SendmailTransport, SentEmail, SmtpConfig, SmtpTls, SmtpTransport,
+    SuppressionReason, TransportError,
+};
 #[cfg(feature = "plugin_auth")]
 pub use mailer::{DefaultMailTemplates, EmailTemplates};
 
@@ -84,7 +151,7 @@ impl AppData {
         mut self,
         templates: T,
     ) -> Self {
-        self.mailer = Mailer::new(Box::new(templates));
+        self.mailer.templates = Box::new(templates);
         self
     }
 }
@@ -108,10 +175,24 @@ fn load_env_vars() {
 ///
 /// # Panics
 ///
-/// Panics if required environment variables are not present
-/// TODO: should we panic here? wouldn't it be better to return a Result and let the user handle the error?
+/// Panics if required environment variables are not present, or if the database pool can't be set up
+/// (see [`Database::new`]); to handle that error, use [`try_setup`], and to tune the pool, call
+/// [`Database::init`] first
 #[must_use]
 pub fn setup() -> AppData {
+    try_setup().unwrap_or_else(|err| panic!("ERROR: {err}"))
+}
+
+/// like [`setup`], returning the error instead of panicking when the configuration is invalid or
+/// the database is unreachable
+///
+/// the app's pool is set up from the environment (see [`Database::init`]), unless it already was
+///
+/// # Errors
+/// * [`DatabaseError::Config`] if a required environment variable (`DATABASE_URL`, or `SECRET_KEY`
+///   with the auth plugin) is missing, or the pool settings are invalid
+/// * [`DatabaseError::Connection`] if the database can't be reached
+pub fn try_setup() -> Result<AppData, DatabaseError> {
     // Only load dotenv in development
     #[cfg(debug_assertions)]
     {
@@ -122,22 +203,70 @@ pub fn setup() -> AppData {
     }
 
     #[cfg(feature = "plugin_auth")]
-    assert!(
-        std::env::var("SECRET_KEY").is_ok(),
-        "No SECRET_KEY environment variable set!"
-    );
-
-    assert!(
-        std::env::var("DATABASE_URL").is_ok(),
-        "No DATABASE_URL environment variable set!"
-    );
-
-    AppData {
-        mailer: Mailer::default(),
-        database: Database::new(),
+    if std::env::var("SECRET_KEY").is_err() {
+        return Err(DatabaseError::Config(
+            "No SECRET_KEY environment variable set!".to_string(),
+        ));
+    }
+
+    let database = Database::try_shared()?;
+
+    Ok(AppData {
+        mailer: Mailer::default().with_suppression_list(database.clone()),
+        database,
         #[cfg(feature = "plugin_storage")]
         storage: Storage::new(),
+    })
+}
+
+/// how [`setup_with`] prepares the database
+#[cfg(feature = "database_migrations")]
+#[derive(Clone, Copy, Default)]
+pub struct SetupOptions {
+    /// the app's migrations, embedded with `diesel_migrations::embed_migrations!("migrations")`; they run
+    /// after the plugins' migrations, so they can reference the plugins' tables
+    pub app_migrations: Option<&'static diesel_migrations::EmbeddedMigrations>,
+    /// run the pending migrations of the app and of the plugins at startup (see [`Database::run_migrations`]);
+    /// otherwise, the pending ones are only reported
+    pub migrate: bool,
+}
+
+/// like [`setup`], then runs the pending migrations (when [`SetupOptions::migrate`] is set) or prints
+/// the ones that are pending, since release builds have no dev server to show them
+///
+/// # Panics
+///
+/// like [`setup`], and if the migrations can't be run (or read)
+#[cfg(feature = "database_migrations")]
+#[must_use]
+pub fn setup_with(options: SetupOptions) -> AppData {
+    let app_data = setup();
+
+    if options.migrate {
+        let ran = app_data
+            .database
+            .run_migrations(options.app_migrations)
+            .unwrap_or_else(|err| panic!("ERROR: {err}"));
+        for migration in ran {
+            println!(
+                "Ran the migration {} ({})",
+                migration.name, migration.source
+            );
+        }
+    } else {
+        let status = app_data
+            .database
+            .migration_status(options.app_migrations)
+            .unwrap_or_else(|err| panic!("ERROR: {err}"));
+        for migration in status.iter().filter(|migration| !migration.applied) {
+            println!(
+                "Pending migration: {} ({})",
+                migration.name, migration.source
+            );
+        }
     }
+
+    app_data
 }
 
 #[cfg(feature = "backend_poem")]
@@ -0,0 +1,379 @@
+use std::fmt;
+use std::str::FromStr;
+
+use lettre::message::header::{ContentType, HeaderName, HeaderValue};
+use lettre::message::{Attachment, Mailbox, Message, MultiPart, SinglePart};
+use serde::{Deserialize, Serialize};
+
+use super::MailError;
+
+/// A valid email address, optionally with a display name (`Ada Lovelace <ada@example.com>`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EmailAddress(Mailbox);
+
+impl EmailAddress {
+    /// # Errors
+    /// * [`MailError::InvalidAddress`] if `address` is not a valid email address
+    pub fn new(address: &str) -> Result<Self, MailError> {
+        address.trim().parse::<Mailbox>().map(Self).map_err(|err| {
+            MailError::InvalidAddress(format!("Invalid address '{address}' ({err})"))
+        })
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.0.name = Some(name.into());
+        self
+    }
+
+    /// the address, without the display name
+    #[must_use]
+    pub fn address(&self) -> &str {
+        self.0.email.as_ref()
+    }
+
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.0.name.as_deref()
+    }
+
+    pub(crate) fn mailbox(&self) -> Mailbox {
+        self.0.clone()
+    }
+}
+
+impl FromStr for EmailAddress {
+    type Err = MailError;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Self::new(address)
+    }
+}
+
+impl TryFrom<&str> for EmailAddress {
+    type Error = MailError;
+
+    fn try_from(address: &str) -> Result<Self, Self::Error> {
+        Self::new(address)
+    }
+}
+
+impl TryFrom<String> for EmailAddress {
+    type Error = MailError;
+
+    fn try_from(address: String) -> Result<Self, Self::Error> {
+        Self::new(&address)
+    }
+}
+
+impl From<EmailAddress> for String {
+    fn from(address: EmailAddress) -> Self {
+        address.to_string()
+    }
+}
+
+impl fmt::Display for EmailAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A file attached to an [`Email`]
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+    /// set for inline images, which the html refers to as `<img src="cid:{content_id}">`
+    pub content_id: Option<String>,
+}
+
+impl EmailAttachment {
+    /// a file the recipient can download
+    #[must_use]
+    pub fn new(
+        file_name: impl Into<String>,
+        content_type: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        Self {
+            file_name: file_name.into(),
+            content_type: content_type.into(),
+            bytes,
+            content_id: None,
+        }
+    }
+
+    /// an image shown in the html, as `<img src="cid:{content_id}">`
+    #[must_use]
+    pub fn inline(
+        content_id: impl Into<String>,
+        content_type: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        let content_id = content_id.into();
+        Self {
+            file_name: content_id.clone(),
+            content_type: content_type.into(),
+            bytes,
+            content_id: Some(content_id),
+        }
+    }
+
+    /// the file of a plugin_storage [`Attachment`](`crate::Attachment`)
+    ///
+    /// # Errors
+    /// * [`MailError::Database`] if the attachment's blob could not be found
+    /// * [`MailError::Attachment`] if the file could not be downloaded
+    #[cfg(feature = "plugin_storage")]
+    pub async fn from_storage(
+        storage: &crate::Storage,
+        db: &mut crate::Connection,
+        attachment: &crate::Attachment,
+    ) -> Result<Self, MailError> {
+        use tokio::io::AsyncReadExt;
+
+        let blob = crate::AttachmentBlob::find_by_id(db, attachment.blob_id)?;
+        let mut reader = storage.blob_download_stream(&blob).await?;
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await.map_err(|err| {
+            MailError::Attachment(format!("Could not download '{}' ({err})", blob.file_name))
+        })?;
+
+        Ok(Self::new(
+            blob.file_name,
+            blob.content_type
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            bytes,
+        ))
+    }
+
+    fn part(&self) -> Result<SinglePart, MailError> {
+        let content_type = ContentType::parse(&self.content_type).map_err(|err| {
+            MailError::InvalidMessage(format!(
+                "Invalid content type '{}' for '{}' ({err})",
+                self.content_type, self.file_name
+            ))
+        })?;
+        let attachment = match &self.content_id {
+            Some(content_id) => Attachment::new_inline(content_id.clone()),
+            None => Attachment::new(self.file_name.clone()),
+        };
+
+        Ok(attachment.body(self.bytes.clone(), content_type))
+    }
+}
+
+/// An email for [`Mailer::send_email`](`super::Mailer::send_email`):
+///
+/// ```rust,ignore
+/// let email = Email::new(EmailAddress::new("user@example.com")?, "Your invoice")
+///     .cc(EmailAddress::new("accounting@example.com")?)
+///     .reply_to(EmailAddress::new("billing@example.com")?)
+///     .text("Your invoice is attached.")
+///     .html(r#"<img src="cid:logo"><p>Your invoice is attached.</p>"#)
+///     .attach(EmailAttachment::inline("logo", "image/png", logo))
+///     .attach(EmailAttachment::new("invoice.pdf", "application/pdf", invoice));
+///
+/// mailer.send_email(&email)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Email {
+    pub to: Vec<EmailAddress>,
+    pub cc: Vec<EmailAddress>,
+    pub bcc: Vec<EmailAddress>,
+    pub reply_to: Option<EmailAddress>,
+    pub subject: String,
+    pub text: String,
+    /// sent as an alternative to `text`, if set
+    pub html: Option<String>,
+    /// extra headers, like `List-Unsubscribe`
+    pub headers: Vec<(String, String)>,
+    pub attachments: Vec<EmailAttachment>,
+}
+
+impl Email {
+    #[must_use]
+    pub fn new(to: EmailAddress, subject: impl Into<String>) -> Self {
+        Self {
+            to: vec![to],
+            subject: subject.into(),
+            ..Self::default()
+        }
+    }
+
+    /// adds a recipient
+    #[must_use]
+    pub fn to(mut self, address: EmailAddress) -> Self {
+        self.to.push(address);
+        self
+    }
+
+    #[must_use]
+    pub fn cc(mut self, address: EmailAddress) -> Self {
+        self.cc.push(address);
+        self
+    }
+
+    #[must_use]
+    pub fn bcc(mut self, address: EmailAddress) -> Self {
+        self.bcc.push(address);
+        self
+    }
+
+    #[must_use]
+    pub fn reply_to(mut self, address: EmailAddress) -> Self {
+        self.reply_to = Some(address);
+        self
+    }
+
+    #[must_use]
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    #[must_use]
+    pub fn html(mut self, html: impl Into<String>) -> Self {
+        self.html = Some(html.into());
+        self
+    }
+
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn attach(mut self, attachment: EmailAttachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    /// the MIME message from `from`: the text and html alternatives, the html related to its inline
+    /// images, and all of it mixed with the other attachments
+    pub(crate) fn message(&self, from: &EmailAddress) -> Result<Message, MailError> {
+        let mut builder = Message::builder()
+            .from(from.mailbox())
+            .subject(self.subject.clone());
+        for address in &self.to {
+            builder = builder.to(address.mailbox());
+        }
+        for address in &self.cc {
+            builder = builder.cc(address.mailbox());
+        }
+        for address in &self.bcc {
+            builder = builder.bcc(address.mailbox());
+        }
+        if let Some(address) = &self.reply_to {
+            builder = builder.reply_to(address.mailbox());
+        }
+
+        let (inline, files): (Vec<_>, Vec<_>) = self
+            .attachments
+            .iter()
+            .partition(|attachment| attachment.content_id.is_some());
+
+        let body = match &self.html {
+            Some(html) if inline.is_empty() => {
+                MultiPart::alternative_plain_html(self.text.clone(), html.clone())
+            }
+            Some(html) => {
+                let mut related = MultiPart::related().singlepart(SinglePart::html(html.clone()));
+                for attachment in inline {
+                    related = related.singlepart(attachment.part()?);
+                }
+                MultiPart::alternative()
+                    .singlepart(SinglePart::plain(self.text.clone()))
+                    .multipart(related)
+            }
+            None if inline.is_empty() && files.is_empty() => {
+                return Self::with_headers(
+                    builder.singlepart(SinglePart::plain(self.text.clone())),
+                    &self.headers,
+                );
+            }
+            None => MultiPart::mixed().singlepart(SinglePart::plain(self.text.clone())),
+        };
+
+        let body = if files.is_empty() {
+            body
+        } else {
+            let mut mixed = MultiPart::mixed().multipart(body);
+            for attachment in files {
+                mixed = mixed.singlepart(attachment.part()?);
+            }
+            mixed
+        };
+
+        Self::with_headers(builder.multipart(body), &self.headers)
+    }
+
+    fn with_headers(
+        message: Result<Message, lettre::error::Error>,
+        headers: &[(String, String)],
+    ) -> Result<Message, MailError> {
+        let mut message = message.map_err(|err| MailError::InvalidMessage(err.to_string()))?;
+        for (name, value) in headers {
+            let header_name = HeaderName::new_from_ascii(name.clone()).map_err(|err| {
+                MailError::InvalidMessage(format!("Invalid header name '{name}' ({err})"))
+            })?;
+            message
+                .headers_mut()
+                .insert_raw(HeaderValue::new(header_name, value.clone()));
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_multipart_messages() {
+        assert!(matches!(
+            EmailAddress::new("not an address"),
+            Err(MailError::InvalidAddress(_))
+        ));
+
+        let from = EmailAddress::new("App <app@example.com>").unwrap();
+        let email = Email::new(EmailAddress::new("user@example.com").unwrap(), "Invoice")
+            .cc(EmailAddress::new("cc@example.com").unwrap())
+            .bcc(EmailAddress::new("bcc@example.com").unwrap())
+            .reply_to(EmailAddress::new("billing@example.com").unwrap())
+            .header("X-Campaign", "invoices")
+            .text("See attached")
+            .html(r#"<img src="cid:logo">"#)
+            .attach(EmailAttachment::inline("logo", "image/png", vec![1, 2, 3]))
+            .attach(EmailAttachment::new(
+                "invoice.pdf",
+                "application/pdf",
+                vec![4, 5, 6],
+            ));
+
+        let message = email.message(&from).unwrap();
+        let recipients = message.envelope().to();
+        assert_eq!(recipients.len(), 3);
+
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("From: App <app@example.com>\r\n"));
+        assert!(formatted.contains("Reply-To: billing@example.com\r\n"));
+        assert!(formatted.contains("X-Campaign: invoices\r\n"));
+        assert!(!formatted.contains("bcc@example.com"));
+        assert!(formatted.contains("multipart/mixed"));
+        assert!(formatted.contains("multipart/related"));
+        assert!(formatted.contains("Content-ID: <logo>"));
+        assert!(formatted.contains("filename=\"invoice.pdf\""));
+
+        let bad_header = Email::new(EmailAddress::new("user@example.com").unwrap(), "Hi")
+            .header("Bad Header", "value");
+        assert!(matches!(
+            bad_header.message(&from),
+            Err(MailError::InvalidMessage(_))
+        ));
+    }
+}
@@ -0,0 +1,44 @@
+use actix_http::StatusCode;
+use actix_web::{
+    post,
+    web::{self, Bytes, Data, Query},
+    HttpResponse, Result, Scope,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::mailer::webhooks;
+use crate::Database;
+
+#[derive(Deserialize)]
+struct WebhookParams {
+    token: Option<String>,
+}
+
+/// handler for POST requests at the .../webhooks endpoint
+///
+/// see [`receive_notifications`](`webhooks::receive_notifications`)
+#[post("/webhooks")]
+async fn receive_notifications(
+    db: Data<Database>,
+    Query(params): Query<WebhookParams>,
+    body: Bytes,
+) -> Result<HttpResponse> {
+    let result =
+        web::block(move || webhooks::receive_notifications(&db, params.token.as_deref(), &body))
+            .await?;
+
+    match result {
+        Ok(suppressed) => Ok(HttpResponse::Ok().json(json!({ "suppressed": suppressed }))),
+        Err((status_code, error_message)) => Ok(HttpResponse::build(
+            StatusCode::from_u16(status_code).unwrap(),
+        )
+        .body(json!({ "message": error_message }).to_string())),
+    }
+}
+
+/// returns the endpoints for the mail webhooks
+#[must_use]
+pub fn endpoints(scope: Scope) -> Scope {
+    scope.service(receive_notifications)
+}
@@ -0,0 +1,43 @@
+use poem::{
+    handler,
+    http::StatusCode,
+    post,
+    web::{Data, Json, Query},
+    Error, Result, Route,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::mailer::webhooks;
+use crate::Database;
+
+#[derive(Deserialize)]
+struct WebhookParams {
+    token: Option<String>,
+}
+
+/// handler for POST requests at the .../webhooks endpoint
+///
+/// see [`receive_notifications`](`webhooks::receive_notifications`)
+#[handler]
+async fn receive_notifications(
+    db: Data<&Database>,
+    Query(params): Query<WebhookParams>,
+    body: Vec<u8>,
+) -> Result<Json<Value>> {
+    let result = webhooks::receive_notifications(db.0, params.token.as_deref(), &body);
+
+    match result {
+        Ok(suppressed) => Ok(Json(json!({ "suppressed": suppressed }))),
+        Err((status_code, message)) => Err(Error::from_string(
+            json!({ "message": message }).to_string(),
+            StatusCode::from_u16(status_code).unwrap(),
+        )),
+    }
+}
+
+/// returns a poem Route with the endpoints for the mail webhooks
+#[must_use]
+pub fn api() -> Route {
+    Route::new().at("/webhooks", post(receive_notifications))
+}
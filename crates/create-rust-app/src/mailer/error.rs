@@ -0,0 +1,77 @@
+use std::fmt;
+
+use super::TransportError;
+
+/// Why an email could not be sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailError {
+    /// a recipient, or the mailer's `from_address`, is not a valid email address
+    InvalidAddress(String),
+    /// the message could not be built, like when a header name or a content type is invalid
+    InvalidMessage(String),
+    /// a template is missing or could not be rendered
+    Template(String),
+    /// an attachment could not be loaded
+    Attachment(String),
+    /// a query (to the outbox, or for an attachment) failed
+    Database(String),
+    /// every recipient is on the suppression list, see [`EmailSuppression`](`super::EmailSuppression`)
+    Suppressed(String),
+    /// the transport could not deliver the message
+    Transport(TransportError),
+}
+
+impl MailError {
+    /// the description of the error
+    #[must_use]
+    pub fn message(&self) -> &str {
+        match self {
+            Self::InvalidAddress(message)
+            | Self::InvalidMessage(message)
+            | Self::Template(message)
+            | Self::Attachment(message)
+            | Self::Database(message)
+            | Self::Suppressed(message) => message,
+            Self::Transport(error) => &error.message,
+        }
+    }
+
+    /// whether trying again later may succeed, like when the SMTP server is unreachable
+    #[must_use]
+    pub const fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Transport(TransportError {
+                permanent: false,
+                ..
+            })
+        )
+    }
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for MailError {}
+
+impl From<TransportError> for MailError {
+    fn from(error: TransportError) -> Self {
+        Self::Transport(error)
+    }
+}
+
+impl From<diesel::result::Error> for MailError {
+    fn from(err: diesel::result::Error) -> Self {
+        Self::Database(err.to_string())
+    }
+}
+
+#[cfg(feature = "plugin_storage")]
+impl From<crate::StorageError> for MailError {
+    fn from(err: crate::StorageError) -> Self {
+        Self::Attachment(err.to_string())
+    }
+}
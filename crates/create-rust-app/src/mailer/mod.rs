@@ -0,0 +1,424 @@
+#[cfg(feature = "plugin_auth")]
+use dyn_clone::{clone_trait_object, DynClone};
+
+use std::sync::Arc;
+
+use lettre::address::Envelope;
+
+use crate::Database;
+
+mod email;
+mod endpoints;
+mod error;
+mod outbox;
+mod schema;
+mod suppression;
+mod templates;
+mod transport;
+mod webhooks;
+
+pub use email::{Email, EmailAddress, EmailAttachment};
+#[cfg(feature = "backend_poem")]
+pub use endpoints::api;
+#[cfg(feature = "backend_actix-web")]
+pub use endpoints::endpoints;
+pub use error::MailError;
+#[cfg(feature = "plugin_tasks")]
+pub use outbox::DeliverOutbox;
+pub use outbox::{OutboxEmail, OutboxOptions, OutboxReport, OutboxStatus};
+pub use suppression::{EmailSuppression, SuppressionReason};
+pub use templates::{preferred_locale, MailTemplates, RenderedEmail};
+pub use transport::{
+    FileFormat, FileTransport, HttpRequestBody, HttpTransport, MailTransport, MemoryTransport,
+    SendmailTransport, SentEmail, SmtpConfig, SmtpTls, SmtpTransport, TransportError,
+};
+pub use webhooks::MailNotification;
+
+// the DyncClone trait bound is for cloning, and the
+// Send trait bound is for thread-safety
+#[cfg(feature = "plugin_auth")]
+/// A trait that defines how the auth emails are rendered
+///
+/// the auth controllers write them to the outbox (see [`Mailer::enqueue`]) in the transaction making
+/// the change they're about, and the outbox worker delivers them
+///
+/// `locale` is the recipient's (like `fr-CA`) when it's known, see [`preferred_locale`]
+///
+/// The methods fail with a [`MailError`] when the email could not be rendered
+pub trait EmailTemplates: DynClone + Sync + Send {
+    fn activated(&self, to_email: &str, locale: Option<&str>) -> Result<Email, MailError>;
+    fn password_changed(&self, to_email: &str, locale: Option<&str>) -> Result<Email, MailError>;
+    fn password_reset(&self, to_email: &str, locale: Option<&str>) -> Result<Email, MailError>;
+    fn recover_existent_account(
+        &self,
+        to_email: &str,
+        link: &str,
+        locale: Option<&str>,
+    ) -> Result<Email, MailError>;
+    fn recover_nonexistent_account(
+        &self,
+        to_email: &str,
+        link: &str,
+        locale: Option<&str>,
+    ) -> Result<Email, MailError>;
+    fn register(
+        &self,
+        to_email: &str,
+        link: &str,
+        locale: Option<&str>,
+    ) -> Result<Email, MailError>;
+}
+
+#[cfg(feature = "plugin_auth")]
+clone_trait_object!(EmailTemplates);
+
+#[derive(Clone)]
+/// struct used to handle sending emails
+pub struct Mailer {
+    /// the email address emails should be sent from
+    ///
+    /// set by the `SMTP_FROM_ADDRESS` environment variable
+    pub from_address: String,
+    /// the smtp server to connect to for purposes of sending emails
+    ///
+    /// set by the `SMTP_SERVER` environment variable
+    pub smtp_server: String,
+    /// username used to log into `SMTP_SERVER`
+    ///
+    /// set by the `SMTP_USERNAME` environment variable
+    pub smtp_username: String,
+    /// the password used to log into `SMTP_SERVER`
+    ///
+    /// set by the `SMTP_PASSWORD` environment variable
+    pub smtp_password: String,
+    /// whether or not emails should actually be sent when requested
+    ///
+    /// it may be useful to set this to false in some devolopment environments
+    /// while setting it to true in production
+    ///
+    /// set by the `SEND_MAIL` environment variable
+    pub actually_send: bool,
+    /// delivers the emails; selected by the `MAIL_TRANSPORT` environment variable, see [`Mailer::with_transport`]
+    transport: Arc<dyn MailTransport>,
+    /// see [`Mailer::with_suppression_list`]
+    suppressions: Option<Database>,
+    #[cfg(feature = "plugin_auth")]
+    // Structure containing email templates to be used for various purposes
+    pub templates: Box<dyn EmailTemplates + Sync + Send>,
+}
+
+impl Default for Mailer {
+    #[cfg(feature = "plugin_auth")]
+    fn default() -> Self {
+        Self::new(Box::<DefaultMailTemplates>::default())
+    }
+    #[cfg(not(feature = "plugin_auth"))]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mailer {
+    /// using information stored in the `SMTP_FROM_ADDRESS`, `SMTP_SERVER`, `SMTP_USERNAME`, `SMTP_PASSWORD`, and `SEND_MAIL`
+    /// environment variables to connect to a remote SMTP server,
+    ///
+    /// allows webservers to send emails to users for purposes
+    /// like marketing, user authentification, etc.
+    #[cfg(not(feature = "plugin_auth"))]
+    pub fn new() -> Self {
+        Mailer::check_environment_variables();
+
+        let from_address: String = std::env::var("SMTP_FROM_ADDRESS")
+            .unwrap_or_else(|_| "create-rust-app@localhost".to_string());
+        let smtp_server: String = std::env::var("SMTP_SERVER").unwrap_or_else(|_| "".to_string());
+        let smtp_username: String =
+            std::env::var("SMTP_USERNAME").unwrap_or_else(|_| "".to_string());
+        let smtp_password: String =
+            std::env::var("SMTP_PASSWORD").unwrap_or_else(|_| "".to_string());
+        let actually_send: bool = std::env::var("SEND_MAIL")
+            .unwrap_or_else(|_| "false".to_string())
+            .eq_ignore_ascii_case("true");
+        let transport = Self::transport_from_env(actually_send);
+        Mailer {
+            from_address,
+            smtp_server,
+            smtp_username,
+            smtp_password,
+            actually_send,
+            transport,
+            suppressions: None,
+        }
+    }
+
+    #[cfg(feature = "plugin_auth")]
+    #[must_use]
+    pub fn new(templates: Box<dyn EmailTemplates + Sync + Send>) -> Self {
+        Self::check_environment_variables();
+
+        let from_address: String = std::env::var("SMTP_FROM_ADDRESS")
+            .unwrap_or_else(|_| "create-rust-app@localhost".to_string());
+        let smtp_server: String = std::env::var("SMTP_SERVER").unwrap_or_else(|_| String::new());
+        let smtp_username: String =
+            std::env::var("SMTP_USERNAME").unwrap_or_else(|_| String::new());
+        let smtp_password: String =
+            std::env::var("SMTP_PASSWORD").unwrap_or_else(|_| String::new());
+        let actually_send: bool = std::env::var("SEND_MAIL")
+            .unwrap_or_else(|_| "false".to_string())
+            .eq_ignore_ascii_case("true");
+        let transport = Self::transport_from_env(actually_send);
+        Self {
+            from_address,
+            smtp_server,
+            smtp_username,
+            smtp_password,
+            actually_send,
+            transport,
+            suppressions: None,
+            templates,
+        }
+    }
+
+    /// checks that the required environment variables are set
+    ///
+    /// prints warnings denoting which, if any, of the required
+    /// environment variables were not set (this runs before apps set up their logger)
+    pub fn check_environment_variables() {
+        let mut vars = vec!["SMTP_FROM_ADDRESS", "SEND_MAIL"];
+        if std::env::var("MAIL_TRANSPORT").map_or(true, |name| name.eq_ignore_ascii_case("smtp")) {
+            vars.push("SMTP_SERVER");
+        }
+
+        let unset_vars = vars
+            .into_iter()
+            .filter(|v| std::env::var(v).is_err())
+            .collect::<Vec<_>>();
+
+        if !unset_vars.is_empty() {
+            println!(
+                "Warning: Mailing disabled; the following variables must be set: {}",
+                unset_vars.join(", ")
+            );
+        }
+
+        let send_mail_value = std::env::var("SEND_MAIL").unwrap_or_default();
+        if !send_mail_value.eq_ignore_ascii_case("true")
+            && !send_mail_value.eq_ignore_ascii_case("false")
+        {
+            println!("Warning: SEND_MAIL must be `true` or `false`");
+        }
+    }
+
+    /// replaces the transport emails are delivered with, like a [`MemoryTransport`] in tests;
+    /// emails are then delivered whatever `actually_send` is
+    #[must_use]
+    pub fn with_transport(mut self, transport: impl MailTransport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// send an email with the specifified content and subject to the specified user
+    ///
+    /// will only send an email if the `SEND_MAIL` environment variable was set to true when
+    /// this mailer was initialized.
+    ///
+    /// # Arguments
+    /// * `to` - the email address of the intended recipient
+    /// * `subject` - subject field of the email
+    /// * `text` - text content of the email
+    /// * `html` - html content of the email
+    ///
+    /// # Errors
+    /// * see [`Mailer::send_email`]
+    pub fn send(
+        &self,
+        to: &EmailAddress,
+        subject: &str,
+        text: &str,
+        html: &str,
+    ) -> Result<(), MailError> {
+        self.send_email(&Email::new(to.clone(), subject).text(text).html(html))
+    }
+
+    /// send an [`Email`], with its copies, headers and attachments
+    ///
+    /// # Errors
+    /// * [`MailError::InvalidAddress`] if `from_address` is not a valid email address
+    /// * [`MailError::InvalidMessage`] if a header or an attachment's content type is invalid
+    /// * [`MailError::Suppressed`] if all the recipients are on the suppression list
+    /// * [`MailError::Transport`] if the transport could not deliver it
+    pub fn send_email(&self, email: &Email) -> Result<(), MailError> {
+        let message = email.message(&self.from()?)?;
+        let envelope = self.without_suppressed(message.envelope())?;
+
+        let result = self.deliver(&envelope, &message.formatted());
+        // not the body, which can hold activation and password reset links
+        log::debug!(
+            "Sent email {:?} to {:?} from {}: {:?}",
+            email.subject,
+            email.to.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            self.from_address,
+            result
+        );
+
+        result.map_err(MailError::from)
+    }
+
+    /// the parsed `from_address`
+    fn from(&self) -> Result<EmailAddress, MailError> {
+        EmailAddress::new(&self.from_address)
+    }
+
+    /// hands the formatted `message` to the transport
+    fn deliver(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError> {
+        self.transport.send(envelope, message)
+    }
+
+    /// the transport selected by `MAIL_TRANSPORT`; if `actually_send` is false, one discarding every email,
+    /// or capturing them in the development server when it started the app
+    fn transport_from_env(actually_send: bool) -> Arc<dyn MailTransport> {
+        if !actually_send {
+            #[cfg(all(feature = "plugin_dev", debug_assertions))]
+            if let Some(transport) = transport::dev_server() {
+                return transport;
+            }
+            return Arc::new(transport::StubTransport);
+        }
+
+        transport::from_env().unwrap_or_else(|error| {
+            println!("Warning: Mailing disabled; {error}");
+            Arc::new(transport::StubTransport)
+        })
+    }
+}
+
+#[cfg(feature = "plugin_auth")]
+#[derive(Clone)]
+/// renders the auth emails with [`MailTemplates`], so their copy and branding can be changed with template files
+pub struct DefaultMailTemplates {
+    pub base_url: String,
+    pub templates: MailTemplates,
+}
+#[cfg(feature = "plugin_auth")]
+impl DefaultMailTemplates {
+    /// uses the templates in the mail templates folder, see [`MailTemplates`]
+    #[must_use]
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            templates: MailTemplates::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_templates(mut self, templates: MailTemplates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// renders the email `name`; `link` is relative to `base_url`
+    ///
+    /// # Errors
+    /// * [`MailError::InvalidAddress`] if `to_email` is not a valid email address
+    /// * [`MailError::Template`] if the email could not be rendered
+    fn render(
+        &self,
+        name: &str,
+        to_email: &str,
+        link: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<Email, MailError> {
+        let to = EmailAddress::new(to_email)?;
+        let mut context = tera::Context::new();
+        context.insert("email", to_email);
+        context.insert("base_url", &self.base_url);
+        if let Some(url_path) = link {
+            context.insert(
+                "link",
+                &format!("{base_url}{url_path}", base_url = self.base_url),
+            );
+        }
+
+        let email = self
+            .templates
+            .render(name, locale, &context)
+            .map_err(MailError::Template)?;
+        Ok(Email::new(to, email.subject)
+            .text(email.text)
+            .html(email.html))
+    }
+}
+#[cfg(feature = "plugin_auth")]
+impl Default for DefaultMailTemplates {
+    fn default() -> Self {
+        Self::new("http://localhost:3000/")
+    }
+}
+#[cfg(feature = "plugin_auth")]
+impl EmailTemplates for DefaultMailTemplates {
+    fn activated(&self, to_email: &str, locale: Option<&str>) -> Result<Email, MailError> {
+        self.render("activated", to_email, None, locale)
+    }
+    fn password_changed(&self, to_email: &str, locale: Option<&str>) -> Result<Email, MailError> {
+        self.render("password_changed", to_email, None, locale)
+    }
+    fn password_reset(&self, to_email: &str, locale: Option<&str>) -> Result<Email, MailError> {
+        self.render("password_reset", to_email, None, locale)
+    }
+    fn recover_existent_account(
+        &self,
+        to_email: &str,
+        url_path: &str,
+        locale: Option<&str>,
+    ) -> Result<Email, MailError> {
+        self.render("recover_existent_account", to_email, Some(url_path), locale)
+    }
+    fn recover_nonexistent_account(
+        &self,
+        to_email: &str,
+        url_path: &str,
+        locale: Option<&str>,
+    ) -> Result<Email, MailError> {
+        self.render(
+            "recover_nonexistent_account",
+            to_email,
+            Some(url_path),
+            locale,
+        )
+    }
+    fn register(
+        &self,
+        to_email: &str,
+        url_path: &str,
+        locale: Option<&str>,
+    ) -> Result<Email, MailError> {
+        self.render("register", to_email, Some(url_path), locale)
+    }
+}
+
+#[cfg(all(test, feature = "plugin_auth"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_the_auth_emails() {
+        let templates = DefaultMailTemplates::new("https://example.com/")
+            .with_templates(MailTemplates::built_in_only());
+
+        let email = templates
+            .register("ada@example.com", "activate?token=abc", None)
+            .unwrap();
+        assert_eq!(
+            email.to,
+            vec![EmailAddress::new("ada@example.com").unwrap()]
+        );
+        assert!(email
+            .text
+            .contains("https://example.com/activate?token=abc"));
+        assert!(email.html.is_some());
+
+        assert!(matches!(
+            templates.activated("not an address", None),
+            Err(MailError::InvalidAddress(_))
+        ));
+    }
+}
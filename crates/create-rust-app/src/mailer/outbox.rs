@@ -0,0 +1,519 @@
+use std::fmt;
+use std::time::Duration;
+
+use lettre::address::{Address, Envelope};
+use serde::{Deserialize, Serialize};
+
+use crate::diesel::{
+    insert_into, ExpressionMethods, Identifiable, Insertable, QueryDsl, QueryResult, Queryable,
+    RunQueryDsl,
+};
+use crate::{Connection, Database, Mailer};
+
+use super::schema::{self, email_outbox};
+use super::transport::TransportError;
+use super::{Email, MailError};
+
+pub(super) type ID = i32;
+
+#[cfg(feature = "database_postgres")]
+pub(super) type Utc = chrono::DateTime<chrono::Utc>;
+#[cfg(not(feature = "database_postgres"))]
+pub(super) type Utc = chrono::NaiveDateTime;
+
+pub(super) fn now() -> Utc {
+    #[cfg(feature = "database_postgres")]
+    return chrono::Utc::now();
+    #[cfg(not(feature = "database_postgres"))]
+    return chrono::Utc::now().naive_utc();
+}
+
+/// Where an [`OutboxEmail`] is in its delivery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxStatus {
+    /// waiting to be (re)tried at `next_attempt_at`
+    Pending,
+    Sent,
+    /// gave up, either because it failed permanently or ran out of attempts; see `last_error`
+    Dead,
+}
+
+impl OutboxStatus {
+    const PENDING: &'static str = "pending";
+    const SENT: &'static str = "sent";
+    const DEAD: &'static str = "dead";
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => Self::PENDING,
+            Self::Sent => Self::SENT,
+            Self::Dead => Self::DEAD,
+        }
+    }
+}
+
+/// An email waiting in the `email_outbox` table, see [`Mailer::enqueue`]
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, Identifiable)]
+#[diesel(table_name = email_outbox)]
+pub struct OutboxEmail {
+    pub id: ID,
+
+    pub from_address: String,
+    /// the envelope recipients, comma-separated
+    pub recipients: String,
+    pub subject: String,
+    /// the formatted message, as it's handed to the SMTP server
+    pub message: Vec<u8>,
+    /// see [`OutboxStatus`]
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Utc,
+
+    pub created_at: Utc,
+    pub sent_at: Option<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = email_outbox)]
+struct OutboxEmailChangeset {
+    from_address: String,
+    recipients: String,
+    subject: String,
+    message: Vec<u8>,
+    status: String,
+    next_attempt_at: Utc,
+}
+
+impl OutboxEmail {
+    /// # Errors
+    /// * Diesel error
+    pub fn find_by_id(db: &mut Connection, item_id: ID) -> QueryResult<Self> {
+        schema::email_outbox::table
+            .filter(schema::email_outbox::id.eq(item_id))
+            .first(db)
+    }
+
+    /// the emails which were given up on, oldest first
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn find_dead(db: &mut Connection) -> QueryResult<Vec<Self>> {
+        schema::email_outbox::table
+            .filter(schema::email_outbox::status.eq(OutboxStatus::DEAD))
+            .order(schema::email_outbox::id.asc())
+            .load(db)
+    }
+
+    /// puts a dead email back in the queue, with a fresh set of attempts
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn requeue(db: &mut Connection, item_id: ID) -> QueryResult<usize> {
+        diesel::update(
+            schema::email_outbox::table
+                .filter(schema::email_outbox::id.eq(item_id))
+                .filter(schema::email_outbox::status.eq(OutboxStatus::DEAD)),
+        )
+        .set((
+            schema::email_outbox::status.eq(OutboxStatus::PENDING),
+            schema::email_outbox::attempts.eq(0),
+            schema::email_outbox::next_attempt_at.eq(now()),
+        ))
+        .execute(db)
+    }
+
+    /// removes sent emails which were delivered before `older_than` ago
+    ///
+    /// # Errors
+    /// * [`MailError::InvalidMessage`] if `older_than` is out of range
+    /// * [`MailError::Database`] if the query failed
+    pub fn purge_sent(db: &mut Connection, older_than: Duration) -> Result<usize, MailError> {
+        let older_than = chrono::Duration::from_std(older_than)
+            .map_err(|err| MailError::InvalidMessage(format!("Invalid duration ({err})")))?;
+
+        Ok(diesel::delete(
+            schema::email_outbox::table
+                .filter(schema::email_outbox::status.eq(OutboxStatus::SENT))
+                .filter(schema::email_outbox::sent_at.lt(now() - older_than)),
+        )
+        .execute(db)?)
+    }
+
+    #[must_use]
+    pub fn status(&self) -> OutboxStatus {
+        match self.status.as_str() {
+            OutboxStatus::SENT => OutboxStatus::Sent,
+            OutboxStatus::DEAD => OutboxStatus::Dead,
+            _ => OutboxStatus::Pending,
+        }
+    }
+
+    /// the emails due for delivery, oldest first
+    fn find_due(db: &mut Connection, limit: i64) -> QueryResult<Vec<Self>> {
+        schema::email_outbox::table
+            .filter(schema::email_outbox::status.eq(OutboxStatus::PENDING))
+            .filter(schema::email_outbox::next_attempt_at.le(now()))
+            .order(schema::email_outbox::next_attempt_at.asc())
+            .limit(limit)
+            .load(db)
+    }
+
+    /// counts the attempt and hides the email from other workers for `lease`;
+    /// returns false if another worker claimed it first
+    fn claim(&self, db: &mut Connection, lease: chrono::Duration) -> QueryResult<bool> {
+        let claimed = diesel::update(
+            schema::email_outbox::table
+                .filter(schema::email_outbox::id.eq(self.id))
+                .filter(schema::email_outbox::status.eq(OutboxStatus::PENDING))
+                .filter(schema::email_outbox::attempts.eq(self.attempts)),
+        )
+        .set((
+            schema::email_outbox::attempts.eq(self.attempts + 1),
+            schema::email_outbox::next_attempt_at.eq(now() + lease),
+        ))
+        .execute(db)?;
+
+        Ok(claimed == 1)
+    }
+
+    fn envelope(&self) -> Result<Envelope, String> {
+        let from = self
+            .from_address
+            .parse::<Address>()
+            .map_err(|err| err.to_string())?;
+        let to = self
+            .recipients
+            .split(',')
+            .map(str::parse::<Address>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())?;
+
+        Envelope::new(Some(from), to).map_err(|err| err.to_string())
+    }
+}
+
+/// How [`Mailer::deliver_outbox`] works through the outbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxOptions {
+    /// the most emails delivered in one call
+    pub batch_size: i64,
+    /// including the first one; emails are marked dead after that many failed attempts
+    pub max_attempts: i32,
+    /// the delay before the first retry; it doubles after every attempt, up to `max_backoff`
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// how long a claimed email is hidden from other workers, in case this one dies while sending it
+    pub lease: Duration,
+}
+
+impl Default for OutboxOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 50,
+            max_attempts: 8,
+            initial_backoff: Duration::from_secs(30),
+            max_backoff: Duration::from_secs(60 * 60),
+            lease: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl OutboxOptions {
+    /// the delay after the `attempts`th failed attempt
+    fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = u32::try_from(attempts.saturating_sub(1)).unwrap_or_default();
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_backoff)
+    }
+}
+
+/// What [`Mailer::deliver_outbox`] did
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OutboxReport {
+    pub sent: usize,
+    /// failed, and will be retried
+    pub retried: usize,
+    /// failed, and won't be retried
+    pub dead: usize,
+}
+
+impl fmt::Display for OutboxReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} sent, {} to retry, {} dead",
+            self.sent, self.retried, self.dead
+        )
+    }
+}
+
+impl Mailer {
+    /// Writes an email to the `email_outbox` table instead of sending it; it's delivered later by
+    /// [`Mailer::deliver_outbox`]. Call this with the connection of the transaction making the change
+    /// the email is about, so it's only sent if that change is committed.
+    ///
+    /// # Errors
+    /// * [`MailError::InvalidAddress`] if the mailer's `from_address` is not a valid email address
+    /// * [`MailError::InvalidMessage`] if the message could not be built
+    /// * [`MailError::Database`] if the query failed
+    pub fn enqueue(&self, db: &mut Connection, email: &Email) -> Result<OutboxEmail, MailError> {
+        let changeset = self.outbox_changeset(email)?;
+
+        #[cfg(not(feature = "database_mysql"))]
+        return Ok(insert_into(schema::email_outbox::table)
+            .values(&changeset)
+            .get_result::<OutboxEmail>(db)?);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(schema::email_outbox::table)
+                .values(&changeset)
+                .execute(db)?;
+            Ok(OutboxEmail::find_by_id(
+                db,
+                crate::database::last_insert_id(db)?,
+            )?)
+        }
+    }
+
+    /// the outbox row of `email`
+    fn outbox_changeset(&self, email: &Email) -> Result<OutboxEmailChangeset, MailError> {
+        let from = self.from()?;
+        let message = email.message(&from)?;
+        let envelope = message.envelope();
+
+        Ok(OutboxEmailChangeset {
+            from_address: from.address().to_string(),
+            recipients: envelope
+                .to()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            subject: email.subject.clone(),
+            message: message.formatted(),
+            status: OutboxStatus::PENDING.to_string(),
+            next_attempt_at: now(),
+        })
+    }
+
+    /// Delivers the emails in the outbox which are due, up to `options.batch_size`. Failed deliveries are
+    /// retried with exponential backoff; emails which fail permanently, run out of attempts, or only have
+    /// suppressed recipients (see [`Mailer::with_suppression_list`]) are marked [dead](`OutboxStatus::Dead`).
+    ///
+    /// Several workers can run this at the same time; each email is only claimed by one of them.
+    /// Sending is blocking, so call this from a worker (see [`Mailer::run_outbox`]) rather than a request handler.
+    ///
+    /// # Errors
+    /// * [`MailError::InvalidMessage`] if `options` are out of range
+    /// * [`MailError::Database`] if a query failed
+    pub fn deliver_outbox(
+        &self,
+        db: &mut Connection,
+        options: &OutboxOptions,
+    ) -> Result<OutboxReport, MailError> {
+        let duration = |duration| {
+            chrono::Duration::from_std(duration)
+                .map_err(|err| MailError::InvalidMessage(format!("Invalid outbox options ({err})")))
+        };
+        let lease = duration(options.lease)?;
+        let mut report = OutboxReport::default();
+
+        for email in OutboxEmail::find_due(db, options.batch_size)? {
+            if !email.claim(db, lease)? {
+                continue;
+            }
+            let attempts = email.attempts + 1;
+
+            let delivered = email
+                .envelope()
+                .map_err(|err| MailError::from(TransportError::permanent(err)))
+                .and_then(|envelope| self.without_suppressed(&envelope))
+                .and_then(|envelope| Ok(self.deliver(&envelope, &email.message)?));
+
+            let update = diesel::update(schema::email_outbox::table.find(email.id));
+            match delivered {
+                Ok(()) => {
+                    update
+                        .set((
+                            schema::email_outbox::status.eq(OutboxStatus::SENT),
+                            schema::email_outbox::sent_at.eq(Some(now())),
+                            schema::email_outbox::last_error.eq(None::<String>),
+                        ))
+                        .execute(db)?;
+                    report.sent += 1;
+                }
+                Err(error) if !error.is_transient() || attempts >= options.max_attempts => {
+                    update
+                        .set((
+                            schema::email_outbox::status.eq(OutboxStatus::DEAD),
+                            schema::email_outbox::last_error.eq(Some(error.to_string())),
+                        ))
+                        .execute(db)?;
+                    report.dead += 1;
+                }
+                Err(error) => {
+                    let backoff = duration(options.backoff(attempts))?;
+                    update
+                        .set((
+                            schema::email_outbox::next_attempt_at.eq(now() + backoff),
+                            schema::email_outbox::last_error.eq(Some(error.to_string())),
+                        ))
+                        .execute(db)?;
+                    report.retried += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Delivers the outbox every `poll_interval`, forever; for a standalone worker process.
+    /// Reports and errors are logged, and the next round is tried anyway.
+    pub fn run_outbox(&self, db: &Database, options: &OutboxOptions, poll_interval: Duration) -> ! {
+        loop {
+            let result = db
+                .get_connection()
+                .map_err(|err| MailError::Database(err.to_string()))
+                .and_then(|mut db| self.deliver_outbox(&mut db, options));
+
+            match result {
+                Ok(report) if report == OutboxReport::default() => {}
+                Ok(report) => log::info!("Email outbox: {report}"),
+                Err(error) => log::error!("Email outbox: {error}"),
+            }
+
+            std::thread::sleep(poll_interval);
+        }
+    }
+}
+
+/// Delivers the email outbox (see [`Mailer::deliver_outbox`]) every minute on the `async` queue,
+/// with the [`Mailer`] built from the environment.
+///
+/// ```rust,ignore
+/// create_rust_app::tasks::async_queue()
+///     .lock()
+///     .unwrap()
+///     .schedule_task(&DeliverOutbox::default() as &dyn AsyncRunnable)
+///     .await?;
+/// ```
+#[cfg(feature = "plugin_tasks")]
+#[derive(Serialize, Deserialize, Default)]
+pub struct DeliverOutbox {
+    pub options: OutboxOptions,
+}
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::insert_into;
+    #[cfg(feature = "database_mysql")]
+    use diesel::QueryDsl;
+    use diesel_async::RunQueryDsl;
+
+    use super::{schema, Email, MailError, Mailer, OutboxEmail};
+    use crate::database::AsyncConnection;
+
+    impl Mailer {
+        /// like [`Mailer::enqueue`], on an [`AsyncConnection`]
+        ///
+        /// # Errors
+        /// * see [`Mailer::enqueue`]
+        pub async fn enqueue_async(
+            &self,
+            db: &mut AsyncConnection,
+            email: &Email,
+        ) -> Result<OutboxEmail, MailError> {
+            let changeset = self.outbox_changeset(email)?;
+
+            #[cfg(not(feature = "database_mysql"))]
+            return Ok(insert_into(schema::email_outbox::table)
+                .values(&changeset)
+                .get_result::<OutboxEmail>(db)
+                .await?);
+
+            #[cfg(feature = "database_mysql")]
+            {
+                insert_into(schema::email_outbox::table)
+                    .values(&changeset)
+                    .execute(db)
+                    .await?;
+                let item_id = crate::database::last_insert_id_async(db).await?;
+                Ok(schema::email_outbox::table
+                    .find(item_id)
+                    .first::<OutboxEmail>(db)
+                    .await?)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "plugin_tasks")]
+mod task {
+    use fang::asynk::async_queue::AsyncQueueable;
+    use fang::{async_trait, typetag, AsyncRunnable, FangError, Scheduled};
+
+    use super::DeliverOutbox;
+    use crate::{Database, MailError, Mailer};
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for DeliverOutbox {
+        async fn run(&self, _queueable: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+            let options = self.options.clone();
+
+            // sending blocks
+            let report = tokio::task::spawn_blocking(move || {
+                let mut db = Database::new()
+                    .get_connection()
+                    .map_err(|err| MailError::Database(err.to_string()))?;
+                Mailer::default().deliver_outbox(&mut db, &options)
+            })
+            .await
+            .map_err(|err| FangError {
+                description: err.to_string(),
+            })?
+            .map_err(|err| FangError {
+                description: err.to_string(),
+            })?;
+            log::info!("Email outbox: {report}");
+
+            Ok(())
+        }
+
+        fn task_type(&self) -> String {
+            "async".to_string()
+        }
+
+        fn uniq(&self) -> bool {
+            true
+        }
+
+        fn cron(&self) -> Option<Scheduled> {
+            Some(Scheduled::CronPattern("0 * * * * * *".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let options = OutboxOptions {
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60),
+            ..OutboxOptions::default()
+        };
+
+        assert_eq!(options.backoff(1), Duration::from_secs(10));
+        assert_eq!(options.backoff(2), Duration::from_secs(20));
+        assert_eq!(options.backoff(3), Duration::from_secs(40));
+        assert_eq!(options.backoff(4), Duration::from_secs(60));
+        assert_eq!(options.backoff(40), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,25 @@
+table! {
+    email_outbox (id) {
+        id -> Integer,
+        from_address -> Text,
+        recipients -> Text,
+        subject -> Text,
+        message -> Blob,
+        status -> Text,
+        attempts -> Integer,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Datetime,
+        created_at -> Datetime,
+        sent_at -> Nullable<Datetime>,
+    }
+}
+
+table! {
+    email_suppressions (id) {
+        id -> Integer,
+        email -> Text,
+        reason -> Text,
+        details -> Nullable<Text>,
+        created_at -> Datetime,
+    }
+}
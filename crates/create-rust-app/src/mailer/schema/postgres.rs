@@ -0,0 +1,25 @@
+table! {
+    email_outbox (id) {
+        id -> Int4,
+        from_address -> Text,
+        recipients -> Text,
+        subject -> Text,
+        message -> Bytea,
+        status -> Text,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamptz,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    email_suppressions (id) {
+        id -> Int4,
+        email -> Text,
+        reason -> Text,
+        details -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
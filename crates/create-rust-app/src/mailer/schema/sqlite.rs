@@ -0,0 +1,25 @@
+table! {
+    email_outbox (id) {
+        id -> Integer,
+        from_address -> Text,
+        recipients -> Text,
+        subject -> Text,
+        message -> Binary,
+        status -> Text,
+        attempts -> Integer,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamp,
+        created_at -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    email_suppressions (id) {
+        id -> Integer,
+        email -> Text,
+        reason -> Text,
+        details -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
@@ -0,0 +1,259 @@
+use lettre::address::Envelope;
+use serde::{Deserialize, Serialize};
+
+use crate::diesel::{
+    insert_into, AsChangeset, ExpressionMethods, Identifiable, Insertable, OptionalExtension,
+    QueryDsl, QueryResult, Queryable, RunQueryDsl,
+};
+use crate::{Connection, Database, Mailer};
+
+use super::outbox::{Utc, ID};
+use super::schema::{self, email_suppressions};
+use super::{EmailAddress, MailError};
+
+/// Why an address is on the suppression list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    /// the address bounced permanently; it doesn't exist, or its mailbox was disabled
+    Bounce,
+    /// the recipient marked an email as spam
+    Complaint,
+    /// added with [`EmailSuppression::suppress`], like when a user asked not to be emailed anymore
+    Manual,
+}
+
+impl SuppressionReason {
+    const BOUNCE: &'static str = "bounce";
+    const COMPLAINT: &'static str = "complaint";
+    const MANUAL: &'static str = "manual";
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Bounce => Self::BOUNCE,
+            Self::Complaint => Self::COMPLAINT,
+            Self::Manual => Self::MANUAL,
+        }
+    }
+}
+
+/// An address the [`Mailer`] doesn't send to anymore, see [`Mailer::with_suppression_list`].
+///
+/// Bounces and complaints are added by the mail webhooks; the other functions are there to look the
+/// list up and to take addresses off it.
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, Identifiable)]
+#[diesel(table_name = email_suppressions)]
+pub struct EmailSuppression {
+    pub id: ID,
+
+    /// lowercased, without a display name
+    pub email: String,
+    /// see [`SuppressionReason`]
+    pub reason: String,
+    /// what the provider said, like the bounce's diagnostic
+    pub details: Option<String>,
+
+    pub created_at: Utc,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = email_suppressions)]
+struct EmailSuppressionChangeset {
+    email: String,
+    reason: String,
+    details: Option<String>,
+}
+
+impl EmailSuppression {
+    /// adds `email` to the suppression list; if it's already there, its reason and details are replaced
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn suppress(
+        db: &mut Connection,
+        email: &str,
+        reason: SuppressionReason,
+        details: Option<&str>,
+    ) -> QueryResult<Self> {
+        let changeset = EmailSuppressionChangeset {
+            email: normalize(email),
+            reason: reason.as_str().to_string(),
+            details: details.map(ToString::to_string),
+        };
+
+        #[cfg(not(any(feature = "database_mysql", feature = "database_any")))]
+        return insert_into(schema::email_suppressions::table)
+            .values(&changeset)
+            .on_conflict(schema::email_suppressions::email)
+            .do_update()
+            .set(&changeset)
+            .get_result(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(schema::email_suppressions::table)
+                .values(&changeset)
+                .on_conflict(diesel::dsl::DuplicatedKeys)
+                .do_update()
+                .set(&changeset)
+                .execute(db)?;
+
+            schema::email_suppressions::table
+                .filter(schema::email_suppressions::email.eq(&changeset.email))
+                .first(db)
+        }
+
+        // `database_any` can't build `ON CONFLICT` clauses, so the row is updated, or inserted if there's none
+        #[cfg(feature = "database_any")]
+        diesel::connection::Connection::transaction::<Self, diesel::result::Error, _>(db, |db| {
+            let updated = diesel::update(
+                schema::email_suppressions::table
+                    .filter(schema::email_suppressions::email.eq(&changeset.email)),
+            )
+            .set(&changeset)
+            .get_result(db)
+            .optional()?;
+
+            match updated {
+                Some(suppression) => Ok(suppression),
+                None => insert_into(schema::email_suppressions::table)
+                    .values(&changeset)
+                    .get_result(db),
+            }
+        })
+    }
+
+    /// # Errors
+    /// * Diesel error
+    pub fn find(db: &mut Connection, email: &str) -> QueryResult<Option<Self>> {
+        schema::email_suppressions::table
+            .filter(schema::email_suppressions::email.eq(normalize(email)))
+            .first(db)
+            .optional()
+    }
+
+    /// the suppressed addresses, newest first
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn read_all(db: &mut Connection, page: i64, page_size: i64) -> QueryResult<Vec<Self>> {
+        schema::email_suppressions::table
+            .order(schema::email_suppressions::id.desc())
+            .limit(page_size)
+            .offset(page * page_size)
+            .load(db)
+    }
+
+    /// takes `email` off the suppression list
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn remove(db: &mut Connection, email: &str) -> QueryResult<usize> {
+        diesel::delete(
+            schema::email_suppressions::table
+                .filter(schema::email_suppressions::email.eq(normalize(email))),
+        )
+        .execute(db)
+    }
+
+    /// empties the suppression list, or only takes the addresses suppressed for `reason` off it
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn clear(db: &mut Connection, reason: Option<SuppressionReason>) -> QueryResult<usize> {
+        match reason {
+            Some(reason) => diesel::delete(
+                schema::email_suppressions::table
+                    .filter(schema::email_suppressions::reason.eq(reason.as_str())),
+            )
+            .execute(db),
+            None => diesel::delete(schema::email_suppressions::table).execute(db),
+        }
+    }
+
+    #[must_use]
+    pub fn reason(&self) -> SuppressionReason {
+        match self.reason.as_str() {
+            SuppressionReason::BOUNCE => SuppressionReason::Bounce,
+            SuppressionReason::COMPLAINT => SuppressionReason::Complaint,
+            _ => SuppressionReason::Manual,
+        }
+    }
+
+    /// which of `emails` (already normalized) are suppressed
+    fn suppressed(db: &mut Connection, emails: &[String]) -> QueryResult<Vec<String>> {
+        schema::email_suppressions::table
+            .filter(schema::email_suppressions::email.eq_any(emails))
+            .select(schema::email_suppressions::email)
+            .load(db)
+    }
+}
+
+/// the lowercased address, without its display name
+fn normalize(email: &str) -> String {
+    EmailAddress::new(email)
+        .map_or_else(
+            |_| email.trim().to_string(),
+            |email| email.address().to_string(),
+        )
+        .to_lowercase()
+}
+
+impl Mailer {
+    /// skips the recipients on the suppression list (see [`EmailSuppression`]) of `db`, when sending
+    /// emails and delivering the outbox
+    #[must_use]
+    pub fn with_suppression_list(mut self, db: Database) -> Self {
+        self.suppressions = Some(db);
+        self
+    }
+
+    /// `envelope` without the suppressed recipients; if the suppression list can't be read, nobody is skipped
+    ///
+    /// # Errors
+    /// * [`MailError::Suppressed`] if all the recipients are suppressed
+    pub(super) fn without_suppressed(&self, envelope: &Envelope) -> Result<Envelope, MailError> {
+        let Some(db) = &self.suppressions else {
+            return Ok(envelope.clone());
+        };
+
+        let recipients = envelope
+            .to()
+            .iter()
+            .map(|address| normalize(address.as_ref()))
+            .collect::<Vec<_>>();
+        let suppressed =
+            match db
+                .get_connection()
+                .map_err(|err| err.to_string())
+                .and_then(|mut db| {
+                    EmailSuppression::suppressed(&mut db, &recipients)
+                        .map_err(|err| err.to_string())
+                }) {
+                Ok(suppressed) => suppressed,
+                Err(error) => {
+                    println!("Warning: could not check the email suppression list ({error})");
+                    return Ok(envelope.clone());
+                }
+            };
+        if suppressed.is_empty() {
+            return Ok(envelope.clone());
+        }
+
+        let to = envelope
+            .to()
+            .iter()
+            .filter(|address| !suppressed.contains(&normalize(address.as_ref())))
+            .cloned()
+            .collect::<Vec<_>>();
+        if to.is_empty() {
+            return Err(MailError::Suppressed(format!(
+                "All the recipients are on the suppression list ({})",
+                suppressed.join(", ")
+            )));
+        }
+
+        Envelope::new(envelope.from().cloned(), to)
+            .map_err(|err| MailError::InvalidMessage(err.to_string()))
+    }
+}
@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tera::{Context, Tera};
+
+use crate::util::workspace_utils::mail_templates_dir;
+
+/// the built-in layouts, which the emails' templates extend
+const LAYOUTS: [(&str, &str); 2] = [
+    ("layout.html", include_str!("layout.html")),
+    ("layout.txt", include_str!("layout.txt")),
+];
+
+/// An email rendered by [`MailTemplates::render`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// Renders emails from [Tera](https://keats.github.io/tera/) templates.
+///
+/// An email named `register` is made of three templates: `register.subject.txt`, `register.txt`
+/// and `register.html`. They're looked up in the mail templates folder (`backend/mail/templates`, or
+/// `CRA_MAIL_TEMPLATES_DIR`), and fall back to the built-in ones (the layouts and, with the auth plugin,
+/// the auth emails) when missing or broken. The built-in emails extend `layout.txt` and `layout.html`,
+/// so overriding those two changes the branding of all of them.
+///
+/// Locale-specific variants go in a sub folder named after the locale; for `fr-CA`, the templates in
+/// `fr-CA/` are tried first, then the ones in `fr/`, then the default ones:
+///
+/// ```text
+/// backend/mail/templates/
+/// ├── layout.html
+/// ├── register.html
+/// └── fr/
+///     ├── register.subject.txt
+///     ├── register.txt
+///     └── register.html
+/// ```
+///
+/// Every template gets the requested `locale` variable (empty if there is none), and the html and text
+/// ones get the rendered `subject`.
+#[derive(Clone)]
+pub struct MailTemplates {
+    /// the built-in templates, overridden by the ones in the folder
+    tera: Arc<Tera>,
+    /// the built-in templates alone
+    built_in: Arc<Tera>,
+}
+
+impl Default for MailTemplates {
+    fn default() -> Self {
+        Self::new(mail_templates_dir())
+    }
+}
+
+impl MailTemplates {
+    /// loads the templates in `dir`, if it exists; invalid ones are reported and ignored
+    #[must_use]
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        let built_in = Self::built_in();
+        let mut tera = built_in.clone();
+
+        let mut pending = vec![];
+        Self::find_templates(dir.as_ref(), dir.as_ref(), &mut pending);
+        // added one by one, so a broken template doesn't take the others down; a template can only be
+        // added after the one it extends, so go over them until no more can be added
+        let mut errors = vec![];
+        loop {
+            let count = pending.len();
+            errors.clear();
+            pending.retain(|(path, name): &(PathBuf, String)| {
+                let mut with_template = tera.clone();
+                match with_template.add_template_file(path, Some(name)) {
+                    Ok(()) => {
+                        tera = with_template;
+                        false
+                    }
+                    Err(error) => {
+                        errors.push(format!("'{}' ({})", path.display(), Self::describe(&error)));
+                        true
+                    }
+                }
+            });
+            if pending.is_empty() || pending.len() == count {
+                break;
+            }
+        }
+        for error in errors {
+            println!("Warning: ignoring the mail template {error}");
+        }
+
+        Self {
+            tera: Arc::new(tera),
+            built_in: Arc::new(built_in),
+        }
+    }
+
+    /// only the built-in templates
+    #[must_use]
+    pub fn built_in_only() -> Self {
+        let built_in = Arc::new(Self::built_in());
+        Self {
+            tera: built_in.clone(),
+            built_in,
+        }
+    }
+
+    fn built_in() -> Tera {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(LAYOUTS)
+            .expect("the built-in mail layouts are valid");
+        #[cfg(feature = "plugin_auth")]
+        tera.add_raw_templates(crate::auth::mail::TEMPLATES)
+            .expect("the built-in auth mail templates are valid");
+        tera
+    }
+
+    /// the `.html` and `.txt` files in `dir` and its sub folders, with their names relative to `root`
+    fn find_templates(root: &Path, dir: &Path, files: &mut Vec<(PathBuf, String)>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                Self::find_templates(root, &path, files);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "html" || extension == "txt")
+            {
+                let Ok(name) = path.strip_prefix(root) else {
+                    continue;
+                };
+                let name = name
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push((path, name));
+            }
+        }
+    }
+
+    /// Tera's errors only say which template failed; the cause is in their source
+    fn describe(error: &tera::Error) -> String {
+        let mut message = error.to_string();
+        let mut source = std::error::Error::source(error);
+        while let Some(cause) = source {
+            message = format!("{message}: {cause}");
+            source = cause.source();
+        }
+        message
+    }
+
+    /// the template names to try for `file` in `locale`, most specific first
+    fn candidates(file: &str, locale: Option<&str>) -> Vec<String> {
+        let mut candidates = vec![];
+        if let Some(locale) = locale.filter(|locale| !locale.is_empty()) {
+            candidates.push(format!("{locale}/{file}"));
+            if let Some((language, _)) = locale.split_once(['-', '_']) {
+                candidates.push(format!("{language}/{file}"));
+            }
+        }
+        candidates.push(file.to_string());
+        candidates
+    }
+
+    fn render_file(
+        &self,
+        file: &str,
+        locale: Option<&str>,
+        context: &Context,
+    ) -> Result<String, String> {
+        let Some(name) = Self::candidates(file, locale)
+            .into_iter()
+            .find(|name| self.tera.get_template(name).is_ok())
+        else {
+            return Err(format!("No mail template named '{file}'"));
+        };
+
+        self.tera.render(&name, context).or_else(|error| {
+            let message = format!(
+                "Could not render the mail template '{name}' ({})",
+                Self::describe(&error)
+            );
+            if self.built_in.get_template(file).is_err() {
+                return Err(message);
+            }
+
+            println!("Warning: {message}; using the built-in one");
+            self.built_in
+                .render(file, context)
+                .map_err(|error| Self::describe(&error))
+        })
+    }
+
+    /// renders the email `name` in `locale` (a tag like `fr-CA`, or `None` for the default templates)
+    ///
+    /// # Errors
+    /// * there is no template for one of the parts of the email
+    /// * a template could not be rendered, and there is no built-in one to fall back to
+    pub fn render(
+        &self,
+        name: &str,
+        locale: Option<&str>,
+        context: &Context,
+    ) -> Result<RenderedEmail, String> {
+        let mut context = context.clone();
+        context.insert("locale", locale.unwrap_or_default());
+
+        let subject = self
+            .render_file(&format!("{name}.subject.txt"), locale, &context)?
+            .trim()
+            .to_string();
+        context.insert("subject", &subject);
+
+        Ok(RenderedEmail {
+            text: self.render_file(&format!("{name}.txt"), locale, &context)?,
+            html: self.render_file(&format!("{name}.html"), locale, &context)?,
+            subject,
+        })
+    }
+}
+
+/// the preferred locale in an `Accept-Language` header, like `fr-CA` for `fr-CA,fr;q=0.8,en;q=0.5`
+#[must_use]
+pub fn preferred_locale(accept_language: &str) -> Option<String> {
+    accept_language
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let locale = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |quality| quality.trim().parse::<f32>().ok())?;
+            (!locale.is_empty() && locale != "*" && quality > 0.0).then_some((locale, quality))
+        })
+        // the first of the best ones
+        .fold(
+            None,
+            |best: Option<(&str, f32)>, (locale, quality)| match best {
+                Some((_, best_quality)) if best_quality >= quality => best,
+                _ => Some((locale, quality)),
+            },
+        )
+        .map(|(locale, _)| locale.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_most_specific_locale() {
+        let dir = std::env::temp_dir().join(format!("cra-mail-templates-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("fr")).unwrap();
+        std::fs::write(dir.join("welcome.subject.txt"), "Welcome").unwrap();
+        std::fs::write(dir.join("welcome.txt"), "Hello {{ name }}").unwrap();
+        std::fs::write(
+            dir.join("welcome.html"),
+            r#"{% extends "layout.html" %}{% block content %}<p>Hello {{ name }}</p>{% endblock content %}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("fr/welcome.subject.txt"), "Bienvenue").unwrap();
+        std::fs::write(dir.join("fr/welcome.txt"), "Bonjour {{ name }}").unwrap();
+        std::fs::create_dir_all(dir.join("de")).unwrap();
+        std::fs::write(dir.join("de/welcome.txt"), "Hallo {{ missing }}").unwrap();
+
+        let templates = MailTemplates::new(&dir);
+        let mut context = Context::new();
+        context.insert("name", "<Ada>");
+
+        let email = templates
+            .render("welcome", Some("fr-CA"), &context)
+            .unwrap();
+        assert_eq!(email.subject, "Bienvenue");
+        assert_eq!(email.text, "Bonjour <Ada>");
+        assert!(email.html.contains(r#"<html lang="fr-CA">"#));
+        assert!(email.html.contains("<p>Hello &lt;Ada&gt;</p>"));
+        assert_eq!(
+            templates.render("welcome", None, &context).unwrap().subject,
+            "Welcome"
+        );
+        // broken, and there's no built-in one to fall back to
+        assert!(templates.render("welcome", Some("de"), &context).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            preferred_locale("en;q=0.5, fr-CA, fr;q=0.8"),
+            Some("fr-CA".to_string())
+        );
+        assert_eq!(preferred_locale("*"), None);
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lettre::address::Envelope;
+
+use super::{MailTransport, TransportError};
+
+/// the number of messages written by this process, to keep file names unique
+static WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// How [`FileTransport`] lays out the messages it writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// one `.eml` file per message, directly in the folder
+    Eml,
+    /// a [Maildir](https://cr.yp.to/proto/maildir.html): messages are written to `tmp/` and moved to `new/`,
+    /// so mail clients (mutt, `aerc`, ...) can open the folder as a mailbox
+    Maildir,
+}
+
+/// Writes emails to a local folder instead of sending them; useful in development.
+///
+/// The envelope is recorded in `X-Envelope-From` and `X-Envelope-To` headers, prepended to the message.
+#[derive(Debug, Clone)]
+pub struct FileTransport {
+    dir: PathBuf,
+    format: FileFormat,
+}
+
+impl FileTransport {
+    /// writes `.eml` files to `dir`, which is created if needed
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            format: FileFormat::Eml,
+        }
+    }
+
+    /// writes to a Maildir in `dir`, which is created if needed
+    #[must_use]
+    pub fn maildir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            format: FileFormat::Maildir,
+        }
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// a name no other message written to this folder has, sorting by the time it was written
+    fn unique_name() -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        format!(
+            "{}.M{}P{}Q{}.create-rust-app",
+            now.as_secs(),
+            now.subsec_micros(),
+            std::process::id(),
+            WRITTEN.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+}
+
+impl MailTransport for FileTransport {
+    fn send(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError> {
+        let io_error = |err: std::io::Error| {
+            TransportError::transient(format!(
+                "Could not write the email to '{}' ({err})",
+                self.dir.display()
+            ))
+        };
+
+        let (from, to) = super::envelope_addresses(envelope);
+        let mut contents = format!(
+            "X-Envelope-From: <{}>\r\nX-Envelope-To: {}\r\n",
+            from.unwrap_or_default(),
+            to.join(", ")
+        )
+        .into_bytes();
+        contents.extend_from_slice(message);
+
+        let name = Self::unique_name();
+        match self.format {
+            FileFormat::Eml => {
+                std::fs::create_dir_all(&self.dir).map_err(io_error)?;
+                std::fs::write(self.dir.join(format!("{name}.eml")), contents).map_err(io_error)?;
+            }
+            FileFormat::Maildir => {
+                for sub_dir in ["tmp", "new", "cur"] {
+                    std::fs::create_dir_all(self.dir.join(sub_dir)).map_err(io_error)?;
+                }
+                let tmp = self.dir.join("tmp").join(&name);
+                std::fs::write(&tmp, contents).map_err(io_error)?;
+                std::fs::rename(&tmp, self.dir.join("new").join(&name)).map_err(io_error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
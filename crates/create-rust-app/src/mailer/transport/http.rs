@@ -0,0 +1,218 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use lettre::address::Envelope;
+
+use super::{MailTransport, TransportError};
+
+/// What [`HttpTransport`] posts for an email
+#[derive(Debug, Clone)]
+pub struct HttpRequestBody {
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+type BuildBody = dyn Fn(&Envelope, &[u8]) -> Result<HttpRequestBody, TransportError> + Send + Sync;
+
+/// Posts emails to an HTTP API, like the ones of SES, Postmark or Mailgun.
+///
+/// By default, it posts them as JSON:
+///
+/// ```json
+/// { "from": "app@example.com", "to": ["user@example.com"], "message": "<the formatted message>" }
+/// ```
+///
+/// Use [`HttpTransport::with_body`] to post what your provider expects instead. Responses with a
+/// 2xx status are deliveries, 408, 429 and 5xx ones are retried, and the other ones are permanent failures.
+/// In tests, point it to a local stub server.
+#[derive(Clone)]
+pub struct HttpTransport {
+    url: String,
+    headers: Vec<(String, String)>,
+    build_body: Arc<BuildBody>,
+    agent: ureq::Agent,
+}
+
+impl HttpTransport {
+    /// # Errors
+    /// * could not set up TLS
+    pub fn new(url: impl Into<String>) -> Result<Self, TransportError> {
+        let tls = ureq::native_tls::TlsConnector::new()
+            .map_err(|err| TransportError::permanent(format!("Could not set up TLS ({err})")))?;
+
+        Ok(Self {
+            url: url.into(),
+            headers: vec![],
+            build_body: Arc::new(Self::json_body),
+            agent: ureq::AgentBuilder::new()
+                .tls_connector(Arc::new(tls))
+                .timeout(Duration::from_secs(30))
+                .build(),
+        })
+    }
+
+    /// posts to `MAIL_HTTP_URL`, with `MAIL_HTTP_TOKEN` as bearer token if it's set
+    ///
+    /// # Errors
+    /// * `MAIL_HTTP_URL` is not set
+    /// * see [`HttpTransport::new`]
+    pub fn from_env() -> Result<Self, TransportError> {
+        let url = std::env::var("MAIL_HTTP_URL").map_err(|_| {
+            TransportError::permanent("MAIL_HTTP_URL must be set to use the http mail transport")
+        })?;
+        let transport = Self::new(url)?;
+
+        Ok(match std::env::var("MAIL_HTTP_TOKEN") {
+            Ok(token) => transport.bearer_token(&token),
+            Err(_) => transport,
+        })
+    }
+
+    /// adds a header to every request
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn bearer_token(self, token: &str) -> Self {
+        self.header("Authorization", format!("Bearer {token}"))
+    }
+
+    /// builds the request body from the envelope and the formatted message
+    #[must_use]
+    pub fn with_body(
+        mut self,
+        build_body: impl Fn(&Envelope, &[u8]) -> Result<HttpRequestBody, TransportError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.build_body = Arc::new(build_body);
+        self
+    }
+
+    fn json_body(envelope: &Envelope, message: &[u8]) -> Result<HttpRequestBody, TransportError> {
+        let (from, to) = super::envelope_addresses(envelope);
+        let body = serde_json::json!({
+            "from": from,
+            "to": to,
+            "message": String::from_utf8_lossy(message),
+        });
+
+        Ok(HttpRequestBody {
+            content_type: "application/json".to_string(),
+            body: body.to_string().into_bytes(),
+        })
+    }
+}
+
+impl MailTransport for HttpTransport {
+    fn send(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError> {
+        let HttpRequestBody { content_type, body } = (self.build_body)(envelope, message)?;
+
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", &content_type);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+
+        match request.send_bytes(&body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                let message = format!(
+                    "{} responded with {status} ({})",
+                    self.url,
+                    response.into_string().unwrap_or_default().trim()
+                );
+                if status == 408 || status == 429 || status >= 500 {
+                    Err(TransportError::transient(message))
+                } else {
+                    Err(TransportError::permanent(message))
+                }
+            }
+            Err(ureq::Error::Transport(err)) => Err(TransportError::transient(format!(
+                "Could not reach {} ({err})",
+                self.url
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// answers each request with the next status, and returns the requests it received
+    fn stub_server(statuses: Vec<u16>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/send", listener.local_addr().unwrap());
+
+        let server = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                requests.push(request);
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Stub\r\nContent-Length: 4\r\nConnection: close\r\n\r\nstub"
+                )
+                .unwrap();
+            }
+            requests
+        });
+
+        (url, server)
+    }
+
+    #[test]
+    fn posts_emails_and_classifies_failures() {
+        let (url, server) = stub_server(vec![202, 503, 422]);
+        let transport = HttpTransport::new(url).unwrap().bearer_token("secret");
+        let envelope = Envelope::new(
+            Some("app@example.com".parse().unwrap()),
+            vec!["user@example.com".parse().unwrap()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            transport.send(&envelope, b"Subject: Hi\r\n\r\nHello"),
+            Ok(())
+        );
+        assert!(!transport.send(&envelope, b"").unwrap_err().permanent);
+        assert!(transport.send(&envelope, b"").unwrap_err().permanent);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("POST /send HTTP/1.1\r\n"));
+        assert!(requests[0].contains("Authorization: Bearer secret\r\n"));
+        assert!(requests[0].contains(r#""to":["user@example.com"]"#));
+        assert!(requests[0].contains(r#""message":"Subject: Hi\r\n\r\nHello""#));
+    }
+}
@@ -0,0 +1,227 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use lettre::address::Envelope;
+
+use super::{MailTransport, TransportError};
+
+/// An email captured by a [`MemoryTransport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentEmail {
+    /// the envelope sender
+    pub from: Option<String>,
+    /// the envelope recipients
+    pub to: Vec<String>,
+    /// the formatted message
+    pub message: Vec<u8>,
+}
+
+impl SentEmail {
+    /// the formatted message, as text
+    #[must_use]
+    pub fn raw(&self) -> String {
+        String::from_utf8_lossy(&self.message).into_owned()
+    }
+
+    /// the (unfolded) value of the first `name` header, if the message has one
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<String> {
+        let raw = self.raw();
+        let headers = raw.split("\r\n\r\n").next().unwrap_or_default();
+
+        let mut value: Option<String> = None;
+        for line in headers.split("\r\n") {
+            if line.starts_with([' ', '\t']) {
+                if let Some(value) = value.as_mut() {
+                    value.push_str(line);
+                }
+                continue;
+            }
+            if value.is_some() {
+                break;
+            }
+            if let Some((key, rest)) = line.split_once(':') {
+                if key.eq_ignore_ascii_case(name) {
+                    value = Some(rest.trim_start().to_string());
+                }
+            }
+        }
+
+        value
+    }
+
+    /// the `Subject` header; encoded words are left as they are
+    #[must_use]
+    pub fn subject(&self) -> Option<String> {
+        self.header("Subject")
+    }
+
+    /// whether the formatted message contains `text`; parts are often quoted-printable encoded,
+    /// so look for short strings without `=`, non-ASCII characters or line breaks
+    #[must_use]
+    pub fn contains(&self, text: &str) -> bool {
+        self.raw().contains(text)
+    }
+
+    #[must_use]
+    pub fn is_to(&self, address: &str) -> bool {
+        self.to
+            .iter()
+            .any(|recipient| recipient.eq_ignore_ascii_case(address))
+    }
+}
+
+/// Keeps the emails it's given in memory; for tests.
+///
+/// Clones share the same emails, so keep a clone around to inspect what the [`Mailer`](`crate::Mailer`) sent:
+///
+/// ```rust,ignore
+/// let outbox = MemoryTransport::new();
+/// let mailer = Mailer::default().with_transport(outbox.clone());
+///
+/// register(&mailer, "user@example.com");
+///
+/// outbox.assert_count(1);
+/// assert!(outbox.last().unwrap().is_to("user@example.com"));
+/// ```
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    sent: Vec<SentEmail>,
+    /// returned instead of capturing the next emails, to test failures
+    failure: Option<TransportError>,
+}
+
+impl MemoryTransport {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // a panicking test shouldn't hide the emails from the others
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// the captured emails, oldest first
+    #[must_use]
+    pub fn sent(&self) -> Vec<SentEmail> {
+        self.state().sent.clone()
+    }
+
+    #[must_use]
+    pub fn last(&self) -> Option<SentEmail> {
+        self.state().sent.last().cloned()
+    }
+
+    /// the captured emails with `address` as one of their recipients
+    #[must_use]
+    pub fn sent_to(&self, address: &str) -> Vec<SentEmail> {
+        self.state()
+            .sent
+            .iter()
+            .filter(|email| email.is_to(address))
+            .cloned()
+            .collect()
+    }
+
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.state().sent.len()
+    }
+
+    /// # Panics
+    /// * if a different number of emails was captured
+    pub fn assert_count(&self, expected: usize) {
+        let sent = self.sent();
+        assert!(
+            sent.len() == expected,
+            "expected {expected} email(s) to be sent, got {}: {:#?}",
+            sent.len(),
+            sent.iter()
+                .map(|email| (&email.to, email.subject()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// # Panics
+    /// * if no email with `address` as one of its recipients was captured
+    pub fn assert_sent_to(&self, address: &str) {
+        let sent = self.sent();
+        assert!(
+            sent.iter().any(|email| email.is_to(address)),
+            "expected an email to be sent to '{address}', got emails to {:?}",
+            sent.iter().map(|email| &email.to).collect::<Vec<_>>()
+        );
+    }
+
+    /// forgets the captured emails
+    pub fn clear(&self) {
+        self.state().sent.clear();
+    }
+
+    /// makes the following sends fail with `error` (until [`MemoryTransport::succeed`] is called)
+    pub fn fail_with(&self, error: TransportError) {
+        self.state().failure = Some(error);
+    }
+
+    pub fn succeed(&self) {
+        self.state().failure = None;
+    }
+}
+
+impl MailTransport for MemoryTransport {
+    fn send(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError> {
+        let mut state = self.state();
+        if let Some(error) = &state.failure {
+            return Err(error.clone());
+        }
+
+        let (from, to) = super::envelope_addresses(envelope);
+        state.sent.push(SentEmail {
+            from,
+            to,
+            message: message.to_vec(),
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmailAddress, MailError, Mailer};
+
+    #[test]
+    fn captures_what_the_mailer_sends() {
+        let transport = MemoryTransport::new();
+        let mailer = Mailer::default().with_transport(transport.clone());
+
+        let to = EmailAddress::new("user@example.com").unwrap();
+        mailer
+            .send(&to, "Welcome", "Hello there", "<p>Hello there</p>")
+            .unwrap();
+
+        transport.assert_count(1);
+        transport.assert_sent_to("user@example.com");
+        let email = transport.last().unwrap();
+        assert_eq!(email.subject().as_deref(), Some("Welcome"));
+        assert!(email.contains("Hello there"));
+
+        transport.fail_with(TransportError::permanent("mailbox unavailable"));
+        assert!(matches!(
+            mailer.send(&to, "Again", "", ""),
+            Err(MailError::Transport(TransportError {
+                permanent: true,
+                ..
+            }))
+        ));
+        transport.assert_count(1);
+    }
+}
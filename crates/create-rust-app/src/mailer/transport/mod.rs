@@ -0,0 +1,130 @@
+use std::fmt;
+use std::sync::Arc;
+
+use lettre::address::Envelope;
+
+mod file;
+mod http;
+mod memory;
+mod sendmail;
+mod smtp;
+
+pub use file::{FileFormat, FileTransport};
+pub use http::{HttpRequestBody, HttpTransport};
+pub use memory::{MemoryTransport, SentEmail};
+pub use sendmail::SendmailTransport;
+pub use smtp::{SmtpConfig, SmtpTls, SmtpTransport};
+
+/// Something which delivers formatted emails.
+///
+/// The [`Mailer`](`super::Mailer`) builds the message (see [`lettre::Message::formatted`]) and hands it,
+/// with the envelope, to its transport; so the same email can be delivered again later by the
+/// outbox, whichever transport is used.
+pub trait MailTransport: Send + Sync {
+    /// deliver `message` from the envelope's sender to its recipients
+    ///
+    /// # Errors
+    /// * could not deliver the message; see [`TransportError::permanent`]
+    fn send(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError>;
+}
+
+impl<T: MailTransport + ?Sized> MailTransport for Arc<T> {
+    fn send(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError> {
+        (**self).send(envelope, message)
+    }
+}
+
+/// Why a [`MailTransport`] could not deliver a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportError {
+    /// retrying won't help, like when the mailbox doesn't exist or the credentials are wrong
+    pub permanent: bool,
+    pub message: String,
+}
+
+impl TransportError {
+    #[must_use]
+    pub fn transient(message: impl Into<String>) -> Self {
+        Self {
+            permanent: false,
+            message: message.into(),
+        }
+    }
+
+    #[must_use]
+    pub fn permanent(message: impl Into<String>) -> Self {
+        Self {
+            permanent: true,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// Discards every message; used when `SEND_MAIL` is false
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct StubTransport;
+
+impl MailTransport for StubTransport {
+    fn send(&self, _envelope: &Envelope, _message: &[u8]) -> Result<(), TransportError> {
+        Ok(())
+    }
+}
+
+/// the transport selected by the `MAIL_TRANSPORT` environment variable (`smtp` by default):
+///
+/// * `smtp`: see [`SmtpTransport::from_env`]
+/// * `sendmail`: see [`SendmailTransport::from_env`]
+/// * `file` or `maildir`: writes to the `MAIL_DIR` folder (`.mail` by default), see [`FileTransport`]
+/// * `http`: see [`HttpTransport::from_env`]
+///
+/// # Errors
+/// * `MAIL_TRANSPORT` is not one of the above
+/// * the selected transport is not configured properly
+pub(crate) fn from_env() -> Result<Arc<dyn MailTransport>, TransportError> {
+    let name = std::env::var("MAIL_TRANSPORT").unwrap_or_else(|_| "smtp".to_string());
+    let mail_dir = || std::env::var("MAIL_DIR").unwrap_or_else(|_| ".mail".to_string());
+
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "smtp" => Arc::new(SmtpTransport::from_env()?),
+        "sendmail" => Arc::new(SendmailTransport::from_env()),
+        "file" => Arc::new(FileTransport::new(mail_dir())),
+        "maildir" => Arc::new(FileTransport::maildir(mail_dir())),
+        "http" => Arc::new(HttpTransport::from_env()?),
+        _ => {
+            return Err(TransportError::permanent(format!(
+                "Invalid MAIL_TRANSPORT '{name}' (expected smtp, sendmail, file, maildir or http)"
+            )))
+        }
+    })
+}
+
+/// posts the emails to the mail catcher of the development server which started the app (see `DEV_SERVER_PORT`),
+/// which shows them in the admin dashboard
+#[cfg(all(feature = "plugin_dev", debug_assertions))]
+pub(crate) fn dev_server() -> Option<Arc<dyn MailTransport>> {
+    let port = std::env::var("DEV_SERVER_PORT").ok()?;
+
+    match HttpTransport::new(format!("http://localhost:{port}/mail")) {
+        Ok(transport) => Some(Arc::new(transport)),
+        Err(error) => {
+            println!("Warning: Could not capture emails in the development server; {error}");
+            None
+        }
+    }
+}
+
+/// the envelope's sender and recipients, as strings
+fn envelope_addresses(envelope: &Envelope) -> (Option<String>, Vec<String>) {
+    (
+        envelope.from().map(ToString::to_string),
+        envelope.to().iter().map(ToString::to_string).collect(),
+    )
+}
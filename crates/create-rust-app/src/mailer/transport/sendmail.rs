@@ -0,0 +1,48 @@
+use lettre::address::Envelope;
+use lettre::Transport;
+
+use super::{MailTransport, TransportError};
+
+/// Pipes emails to the local `sendmail` command (or a compatible one, like msmtp's), which takes care of delivering them.
+#[derive(Debug, Clone)]
+pub struct SendmailTransport {
+    transport: lettre::SendmailTransport,
+}
+
+impl Default for SendmailTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SendmailTransport {
+    /// uses `sendmail` from the `PATH`
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            transport: lettre::SendmailTransport::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_command(command: impl Into<String>) -> Self {
+        Self {
+            transport: lettre::SendmailTransport::new_with_command(command.into()),
+        }
+    }
+
+    /// uses the command in the `SENDMAIL_COMMAND` environment variable, if it's set
+    #[must_use]
+    pub fn from_env() -> Self {
+        std::env::var("SENDMAIL_COMMAND").map_or_else(|_| Self::new(), Self::with_command)
+    }
+}
+
+impl MailTransport for SendmailTransport {
+    fn send(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError> {
+        // sendmail queues what it can't deliver, so its failures are local ones, like a missing command
+        self.transport
+            .send_raw(envelope, message)
+            .map_err(|err| TransportError::transient(err.to_string()))
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use lettre::address::Envelope;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::Transport;
+
+use super::{MailTransport, TransportError};
+
+/// How the connection to the SMTP server is encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmtpTls {
+    /// TLS from the start of the connection ("SMTPS"), port 465 by default
+    #[default]
+    Implicit,
+    /// upgrade a plain connection with `STARTTLS`, port 587 by default
+    StartTls,
+    /// no encryption, port 25 by default; only for local servers like a mail catcher
+    None,
+}
+
+impl SmtpTls {
+    #[must_use]
+    pub const fn default_port(self) -> u16 {
+        match self {
+            Self::Implicit => 465,
+            Self::StartTls => 587,
+            Self::None => 25,
+        }
+    }
+}
+
+/// Settings for [`SmtpTransport::new`]
+#[derive(Clone, Default)]
+pub struct SmtpConfig {
+    /// the host name of the SMTP server
+    pub server: String,
+    /// defaults to [`SmtpTls::default_port`]
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    /// no authentication if empty
+    pub username: String,
+    pub password: String,
+    /// for connecting and for each command; lettre's default (60s) if unset
+    pub timeout: Option<Duration>,
+}
+
+impl std::fmt::Debug for SmtpConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmtpConfig")
+            .field("server", &self.server)
+            .field("port", &self.port)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Sends emails to an SMTP server; it keeps a pool of connections, which clones share.
+#[derive(Clone)]
+pub struct SmtpTransport {
+    transport: lettre::SmtpTransport,
+}
+
+impl SmtpTransport {
+    /// # Errors
+    /// * `config.server` is not a valid host name for TLS
+    pub fn new(config: SmtpConfig) -> Result<Self, TransportError> {
+        let tls_parameters = || {
+            TlsParameters::new(config.server.clone()).map_err(|err| {
+                TransportError::permanent(format!(
+                    "Invalid SMTP server '{}' ({err})",
+                    config.server
+                ))
+            })
+        };
+        let tls = match config.tls {
+            SmtpTls::Implicit => Tls::Wrapper(tls_parameters()?),
+            SmtpTls::StartTls => Tls::Required(tls_parameters()?),
+            SmtpTls::None => Tls::None,
+        };
+
+        let mut builder = lettre::SmtpTransport::builder_dangerous(&config.server)
+            .port(config.port.unwrap_or_else(|| config.tls.default_port()))
+            .tls(tls);
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(Some(timeout));
+        }
+        if !config.username.is_empty() {
+            builder = builder.credentials(Credentials::new(config.username, config.password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+
+    /// configured by the `SMTP_SERVER`, `SMTP_PORT`, `SMTP_TLS` (`tls`, `starttls` or `none`; `tls` by default),
+    /// `SMTP_USERNAME` and `SMTP_PASSWORD` environment variables
+    ///
+    /// # Errors
+    /// * `SMTP_PORT` or `SMTP_TLS` is invalid
+    /// * see [`SmtpTransport::new`]
+    pub fn from_env() -> Result<Self, TransportError> {
+        let port = match std::env::var("SMTP_PORT") {
+            Ok(port) => Some(port.parse::<u16>().map_err(|err| {
+                TransportError::permanent(format!("Invalid SMTP_PORT '{port}' ({err})"))
+            })?),
+            Err(_) => None,
+        };
+        let tls = match std::env::var("SMTP_TLS")
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "tls" => SmtpTls::Implicit,
+            "starttls" => SmtpTls::StartTls,
+            "none" => SmtpTls::None,
+            other => {
+                return Err(TransportError::permanent(format!(
+                    "Invalid SMTP_TLS '{other}' (expected tls, starttls or none)"
+                )))
+            }
+        };
+
+        Self::new(SmtpConfig {
+            server: std::env::var("SMTP_SERVER").unwrap_or_default(),
+            port,
+            tls,
+            username: std::env::var("SMTP_USERNAME").unwrap_or_default(),
+            password: std::env::var("SMTP_PASSWORD").unwrap_or_default(),
+            timeout: None,
+        })
+    }
+}
+
+impl MailTransport for SmtpTransport {
+    fn send(&self, envelope: &Envelope, message: &[u8]) -> Result<(), TransportError> {
+        self.transport
+            .send_raw(envelope, message)
+            .map(|_| ())
+            .map_err(|err| TransportError {
+                permanent: err.is_permanent(),
+                message: err.to_string(),
+            })
+    }
+}
@@ -0,0 +1,276 @@
+use serde_json::Value;
+
+use crate::Database;
+
+use super::{EmailSuppression, SuppressionReason};
+
+type StatusCode = u16;
+type Message = &'static str;
+
+/// A bounce or a complaint about an address, from a provider's webhook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailNotification {
+    pub email: String,
+    pub reason: SuppressionReason,
+    pub details: Option<String>,
+}
+
+impl MailNotification {
+    fn new(email: &str, reason: SuppressionReason, details: Option<&str>) -> Self {
+        Self {
+            email: email.to_string(),
+            reason,
+            details: details.map(ToString::to_string),
+        }
+    }
+
+    /// the permanent bounces and the complaints in a webhook's `body`, from one of:
+    ///
+    /// * Amazon SES, through SNS (subscribe an HTTPS endpoint to the topic of the bounce and complaint notifications)
+    /// * Postmark (bounce and spam complaint webhooks)
+    /// * SendGrid (event webhook)
+    /// * Mailgun (failed and complained webhooks)
+    ///
+    /// Temporary bounces and other events are ignored.
+    ///
+    /// # Errors
+    /// * `body` is not one of the above
+    pub fn parse(body: &[u8]) -> Result<Vec<Self>, String> {
+        let value = serde_json::from_slice::<Value>(body)
+            .map_err(|err| format!("Invalid notification ({err})"))?;
+
+        // SendGrid posts batches of events
+        if let Some(events) = value.as_array() {
+            return Ok(events.iter().filter_map(Self::sendgrid).collect());
+        }
+
+        // SES notifications are wrapped in an SNS message
+        if let Some(kind) = value["Type"]
+            .as_str()
+            .filter(|_| value["TopicArn"].is_string())
+        {
+            if kind == "SubscriptionConfirmation" {
+                println!(
+                    "Confirm the SNS subscription of the mail webhook by visiting {}",
+                    value["SubscribeURL"].as_str().unwrap_or_default()
+                );
+                return Ok(vec![]);
+            }
+            let message = value["Message"].as_str().unwrap_or_default();
+            let message = serde_json::from_str::<Value>(message)
+                .map_err(|err| format!("Invalid SNS message ({err})"))?;
+            return Ok(Self::ses(&message));
+        }
+
+        if value["notificationType"].is_string() || value["eventType"].is_string() {
+            return Ok(Self::ses(&value));
+        }
+        if let Some(record_type) = value["RecordType"].as_str() {
+            return Ok(Self::postmark(record_type, &value).into_iter().collect());
+        }
+        if value["event-data"].is_object() {
+            return Ok(Self::mailgun(&value["event-data"]).into_iter().collect());
+        }
+
+        Err("Unrecognized notification".to_string())
+    }
+
+    fn ses(message: &Value) -> Vec<Self> {
+        let kind = message["notificationType"]
+            .as_str()
+            .or_else(|| message["eventType"].as_str())
+            .unwrap_or_default();
+
+        let (reason, recipients, details) = match kind {
+            "Bounce" if message["bounce"]["bounceType"] == "Permanent" => (
+                SuppressionReason::Bounce,
+                &message["bounce"]["bouncedRecipients"],
+                message["bounce"]["bounceSubType"].as_str(),
+            ),
+            "Complaint" => (
+                SuppressionReason::Complaint,
+                &message["complaint"]["complainedRecipients"],
+                message["complaint"]["complaintFeedbackType"].as_str(),
+            ),
+            _ => return vec![],
+        };
+
+        recipients
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|recipient| {
+                let details = recipient["diagnosticCode"].as_str().or(details);
+                Some(Self::new(
+                    recipient["emailAddress"].as_str()?,
+                    reason,
+                    details,
+                ))
+            })
+            .collect()
+    }
+
+    fn postmark(record_type: &str, value: &Value) -> Option<Self> {
+        let email = value["Email"].as_str()?;
+        match record_type {
+            // Postmark deactivates the addresses of the hard bounces
+            "Bounce" if value["Inactive"] == true || value["Type"] == "HardBounce" => {
+                Some(Self::new(
+                    email,
+                    SuppressionReason::Bounce,
+                    value["Description"].as_str(),
+                ))
+            }
+            "SpamComplaint" => Some(Self::new(email, SuppressionReason::Complaint, None)),
+            _ => None,
+        }
+    }
+
+    fn sendgrid(event: &Value) -> Option<Self> {
+        let email = event["email"].as_str()?;
+        match event["event"].as_str()? {
+            // "blocked" bounces are temporary
+            "bounce" if event["type"] != "blocked" => Some(Self::new(
+                email,
+                SuppressionReason::Bounce,
+                event["reason"].as_str(),
+            )),
+            "spamreport" => Some(Self::new(email, SuppressionReason::Complaint, None)),
+            _ => None,
+        }
+    }
+
+    fn mailgun(event: &Value) -> Option<Self> {
+        let email = event["recipient"].as_str()?;
+        match event["event"].as_str()? {
+            "failed" if event["severity"] == "permanent" => Some(Self::new(
+                email,
+                SuppressionReason::Bounce,
+                event["delivery-status"]["description"]
+                    .as_str()
+                    .filter(|description| !description.is_empty())
+                    .or_else(|| event["delivery-status"]["message"].as_str()),
+            )),
+            "complained" => Some(Self::new(email, SuppressionReason::Complaint, None)),
+            _ => None,
+        }
+    }
+}
+
+/// compares without short-circuiting, so the secret can't be guessed from the response times
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// /webhooks?token=...
+///
+/// adds the addresses of the bounces and complaints in `body` (see [`MailNotification::parse`]) to the
+/// suppression list; returns how many were added
+///
+/// `token` must be the `MAIL_WEBHOOK_SECRET` environment variable; the webhooks are disabled until it's set
+///
+/// # Errors
+/// - 403: Invalid token
+/// - 400: Unrecognized notification
+/// - 500: Could not update the suppression list
+pub fn receive_notifications(
+    db: &Database,
+    token: Option<&str>,
+    body: &[u8],
+) -> Result<usize, (StatusCode, Message)> {
+    let Ok(secret) = std::env::var("MAIL_WEBHOOK_SECRET") else {
+        println!("Warning: the mail webhooks are disabled; set MAIL_WEBHOOK_SECRET");
+        return Err((403, "Invalid token."));
+    };
+    if secret.is_empty()
+        || !constant_time_eq(secret.as_bytes(), token.unwrap_or_default().as_bytes())
+    {
+        return Err((403, "Invalid token."));
+    }
+
+    let notifications = MailNotification::parse(body).map_err(|error| {
+        println!("Warning: ignoring a mail webhook ({error})");
+        (400, "Unrecognized notification.")
+    })?;
+    if notifications.is_empty() {
+        return Ok(0);
+    }
+
+    let mut db = db
+        .get_connection()
+        .map_err(|_| (500, "Could not update the suppression list."))?;
+    for notification in &notifications {
+        EmailSuppression::suppress(
+            &mut db,
+            &notification.email,
+            notification.reason,
+            notification.details.as_deref(),
+        )
+        .map_err(|_| (500, "Could not update the suppression list."))?;
+    }
+
+    Ok(notifications.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_providers_notifications() {
+        let ses = serde_json::json!({
+            "notificationType": "Bounce",
+            "bounce": {
+                "bounceType": "Permanent",
+                "bounceSubType": "General",
+                "bouncedRecipients": [{ "emailAddress": "gone@example.com", "diagnosticCode": "550 5.1.1 unknown" }]
+            }
+        });
+        let sns = serde_json::json!({
+            "Type": "Notification",
+            "TopicArn": "arn:aws:sns:us-east-1:123456789012:bounces",
+            "Message": ses.to_string(),
+        });
+        assert_eq!(
+            MailNotification::parse(sns.to_string().as_bytes()).unwrap(),
+            vec![MailNotification::new(
+                "gone@example.com",
+                SuppressionReason::Bounce,
+                Some("550 5.1.1 unknown")
+            )]
+        );
+
+        let transient = serde_json::json!({
+            "notificationType": "Bounce",
+            "bounce": { "bounceType": "Transient", "bouncedRecipients": [{ "emailAddress": "full@example.com" }] }
+        });
+        assert!(MailNotification::parse(transient.to_string().as_bytes())
+            .unwrap()
+            .is_empty());
+
+        let postmark = br#"{ "RecordType": "SpamComplaint", "Email": "angry@example.com" }"#;
+        assert_eq!(
+            MailNotification::parse(postmark).unwrap()[0].reason,
+            SuppressionReason::Complaint
+        );
+
+        let sendgrid = br#"[
+            { "event": "delivered", "email": "ok@example.com" },
+            { "event": "bounce", "type": "blocked", "email": "blocked@example.com" },
+            { "event": "bounce", "type": "bounce", "email": "gone@example.com", "reason": "550 unknown" }
+        ]"#;
+        let notifications = MailNotification::parse(sendgrid).unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].email, "gone@example.com");
+
+        let mailgun = br#"{ "signature": {}, "event-data": { "event": "failed", "severity": "permanent", "recipient": "gone@example.com", "delivery-status": { "description": "", "message": "No such user" } } }"#;
+        assert_eq!(
+            MailNotification::parse(mailgun).unwrap()[0]
+                .details
+                .as_deref(),
+            Some("No such user")
+        );
+
+        assert!(MailNotification::parse(br#"{ "hello": "world" }"#).is_err());
+    }
+}
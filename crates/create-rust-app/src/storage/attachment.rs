@@ -1,8 +1,12 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use diesel::result::Error;
 use diesel::QueryResult;
 //use md5;
 //use mime_guess;
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use uuid::Uuid;
 
 use crate::diesel::{
@@ -13,7 +17,9 @@ use crate::storage::attachment_blob::AttachmentBlobChangeset;
 use crate::storage::{schema, AttachmentBlob, Utc, ID};
 use crate::Connection;
 
-use super::{schema::attachments, Storage};
+use super::backend::upload_to_session;
+use super::validation::{detect_content_type, read_head};
+use super::{schema::attachments, Encryption, Storage, StorageError};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(
@@ -41,6 +47,18 @@ pub struct AttachmentChangeset {
     pub blob_id: ID,
 }
 
+/// Where and how the browser should upload a file, see [`Attachment::prepare_direct_upload`]
+#[derive(Debug, Serialize, Clone)]
+#[tsync::tsync]
+pub struct DirectUpload {
+    /// pass this to [`Attachment::confirm_direct_upload`] once the upload is done
+    pub key: String,
+    /// `PUT` the file here
+    pub url: String,
+    /// headers which must be sent along with the file
+    pub headers: HashMap<String, String>,
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub struct AttachmentData {
     pub data: Vec<u8>,
@@ -63,75 +81,64 @@ impl Attachment {
         data: AttachmentData,
         allow_multiple: bool,
         overwrite_existing: bool,
-    ) -> Result<String, String> {
-        let checksum = format!("{:x}", md5::compute(&data.data));
-        let file_name = data.file_name.clone();
-        let content_type = file_name
-            .and_then(|f| mime_guess::from_path(f).first_raw())
-            .map(std::string::ToString::to_string);
-        let key = Uuid::new_v4().to_string();
+    ) -> Result<String, StorageError> {
+        Self::attach_stream(
+            db,
+            storage,
+            name,
+            record_type,
+            record_id,
+            data.data.as_slice(),
+            data.file_name,
+            allow_multiple,
+            overwrite_existing,
+        )
+        .await
+    }
 
-        if !allow_multiple {
-            if let Ok(existing) =
-                Self::find_for_record(db, name.clone(), record_type.clone(), record_id)
-            {
-                // one already exists, we need to delete it
-                if overwrite_existing {
-                    Self::detach(db, storage, existing.id).await.map_err(|_| {
-                        format!("Could not detach the existing attachment for '{name}' attachment on '{record_type}'", name=name.clone(), record_type=record_type.clone())
-                    })?;
-                } else {
-                    // throw the error
-                    return Err(format!("Only 1 attachment is allowed for '{name}' type attachments on '{record_type}'", name=name.clone(), record_type=record_type.clone()));
-                }
-            }
+    /// like [`Attachment::attach`], but uploads whatever `reader` yields without loading it all into memory
+    ///
+    /// if identical contents are already stored, their blob is shared instead (and keeps its original `file_name`)
+    ///
+    /// # Errors
+    /// * Diesel error
+    /// * could not read from `reader` or upload the object
+    /// * the file breaks the [`AttachmentRules`](`super::AttachmentRules`) registered for `name`, or was rejected by the [`ScanHook`](`super::ScanHook`)
+    #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "backend_actix-web")]
+    pub async fn attach_stream(
+        db: &mut Connection,
+        storage: &Storage,
+        name: String,
+        record_type: String,
+        record_id: ID,
+        reader: impl AsyncRead + Unpin,
+        file_name: Option<String>,
+        allow_multiple: bool,
+        overwrite_existing: bool,
+    ) -> Result<String, StorageError> {
+        if allow_multiple {
+            Self::check_count(db, storage, &name, &record_type, record_id)?;
         }
 
-        let attached = diesel::connection::Connection::transaction::<Self, Error, _>(db, |db| {
-            let blob = AttachmentBlob::create(
-                db,
-                #[allow(clippy::cast_possible_wrap)]
-                &AttachmentBlobChangeset {
-                    byte_size: data.data.len() as i64,
-                    service_name: "s3".to_string(),
-                    key: key.clone(),
-                    checksum: checksum.clone(),
-                    content_type: content_type.clone(),
-                    file_name: data.file_name.clone().unwrap_or_default(),
-                },
-            )?;
-
-            let attached = Self::create(
+        // the upload is validated before it replaces an existing attachment
+        let uploaded = Self::upload_blob(storage, &name, reader, file_name).await?;
+        let saved = if allow_multiple {
+            Ok(())
+        } else {
+            Self::replace_existing(
                 db,
-                &AttachmentChangeset {
-                    blob_id: blob.id,
-                    record_id,
-                    record_type,
-                    name,
-                },
-            )?;
-
-            Ok(attached)
-        })
-        .map_err(|err| err.to_string())?;
-
-        let upload_result = storage
-            .upload(
-                key.clone(),
-                data.data,
-                content_type.clone().unwrap_or_default(),
-                checksum.clone(),
+                storage,
+                &name,
+                &record_type,
+                record_id,
+                overwrite_existing,
             )
             .await
-            .map(|()| key);
-
-        if upload_result.is_err() {
-            // attempt to delete the attachment
-            // if it fails, it fails
-            Self::detach(db, storage, attached.id).await?;
         }
+        .and_then(|()| Self::save_upload(db, &uploaded, name, record_type, record_id));
 
-        upload_result
+        Self::settle_upload(storage, &uploaded, saved).await
     }
 
     /// in poem, we need to pass in the pool itself because the Connection is not Send+Sync which poem handlers require
@@ -141,7 +148,7 @@ impl Attachment {
     #[allow(clippy::too_many_arguments)]
     #[cfg(feature = "backend_poem")]
     pub async fn attach(
-        pool: std::sync::Arc<&crate::database::Pool>,
+        pool: &crate::database::Pool,
         storage: &Storage,
         name: String,
         record_type: String,
@@ -149,152 +156,138 @@ impl Attachment {
         data: AttachmentData,
         allow_multiple: bool,
         overwrite_existing: bool,
-    ) -> Result<String, String> {
-        let mut db = pool.clone().get().unwrap();
-
-        let checksum = format!("{:x}", md5::compute(&data.data));
-        let file_name = data.file_name.clone();
-        let content_type = file_name
-            .and_then(|f| mime_guess::from_path(f).first_raw())
-            .map(std::string::ToString::to_string);
-        let key = Uuid::new_v4().to_string();
+    ) -> Result<String, StorageError> {
+        Self::attach_stream(
+            pool,
+            storage,
+            name,
+            record_type,
+            record_id,
+            data.data.as_slice(),
+            data.file_name,
+            allow_multiple,
+            overwrite_existing,
+        )
+        .await
+    }
 
-        if !allow_multiple {
-            if let Ok(existing) =
-                Self::find_for_record(&mut db, name.clone(), record_type.clone(), record_id)
-            {
-                // one already exists, we need to delete it
-                if overwrite_existing {
-                    Self::detach(pool.clone(), storage, existing.id).await.map_err(|_| {
-                        format!("Could not detach the existing attachment for '{name}' attachment on '{record_type}'", name=name.clone(), record_type=record_type.clone())
-                    })?;
-                } else {
-                    // throw the error
-                    return Err(format!("Only 1 attachment is allowed for '{name}' type attachments on '{record_type}'", name=name.clone(), record_type=record_type.clone()));
-                }
-            }
+    /// like [`Attachment::attach`], but uploads whatever `reader` yields without loading it all into memory
+    ///
+    /// if identical contents are already stored, their blob is shared instead (and keeps its original `file_name`)
+    ///
+    /// # Errors
+    /// * Diesel error
+    /// * could not read from `reader` or upload the object
+    /// * the file breaks the [`AttachmentRules`](`super::AttachmentRules`) registered for `name`, or was rejected by the [`ScanHook`](`super::ScanHook`)
+    /// * the pool is unable to get a connection
+    #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "backend_poem")]
+    pub async fn attach_stream(
+        pool: &crate::database::Pool,
+        storage: &Storage,
+        name: String,
+        record_type: String,
+        record_id: ID,
+        reader: impl AsyncRead + Unpin,
+        file_name: Option<String>,
+        allow_multiple: bool,
+        overwrite_existing: bool,
+    ) -> Result<String, StorageError> {
+        if allow_multiple {
+            Self::check_count(&mut pool.get()?, storage, &name, &record_type, record_id)?;
         }
 
-        let attached =
-            diesel::connection::Connection::transaction::<Self, Error, _>(&mut db, |db| {
-                let blob = AttachmentBlob::create(
-                    db,
-                    &AttachmentBlobChangeset {
-                        byte_size: data.data.len() as i64,
-                        service_name: "s3".to_string(),
-                        key: key.clone(),
-                        checksum: checksum.clone(),
-                        content_type: content_type.clone(),
-                        file_name: data.file_name.clone().unwrap_or(String::new()),
-                    },
-                )?;
-
-                let attached = Attachment::create(
-                    db,
-                    &AttachmentChangeset {
-                        blob_id: blob.id,
-                        record_id,
-                        record_type,
-                        name,
-                    },
-                )?;
-
-                Ok(attached)
-            })
-            .map_err(|err| err.to_string())?;
-
-        let upload_result = storage
-            .upload(
-                key.clone(),
-                data.data,
-                content_type.clone().unwrap_or("".to_string()),
-                checksum.clone(),
+        // the upload is validated before it replaces an existing attachment
+        let uploaded = Self::upload_blob(storage, &name, reader, file_name).await?;
+        let saved = if allow_multiple {
+            Ok(())
+        } else {
+            Self::replace_existing(
+                pool,
+                storage,
+                &name,
+                &record_type,
+                record_id,
+                overwrite_existing,
             )
             .await
-            .map(|_| key);
-
-        if upload_result.is_err() {
-            // attempt to delete the attachment
-            // if it fails, it fails
-            Attachment::detach(pool.clone(), storage, attached.id).await?;
         }
+        .and_then(|()| {
+            Self::save_upload(&mut pool.get()?, &uploaded, name, record_type, record_id)
+        });
 
-        upload_result
+        Self::settle_upload(storage, &uploaded, saved).await
     }
 
+    /// the stored file is only deleted once no other attachment shares its blob
+    ///
     /// in `actix_web` we don't need to support send+sync handlers, so we can use the &mut Connection directly.
     ///
     /// # Errors
     /// * Diesel error
     #[cfg(feature = "backend_actix-web")]
-    pub async fn detach(db: &mut Connection, storage: &Storage, item_id: ID) -> Result<(), String> {
-        let attached = Self::find_by_id(db, item_id).map_err(|_| "Could not load attachment")?;
-        let blob = AttachmentBlob::find_by_id(db, attached.blob_id)
-            .map_err(|_| "Could not load attachment blob")?;
-
-        let delete_result = storage.delete(blob.key.clone()).await;
-
-        if let Err(error) = delete_result {
-            // we continue even if there's an error deleting the actual object
-            // todo: make this more robust by checking why it failed to delete the object
-            //       => is it because it didn't exist?
-            println!("{error}");
-        }
+    pub async fn detach(
+        db: &mut Connection,
+        storage: &Storage,
+        item_id: ID,
+    ) -> Result<(), StorageError> {
+        let attached = Self::find_by_id(db, item_id)
+            .map_err(|_| StorageError::NotFound("Could not load attachment".to_string()))?;
 
-        diesel::connection::Connection::transaction::<(), Error, _>(db, |db| {
-            // delete the attachment first because it references the blobs
-            Self::delete(db, attached.id)?;
-            AttachmentBlob::delete(db, blob.id)?;
+        let released =
+            diesel::connection::Connection::transaction::<Vec<AttachmentBlob>, Error, _>(
+                db,
+                |db| {
+                    // delete the attachment first because it references the blob
+                    Self::delete(db, attached.id)?;
+                    Self::release_blobs(db, vec![attached.blob_id])
+                },
+            )
+            .map_err(StorageError::from)?;
 
-            Ok(())
-        })
-        .map_err(|err| err.to_string())?;
+        Self::delete_objects(storage, &released).await;
 
         Ok(())
     }
 
+    /// the stored file is only deleted once no other attachment shares its blob
+    ///
     /// in poem, we need to pass in the pool itself because the Connection is not Send+Sync which poem handlers require
     ///
     /// # Errors
     /// * Diesel error
-    ///
-    /// # Panics
-    /// * If the pool is unable to get a connection
+    /// * the pool is unable to get a connection
     #[allow(clippy::too_many_arguments)]
     #[cfg(feature = "backend_poem")]
     pub async fn detach(
-        pool: std::sync::Arc<&crate::database::Pool>,
+        pool: &crate::database::Pool,
         storage: &Storage,
         item_id: ID,
-    ) -> Result<(), String> {
-        let mut db = pool.get().unwrap();
+    ) -> Result<(), StorageError> {
+        let mut db = pool.get()?;
 
-        let attached =
-            Self::find_by_id(&mut db, item_id).map_err(|_| "Could not load attachment")?;
-        let blob = AttachmentBlob::find_by_id(&mut db, attached.blob_id)
-            .map_err(|_| "Could not load attachment blob")?;
-
-        let delete_result = storage.delete(blob.key.clone()).await;
-
-        if let Err(error) = delete_result {
-            // we continue even if there's an error deleting the actual object
-            // todo: make this more robust by checking why it failed to delete the object
-            //       => is it because it didn't exist?
-            println!("{}", error);
-        }
+        let attached = Self::find_by_id(&mut db, item_id)
+            .map_err(|_| StorageError::NotFound("Could not load attachment".to_string()))?;
 
-        diesel::connection::Connection::transaction::<(), Error, _>(&mut db, |db| {
-            // delete the attachment first because it references the blobs
-            Self::delete(db, attached.id)?;
-            AttachmentBlob::delete(db, blob.id)?;
+        let released =
+            diesel::connection::Connection::transaction::<Vec<AttachmentBlob>, Error, _>(
+                &mut db,
+                |db| {
+                    // delete the attachment first because it references the blob
+                    Self::delete(db, attached.id)?;
+                    Self::release_blobs(db, vec![attached.blob_id])
+                },
+            )
+            .map_err(StorageError::from)?;
+        drop(db);
 
-            Ok(())
-        })
-        .map_err(|err| err.to_string())?;
+        Self::delete_objects(storage, &released).await;
 
         Ok(())
     }
 
+    /// the stored files are only deleted once no other attachment shares their blobs
+    ///
     /// # Errors
     /// * Diesel error
     pub async fn detach_all(
@@ -303,9 +296,11 @@ impl Attachment {
         name: String,
         record_type: String,
         record_id: ID,
-    ) -> Result<(), String> {
-        let attached = Self::find_all_for_record(db, name, record_type, record_id)
-            .map_err(|_| "Could not load attachments")?;
+    ) -> Result<(), StorageError> {
+        let attached =
+            Self::find_all_for_record(db, name, record_type, record_id).map_err(|err| {
+                StorageError::Database(format!("Could not load attachments (error: '{err}')"))
+            })?;
         let attached_ids = attached
             .iter()
             .map(|attached| attached.id)
@@ -314,38 +309,659 @@ impl Attachment {
             .iter()
             .map(|attached| attached.blob_id)
             .collect::<Vec<_>>();
-        let blobs = AttachmentBlob::find_all_by_id(db, blob_ids.clone())
-            .map_err(|_| "Could not load attachment blobs")?;
-        let keys = blobs
-            .iter()
-            .map(|blob| blob.key.to_string())
-            .collect::<Vec<_>>();
 
-        let delete_result = storage.delete_many(keys).await;
+        let released =
+            diesel::connection::Connection::transaction::<Vec<AttachmentBlob>, Error, _>(
+                db,
+                |db| {
+                    // delete the attachments first because they reference the blobs
+                    Self::delete_all(db, attached_ids)?;
+                    Self::release_blobs(db, blob_ids)
+                },
+            )
+            .map_err(StorageError::from)?;
+
+        Self::delete_objects(storage, &released).await;
+
+        Ok(())
+    }
+
+    /// First step of a direct upload: the browser uploads the file straight to the storage backend
+    /// instead of sending it through the app.
+    ///
+    /// Creates a pending blob and returns the URL (and headers) to `PUT` the file to. The upload only
+    /// succeeds if the file has the declared `byte_size` and (hex-encoded md5) `checksum`.
+    /// Once it's done, call [`Attachment::confirm_direct_upload`] with the returned key; pending blobs
+    /// which are never confirmed are removed by [`Attachment::purge_pending_uploads`].
+    ///
+    /// The app can't encrypt direct uploads, so this fails if the storage's [default encryption](`Storage::default_encryption`)
+    /// isn't [`Encryption::None`]; use the bucket's default encryption for those instead.
+    ///
+    /// # Errors
+    /// * invalid `byte_size` or `checksum`
+    /// * Diesel error
+    /// * the backend does not support direct uploads, or the storage encrypts new blobs
+    pub async fn prepare_direct_upload(
+        db: &mut Connection,
+        storage: &Storage,
+        file_name: String,
+        byte_size: i64,
+        checksum: String,
+        expires_in: Duration,
+    ) -> Result<DirectUpload, StorageError> {
+        let checksum = checksum.to_lowercase();
+        if checksum.len() != 32 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(StorageError::Invalid(
+                "Invalid checksum, expected a hex-encoded md5 digest".to_string(),
+            ));
+        }
+        let Ok(unsigned_byte_size) = u64::try_from(byte_size) else {
+            return Err(StorageError::Invalid("Invalid byte size".to_string()));
+        };
+        if storage.default_encryption() != &Encryption::None {
+            return Err(StorageError::Invalid(
+                "Direct uploads can't be encrypted by the app".to_string(),
+            ));
+        }
+
+        let backend = storage.default_backend()?;
+        let key = Uuid::new_v4().to_string();
+        let content_type = mime_guess::from_path(&file_name)
+            .first_raw()
+            .map(std::string::ToString::to_string);
+
+        let blob = AttachmentBlob::create(
+            db,
+            &AttachmentBlobChangeset {
+                key: key.clone(),
+                file_name,
+                content_type: content_type.clone(),
+                byte_size,
+                checksum: checksum.clone(),
+                sha256: None,
+                encryption: None,
+                encryption_key: None,
+                service_name: backend.service_name().to_string(),
+            },
+        )
+        .map_err(StorageError::from)?;
+
+        let upload_uri = backend
+            .direct_upload_uri(
+                &key,
+                expires_in,
+                &content_type.unwrap_or_default(),
+                unsigned_byte_size,
+                &checksum,
+            )
+            .await;
+
+        let upload_uri = match upload_uri {
+            Ok(upload_uri) => upload_uri,
+            Err(error) => {
+                // nothing can be uploaded for this blob
+                let _ = AttachmentBlob::delete(db, blob.id);
+                return Err(error);
+            }
+        };
+
+        Ok(DirectUpload {
+            key,
+            url: upload_uri.uri.to_string(),
+            headers: upload_uri
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+        })
+    }
+
+    /// Second step of a direct upload: checks the file prepared by [`Attachment::prepare_direct_upload`]
+    /// was uploaded with the declared size and checksum, and attaches it to the record.
+    /// Files which break the [`AttachmentRules`](`super::AttachmentRules`) registered for `name` (or are rejected by the
+    /// [`ScanHook`](`super::ScanHook`)) are removed.
+    ///
+    /// in `actix_web` we don't need to support send+sync handlers, so we can use the `&mut Connection` directly.
+    ///
+    /// # Errors
+    /// * no pending upload with the given key
+    /// * the file wasn't uploaded, or doesn't match
+    /// * Diesel error
+    #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "backend_actix-web")]
+    pub async fn confirm_direct_upload(
+        db: &mut Connection,
+        storage: &Storage,
+        key: &str,
+        name: String,
+        record_type: String,
+        record_id: ID,
+        allow_multiple: bool,
+        overwrite_existing: bool,
+    ) -> Result<Self, StorageError> {
+        let blob = Self::find_pending_blob(db, key)?;
+        Self::verify_direct_upload(storage, &blob).await?;
+
+        if allow_multiple {
+            Self::check_count(db, storage, &name, &record_type, record_id)?;
+        }
+        if let Err(error) = Self::validate_direct_upload(storage, &name, &blob).await {
+            Self::delete_objects(storage, std::slice::from_ref(&blob)).await;
+            let _ = AttachmentBlob::delete(db, blob.id);
+            return Err(error);
+        }
+
+        if !allow_multiple {
+            Self::replace_existing(
+                db,
+                storage,
+                &name,
+                &record_type,
+                record_id,
+                overwrite_existing,
+            )
+            .await?;
+        }
+
+        Self::create(
+            db,
+            &AttachmentChangeset {
+                blob_id: blob.id,
+                record_id,
+                record_type,
+                name,
+            },
+        )
+        .map_err(StorageError::from)
+    }
+
+    /// Second step of a direct upload: checks the file prepared by [`Attachment::prepare_direct_upload`]
+    /// was uploaded with the declared size and checksum, and attaches it to the record.
+    /// Files which break the [`AttachmentRules`](`super::AttachmentRules`) registered for `name` (or are rejected by the
+    /// [`ScanHook`](`super::ScanHook`)) are removed.
+    ///
+    /// in poem, we need to pass in the pool itself because the Connection is not Send+Sync which poem handlers require
+    ///
+    /// # Errors
+    /// * no pending upload with the given key
+    /// * the file wasn't uploaded, or doesn't match
+    /// * Diesel error
+    /// * the pool is unable to get a connection
+    #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "backend_poem")]
+    pub async fn confirm_direct_upload(
+        pool: &crate::database::Pool,
+        storage: &Storage,
+        key: &str,
+        name: String,
+        record_type: String,
+        record_id: ID,
+        allow_multiple: bool,
+        overwrite_existing: bool,
+    ) -> Result<Self, StorageError> {
+        let blob = Self::find_pending_blob(&mut pool.get()?, key)?;
+        Self::verify_direct_upload(storage, &blob).await?;
+
+        if allow_multiple {
+            Self::check_count(&mut pool.get()?, storage, &name, &record_type, record_id)?;
+        }
+        if let Err(error) = Self::validate_direct_upload(storage, &name, &blob).await {
+            Self::delete_objects(storage, std::slice::from_ref(&blob)).await;
+            if let Ok(mut db) = pool.get() {
+                let _ = AttachmentBlob::delete(&mut db, blob.id);
+            }
+            return Err(error);
+        }
+
+        if !allow_multiple {
+            Self::replace_existing(
+                pool,
+                storage,
+                &name,
+                &record_type,
+                record_id,
+                overwrite_existing,
+            )
+            .await?;
+        }
+
+        Self::create(
+            &mut pool.get()?,
+            &AttachmentChangeset {
+                blob_id: blob.id,
+                record_id,
+                record_type,
+                name,
+            },
+        )
+        .map_err(StorageError::from)
+    }
+
+    /// Removes the blobs (and uploaded files) of direct uploads which were prepared more than `older_than` ago
+    /// but never confirmed; `older_than` should be longer than the `expires_in` given to
+    /// [`Attachment::prepare_direct_upload`]. Returns the number of removed blobs.
+    ///
+    /// # Errors
+    /// * could not delete the uploaded files; no blobs are removed then
+    /// * Diesel error
+    pub async fn purge_pending_uploads(
+        db: &mut Connection,
+        storage: &Storage,
+        older_than: Duration,
+    ) -> Result<usize, StorageError> {
+        let older_than = chrono::Duration::from_std(older_than)
+            .map_err(|err| StorageError::Invalid(err.to_string()))?;
+        let blobs = AttachmentBlob::find_pending(db, older_than).map_err(StorageError::from)?;
+
+        // the rows are kept if their objects could not be deleted, so the next purge retries them
+        Self::try_delete_objects(storage, &blobs).await?;
+
+        diesel::connection::Connection::transaction::<usize, Error, _>(db, |db| {
+            Self::delete_blobs(db, &blobs)
+        })
+        .map_err(StorageError::from)
+    }
+
+    /// `blobs` along with the blobs derived from them, which have to be removed together
+    pub(super) fn with_derived_blobs(
+        db: &mut Connection,
+        blobs: Vec<AttachmentBlob>,
+    ) -> QueryResult<Vec<AttachmentBlob>> {
+        #[cfg(feature = "plugin_storage-variants")]
+        {
+            let mut blobs = blobs;
+            let variants =
+                super::variants::variant_blobs(db, blobs.iter().map(|blob| blob.id).collect())?;
+            blobs.extend(variants);
+            Ok(blobs)
+        }
+
+        #[cfg(not(feature = "plugin_storage-variants"))]
+        {
+            let _ = db;
+            Ok(blobs)
+        }
+    }
+
+    /// removes the blobs in `blob_ids` which are no longer attached to anything, along with the blobs
+    /// derived from them; returns the removed blobs so their objects can be deleted once the transaction commits
+    fn release_blobs(db: &mut Connection, blob_ids: Vec<ID>) -> QueryResult<Vec<AttachmentBlob>> {
+        use diesel::dsl::{exists, not};
+
+        let unreferenced = schema::attachment_blobs::table
+            .filter(schema::attachment_blobs::id.eq_any(blob_ids))
+            .filter(not(exists(schema::attachments::table.filter(
+                schema::attachments::blob_id.eq(schema::attachment_blobs::id),
+            ))))
+            .load::<AttachmentBlob>(db)?;
+
+        let blobs = Self::with_derived_blobs(db, unreferenced)?;
+        Self::delete_blobs(db, &blobs)?;
+
+        Ok(blobs)
+    }
+
+    /// removes the rows of `blobs`, which must no longer be attached
+    pub(super) fn delete_blobs(
+        db: &mut Connection,
+        blobs: &[AttachmentBlob],
+    ) -> QueryResult<usize> {
+        let blob_ids = blobs.iter().map(|blob| blob.id).collect::<Vec<_>>();
+
+        #[cfg(feature = "plugin_storage-variants")]
+        super::variants::delete_variant_links(db, blob_ids.clone())?;
 
-        if let Err(error) = delete_result {
+        AttachmentBlob::delete_all(db, blob_ids)
+    }
+
+    /// deletes the stored objects of `blobs`, each from the backend it was stored on
+    pub(super) async fn delete_objects(storage: &Storage, blobs: &[AttachmentBlob]) {
+        if let Err(error) = Self::try_delete_objects(storage, blobs).await {
             // we continue even if there's an error deleting the actual object
             // todo: make this more robust by checking why it failed to delete the objects
             //       => is it because it didn't exist?
             println!("{error}");
         }
+    }
+
+    /// like [`Attachment::delete_objects`], for callers which must keep the rows of objects that weren't deleted
+    pub(super) async fn try_delete_objects(
+        storage: &Storage,
+        blobs: &[AttachmentBlob],
+    ) -> Result<(), StorageError> {
+        let mut keys_by_service: HashMap<&str, Vec<String>> = HashMap::new();
+        for blob in blobs {
+            keys_by_service
+                .entry(&blob.service_name)
+                .or_default()
+                .push(blob.key.to_string());
+        }
 
-        diesel::connection::Connection::transaction::<(), Error, _>(db, |db| {
-            // delete the attachments first because they reference the blobs
-            Self::delete_all(db, attached_ids)?;
-            AttachmentBlob::delete_all(db, blob_ids)?;
+        let mut errors = vec![];
+        for (service_name, keys) in keys_by_service {
+            let delete_result = match storage.backend(service_name) {
+                Ok(backend) => backend.delete_many(&keys).await,
+                Err(error) => Err(error),
+            };
 
-            Ok(())
-        })
-        .map_err(|err| err.to_string())?;
+            if let Err(error) = delete_result {
+                errors.push(error);
+            }
+        }
+
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(StorageError::Other(
+                errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            )),
+        }
+    }
+
+    fn find_pending_blob(db: &mut Connection, key: &str) -> Result<AttachmentBlob, StorageError> {
+        let blob = AttachmentBlob::find_by_key(db, key)
+            .map_err(|_| StorageError::NotFound("Could not find the upload".to_string()))?;
+
+        let attached = schema::attachments::table
+            .filter(schema::attachments::blob_id.eq(blob.id))
+            .count()
+            .get_result::<i64>(db)
+            .map_err(StorageError::from)?;
+        if attached > 0 {
+            return Err(StorageError::Conflict(
+                "This upload was already confirmed".to_string(),
+            ));
+        }
+
+        Ok(blob)
+    }
+
+    async fn verify_direct_upload(
+        storage: &Storage,
+        blob: &AttachmentBlob,
+    ) -> Result<(), StorageError> {
+        let Some(metadata) = storage
+            .backend(&blob.service_name)?
+            .metadata(&blob.key)
+            .await?
+        else {
+            return Err(StorageError::NotFound(
+                "The file has not been uploaded yet".to_string(),
+            ));
+        };
+
+        let size_matches =
+            i64::try_from(metadata.byte_size).is_ok_and(|size| size == blob.byte_size);
+        let checksum_matches = metadata
+            .checksum
+            .is_none_or(|checksum| checksum == blob.checksum);
+        if !size_matches || !checksum_matches {
+            return Err(StorageError::Checksum(
+                "The uploaded file does not match its declared size or checksum".to_string(),
+            ));
+        }
 
         Ok(())
     }
 
+    /// checks a directly uploaded file against the rules registered for `name` and the scan hook
+    async fn validate_direct_upload(
+        storage: &Storage,
+        name: &str,
+        blob: &AttachmentBlob,
+    ) -> Result<(), StorageError> {
+        if storage.encryption_for(name) != &Encryption::None {
+            return Err(StorageError::Invalid(format!(
+                "Attachments named '{name}' are encrypted by the app, so they can't be uploaded directly"
+            )));
+        }
+
+        if let Some(rules) = storage.attachment_rules(name) {
+            rules.check_size(u64::try_from(blob.byte_size).unwrap_or_default())?;
+
+            let mut reader = storage.blob_download_stream(blob).await?;
+            let head = read_head(&mut reader).await.map_err(|err| {
+                StorageError::io(&err)(format!("Could not read the upload (error: '{err}')"))
+            })?;
+            rules
+                .check_content_type(detect_content_type(&head, Some(&blob.file_name)).as_deref())?;
+        }
+
+        storage
+            .scan(&blob.file_name, storage.blob_download_stream(blob))
+            .await
+    }
+
+    /// makes room for a single attachment named `name` on the record
+    #[cfg(feature = "backend_actix-web")]
+    async fn replace_existing(
+        db: &mut Connection,
+        storage: &Storage,
+        name: &str,
+        record_type: &str,
+        record_id: ID,
+        overwrite_existing: bool,
+    ) -> Result<(), StorageError> {
+        if let Ok(existing) =
+            Self::find_for_record(db, name.to_string(), record_type.to_string(), record_id)
+        {
+            // one already exists, we need to delete it
+            if overwrite_existing {
+                Self::detach(db, storage, existing.id).await.map_err(|err| {
+                    StorageError::Other(format!("Could not detach the existing attachment for '{name}' attachment on '{record_type}' (error: '{err}')"))
+                })?;
+            } else {
+                // throw the error
+                return Err(StorageError::Conflict(format!(
+                    "Only 1 attachment is allowed for '{name}' type attachments on '{record_type}'"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// makes room for a single attachment named `name` on the record
+    #[cfg(feature = "backend_poem")]
+    async fn replace_existing(
+        pool: &crate::database::Pool,
+        storage: &Storage,
+        name: &str,
+        record_type: &str,
+        record_id: ID,
+        overwrite_existing: bool,
+    ) -> Result<(), StorageError> {
+        let existing = Self::find_for_record(
+            &mut pool.get()?,
+            name.to_string(),
+            record_type.to_string(),
+            record_id,
+        );
+
+        if let Ok(existing) = existing {
+            // one already exists, we need to delete it
+            if overwrite_existing {
+                Self::detach(pool, storage, existing.id).await.map_err(|err| {
+                    StorageError::Other(format!("Could not detach the existing attachment for '{name}' attachment on '{record_type}' (error: '{err}')"))
+                })?;
+            } else {
+                // throw the error
+                return Err(StorageError::Conflict(format!(
+                    "Only 1 attachment is allowed for '{name}' type attachments on '{record_type}'"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// uploads `reader` to the default backend under a new key, enforcing the rules and encryption registered
+    /// for `name`; the content type is detected from the contents, the file name is only used to refine plain text
+    async fn upload_blob(
+        storage: &Storage,
+        name: &str,
+        mut reader: impl AsyncRead + Unpin,
+        file_name: Option<String>,
+    ) -> Result<AttachmentBlobChangeset, StorageError> {
+        let rules = storage.attachment_rules(name);
+        let head = read_head(&mut reader).await.map_err(|err| {
+            StorageError::io(&err)(format!("Could not read the upload (error: '{err}')"))
+        })?;
+        let content_type = detect_content_type(&head, file_name.as_deref());
+        if let Some(rules) = rules {
+            rules.check_content_type(content_type.as_deref())?;
+        }
+
+        let key = Uuid::new_v4().to_string();
+        let backend = storage.default_backend()?;
+
+        // read one byte more than allowed, to notice when the file is too large
+        let max_read = rules
+            .and_then(|rules| rules.max_bytes)
+            .map_or(u64::MAX, |max_bytes| max_bytes.saturating_add(1));
+        let reader = std::io::Cursor::new(head).chain(reader).take(max_read);
+
+        let (session, recorded) = storage
+            .start_blob_upload(
+                backend,
+                &key,
+                &content_type.clone().unwrap_or_default(),
+                storage.encryption_for(name),
+            )
+            .await?;
+        let uploaded = upload_to_session(session, &key, reader).await?;
+
+        let checked = match rules {
+            Some(rules) => rules.check_size(uploaded.byte_size),
+            None => Ok(()),
+        };
+        let file_name = file_name.unwrap_or_default();
+        let checked = match checked {
+            Ok(()) => {
+                let object = storage.open_object(
+                    backend,
+                    &key,
+                    recorded.encryption.as_deref(),
+                    recorded.encryption_key.as_deref(),
+                );
+                storage.scan(&file_name, object).await
+            }
+            Err(error) => Err(error),
+        };
+        if let Err(error) = checked {
+            if let Err(delete_error) = backend.delete(&key).await {
+                println!("{delete_error}");
+            }
+            return Err(error);
+        }
+
+        #[allow(clippy::cast_possible_wrap)]
+        Ok(AttachmentBlobChangeset {
+            byte_size: uploaded.byte_size as i64,
+            service_name: backend.service_name().to_string(),
+            key,
+            checksum: uploaded.checksum,
+            sha256: Some(uploaded.sha256),
+            encryption: recorded.encryption,
+            encryption_key: recorded.encryption_key,
+            content_type,
+            file_name,
+        })
+    }
+
+    /// checks there is room for another attachment named `name` on the record
+    fn check_count(
+        db: &mut Connection,
+        storage: &Storage,
+        name: &str,
+        record_type: &str,
+        record_id: ID,
+    ) -> Result<(), StorageError> {
+        let Some(rules) = storage.attachment_rules(name) else {
+            return Ok(());
+        };
+
+        let existing = schema::attachments::table
+            .filter(schema::attachments::name.eq(name))
+            .filter(schema::attachments::record_type.eq(record_type))
+            .filter(schema::attachments::record_id.eq(record_id))
+            .count()
+            .get_result::<i64>(db)
+            .map_err(StorageError::from)?;
+
+        rules.check_count(usize::try_from(existing).unwrap_or(usize::MAX))
+    }
+
+    /// attaches the `uploaded` blob to the record, reusing an identical blob if one is stored already
+    fn save_upload(
+        db: &mut Connection,
+        uploaded: &AttachmentBlobChangeset,
+        name: String,
+        record_type: String,
+        record_id: ID,
+    ) -> Result<AttachmentBlob, StorageError> {
+        diesel::connection::Connection::transaction::<AttachmentBlob, Error, _>(db, |db| {
+            let blob = match AttachmentBlob::find_duplicate(db, uploaded)? {
+                Some(blob) => blob,
+                None => AttachmentBlob::create(db, uploaded)?,
+            };
+
+            Self::create(
+                db,
+                &AttachmentChangeset {
+                    blob_id: blob.id,
+                    record_id,
+                    record_type,
+                    name,
+                },
+            )?;
+
+            Ok(blob)
+        })
+        .map_err(StorageError::from)
+    }
+
+    /// removes the uploaded object unless the attachment ended up referencing it,
+    /// and returns the key of the attached blob
+    async fn settle_upload(
+        storage: &Storage,
+        uploaded: &AttachmentBlobChangeset,
+        saved: Result<AttachmentBlob, StorageError>,
+    ) -> Result<String, StorageError> {
+        match saved {
+            Ok(blob) if blob.key == uploaded.key => Ok(blob.key),
+            // an identical blob was stored already, or the attachment could not be saved
+            saved => {
+                let delete_result = match storage.backend(&uploaded.service_name) {
+                    Ok(backend) => backend.delete(&uploaded.key).await,
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = delete_result {
+                    println!("{error}");
+                }
+
+                saved.map(|blob| blob.key)
+            }
+        }
+    }
+
     fn create(db: &mut Connection, item: &AttachmentChangeset) -> QueryResult<Self> {
         use super::schema::attachments::dsl::attachments;
 
-        insert_into(attachments).values(item).get_result::<Self>(db)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(attachments).values(item).get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(attachments).values(item).execute(db)?;
+            Self::find_by_id(db, crate::database::last_insert_id(db)?)
+        }
     }
 
     fn find_by_id(db: &mut Connection, item_id: ID) -> QueryResult<Self> {
@@ -356,6 +972,8 @@ impl Attachment {
 
     /// Find an attachment for a given record type and record id
     ///
+    /// read-only, so `db` can be a replica's, see [`Database::get_read_connection`](`crate::Database::get_read_connection`)
+    ///
     /// # Errors
     /// * Diesel error
     pub fn find_for_record(
@@ -373,6 +991,8 @@ impl Attachment {
 
     /// Find all attachments for a given record type and record id
     ///
+    /// read-only, so `db` can be a replica's, see [`Database::get_read_connection`](`crate::Database::get_read_connection`)
+    ///
     /// # Errors
     /// * Diesel error
     pub fn find_all_for_record(
@@ -390,6 +1010,8 @@ impl Attachment {
 
     /// Find all attachments for a given record type and record ids
     ///
+    /// read-only, so `db` can be a replica's, see [`Database::get_read_connection`](`crate::Database::get_read_connection`)
+    ///
     /// # Errors
     /// * Diesel error
     pub fn find_all_for_records(
@@ -425,3 +1047,70 @@ impl Attachment {
         diesel::delete(attachments.filter(schema::attachments::id.eq_any(item_ids))).execute(db)
     }
 }
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::{ExpressionMethods, QueryDsl, QueryResult};
+    use diesel_async::RunQueryDsl;
+
+    use super::Attachment;
+    use crate::database::AsyncConnection;
+    use crate::storage::{schema, ID};
+
+    /// the lookups of [`Attachment`], on an [`AsyncConnection`]
+    impl Attachment {
+        /// like [`Attachment::find_for_record`]
+        ///
+        /// # Errors
+        /// * Diesel error
+        pub async fn find_for_record_async(
+            db: &mut AsyncConnection,
+            item_name: String,
+            item_record_type: String,
+            item_record_id: ID,
+        ) -> QueryResult<Self> {
+            schema::attachments::table
+                .filter(schema::attachments::name.eq(item_name))
+                .filter(schema::attachments::record_type.eq(item_record_type))
+                .filter(schema::attachments::record_id.eq(item_record_id))
+                .first::<Self>(db)
+                .await
+        }
+
+        /// like [`Attachment::find_all_for_record`]
+        ///
+        /// # Errors
+        /// * Diesel error
+        pub async fn find_all_for_record_async(
+            db: &mut AsyncConnection,
+            item_name: String,
+            item_record_type: String,
+            item_record_id: ID,
+        ) -> QueryResult<Vec<Self>> {
+            schema::attachments::table
+                .filter(schema::attachments::name.eq(item_name))
+                .filter(schema::attachments::record_type.eq(item_record_type))
+                .filter(schema::attachments::record_id.eq(item_record_id))
+                .get_results::<Self>(db)
+                .await
+        }
+
+        /// like [`Attachment::find_all_for_records`]
+        ///
+        /// # Errors
+        /// * Diesel error
+        pub async fn find_all_for_records_async(
+            db: &mut AsyncConnection,
+            item_name: String,
+            item_record_type: String,
+            item_record_ids: Vec<ID>,
+        ) -> QueryResult<Vec<Self>> {
+            schema::attachments::table
+                .filter(schema::attachments::name.eq(item_name))
+                .filter(schema::attachments::record_type.eq(item_record_type))
+                .filter(schema::attachments::record_id.eq_any(item_record_ids))
+                .get_results::<Self>(db)
+                .await
+        }
+    }
+}
@@ -20,6 +20,12 @@ pub struct AttachmentBlob {
     pub content_type: Option<String>,
     pub byte_size: i64,
     pub checksum: String,
+    /// hex-encoded sha256 of the contents; `None` for blobs which are never shared
+    pub sha256: Option<String>,
+    /// how the object is encrypted at rest, see [`Encryption`](`super::Encryption`); `None` if it isn't
+    pub encryption: Option<String>,
+    /// the KMS key id for `sse-kms`, or the wrapped data key for `envelope`
+    pub encryption_key: Option<String>,
     pub service_name: String,
 
     pub created_at: Utc,
@@ -34,6 +40,9 @@ pub struct AttachmentBlobChangeset {
     pub content_type: Option<String>,
     pub byte_size: i64,
     pub checksum: String,
+    pub sha256: Option<String>,
+    pub encryption: Option<String>,
+    pub encryption_key: Option<String>,
     pub service_name: String,
 }
 
@@ -45,9 +54,16 @@ impl AttachmentBlob {
     pub fn create(db: &mut Connection, item: &AttachmentBlobChangeset) -> QueryResult<Self> {
         use super::schema::attachment_blobs::dsl::attachment_blobs;
 
-        insert_into(attachment_blobs)
+        #[cfg(not(feature = "database_mysql"))]
+        return insert_into(attachment_blobs)
             .values(item)
-            .get_result::<Self>(db)
+            .get_result::<Self>(db);
+
+        #[cfg(feature = "database_mysql")]
+        {
+            insert_into(attachment_blobs).values(item).execute(db)?;
+            Self::find_by_id(db, crate::database::last_insert_id(db)?)
+        }
     }
 
     /// Read from [`db`](`Connection`), querying for an entry in the `attachment_blobs` table who's primary key matches [`item_id`](`ID`)
@@ -74,6 +90,88 @@ impl AttachmentBlob {
             .load::<Self>(db)
     }
 
+    /// Read from [`db`](`Connection`), querying for the entry in the `attachment_blobs` table with the given `item_key`
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn find_by_key(db: &mut Connection, item_key: &str) -> QueryResult<Self> {
+        use super::schema::attachment_blobs::dsl::attachment_blobs;
+
+        attachment_blobs
+            .filter(schema::attachment_blobs::key.eq(item_key))
+            .first::<Self>(db)
+    }
+
+    /// Read from [`db`](`Connection`), querying for a blob stored on the same service with the same contents
+    /// (sha256 and size) and encryption as `item`, which can be attached instead of storing the contents again
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn find_duplicate(
+        db: &mut Connection,
+        item: &AttachmentBlobChangeset,
+    ) -> QueryResult<Option<Self>> {
+        use diesel::OptionalExtension;
+
+        let Some(sha256) = &item.sha256 else {
+            return Ok(None);
+        };
+
+        let query = schema::attachment_blobs::table
+            .filter(schema::attachment_blobs::sha256.eq(sha256))
+            .filter(schema::attachment_blobs::byte_size.eq(item.byte_size))
+            .filter(schema::attachment_blobs::service_name.eq(&item.service_name))
+            .into_boxed();
+
+        // envelope-encrypted blobs each have their own data key, so only the mode has to match
+        let query = match &item.encryption {
+            Some(encryption) => query.filter(schema::attachment_blobs::encryption.eq(encryption)),
+            None => query.filter(schema::attachment_blobs::encryption.is_null()),
+        };
+        let query = match (item.encryption.as_deref(), &item.encryption_key) {
+            (Some(super::encryption::SSE_KMS), Some(key_id)) => {
+                query.filter(schema::attachment_blobs::encryption_key.eq(key_id))
+            }
+            (Some(super::encryption::SSE_KMS), None) => {
+                query.filter(schema::attachment_blobs::encryption_key.is_null())
+            }
+            _ => query,
+        };
+
+        query.first::<Self>(db).optional()
+    }
+
+    /// Read from [`db`](`Connection`), querying for entries in the `attachment_blobs` table which no attachment
+    /// references (i.e. direct uploads which were never confirmed) and which are older than `older_than`
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn find_pending(
+        db: &mut Connection,
+        older_than: chrono::Duration,
+    ) -> QueryResult<Vec<Self>> {
+        use diesel::dsl::{exists, not};
+
+        #[cfg(feature = "database_postgres")]
+        let cutoff = chrono::Utc::now() - older_than;
+        #[cfg(not(feature = "database_postgres"))]
+        let cutoff = chrono::Utc::now().naive_utc() - older_than;
+
+        let query = schema::attachment_blobs::table
+            .filter(not(exists(schema::attachments::table.filter(
+                schema::attachments::blob_id.eq(schema::attachment_blobs::id),
+            ))))
+            .filter(schema::attachment_blobs::created_at.lt(cutoff));
+
+        // variants aren't attached to anything either
+        #[cfg(feature = "plugin_storage-variants")]
+        let query = query.filter(not(exists(schema::attachment_blob_variants::table.filter(
+            schema::attachment_blob_variants::variant_blob_id.eq(schema::attachment_blobs::id),
+        ))));
+
+        query.load::<Self>(db)
+    }
+
     // fn read_all(db: &mut Connection, pagination: &PaginationParams) -> QueryResult<Vec<Self>> {
     //     use super::schema::attachment_blobs::dsl::*;
     //
@@ -114,3 +212,55 @@ impl AttachmentBlob {
         diesel::delete(query).execute(db)
     }
 }
+
+#[cfg(feature = "database_async")]
+mod database_async {
+    use diesel::{ExpressionMethods, QueryDsl, QueryResult};
+    use diesel_async::RunQueryDsl;
+
+    use super::AttachmentBlob;
+    use crate::database::AsyncConnection;
+    use crate::storage::{schema, ID};
+
+    /// the lookups of [`AttachmentBlob`], on an [`AsyncConnection`]
+    impl AttachmentBlob {
+        /// like [`AttachmentBlob::find_by_id`]
+        ///
+        /// # Errors
+        /// * Diesel error
+        pub async fn find_by_id_async(db: &mut AsyncConnection, item_id: ID) -> QueryResult<Self> {
+            schema::attachment_blobs::table
+                .filter(schema::attachment_blobs::id.eq(item_id))
+                .first::<Self>(db)
+                .await
+        }
+
+        /// like [`AttachmentBlob::find_all_by_id`]
+        ///
+        /// # Errors
+        /// * Diesel error
+        pub async fn find_all_by_id_async(
+            db: &mut AsyncConnection,
+            item_ids: Vec<ID>,
+        ) -> QueryResult<Vec<Self>> {
+            schema::attachment_blobs::table
+                .filter(schema::attachment_blobs::id.eq_any(item_ids))
+                .load::<Self>(db)
+                .await
+        }
+
+        /// like [`AttachmentBlob::find_by_key`]
+        ///
+        /// # Errors
+        /// * Diesel error
+        pub async fn find_by_key_async(
+            db: &mut AsyncConnection,
+            item_key: &str,
+        ) -> QueryResult<Self> {
+            schema::attachment_blobs::table
+                .filter(schema::attachment_blobs::key.eq(item_key))
+                .first::<Self>(db)
+                .await
+        }
+    }
+}
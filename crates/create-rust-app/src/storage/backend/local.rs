@@ -0,0 +1,282 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use super::{ObjectMetadata, ObjectReader, StorageBackend, UploadSession};
+use crate::storage::{StorageError, UploadURI};
+
+/// Stores blobs as files under a root directory; meant for local development and tests.
+#[derive(Clone)]
+pub struct LocalBackend {
+    root: PathBuf,
+    // when set, download URIs point here (e.g. a static file server in front of `root`)
+    public_url: Option<String>,
+}
+
+impl LocalBackend {
+    pub const SERVICE_NAME: &'static str = "local";
+
+    #[must_use]
+    pub fn new(root: impl Into<PathBuf>, public_url: Option<String>) -> Self {
+        Self {
+            root: root.into(),
+            public_url,
+        }
+    }
+
+    /// resolves `key` below the root directory, refusing keys which would escape it
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let relative = Path::new(key);
+        let is_safe = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+        if key.is_empty() || !is_safe {
+            return Err(StorageError::Invalid(format!(
+                "Invalid storage key '{key}'"
+            )));
+        }
+
+        Ok(self.root.join(relative))
+    }
+
+    fn error(
+        message: &'static str,
+        key: impl std::fmt::Display,
+        error: &std::io::Error,
+    ) -> StorageError {
+        StorageError::io(error)(format!(
+            "{message} (service: 'local', key: '{key}', error: '{error}')"
+        ))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    fn service_name(&self) -> &str {
+        Self::SERVICE_NAME
+    }
+
+    async fn upload(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        _content_type: &str,
+        _content_md5: &str,
+    ) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|err| Self::error("Could not upload object", key, &err))?;
+        }
+
+        tokio::fs::write(&path, bytes)
+            .await
+            .map_err(|err| Self::error("Could not upload object", key, &err))
+    }
+
+    async fn start_upload<'a>(
+        &'a self,
+        key: &str,
+        _content_type: &str,
+    ) -> Result<Box<dyn UploadSession + 'a>, StorageError> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|err| Self::error("Could not upload object", key, &err))?;
+        }
+
+        let file = tokio::fs::File::create(&path)
+            .await
+            .map_err(|err| Self::error("Could not upload object", key, &err))?;
+
+        Ok(Box::new(LocalUpload {
+            key: key.to_string(),
+            path,
+            file,
+        }))
+    }
+
+    async fn download(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        tokio::fs::read(self.path(key)?)
+            .await
+            .map_err(|err| Self::error("Could not download object", key, &err))
+    }
+
+    async fn download_stream(&self, key: &str) -> Result<ObjectReader, StorageError> {
+        let file = tokio::fs::File::open(self.path(key)?)
+            .await
+            .map_err(|err| Self::error("Could not download object", key, &err))?;
+
+        Ok(Box::pin(file))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(Self::error("Could not delete object", key, &err))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        tokio::fs::try_exists(self.path(key)?)
+            .await
+            .map_err(|err| Self::error("Could not check object", key, &err))
+    }
+
+    async fn metadata(&self, key: &str) -> Result<Option<ObjectMetadata>, StorageError> {
+        match tokio::fs::metadata(self.path(key)?).await {
+            Ok(metadata) => Ok(Some(ObjectMetadata {
+                byte_size: metadata.len(),
+                checksum: None,
+                last_modified: metadata.modified().ok(),
+            })),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Self::error("Could not check object", key, &err)),
+        }
+    }
+
+    async fn list(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        let mut keys = vec![];
+        let mut directories = vec![self.root.clone()];
+
+        while let Some(directory) = directories.pop() {
+            let mut entries = match tokio::fs::read_dir(&directory).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(Self::error("Could not list objects", "*", &err)),
+            };
+
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|err| Self::error("Could not list objects", "*", &err))?
+            {
+                let path = entry.path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                    let key = relative
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    if prefix.is_none_or(|prefix| key.starts_with(prefix)) {
+                        keys.push(key);
+                    }
+                }
+            }
+        }
+
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn download_uri(
+        &self,
+        key: &str,
+        _expires_in: Option<Duration>,
+    ) -> Result<String, StorageError> {
+        match &self.public_url {
+            Some(public_url) => Ok(format!("{}/{key}", public_url.trim_end_matches('/'))),
+            None => Ok(format!("file://{}", self.path(key)?.display())),
+        }
+    }
+
+    async fn upload_uri(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+    ) -> Result<UploadURI, StorageError> {
+        Err(StorageError::Invalid(
+            "Direct uploads are not supported by the local storage backend".to_string(),
+        ))
+    }
+}
+
+struct LocalUpload {
+    key: String,
+    path: PathBuf,
+    file: tokio::fs::File,
+}
+
+#[async_trait]
+impl UploadSession for LocalUpload {
+    async fn write(&mut self, chunk: Vec<u8>) -> Result<(), StorageError> {
+        self.file
+            .write_all(&chunk)
+            .await
+            .map_err(|err| LocalBackend::error("Could not upload object", &self.key, &err))
+    }
+
+    async fn finish(mut self: Box<Self>, _content_md5: &str) -> Result<(), StorageError> {
+        self.file
+            .flush()
+            .await
+            .map_err(|err| LocalBackend::error("Could not upload object", &self.key, &err))
+    }
+
+    async fn abort(self: Box<Self>) {
+        drop(self.file);
+        let _ = tokio::fs::remove_file(&self.path).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{upload_from_reader, UPLOAD_CHUNK_SIZE};
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn stores_objects_below_root() {
+        let root = std::env::temp_dir().join(format!("cra-storage-{}", uuid::Uuid::new_v4()));
+        let backend = LocalBackend::new(&root, Some("http://localhost:3000/files/".to_string()));
+
+        backend
+            .upload("a/b.txt", b"hi".to_vec(), "", "")
+            .await
+            .unwrap();
+        backend
+            .upload("c.txt", b"yo".to_vec(), "", "")
+            .await
+            .unwrap();
+
+        assert_eq!(backend.download("a/b.txt").await.unwrap(), b"hi");
+        assert_eq!(backend.list(None).await.unwrap(), vec!["a/b.txt", "c.txt"]);
+        assert_eq!(
+            backend.download_uri("a/b.txt", None).await.unwrap(),
+            "http://localhost:3000/files/a/b.txt"
+        );
+        assert!(backend.upload("../escape", vec![], "", "").await.is_err());
+
+        let large = vec![7u8; UPLOAD_CHUNK_SIZE + 1];
+        let uploaded = upload_from_reader(&backend, "large.bin", large.as_slice(), "")
+            .await
+            .unwrap();
+        assert_eq!(uploaded.byte_size, large.len() as u64);
+        assert_eq!(uploaded.checksum, format!("{:x}", md5::compute(&large)));
+        let mut streamed = vec![];
+        backend
+            .download_stream("large.bin")
+            .await
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, large);
+
+        backend.delete("a/b.txt").await.unwrap();
+        assert!(!backend.exists("a/b.txt").await.unwrap());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
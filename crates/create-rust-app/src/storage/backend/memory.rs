@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+
+use super::{ObjectMetadata, StorageBackend};
+use crate::storage::{StorageError, UploadURI};
+
+/// Keeps blobs in memory; useful in tests.
+///
+/// Clones share the same objects.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    objects: Arc<RwLock<BTreeMap<String, StoredObject>>>,
+}
+
+struct StoredObject {
+    bytes: Vec<u8>,
+    last_modified: SystemTime,
+}
+
+impl MemoryBackend {
+    pub const SERVICE_NAME: &'static str = "memory";
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    fn service_name(&self) -> &str {
+        Self::SERVICE_NAME
+    }
+
+    async fn upload(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        _content_type: &str,
+        _content_md5: &str,
+    ) -> Result<(), StorageError> {
+        self.objects
+            .write()
+            .map_err(|err| StorageError::Other(err.to_string()))?
+            .insert(
+                key.to_string(),
+                StoredObject {
+                    bytes,
+                    last_modified: SystemTime::now(),
+                },
+            );
+
+        Ok(())
+    }
+
+    async fn download(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        self.objects
+            .read()
+            .map_err(|err| StorageError::Other(err.to_string()))?
+            .get(key)
+            .map(|object| object.bytes.clone())
+            .ok_or_else(|| {
+                StorageError::NotFound(format!(
+                    "Could not download object (key: '{key}', error: 'not found')"
+                ))
+            })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.objects
+            .write()
+            .map_err(|err| StorageError::Other(err.to_string()))?
+            .remove(key);
+
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        Ok(self
+            .objects
+            .read()
+            .map_err(|err| StorageError::Other(err.to_string()))?
+            .contains_key(key))
+    }
+
+    async fn metadata(&self, key: &str) -> Result<Option<ObjectMetadata>, StorageError> {
+        Ok(self
+            .objects
+            .read()
+            .map_err(|err| StorageError::Other(err.to_string()))?
+            .get(key)
+            .map(|object| ObjectMetadata {
+                byte_size: object.bytes.len() as u64,
+                checksum: Some(format!("{:x}", md5::compute(&object.bytes))),
+                last_modified: Some(object.last_modified),
+            }))
+    }
+
+    async fn list(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .objects
+            .read()
+            .map_err(|err| StorageError::Other(err.to_string()))?
+            .keys()
+            .filter(|key| prefix.is_none_or(|prefix| key.starts_with(prefix)))
+            .cloned()
+            .collect())
+    }
+
+    async fn download_uri(
+        &self,
+        key: &str,
+        _expires_in: Option<Duration>,
+    ) -> Result<String, StorageError> {
+        Ok(format!("memory://{key}"))
+    }
+
+    async fn upload_uri(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+    ) -> Result<UploadURI, StorageError> {
+        Err(StorageError::Invalid(
+            "Direct uploads are not supported by the in-memory storage backend".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_lists_and_deletes_objects() {
+        let backend = MemoryBackend::new();
+
+        backend.upload("a/1", vec![1], "", "").await.unwrap();
+        backend.upload("b/2", vec![2], "", "").await.unwrap();
+
+        assert_eq!(backend.download("a/1").await.unwrap(), vec![1]);
+        assert_eq!(backend.list(Some("a/")).await.unwrap(), vec!["a/1"]);
+
+        backend.delete_many(&["a/1".to_string()]).await.unwrap();
+        assert!(!backend.exists("a/1").await.unwrap());
+        assert!(backend.exists("b/2").await.unwrap());
+        assert!(backend.download("a/1").await.is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use std::pin::Pin;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{StorageError, UploadURI};
+
+mod local;
+mod memory;
+mod s3;
+
+pub use local::LocalBackend;
+pub use memory::MemoryBackend;
+pub use s3::{S3Backend, S3Config, S3Credentials};
+
+/// streamed uploads are handed to the backend in chunks of this size (except for the last one);
+/// this is also the S3 multipart part size, which must be at least 5 MiB
+pub const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// the contents of a downloaded object, read incrementally
+pub type ObjectReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// A place where blobs can be stored.
+///
+/// Every [`AttachmentBlob`](`super::AttachmentBlob`) records the [`service_name`](`StorageBackend::service_name`)
+/// of the backend it was uploaded to, so several backends can be registered on a [`Storage`](`super::Storage`)
+/// at the same time (for example, when migrating from one bucket to another).
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// name stored in `attachment_blobs.service_name` for blobs uploaded to this backend
+    fn service_name(&self) -> &str;
+
+    /// store `bytes` under `key`, replacing any existing object; `content_md5` is the hex-encoded md5
+    /// of `bytes`, which backends sending them over the network use to detect corruption
+    ///
+    /// # Errors
+    /// * could not upload the object
+    /// * [`StorageError::Checksum`] if the backend received something else
+    async fn upload(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        content_type: &str,
+        content_md5: &str,
+    ) -> Result<(), StorageError>;
+
+    /// begin a chunked upload of the object stored under `key`; see [`UPLOAD_CHUNK_SIZE`]
+    ///
+    /// the default implementation buffers the chunks and calls [`StorageBackend::upload`] when finished
+    ///
+    /// # Errors
+    /// * could not start the upload
+    async fn start_upload<'a>(
+        &'a self,
+        key: &str,
+        content_type: &str,
+    ) -> Result<Box<dyn UploadSession + 'a>, StorageError> {
+        Ok(Box::new(BufferedUpload {
+            backend: self,
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            buffer: vec![],
+        }))
+    }
+
+    /// like [`StorageBackend::start_upload`], but the backend encrypts the object at rest
+    ///
+    /// # Errors
+    /// * could not start the upload, or the backend doesn't support server-side encryption
+    async fn start_encrypted_upload<'a>(
+        &'a self,
+        _key: &str,
+        _content_type: &str,
+        _encryption: &ServerSideEncryption,
+    ) -> Result<Box<dyn UploadSession + 'a>, StorageError> {
+        Err(StorageError::Invalid(format!(
+            "The '{}' backend does not support server-side encryption",
+            self.service_name()
+        )))
+    }
+
+    /// fetch the object stored under `key`
+    ///
+    /// # Errors
+    /// * the object does not exist, or could not be downloaded
+    async fn download(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// stream the object stored under `key`
+    ///
+    /// the default implementation downloads the whole object first
+    ///
+    /// # Errors
+    /// * the object does not exist, or could not be downloaded
+    async fn download_stream(&self, key: &str) -> Result<ObjectReader, StorageError> {
+        Ok(Box::pin(std::io::Cursor::new(self.download(key).await?)))
+    }
+
+    /// remove the object stored under `key`; deleting a missing object is not an error
+    ///
+    /// # Errors
+    /// * could not delete the object
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// remove every object in `keys`
+    ///
+    /// # Errors
+    /// * could not delete one of the objects; the error lists the keys that weren't deleted
+    async fn delete_many(&self, keys: &[String]) -> Result<(), StorageError> {
+        for key in keys {
+            self.delete(key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// check whether an object is stored under `key`
+    ///
+    /// # Errors
+    /// * could not reach the backend
+    async fn exists(&self, key: &str) -> Result<bool, StorageError>;
+
+    /// size (and, if the backend knows it, checksum) of the object stored under `key`;
+    /// `None` if there is no such object
+    ///
+    /// # Errors
+    /// * could not reach the backend
+    async fn metadata(&self, key: &str) -> Result<Option<ObjectMetadata>, StorageError>;
+
+    /// list the keys of every stored object starting with `prefix`
+    ///
+    /// # Errors
+    /// * could not list the objects
+    async fn list(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError>;
+
+    /// a URI the object can be downloaded from;
+    /// if `expires_in` is `None`, the object is assumed to be publicly accessible
+    ///
+    /// # Errors
+    /// * could not build the URI
+    async fn download_uri(
+        &self,
+        key: &str,
+        expires_in: Option<Duration>,
+    ) -> Result<String, StorageError>;
+
+    /// a URI (and the headers to send with it) the object can be uploaded to directly
+    ///
+    /// # Errors
+    /// * could not build the URI, or the backend doesn't support direct uploads
+    async fn upload_uri(&self, key: &str, expires_in: Duration) -> Result<UploadURI, StorageError>;
+
+    /// like [`StorageBackend::upload_uri`], but the upload is only accepted if it has the given
+    /// content type, size and (hex-encoded) md5 checksum, when the backend is able to enforce that
+    ///
+    /// # Errors
+    /// * could not build the URI, or the backend doesn't support direct uploads
+    async fn direct_upload_uri(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        _content_type: &str,
+        _byte_size: u64,
+        _content_md5: &str,
+    ) -> Result<UploadURI, StorageError> {
+        self.upload_uri(key, expires_in).await
+    }
+}
+
+/// Encryption at rest performed by the backend itself, see [`StorageBackend::start_encrypted_upload`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerSideEncryption {
+    /// keys managed by the storage provider (`SSE-S3` on S3)
+    Managed,
+    /// keys kept in a key management service (`SSE-KMS` on S3); `None` uses the account's default key
+    Kms { key_id: Option<String> },
+}
+
+/// What a backend knows about a stored object
+pub struct ObjectMetadata {
+    pub byte_size: u64,
+    /// hex-encoded md5 of the contents; `None` when the backend can't tell (like S3 for objects
+    /// uploaded in parts or encrypted with KMS)
+    pub checksum: Option<String>,
+    pub last_modified: Option<SystemTime>,
+}
+
+/// An upload in progress, see [`StorageBackend::start_upload`]
+#[async_trait]
+pub trait UploadSession: Send {
+    /// append the next chunk of the object
+    ///
+    /// # Errors
+    /// * could not upload the chunk
+    async fn write(&mut self, chunk: Vec<u8>) -> Result<(), StorageError>;
+
+    /// store the object; `content_md5` is the hex-encoded md5 of everything written
+    ///
+    /// # Errors
+    /// * could not store the object
+    async fn finish(self: Box<Self>, content_md5: &str) -> Result<(), StorageError>;
+
+    /// discard everything written so far
+    async fn abort(self: Box<Self>);
+}
+
+struct BufferedUpload<'a, B: ?Sized> {
+    backend: &'a B,
+    key: String,
+    content_type: String,
+    buffer: Vec<u8>,
+}
+
+#[async_trait]
+impl<B: StorageBackend + ?Sized> UploadSession for BufferedUpload<'_, B> {
+    async fn write(&mut self, chunk: Vec<u8>) -> Result<(), StorageError> {
+        self.buffer.extend_from_slice(&chunk);
+        Ok(())
+    }
+
+    async fn finish(self: Box<Self>, content_md5: &str) -> Result<(), StorageError> {
+        self.backend
+            .upload(&self.key, self.buffer, &self.content_type, content_md5)
+            .await
+    }
+
+    async fn abort(self: Box<Self>) {}
+}
+
+/// size and hex-encoded md5 and sha256 of a streamed upload
+pub struct UploadedObject {
+    pub byte_size: u64,
+    pub checksum: String,
+    pub sha256: String,
+}
+
+/// upload everything `reader` yields to `backend`, computing the checksum along the way;
+/// at most [`UPLOAD_CHUNK_SIZE`] bytes are held in memory at once
+///
+/// # Errors
+/// * could not read from `reader`
+/// * could not upload the object
+pub async fn upload_from_reader(
+    backend: &dyn StorageBackend,
+    key: &str,
+    reader: impl AsyncRead + Unpin,
+    content_type: &str,
+) -> Result<UploadedObject, StorageError> {
+    let session = backend.start_upload(key, content_type).await?;
+    upload_to_session(session, key, reader).await
+}
+
+/// write everything `reader` yields to `session` and finish it, computing the checksum along the way
+///
+/// # Errors
+/// * could not read from `reader`
+/// * could not upload the object
+pub(crate) async fn upload_to_session(
+    mut session: Box<dyn UploadSession + '_>,
+    key: &str,
+    mut reader: impl AsyncRead + Unpin,
+) -> Result<UploadedObject, StorageError> {
+    let mut context = md5::Context::new();
+    let mut sha256 = Sha256::new();
+    let mut byte_size = 0u64;
+
+    loop {
+        let chunk = match read_chunk(&mut reader).await {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                session.abort().await;
+                return Err(StorageError::io(&err)(format!(
+                    "Could not upload object (key: '{key}', error: '{err}')"
+                )));
+            }
+        };
+        if chunk.is_empty() {
+            break;
+        }
+
+        context.consume(&chunk);
+        sha256.update(&chunk);
+        byte_size += chunk.len() as u64;
+
+        if let Err(error) = session.write(chunk).await {
+            session.abort().await;
+            return Err(error);
+        }
+    }
+
+    let checksum = format!("{:x}", context.compute());
+    session.finish(&checksum).await?;
+
+    Ok(UploadedObject {
+        byte_size,
+        checksum,
+        sha256: format!("{:x}", sha256.finalize()),
+    })
+}
+
+/// reads up to [`UPLOAD_CHUNK_SIZE`] bytes; only returns less at the end of the stream
+async fn read_chunk(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(UPLOAD_CHUNK_SIZE);
+    reader
+        .take(UPLOAD_CHUNK_SIZE as u64)
+        .read_to_end(&mut chunk)
+        .await?;
+    Ok(chunk)
+}
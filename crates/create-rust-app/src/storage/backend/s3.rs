@@ -0,0 +1,1424 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use aws_config::default_provider::credentials::DefaultCredentialsChain;
+use aws_config::provider_config::ProviderConfig;
+use aws_credential_types::provider::{future, ProvideCredentials};
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::config::{
+    BehaviorVersion, Credentials, Region, RequestChecksumCalculation, ResponseChecksumValidation,
+    SharedCredentialsProvider, SharedHttpClient,
+};
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::presigning::{PresignedRequest, PresigningConfig};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{
+    CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier,
+    ServerSideEncryption as S3ServerSideEncryption,
+};
+use aws_sdk_s3::{Client, Config};
+use aws_smithy_http_client::tls::{self, rustls_provider::CryptoMode, TlsContext, TrustStore};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use http::header::{HeaderName, HeaderValue};
+use http::{HeaderMap, Uri};
+use rustls_pki_types::{pem::PemObject, CertificateDer};
+
+use super::{ObjectMetadata, ObjectReader, ServerSideEncryption, StorageBackend, UploadSession};
+use crate::storage::{RetryPolicy, StorageError, UploadURI};
+
+/// the most keys a `DeleteObjects` request accepts
+const DELETE_OBJECTS_LIMIT: usize = 1000;
+
+/// How to reach an S3-compatible bucket, see [`S3Backend::from_config`]
+#[derive(Debug, Clone, Default)]
+pub struct S3Config {
+    /// the URL of the S3-compatible service (like MinIO, R2 or Wasabi); `None` for AWS itself
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub bucket: String,
+    /// `None` to use the default AWS credentials chain: the `AWS_*` environment variables,
+    /// the shared config and credentials files (including SSO), web identity tokens, ECS and EC2 instance roles
+    pub credentials: Option<S3Credentials>,
+    /// address the bucket by path (`{endpoint}/{bucket}/{key}`) instead of by host (`{bucket}.{endpoint}/{key}`);
+    /// most self-hosted services need this
+    pub force_path_style: bool,
+    /// PEM-encoded certificates to trust in addition to the platform's, like the CA of a self-signed endpoint
+    pub ca_certificates: Vec<Vec<u8>>,
+    /// where objects are served from when [`StorageBackend::download_uri`] isn't given an expiry
+    /// (like a CDN or an R2 public bucket URL); defaults to the bucket's own URL
+    pub public_url: Option<String>,
+}
+
+/// Static credentials for [`S3Config`]
+#[derive(Clone)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// for temporary credentials
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"** redacted **")
+            .field(
+                "session_token",
+                &self.session_token.as_ref().map(|_| "** redacted **"),
+            )
+            .finish()
+    }
+}
+
+/// Stores blobs in an S3-compatible bucket
+#[derive(Clone)]
+pub struct S3Backend {
+    client: Client,
+    bucket: String,
+    public_url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl S3Backend {
+    pub const SERVICE_NAME: &'static str = "s3";
+
+    /// connect to the bucket at `host`, addressing it by path
+    ///
+    /// # Errors
+    /// * `host` is not a valid URI
+    pub fn new(
+        host: &str,
+        region: &str,
+        bucket: &str,
+        access_key_id: String,
+        secret_access_key: String,
+    ) -> Result<Self, StorageError> {
+        Self::from_config(S3Config {
+            endpoint: Some(host.to_string()),
+            region: region.to_string(),
+            bucket: bucket.to_string(),
+            credentials: Some(S3Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: None,
+            }),
+            force_path_style: true,
+            ..S3Config::default()
+        })
+    }
+
+    /// connect to the bucket described by `config`
+    ///
+    /// # Errors
+    /// * the endpoint is not a valid URI, or one of the CA certificates isn't valid PEM
+    pub fn from_config(config: S3Config) -> Result<Self, StorageError> {
+        let not_configured = |error: &dyn std::fmt::Display| {
+            StorageError::NotConfigured(format!("Could not initialize storage (error: '{error}')"))
+        };
+
+        let endpoint = config
+            .endpoint
+            .as_deref()
+            .map(|endpoint| {
+                let uri = Uri::from_str(endpoint).map_err(|err| not_configured(&err))?;
+                match (uri.scheme_str(), uri.host()) {
+                    (Some(_), Some(_)) => Ok(endpoint.trim_end_matches('/').to_string()),
+                    _ => Err(not_configured(&"the endpoint must be an absolute URL")),
+                }
+            })
+            .transpose()?;
+
+        let mut trust_store = TrustStore::default();
+        for pem in config.ca_certificates {
+            let certificates = CertificateDer::pem_slice_iter(&pem)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| not_configured(&err))?;
+            if certificates.is_empty() {
+                return Err(not_configured(&"no certificate found in the CA bundle"));
+            }
+            trust_store.add_pem_certificate(pem);
+        }
+        let tls_context = TlsContext::builder()
+            .with_trust_store(trust_store)
+            .build()
+            .map_err(|err| not_configured(&err))?;
+        let http_client = aws_smithy_http_client::Builder::new()
+            .tls_provider(tls::Provider::Rustls(CryptoMode::Ring))
+            .tls_context(tls_context)
+            .build_https();
+
+        let region = Region::new(config.region);
+        let credentials = match config.credentials {
+            Some(credentials) => SharedCredentialsProvider::new(Credentials::new(
+                credentials.access_key_id,
+                credentials.secret_access_key,
+                credentials.session_token,
+                None,
+                "create-rust-app",
+            )),
+            None => SharedCredentialsProvider::new(DefaultCredentials {
+                region: region.clone(),
+                http_client: http_client.clone(),
+                chain: tokio::sync::OnceCell::new(),
+            }),
+        };
+
+        let public_url = match (config.public_url, &endpoint) {
+            (Some(public_url), _) => public_url.trim_end_matches('/').to_string(),
+            (None, Some(endpoint)) if config.force_path_style => {
+                format!("{endpoint}/{bucket}", bucket = config.bucket)
+            }
+            (None, Some(endpoint)) => {
+                let (scheme, host) = endpoint.split_once("://").unwrap_or(("https", endpoint));
+                format!("{scheme}://{bucket}.{host}", bucket = config.bucket)
+            }
+            (None, None) => format!(
+                "https://{bucket}.s3.{region}.amazonaws.com",
+                bucket = config.bucket
+            ),
+        };
+
+        let mut s3_config = Config::builder();
+        s3_config.set_endpoint_url(endpoint);
+        let s3_config = s3_config
+            .behavior_version(BehaviorVersion::latest())
+            .region(region)
+            .force_path_style(config.force_path_style)
+            .credentials_provider(credentials)
+            .http_client(http_client)
+            // uploads are checked with `Content-MD5`; not every S3-compatible service supports the newer checksums
+            .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+            .response_checksum_validation(ResponseChecksumValidation::WhenRequired)
+            // requests are retried by `retry_policy` instead
+            .retry_config(RetryConfig::disabled())
+            .build();
+
+        Ok(Self {
+            client: Client::from_conf(s3_config),
+            bucket: config.bucket,
+            public_url,
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// connect using the `S3_*` environment variables:
+    ///
+    /// * `S3_BUCKET`
+    /// * `S3_REGION` (or `AWS_REGION`)
+    /// * `S3_HOST` - the endpoint of an S3-compatible service, leave it unset for AWS
+    /// * `S3_ACCESS_KEY_ID`, `S3_SECRET_ACCESS_KEY` and optionally `S3_SESSION_TOKEN`;
+    ///   if they're unset, the default AWS credentials chain is used
+    /// * `S3_FORCE_PATH_STYLE` - `true` or `false`, defaults to whether `S3_HOST` is set
+    /// * `S3_CA_BUNDLE` - the path of a PEM file with additional CA certificates to trust
+    /// * `S3_PUBLIC_URL` - see [`S3Config::public_url`]
+    ///
+    /// # Errors
+    /// * a required variable is missing or invalid, or the CA bundle can't be read
+    pub fn from_env() -> Result<Self, StorageError> {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+        let region = var("S3_REGION").or_else(|| var("AWS_REGION"));
+        let bucket = var("S3_BUCKET");
+        let unset_vars = [
+            ("S3_REGION", region.is_none()),
+            ("S3_BUCKET", bucket.is_none()),
+        ]
+        .into_iter()
+        .filter_map(|(name, unset)| unset.then_some(name))
+        .collect::<Vec<_>>();
+        if !unset_vars.is_empty() {
+            return Err(StorageError::NotConfigured(format!(
+                "the following variables must be set: {}",
+                unset_vars.join(", ")
+            )));
+        }
+
+        let credentials = match (var("S3_ACCESS_KEY_ID"), var("S3_SECRET_ACCESS_KEY")) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(S3Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: var("S3_SESSION_TOKEN"),
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(StorageError::NotConfigured(
+                    "S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY must be set together".to_string(),
+                ))
+            }
+        };
+
+        let endpoint = var("S3_HOST");
+        let force_path_style = match var("S3_FORCE_PATH_STYLE") {
+            Some(value) => value.parse::<bool>().map_err(|_| {
+                StorageError::NotConfigured(format!(
+                    "S3_FORCE_PATH_STYLE must be 'true' or 'false', not '{value}'"
+                ))
+            })?,
+            None => endpoint.is_some(),
+        };
+
+        let ca_certificates = match var("S3_CA_BUNDLE") {
+            Some(path) => vec![std::fs::read(&path).map_err(|err| {
+                StorageError::NotConfigured(format!(
+                    "Could not read S3_CA_BUNDLE (path: '{path}', error: '{err}')"
+                ))
+            })?],
+            None => vec![],
+        };
+
+        Self::from_config(S3Config {
+            endpoint,
+            region: region.unwrap_or_default(),
+            bucket: bucket.unwrap_or_default(),
+            credentials,
+            force_path_style,
+            ca_certificates,
+            public_url: var("S3_PUBLIC_URL"),
+        })
+    }
+
+    /// how requests failing with a transient error (throttling, timeouts, 5xx responses) are retried;
+    /// defaults to [`RetryPolicy::default`]
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    fn error(
+        &self,
+        kind: fn(String) -> StorageError,
+        message: &'static str,
+        key: impl std::fmt::Display,
+        error: impl std::fmt::Display,
+    ) -> StorageError {
+        let bucket = &self.bucket;
+        kind(format!(
+            "{message} (bucket: '{bucket}', key: '{key}', error: '{error}')"
+        ))
+    }
+
+    /// send one `DeleteObjects` request; returns the keys S3 could not delete, with why
+    async fn delete_chunk(&self, keys: &[String]) -> Result<Vec<String>, StorageError> {
+        let invalid = |err| {
+            self.error(
+                StorageError::Invalid,
+                "Could not delete objects",
+                format!("{keys:#?}"),
+                err,
+            )
+        };
+        let ids = keys
+            .iter()
+            .map(|k| ObjectIdentifier::builder().key(k).build().map_err(invalid))
+            .collect::<Result<Vec<ObjectIdentifier>, _>>()?;
+        let delete = &Delete::builder()
+            .set_objects(Some(ids))
+            .quiet(true)
+            .build()
+            .map_err(invalid)?;
+
+        let output = self
+            .retry_policy
+            .run(|| async move {
+                self.client
+                    .delete_objects()
+                    .bucket(&self.bucket)
+                    .delete(delete.clone())
+                    .send()
+                    .await
+                    .map_err(|err| {
+                        self.sdk_error("Could not delete objects", format!("{keys:#?}"), &err)
+                    })
+            })
+            .await?;
+
+        Ok(output
+            .errors()
+            .iter()
+            .map(|error| {
+                format!(
+                    "{} ({}: {})",
+                    error.key().unwrap_or_default(),
+                    error.code().unwrap_or("unknown"),
+                    error.message().unwrap_or_default()
+                )
+            })
+            .collect())
+    }
+
+    fn sdk_error<E: ProvideErrorMetadata + std::error::Error + 'static>(
+        &self,
+        message: &'static str,
+        key: impl std::fmt::Display,
+        error: &SdkError<E>,
+    ) -> StorageError {
+        self.error(
+            sdk_error_kind(error),
+            message,
+            key,
+            DisplayErrorContext(error),
+        )
+    }
+
+    async fn put_object(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        content_type: &str,
+        content_md5: &str,
+        encryption: Option<&ServerSideEncryption>,
+    ) -> Result<(), StorageError> {
+        let (server_side_encryption, kms_key_id) = sse_headers(encryption);
+        let content_md5 = md5_to_base64(content_md5).ok_or_else(|| {
+            self.error(
+                StorageError::Invalid,
+                "Could not upload object",
+                key,
+                "invalid md5 checksum",
+            )
+        })?;
+
+        let (bytes, content_md5) = (&bytes, &content_md5);
+        let (server_side_encryption, kms_key_id) = (&server_side_encryption, &kms_key_id);
+        self.retry_policy
+            .run(|| async move {
+                self.client
+                    .put_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .body(ByteStream::from(bytes.clone()))
+                    .content_type(content_type)
+                    .content_md5(content_md5)
+                    .set_server_side_encryption(server_side_encryption.clone())
+                    .set_ssekms_key_id(kms_key_id.clone())
+                    .send()
+                    .await
+                    .map_err(|err| self.sdk_error("Could not upload object", key, &err))
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    fn presigning_config(
+        &self,
+        message: &'static str,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<PresigningConfig, StorageError> {
+        PresigningConfig::expires_in(expires_in)
+            .map_err(|err| self.error(StorageError::Invalid, message, key, err))
+    }
+
+    fn upload_uri_from(
+        &self,
+        key: &str,
+        request: &PresignedRequest,
+    ) -> Result<UploadURI, StorageError> {
+        let invalid = |err: &dyn std::fmt::Display| {
+            self.error(
+                StorageError::Other,
+                "Could not retrieve upload URI",
+                key,
+                err,
+            )
+        };
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in request.headers() {
+            headers.insert(
+                HeaderName::from_str(name).map_err(|err| invalid(&err))?,
+                HeaderValue::from_str(value).map_err(|err| invalid(&err))?,
+            );
+        }
+
+        Ok(UploadURI {
+            uri: Uri::from_str(request.uri()).map_err(|err| invalid(&err))?,
+            headers,
+        })
+    }
+}
+
+/// the default AWS credentials chain; building it reads the shared config files, so it's done on first use
+#[derive(Debug)]
+struct DefaultCredentials {
+    region: Region,
+    http_client: SharedHttpClient,
+    chain: tokio::sync::OnceCell<DefaultCredentialsChain>,
+}
+
+impl ProvideCredentials for DefaultCredentials {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(async move {
+            let chain = self
+                .chain
+                .get_or_init(|| {
+                    DefaultCredentialsChain::builder()
+                        .configure(
+                            ProviderConfig::default()
+                                .with_http_client(self.http_client.clone())
+                                .with_region(Some(self.region.clone())),
+                        )
+                        .build()
+                })
+                .await;
+
+            chain.provide_credentials().await
+        })
+    }
+}
+
+/// which [`StorageError`] a failed request maps to
+fn sdk_error_kind<E: ProvideErrorMetadata>(error: &SdkError<E>) -> fn(String) -> StorageError {
+    match error {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            StorageError::Network
+        }
+        SdkError::ServiceError(context) => match context.err().code() {
+            Some("NoSuchKey" | "NoSuchBucket" | "NoSuchUpload" | "NotFound") => {
+                StorageError::NotFound
+            }
+            Some(
+                "AccessDenied"
+                | "AllAccessDisabled"
+                | "InvalidAccessKeyId"
+                | "SignatureDoesNotMatch"
+                | "ExpiredToken",
+            ) => StorageError::AccessDenied,
+            Some("SlowDown" | "Throttling" | "ThrottlingException" | "RequestLimitExceeded") => {
+                StorageError::Throttled
+            }
+            Some("BadDigest" | "InvalidDigest" | "IncompleteBody") => StorageError::Checksum,
+            // HEAD responses have no body, so only the status tells what went wrong
+            _ => match context.raw().status().as_u16() {
+                404 => StorageError::NotFound,
+                401 | 403 => StorageError::AccessDenied,
+                429 | 503 => StorageError::Throttled,
+                500..=599 => StorageError::Network,
+                _ => StorageError::Other,
+            },
+        },
+        _ => StorageError::Other,
+    }
+}
+
+/// the `x-amz-server-side-encryption` and `x-amz-server-side-encryption-aws-kms-key-id` headers
+fn sse_headers(
+    encryption: Option<&ServerSideEncryption>,
+) -> (Option<S3ServerSideEncryption>, Option<String>) {
+    match encryption {
+        None => (None, None),
+        Some(ServerSideEncryption::Managed) => (Some(S3ServerSideEncryption::Aes256), None),
+        Some(ServerSideEncryption::Kms { key_id }) => {
+            (Some(S3ServerSideEncryption::AwsKms), key_id.clone())
+        }
+    }
+}
+
+/// Objects which fit in a single chunk are uploaded with a plain `PutObject`;
+/// larger ones use a multipart upload, one part per chunk.
+struct S3Upload<'a> {
+    backend: &'a S3Backend,
+    key: String,
+    content_type: String,
+    encryption: Option<ServerSideEncryption>,
+    // set once the multipart upload has been started
+    upload_id: Option<String>,
+    // the latest chunk; held back so a single-chunk object doesn't need a multipart upload
+    pending: Option<Vec<u8>>,
+    parts: Vec<CompletedPart>,
+}
+
+impl S3Upload<'_> {
+    async fn upload_part(&mut self, chunk: Vec<u8>) -> Result<(), StorageError> {
+        let backend = self.backend;
+        let key = self.key.as_str();
+
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let (server_side_encryption, kms_key_id) = &sse_headers(self.encryption.as_ref());
+                let content_type = self.content_type.as_str();
+                let output = backend
+                    .retry_policy
+                    .run(|| async move {
+                        backend
+                            .client
+                            .create_multipart_upload()
+                            .bucket(&backend.bucket)
+                            .key(key)
+                            .content_type(content_type)
+                            .set_server_side_encryption(server_side_encryption.clone())
+                            .set_ssekms_key_id(kms_key_id.clone())
+                            .send()
+                            .await
+                            .map_err(|err| backend.sdk_error("Could not upload object", key, &err))
+                    })
+                    .await?;
+                let upload_id = output
+                    .upload_id()
+                    .ok_or_else(|| {
+                        backend.error(
+                            StorageError::Other,
+                            "Could not upload object",
+                            key,
+                            "missing upload id",
+                        )
+                    })?
+                    .to_string();
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            }
+        };
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let part_number = self.parts.len() as i32 + 1;
+        // S3 verifies every part against its md5
+        let content_md5 = &BASE64.encode(md5::compute(&chunk).0);
+        let (chunk, upload_id) = (&chunk, &upload_id);
+        let output = backend
+            .retry_policy
+            .run(|| async move {
+                backend
+                    .client
+                    .upload_part()
+                    .bucket(&backend.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .content_md5(content_md5)
+                    .body(ByteStream::from(chunk.clone()))
+                    .send()
+                    .await
+                    .map_err(|err| backend.sdk_error("Could not upload object", key, &err))
+            })
+            .await?;
+
+        self.parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(output.e_tag().map(str::to_string))
+                .build(),
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UploadSession for S3Upload<'_> {
+    async fn write(&mut self, chunk: Vec<u8>) -> Result<(), StorageError> {
+        if let Some(previous) = self.pending.replace(chunk) {
+            self.upload_part(previous).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>, content_md5: &str) -> Result<(), StorageError> {
+        let last = self.pending.take().unwrap_or_default();
+
+        if self.upload_id.is_none() {
+            return self
+                .backend
+                .put_object(
+                    &self.key,
+                    last,
+                    &self.content_type,
+                    content_md5,
+                    self.encryption.as_ref(),
+                )
+                .await;
+        }
+
+        if let Err(error) = self.upload_part(last).await {
+            self.abort().await;
+            return Err(error);
+        }
+
+        let (backend, key, upload_id) = (self.backend, &self.key, &self.upload_id);
+        let parts = &std::mem::take(&mut self.parts);
+        let result = backend
+            .retry_policy
+            .run(|| async move {
+                backend
+                    .client
+                    .complete_multipart_upload()
+                    .bucket(&backend.bucket)
+                    .key(key)
+                    .set_upload_id(upload_id.clone())
+                    .multipart_upload(
+                        CompletedMultipartUpload::builder()
+                            .set_parts(Some(parts.clone()))
+                            .build(),
+                    )
+                    .send()
+                    .await
+                    .map_err(|err| backend.sdk_error("Could not upload object", key, &err))
+            })
+            .await;
+
+        if let Err(error) = result {
+            self.abort().await;
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    async fn abort(self: Box<Self>) {
+        let Some(upload_id) = &self.upload_id else {
+            return;
+        };
+
+        let result = self
+            .backend
+            .client
+            .abort_multipart_upload()
+            .bucket(&self.backend.bucket)
+            .key(&self.key)
+            .upload_id(upload_id)
+            .send()
+            .await;
+
+        if let Err(err) = result {
+            // the bucket's lifecycle rules will have to clean up the parts
+            println!(
+                "{}",
+                self.backend
+                    .sdk_error("Could not abort multipart upload", &self.key, &err)
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Backend {
+    fn service_name(&self) -> &str {
+        Self::SERVICE_NAME
+    }
+
+    async fn upload(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        content_type: &str,
+        content_md5: &str,
+    ) -> Result<(), StorageError> {
+        self.put_object(key, bytes, content_type, content_md5, None)
+            .await
+    }
+
+    async fn start_upload<'a>(
+        &'a self,
+        key: &str,
+        content_type: &str,
+    ) -> Result<Box<dyn UploadSession + 'a>, StorageError> {
+        Ok(Box::new(S3Upload {
+            backend: self,
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            encryption: None,
+            upload_id: None,
+            pending: None,
+            parts: vec![],
+        }))
+    }
+
+    async fn start_encrypted_upload<'a>(
+        &'a self,
+        key: &str,
+        content_type: &str,
+        encryption: &ServerSideEncryption,
+    ) -> Result<Box<dyn UploadSession + 'a>, StorageError> {
+        Ok(Box::new(S3Upload {
+            backend: self,
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            encryption: Some(encryption.clone()),
+            upload_id: None,
+            pending: None,
+            parts: vec![],
+        }))
+    }
+
+    async fn download(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        self.retry_policy
+            .run(|| async move {
+                let response = self
+                    .client
+                    .get_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(|err| self.sdk_error("Could not download object", key, &err))?;
+
+                let data = response.body.collect().await.map_err(|err| {
+                    self.error(StorageError::Network, "Could not download object", key, err)
+                })?;
+
+                Ok(data.into_bytes().to_vec())
+            })
+            .await
+    }
+
+    async fn download_stream(&self, key: &str) -> Result<ObjectReader, StorageError> {
+        let response = self
+            .retry_policy
+            .run(|| async move {
+                self.client
+                    .get_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(|err| self.sdk_error("Could not download object", key, &err))
+            })
+            .await?;
+
+        Ok(Box::pin(response.body.into_async_read()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.retry_policy
+            .run(|| async move {
+                self.client
+                    .delete_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(|err| self.sdk_error("Could not delete object", key, &err))
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_many(&self, keys: &[String]) -> Result<(), StorageError> {
+        let mut failed = vec![];
+        for chunk in keys.chunks(DELETE_OBJECTS_LIMIT) {
+            failed.extend(self.delete_chunk(chunk).await?);
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(self.error(
+                StorageError::Other,
+                "Could not delete some objects",
+                failed.join("', '"),
+                format!(
+                    "{} of {} objects were not deleted",
+                    failed.len(),
+                    keys.len()
+                ),
+            ))
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        Ok(self.metadata(key).await?.is_some())
+    }
+
+    async fn metadata(&self, key: &str) -> Result<Option<ObjectMetadata>, StorageError> {
+        let result = self
+            .retry_policy
+            .run(|| async move {
+                self.client
+                    .head_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(|err| self.sdk_error("Could not check object", key, &err))
+            })
+            .await;
+
+        match result {
+            #[allow(clippy::cast_sign_loss)]
+            Ok(output) => Ok(Some(ObjectMetadata {
+                byte_size: output.content_length().unwrap_or_default().max(0) as u64,
+                checksum: output.e_tag().and_then(|e_tag| {
+                    md5_e_tag(
+                        e_tag,
+                        output.server_side_encryption(),
+                        output.sse_customer_algorithm().is_some(),
+                    )
+                }),
+                last_modified: output
+                    .last_modified()
+                    .and_then(|last_modified| SystemTime::try_from(*last_modified).ok()),
+            })),
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn list(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        let mut keys = vec![];
+        let mut continuation_token = None;
+
+        loop {
+            let token = continuation_token.as_deref();
+            let response = self
+                .retry_policy
+                .run(|| async move {
+                    self.client
+                        .list_objects_v2()
+                        .bucket(&self.bucket)
+                        .set_prefix(prefix.map(str::to_string))
+                        .set_continuation_token(token.map(str::to_string))
+                        .send()
+                        .await
+                        .map_err(|err| {
+                            self.sdk_error("Could not list objects", prefix.unwrap_or("*"), &err)
+                        })
+                })
+                .await?;
+
+            keys.extend(
+                response
+                    .contents()
+                    .iter()
+                    .filter_map(|object| object.key().map(str::to_string)),
+            );
+
+            continuation_token = response.next_continuation_token().map(str::to_string);
+            if !response.is_truncated().unwrap_or_default() || continuation_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// TODO: validate the uri we return in the public case is valid, and if not, return an error
+    async fn download_uri(
+        &self,
+        key: &str,
+        expires_in: Option<Duration>,
+    ) -> Result<String, StorageError> {
+        let Some(expires_in) = expires_in else {
+            return Ok(format!("{}/{key}", self.public_url));
+        };
+
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(self.presigning_config(
+                "Could not retrieve download URI",
+                key,
+                expires_in,
+            )?)
+            .await
+            .map_err(|err| self.sdk_error("Could not retrieve download URI", key, &err))?;
+
+        Ok(response.uri().to_string())
+    }
+
+    async fn upload_uri(&self, key: &str, expires_in: Duration) -> Result<UploadURI, StorageError> {
+        let response = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(self.presigning_config("Could not retrieve upload URI", key, expires_in)?)
+            .await
+            .map_err(|err| self.sdk_error("Could not retrieve upload URI", key, &err))?;
+
+        self.upload_uri_from(key, &response)
+    }
+
+    async fn direct_upload_uri(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        content_type: &str,
+        byte_size: u64,
+        content_md5: &str,
+    ) -> Result<UploadURI, StorageError> {
+        let content_md5 = md5_to_base64(content_md5).ok_or_else(|| {
+            self.error(
+                StorageError::Invalid,
+                "Could not retrieve upload URI",
+                key,
+                "invalid md5 checksum",
+            )
+        })?;
+
+        // these headers are signed, so S3 rejects uploads which don't match them
+        #[allow(clippy::cast_possible_wrap)]
+        let response = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .content_length(byte_size as i64)
+            .content_md5(content_md5)
+            .presigned(self.presigning_config("Could not retrieve upload URI", key, expires_in)?)
+            .await
+            .map_err(|err| self.sdk_error("Could not retrieve upload URI", key, &err))?;
+
+        self.upload_uri_from(key, &response)
+    }
+}
+
+/// S3 expects the `Content-MD5` header to hold the base64 of the raw digest, not of its hex encoding
+fn md5_to_base64(hex: &str) -> Option<String> {
+    if hex.len() != 32 {
+        return None;
+    }
+
+    let digest = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some(BASE64.encode(digest))
+}
+
+/// the md5 of an object's contents from its `ETag`, which is only that for objects which weren't uploaded in parts
+/// and aren't encrypted with KMS (`SSE-KMS`, `DSSE-KMS`) or customer-provided keys (`SSE-C`)
+fn md5_e_tag(
+    e_tag: &str,
+    encryption: Option<&S3ServerSideEncryption>,
+    customer_key: bool,
+) -> Option<String> {
+    let kms = matches!(
+        encryption,
+        Some(S3ServerSideEncryption::AwsKms | S3ServerSideEncryption::AwsKmsDsse)
+    );
+    let e_tag = e_tag.trim_matches('"');
+
+    (!kms && !customer_key && !e_tag.contains('-')).then(|| e_tag.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn encodes_raw_md5_digest() {
+        // md5("")
+        assert_eq!(
+            md5_to_base64("d41d8cd98f00b204e9800998ecf8427e").as_deref(),
+            Some("1B2M2Y8AsgTpgAmY7PhCfg==")
+        );
+        assert_eq!(md5_to_base64("not-a-digest"), None);
+    }
+
+    #[test]
+    fn only_trusts_the_e_tags_of_unencrypted_single_part_objects() {
+        let e_tag = "\"d41d8cd98f00b204e9800998ecf8427e\"";
+
+        assert_eq!(
+            md5_e_tag(e_tag, None, false).as_deref(),
+            Some("d41d8cd98f00b204e9800998ecf8427e")
+        );
+        assert!(md5_e_tag(e_tag, Some(&S3ServerSideEncryption::Aes256), false).is_some());
+        assert_eq!(
+            md5_e_tag(e_tag, Some(&S3ServerSideEncryption::AwsKms), false),
+            None
+        );
+        assert_eq!(md5_e_tag(e_tag, None, true), None);
+        assert_eq!(
+            md5_e_tag("\"d41d8cd98f00b204e9800998ecf8427e-2\"", None, false),
+            None
+        );
+    }
+
+    fn backend(endpoint: Option<&str>, force_path_style: bool) -> S3Backend {
+        S3Backend::from_config(S3Config {
+            endpoint: endpoint.map(str::to_string),
+            region: "us-east-1".to_string(),
+            bucket: "bucket".to_string(),
+            credentials: Some(S3Credentials {
+                access_key_id: "access_key".to_string(),
+                secret_access_key: "secret_key".to_string(),
+                session_token: Some("token".to_string()),
+            }),
+            force_path_style,
+            ..S3Config::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn addresses_the_bucket_by_path_or_host() {
+        let expires_in = Some(Duration::from_secs(60));
+
+        let minio = backend(Some("http://localhost:9000/"), true);
+        let uri = minio.download_uri("a/b.txt", expires_in).await.unwrap();
+        assert!(uri.starts_with("http://localhost:9000/bucket/a/b.txt?"));
+        assert!(uri.contains("X-Amz-Security-Token=token"));
+        assert_eq!(
+            minio.download_uri("a/b.txt", None).await.unwrap(),
+            "http://localhost:9000/bucket/a/b.txt"
+        );
+
+        let aws = backend(None, false);
+        let uri = aws.download_uri("a/b.txt", expires_in).await.unwrap();
+        assert!(uri.starts_with("https://bucket.s3.us-east-1.amazonaws.com/a/b.txt?"));
+        assert_eq!(
+            aws.download_uri("a/b.txt", None).await.unwrap(),
+            "https://bucket.s3.us-east-1.amazonaws.com/a/b.txt"
+        );
+
+        let invalid = S3Backend::from_config(S3Config {
+            ca_certificates: vec![b"not a certificate".to_vec()],
+            ..S3Config::default()
+        });
+        assert!(matches!(invalid, Err(StorageError::NotConfigured(_))));
+    }
+
+    /// A stand-in for S3 on a local port: every request is recorded and answered by `respond`
+    struct Stub {
+        endpoint: String,
+        requests: Arc<Mutex<Vec<http::Request<Vec<u8>>>>>,
+    }
+
+    impl Stub {
+        async fn start(
+            respond: impl Fn(&http::Request<Vec<u8>>) -> http::Response<String> + Send + Sync + 'static,
+        ) -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(vec![]));
+            let respond = Arc::new(respond);
+
+            let recorded = Arc::clone(&requests);
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let (recorded, respond) = (Arc::clone(&recorded), Arc::clone(&respond));
+                    tokio::spawn(async move {
+                        let mut stream = tokio::io::BufReader::new(stream);
+                        let Some(request) = read_request(&mut stream).await else {
+                            return;
+                        };
+                        let response = respond(&request);
+                        recorded.lock().unwrap().push(request);
+                        write_response(stream.get_mut(), response).await;
+                    });
+                }
+            });
+
+            Self { endpoint, requests }
+        }
+
+        fn backend(&self) -> S3Backend {
+            backend(Some(&self.endpoint), true).with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(1),
+            })
+        }
+
+        /// the method and target of every request so far
+        fn requests(&self) -> Vec<String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| format!("{} {}", request.method(), request.uri()))
+                .collect()
+        }
+
+        fn bodies(&self) -> Vec<String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| String::from_utf8_lossy(request.body()).to_string())
+                .collect()
+        }
+    }
+
+    async fn read_request(
+        stream: &mut tokio::io::BufReader<tokio::net::TcpStream>,
+    ) -> Option<http::Request<Vec<u8>>> {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+        let mut line = String::new();
+        stream.read_line(&mut line).await.ok()?;
+        let mut parts = line.split_whitespace();
+        let mut request = http::Request::builder()
+            .method(parts.next()?)
+            .uri(parts.next()?);
+
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.ok()?;
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            request = request.header(name.trim(), value.trim());
+        }
+
+        let headers = request.headers_ref()?;
+        let length = headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse::<usize>().ok())
+            .unwrap_or_default();
+        if headers.contains_key(http::header::EXPECT) {
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .await
+                .ok()?;
+        }
+
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await.ok()?;
+        request.body(body).ok()
+    }
+
+    async fn write_response(stream: &mut tokio::net::TcpStream, response: http::Response<String>) {
+        use tokio::io::AsyncWriteExt;
+
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| format!("{name}: {}\r\n", value.to_str().unwrap()))
+            .collect::<String>();
+        let head = format!(
+            "HTTP/1.1 {}\r\n{headers}content-length: {}\r\nconnection: close\r\n\r\n",
+            response.status(),
+            response.body().len()
+        );
+
+        let _ = stream.write_all(head.as_bytes()).await;
+        let _ = stream.write_all(response.body().as_bytes()).await;
+    }
+
+    fn response(status: u16, body: &str) -> http::Response<String> {
+        http::Response::builder()
+            .status(status)
+            .body(body.to_string())
+            .unwrap()
+    }
+
+    fn s3_error(status: u16, code: &str) -> http::Response<String> {
+        response(
+            status,
+            &format!("<Error><Code>{code}</Code><Message>{code}</Message></Error>"),
+        )
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let attempts = AtomicUsize::new(0);
+        let stub = Stub::start(move |request| match request.uri().path() {
+            "/bucket/throttled" if attempts.fetch_add(1, Ordering::SeqCst) == 0 => {
+                s3_error(503, "SlowDown")
+            }
+            "/bucket/forbidden" => s3_error(403, "AccessDenied"),
+            _ => response(200, ""),
+        })
+        .await;
+        let backend = stub.backend();
+        let md5 = format!("{:x}", md5::compute(b"hi"));
+
+        backend
+            .upload("throttled", b"hi".to_vec(), "text/plain", &md5)
+            .await
+            .unwrap();
+        assert_eq!(
+            stub.requests(),
+            vec!["PUT /bucket/throttled?x-id=PutObject"; 2]
+        );
+        assert_eq!(stub.bodies(), vec!["hi"; 2]);
+
+        // errors which aren't transient are returned right away
+        let result = backend
+            .upload("forbidden", b"hi".to_vec(), "text/plain", &md5)
+            .await;
+        assert!(matches!(result, Err(StorageError::AccessDenied(_))));
+        assert_eq!(stub.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn uploads_large_objects_in_parts() {
+        let failed_part = AtomicUsize::new(0);
+        let stub = Stub::start(move |request| {
+            let query = request.uri().query().unwrap_or_default();
+            match request.method().as_str() {
+                "POST" if query.starts_with("uploads") => response(
+                    200,
+                    "<InitiateMultipartUploadResult><UploadId>upload-1</UploadId></InitiateMultipartUploadResult>",
+                ),
+                // the second part fails once
+                "PUT" if query.contains("partNumber=2") && failed_part.fetch_add(1, Ordering::SeqCst) == 0 => {
+                    s3_error(500, "InternalError")
+                }
+                "PUT" => {
+                    let part = query
+                        .split('&')
+                        .find_map(|param| param.strip_prefix("partNumber="))
+                        .unwrap_or_default();
+                    http::Response::builder()
+                        .header("ETag", format!("\"etag-{part}\""))
+                        .body(String::new())
+                        .unwrap()
+                }
+                _ => response(
+                    200,
+                    "<CompleteMultipartUploadResult><ETag>\"etag-2\"</ETag></CompleteMultipartUploadResult>",
+                ),
+            }
+        })
+        .await;
+        let backend = stub.backend();
+
+        let mut upload = backend.start_upload("large", "text/plain").await.unwrap();
+        upload.write(b"first".to_vec()).await.unwrap();
+        upload.write(b"second".to_vec()).await.unwrap();
+        upload
+            .finish(&format!("{:x}", md5::compute(b"firstsecond")))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stub.requests(),
+            vec![
+                "POST /bucket/large?uploads",
+                "PUT /bucket/large?x-id=UploadPart&partNumber=1&uploadId=upload-1",
+                "PUT /bucket/large?x-id=UploadPart&partNumber=2&uploadId=upload-1",
+                "PUT /bucket/large?x-id=UploadPart&partNumber=2&uploadId=upload-1",
+                "POST /bucket/large?uploadId=upload-1",
+            ]
+        );
+        let bodies = stub.bodies();
+        assert_eq!(bodies[1..4], ["first", "second", "second"]);
+        assert!(bodies[4].contains("<ETag>&quot;etag-1&quot;</ETag><PartNumber>1</PartNumber>"));
+        assert!(bodies[4].contains("<ETag>&quot;etag-2&quot;</ETag><PartNumber>2</PartNumber>"));
+    }
+
+    #[tokio::test]
+    async fn deletes_objects_in_batches() {
+        let stub = Stub::start(|request| {
+            let body = String::from_utf8_lossy(request.body());
+            if body.contains("<Key>object-1500</Key>") {
+                response(
+                    200,
+                    "<DeleteResult><Error><Key>object-1500</Key><Code>AccessDenied</Code>\
+                     <Message>Access Denied</Message></Error></DeleteResult>",
+                )
+            } else {
+                response(200, "<DeleteResult></DeleteResult>")
+            }
+        })
+        .await;
+        let backend = stub.backend();
+
+        let keys = (0..2001).map(|i| format!("object-{i}")).collect::<Vec<_>>();
+        let error = backend.delete_many(&keys).await.unwrap_err().to_string();
+        assert!(error.contains("object-1500 (AccessDenied: Access Denied)"));
+        assert!(error.contains("1 of 2001 objects were not deleted"));
+
+        assert_eq!(stub.requests(), vec!["POST /bucket/?delete"; 3]);
+        let batch_sizes = stub
+            .bodies()
+            .iter()
+            .map(|body| body.matches("<Key>").count())
+            .collect::<Vec<_>>();
+        assert_eq!(batch_sizes, vec![1000, 1000, 1]);
+
+        backend.delete_many(&keys[..10]).await.unwrap();
+        backend.delete_many(&[]).await.unwrap();
+        assert_eq!(stub.requests().len(), 4);
+    }
+
+    /// runs against the server configured by the `S3_*` variables, like a local MinIO:
+    ///
+    /// `docker run -p 9000:9000 minio/minio server /data`, then
+    /// `S3_HOST=http://localhost:9000 S3_REGION=us-east-1 S3_BUCKET=test S3_ACCESS_KEY_ID=minioadmin
+    /// S3_SECRET_ACCESS_KEY=minioadmin cargo test -p create-rust-app --features plugin_storage -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible server"]
+    async fn stores_objects_in_the_bucket() {
+        use super::super::{upload_from_reader, UPLOAD_CHUNK_SIZE};
+        use tokio::io::AsyncReadExt;
+
+        let backend = S3Backend::from_env().unwrap();
+        // the bucket may exist already
+        let _ = backend
+            .client
+            .create_bucket()
+            .bucket(&backend.bucket)
+            .send()
+            .await;
+        let prefix = format!("cra-test-{}/", uuid::Uuid::new_v4());
+        let key = |name: &str| format!("{prefix}{name}");
+
+        let small = b"hi".to_vec();
+        backend
+            .upload(
+                &key("small"),
+                small.clone(),
+                "text/plain",
+                &format!("{:x}", md5::compute(&small)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(backend.download(&key("small")).await.unwrap(), small);
+        let metadata = backend.metadata(&key("small")).await.unwrap().unwrap();
+        assert_eq!(metadata.byte_size, 2);
+        assert_eq!(
+            metadata.checksum,
+            Some(format!("{:x}", md5::compute(&small)))
+        );
+
+        // a corrupted body is rejected by the server
+        let result = backend
+            .upload(
+                &key("corrupt"),
+                small.clone(),
+                "",
+                &format!("{:x}", md5::compute(b"yo")),
+            )
+            .await;
+        assert!(matches!(result, Err(StorageError::Checksum(_))));
+
+        let large = vec![7u8; UPLOAD_CHUNK_SIZE + 1];
+        upload_from_reader(&backend, &key("large"), large.as_slice(), "")
+            .await
+            .unwrap();
+        let mut streamed = vec![];
+        backend
+            .download_stream(&key("large"))
+            .await
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, large);
+
+        assert_eq!(
+            backend.list(Some(&prefix)).await.unwrap(),
+            vec![key("large"), key("small")]
+        );
+        backend
+            .delete_many(&[key("large"), key("small")])
+            .await
+            .unwrap();
+        assert!(backend.metadata(&key("small")).await.unwrap().is_none());
+        assert!(matches!(
+            backend.download(&key("small")).await,
+            Err(StorageError::NotFound(_))
+        ));
+    }
+}
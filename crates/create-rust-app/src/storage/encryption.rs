@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio_util::io::StreamReader;
+
+use super::backend::{
+    ObjectReader, ServerSideEncryption, StorageBackend, UploadSession, UPLOAD_CHUNK_SIZE,
+};
+use super::{AttachmentBlob, Storage, StorageError};
+
+/// values of `attachment_blobs.encryption`
+pub(super) const SSE_MANAGED: &str = "sse-managed";
+pub(super) const SSE_KMS: &str = "sse-kms";
+pub(super) const ENVELOPE: &str = "envelope";
+
+/// envelope-encrypted objects are split into segments of this size (plus a tag each),
+/// so they can be decrypted while they are streamed
+const SEGMENT_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+/// How new blobs are encrypted at rest, see [`Storage::with_encryption`]
+///
+/// ```rust,ignore
+/// let storage = Storage::new()
+///     .with_master_key("2024-01", master_key)
+///     .with_attachment_encryption("medical_records", Encryption::Envelope);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encryption {
+    /// objects are stored as uploaded
+    #[default]
+    None,
+    /// the backend encrypts objects (only supported by [`S3Backend`](`super::S3Backend`))
+    ServerSide(ServerSideEncryption),
+    /// the app encrypts objects with AES-256-GCM before they are uploaded, using a data key per blob.
+    /// The data key is stored in `attachment_blobs.encryption_key`, wrapped by the current master key
+    /// (see [`Storage::with_master_key`]). Works with every backend, but such blobs can only be
+    /// downloaded through the app, see [`Storage::blob_download_stream`].
+    Envelope,
+}
+
+impl Encryption {
+    /// the encryption `blob` was stored with
+    #[must_use]
+    pub fn of_blob(blob: &AttachmentBlob) -> Self {
+        match blob.encryption.as_deref() {
+            Some(SSE_MANAGED) => Self::ServerSide(ServerSideEncryption::Managed),
+            Some(SSE_KMS) => Self::ServerSide(ServerSideEncryption::Kms {
+                key_id: blob.encryption_key.clone(),
+            }),
+            Some(ENVELOPE) => Self::Envelope,
+            _ => Self::None,
+        }
+    }
+}
+
+/// what is recorded in `attachment_blobs.encryption` and `attachment_blobs.encryption_key` for an upload
+pub(super) struct BlobEncryption {
+    pub encryption: Option<String>,
+    pub encryption_key: Option<String>,
+}
+
+/// The master keys data keys are wrapped with. The last one added wraps new data keys,
+/// the others are kept to unwrap the data keys of existing blobs.
+#[derive(Clone, Default)]
+pub(super) struct MasterKeys {
+    current: Option<String>,
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl MasterKeys {
+    fn insert(&mut self, id: String, key: [u8; 32]) {
+        self.keys.insert(id.clone(), key);
+        self.current = Some(id);
+    }
+
+    /// the base64-encoded 256-bit key in `STORAGE_MASTER_KEY`, identified by `STORAGE_MASTER_KEY_ID`
+    /// (default: `default`); `None` if it isn't set
+    pub(super) fn from_env() -> Result<Option<(String, [u8; 32])>, StorageError> {
+        let Ok(key) = std::env::var("STORAGE_MASTER_KEY") else {
+            return Ok(None);
+        };
+        let key = BASE64
+            .decode(key.trim())
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .ok_or_else(|| {
+                StorageError::NotConfigured(
+                    "STORAGE_MASTER_KEY must be a base64-encoded 256-bit key".to_string(),
+                )
+            })?;
+        let id = std::env::var("STORAGE_MASTER_KEY_ID").unwrap_or_else(|_| "default".to_string());
+
+        Ok(Some((id, key)))
+    }
+
+    /// encrypts `data_key` with the current master key, as `{master key id}:{base64 of nonce and ciphertext}`
+    fn wrap(&self, data_key: &[u8]) -> Result<String, StorageError> {
+        let (id, master_key) = self
+            .current
+            .as_ref()
+            .and_then(|id| Some((id, self.keys.get(id)?)))
+            .ok_or_else(|| {
+                StorageError::NotConfigured(
+                    "Envelope encryption requires a master key; did you set STORAGE_MASTER_KEY?"
+                        .to_string(),
+                )
+            })?;
+
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| StorageError::Other("Could not generate a nonce".to_string()))?;
+        let mut wrapped = data_key.to_vec();
+        aead_key(master_key)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(id.as_bytes()),
+                &mut wrapped,
+            )
+            .map_err(|_| StorageError::Other("Could not wrap the data key".to_string()))?;
+
+        Ok(format!(
+            "{id}:{}",
+            BASE64.encode([&nonce[..], &wrapped].concat())
+        ))
+    }
+
+    /// the data key wrapped by [`MasterKeys::wrap`]
+    fn unwrap(&self, wrapped: &str) -> Result<LessSafeKey, StorageError> {
+        let (id, wrapped) = wrapped
+            .rsplit_once(':')
+            .ok_or_else(|| StorageError::Other("Invalid wrapped data key".to_string()))?;
+        let master_key = self
+            .keys
+            .get(id)
+            .ok_or_else(|| StorageError::NotConfigured(format!("Unknown master key '{id}'")))?;
+
+        let mut wrapped = BASE64
+            .decode(wrapped)
+            .map_err(|_| StorageError::Other("Invalid wrapped data key".to_string()))?;
+        if wrapped.len() < NONCE_LEN {
+            return Err(StorageError::Other("Invalid wrapped data key".to_string()));
+        }
+        let mut data_key = wrapped.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&wrapped)
+            .map_err(|_| StorageError::Other("Invalid wrapped data key".to_string()))?;
+        let data_key = aead_key(master_key)?
+            .open_in_place(nonce, Aad::from(id.as_bytes()), &mut data_key)
+            .map_err(|_| {
+                StorageError::Other(format!(
+                    "Could not unwrap the data key with master key '{id}'"
+                ))
+            })?;
+
+        aead_key(data_key)
+    }
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey, StorageError> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| StorageError::Other("Invalid encryption key".to_string()))
+}
+
+/// the nonce of the `index`th segment; the last one is marked, so a truncated object can't be decrypted
+fn segment_nonce(index: u64, last: bool) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..8].copy_from_slice(&index.to_be_bytes());
+    nonce[NONCE_LEN - 1] = u8::from(last);
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// Encrypts the chunks before handing them to `inner`, which still receives [`UPLOAD_CHUNK_SIZE`] chunks.
+struct EnvelopeUpload<'a> {
+    inner: Box<dyn UploadSession + 'a>,
+    data_key: LessSafeKey,
+    segments: u64,
+    // plaintext not encrypted yet; at least the last segment is held back until `finish`
+    plaintext: Vec<u8>,
+    // ciphertext not handed to `inner` yet
+    ciphertext: Vec<u8>,
+    // the md5 of the ciphertext, which is what the backend stores
+    checksum: md5::Context,
+}
+
+impl EnvelopeUpload<'_> {
+    fn seal(&mut self, mut segment: Vec<u8>, last: bool) -> Result<(), StorageError> {
+        self.data_key
+            .seal_in_place_append_tag(
+                segment_nonce(self.segments, last),
+                Aad::empty(),
+                &mut segment,
+            )
+            .map_err(|_| StorageError::Other("Could not encrypt the object".to_string()))?;
+        self.segments += 1;
+        self.ciphertext.extend_from_slice(&segment);
+
+        Ok(())
+    }
+
+    async fn flush(&mut self, finished: bool) -> Result<(), StorageError> {
+        while self.ciphertext.len() >= UPLOAD_CHUNK_SIZE
+            || (finished && !self.ciphertext.is_empty())
+        {
+            let rest = self
+                .ciphertext
+                .split_off(self.ciphertext.len().min(UPLOAD_CHUNK_SIZE));
+            let chunk = std::mem::replace(&mut self.ciphertext, rest);
+            self.checksum.consume(&chunk);
+            self.inner.write(chunk).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UploadSession for EnvelopeUpload<'_> {
+    async fn write(&mut self, chunk: Vec<u8>) -> Result<(), StorageError> {
+        self.plaintext.extend_from_slice(&chunk);
+
+        let mut sealed = 0;
+        while self.plaintext.len() - sealed > SEGMENT_SIZE {
+            let segment = self.plaintext[sealed..sealed + SEGMENT_SIZE].to_vec();
+            self.seal(segment, false)?;
+            sealed += SEGMENT_SIZE;
+        }
+        self.plaintext.drain(..sealed);
+
+        self.flush(false).await
+    }
+
+    async fn finish(mut self: Box<Self>, _content_md5: &str) -> Result<(), StorageError> {
+        let last = std::mem::take(&mut self.plaintext);
+        let sealed = match self.seal(last, true) {
+            Ok(()) => self.flush(true).await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = sealed {
+            self.inner.abort().await;
+            return Err(error);
+        }
+
+        let checksum = std::mem::replace(&mut self.checksum, md5::Context::new()).compute();
+        self.inner.finish(&format!("{checksum:x}")).await
+    }
+
+    async fn abort(self: Box<Self>) {
+        self.inner.abort().await;
+    }
+}
+
+/// decrypts an object written by [`EnvelopeUpload`] as it is read
+fn decrypt_reader(reader: ObjectReader, data_key: LessSafeKey) -> ObjectReader {
+    async fn read_segment(reader: &mut ObjectReader) -> std::io::Result<Vec<u8>> {
+        let mut segment = Vec::with_capacity(SEGMENT_SIZE + TAG_LEN);
+        reader
+            .take((SEGMENT_SIZE + TAG_LEN) as u64)
+            .read_to_end(&mut segment)
+            .await?;
+        Ok(segment)
+    }
+
+    // one segment is read ahead, to know whether the current one is the last
+    let segments = futures_util::stream::try_unfold(
+        (reader, data_key, 0, None),
+        |(mut reader, data_key, index, segment): (_, _, u64, Option<Vec<u8>>)| async move {
+            let mut segment = match segment {
+                Some(segment) => segment,
+                None if index == 0 => read_segment(&mut reader).await?,
+                None => return Ok(None),
+            };
+            let next = read_segment(&mut reader).await?;
+            let last = next.is_empty();
+
+            let len = data_key
+                .open_in_place(segment_nonce(index, last), Aad::empty(), &mut segment)
+                .map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Could not decrypt the object",
+                    )
+                })?
+                .len();
+            segment.truncate(len);
+
+            let next = (!last).then_some(next);
+            Ok::<_, std::io::Error>(Some((
+                Cursor::new(segment),
+                (reader, data_key, index + 1, next),
+            )))
+        },
+    );
+
+    Box::pin(StreamReader::new(segments))
+}
+
+impl Storage {
+    /// encrypts new blobs with `encryption`, unless another one was registered for their attachment name
+    /// with [`Storage::with_attachment_encryption`]
+    #[must_use]
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// encrypts new blobs attached under `name` with `encryption`
+    #[must_use]
+    pub fn with_attachment_encryption(
+        mut self,
+        name: impl Into<String>,
+        encryption: Encryption,
+    ) -> Self {
+        self.attachment_encryption.insert(name.into(), encryption);
+        self
+    }
+
+    /// adds a 256-bit master key for [`Encryption::Envelope`]. The last key added wraps the data keys
+    /// of new blobs; keep the previous ones registered until no blob's data key is wrapped by them.
+    #[must_use]
+    pub fn with_master_key(mut self, id: impl Into<String>, key: [u8; 32]) -> Self {
+        self.master_keys.insert(id.into(), key);
+        self
+    }
+
+    /// how blobs without an attachment name (direct uploads, for example) are encrypted
+    #[must_use]
+    pub fn default_encryption(&self) -> &Encryption {
+        &self.encryption
+    }
+
+    /// how new blobs attached under `name` are encrypted
+    #[must_use]
+    pub fn encryption_for(&self, name: &str) -> &Encryption {
+        self.attachment_encryption
+            .get(name)
+            .unwrap_or(&self.encryption)
+    }
+
+    /// begins uploading an object to `backend`, encrypted with `encryption`;
+    /// returns what has to be recorded on its blob to read it back
+    ///
+    /// # Errors
+    /// * the backend doesn't support server-side encryption
+    /// * envelope encryption without a master key
+    /// * could not start the upload
+    pub(super) async fn start_blob_upload<'a>(
+        &self,
+        backend: &'a dyn StorageBackend,
+        key: &str,
+        content_type: &str,
+        encryption: &Encryption,
+    ) -> Result<(Box<dyn UploadSession + 'a>, BlobEncryption), StorageError> {
+        match encryption {
+            Encryption::None => Ok((
+                backend.start_upload(key, content_type).await?,
+                BlobEncryption {
+                    encryption: None,
+                    encryption_key: None,
+                },
+            )),
+            Encryption::ServerSide(server_side) => {
+                let session = backend
+                    .start_encrypted_upload(key, content_type, server_side)
+                    .await?;
+                let recorded = match server_side {
+                    ServerSideEncryption::Managed => BlobEncryption {
+                        encryption: Some(SSE_MANAGED.to_string()),
+                        encryption_key: None,
+                    },
+                    ServerSideEncryption::Kms { key_id } => BlobEncryption {
+                        encryption: Some(SSE_KMS.to_string()),
+                        encryption_key: key_id.clone(),
+                    },
+                };
+
+                Ok((session, recorded))
+            }
+            Encryption::Envelope => {
+                let mut data_key = [0; 32];
+                SystemRandom::new().fill(&mut data_key).map_err(|_| {
+                    StorageError::Other("Could not generate a data key".to_string())
+                })?;
+                let wrapped = self.master_keys.wrap(&data_key)?;
+
+                let session = EnvelopeUpload {
+                    inner: backend.start_upload(key, content_type).await?,
+                    data_key: aead_key(&data_key)?,
+                    segments: 0,
+                    plaintext: vec![],
+                    ciphertext: vec![],
+                    checksum: md5::Context::new(),
+                };
+
+                Ok((
+                    Box::new(session),
+                    BlobEncryption {
+                        encryption: Some(ENVELOPE.to_string()),
+                        encryption_key: Some(wrapped),
+                    },
+                ))
+            }
+        }
+    }
+
+    /// streams the object stored under `key`, decrypting it if it was envelope-encrypted
+    ///
+    /// # Errors
+    /// * unknown encryption, or the data key could not be unwrapped
+    /// * the object does not exist, or could not be downloaded
+    pub(super) async fn open_object(
+        &self,
+        backend: &dyn StorageBackend,
+        key: &str,
+        encryption: Option<&str>,
+        encryption_key: Option<&str>,
+    ) -> Result<ObjectReader, StorageError> {
+        match encryption {
+            // the backend decrypts these itself
+            None | Some(SSE_MANAGED | SSE_KMS) => backend.download_stream(key).await,
+            Some(ENVELOPE) => {
+                let data_key = self.master_keys.unwrap(encryption_key.ok_or_else(|| {
+                    StorageError::Other("The blob's data key is missing".to_string())
+                })?)?;
+                Ok(decrypt_reader(
+                    backend.download_stream(key).await?,
+                    data_key,
+                ))
+            }
+            Some(other) => Err(StorageError::Other(format!(
+                "Unknown encryption '{other}' (key: '{key}')"
+            ))),
+        }
+    }
+
+    /// stream the contents of `blob` from whichever backend it is stored on, decrypting them if needed
+    ///
+    /// # Errors
+    /// * the blob's backend isn't registered
+    /// * the blob's data key could not be unwrapped
+    /// * could not download the object
+    pub async fn blob_download_stream(
+        &self,
+        blob: &AttachmentBlob,
+    ) -> Result<ObjectReader, StorageError> {
+        self.open_object(
+            self.backend(&blob.service_name)?,
+            &blob.key,
+            blob.encryption.as_deref(),
+            blob.encryption_key.as_deref(),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::upload_to_session;
+    use crate::storage::MemoryBackend;
+
+    #[tokio::test]
+    async fn envelope_encrypted_objects_round_trip() {
+        let storage = Storage::from_backend(MemoryBackend::new())
+            .with_master_key("old", [1; 32])
+            .with_master_key("new", [2; 32]);
+        let backend = storage.default_backend().unwrap();
+        #[allow(clippy::cast_possible_truncation)]
+        let contents = (0..3 * SEGMENT_SIZE as u32 + 5)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let (session, recorded) = storage
+            .start_blob_upload(backend, "key", "text/plain", &Encryption::Envelope)
+            .await
+            .unwrap();
+        let uploaded = upload_to_session(session, "key", contents.as_slice())
+            .await
+            .unwrap();
+        let encryption_key = recorded.encryption_key.unwrap();
+
+        // the checksum is of the plaintext, the stored object is encrypted with a key wrapped by the newest master key
+        assert_eq!(uploaded.checksum, format!("{:x}", md5::compute(&contents)));
+        assert!(encryption_key.starts_with("new:"));
+        let stored = backend.download("key").await.unwrap();
+        assert_eq!(stored.len(), contents.len() + 4 * TAG_LEN);
+        assert_ne!(&stored[..contents.len()], contents.as_slice());
+
+        let mut decrypted = vec![];
+        storage
+            .open_object(backend, "key", Some(ENVELOPE), Some(&encryption_key))
+            .await
+            .unwrap()
+            .read_to_end(&mut decrypted)
+            .await
+            .unwrap();
+        assert_eq!(decrypted, contents);
+
+        // a truncated object doesn't decrypt
+        backend
+            .upload(
+                "key",
+                stored[..2 * (SEGMENT_SIZE + TAG_LEN)].to_vec(),
+                "text/plain",
+                "",
+            )
+            .await
+            .unwrap();
+        let mut reader = storage
+            .open_object(backend, "key", Some(ENVELOPE), Some(&encryption_key))
+            .await
+            .unwrap();
+        assert!(reader.read_to_end(&mut vec![]).await.is_err());
+    }
+}
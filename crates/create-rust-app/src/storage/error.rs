@@ -0,0 +1,235 @@
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use http::StatusCode;
+use rand::Rng;
+
+/// Why a storage operation failed; see [`StorageError::status_code`] for the HTTP status it maps to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// no backend is configured, or the one a blob was stored on isn't registered
+    NotConfigured(String),
+    /// the object, blob, attachment or upload does not exist
+    NotFound(String),
+    /// the backend rejected the credentials, or doesn't allow the operation
+    AccessDenied(String),
+    /// the backend is rate limiting requests
+    Throttled(String),
+    /// the backend could not be reached, or failed to respond
+    Network(String),
+    /// a query failed
+    Database(String),
+    /// the contents don't match their checksum (or declared size)
+    Checksum(String),
+    /// the file breaks the [`AttachmentRules`](`super::AttachmentRules`), or was rejected by the [`ScanHook`](`super::ScanHook`)
+    Rejected(String),
+    /// the record already has an attachment with that name, or the upload was already confirmed
+    Conflict(String),
+    /// the arguments are invalid, or the backend doesn't support the operation
+    Invalid(String),
+    /// anything else, like a local I/O error
+    Other(String),
+}
+
+impl StorageError {
+    /// the description of the error
+    #[must_use]
+    pub fn message(&self) -> &str {
+        match self {
+            Self::NotConfigured(message)
+            | Self::NotFound(message)
+            | Self::AccessDenied(message)
+            | Self::Throttled(message)
+            | Self::Network(message)
+            | Self::Database(message)
+            | Self::Checksum(message)
+            | Self::Rejected(message)
+            | Self::Conflict(message)
+            | Self::Invalid(message)
+            | Self::Other(message) => message,
+        }
+    }
+
+    /// whether trying again later may succeed, see [`RetryPolicy`]
+    #[must_use]
+    pub const fn is_transient(&self) -> bool {
+        matches!(self, Self::Throttled(_) | Self::Network(_))
+    }
+
+    /// the status an HTTP handler should respond with
+    #[must_use]
+    pub const fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotConfigured(_) | Self::Throttled(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::AccessDenied(_) => StatusCode::FORBIDDEN,
+            Self::Network(_) => StatusCode::BAD_GATEWAY,
+            Self::Checksum(_) | Self::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Database(_) | Self::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// the variant for a local I/O error
+    pub(crate) fn io(err: &std::io::Error) -> fn(String) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => Self::NotFound,
+            std::io::ErrorKind::PermissionDenied => Self::AccessDenied,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<diesel::result::Error> for StorageError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => Self::NotFound(err.to_string()),
+            err => Self::Database(err.to_string()),
+        }
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for StorageError {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        Self::Database(err.to_string())
+    }
+}
+
+#[cfg(feature = "backend_actix-web")]
+impl actix_web::ResponseError for StorageError {
+    fn status_code(&self) -> StatusCode {
+        self.status_code()
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        actix_web::HttpResponse::build(self.status_code())
+            .json(serde_json::json!({ "message": self.message() }))
+    }
+}
+
+#[cfg(feature = "backend_poem")]
+impl poem::error::ResponseError for StorageError {
+    fn status(&self) -> poem::http::StatusCode {
+        poem::http::StatusCode::from_u16(self.status_code().as_u16())
+            .unwrap_or(poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// How operations failing with a [transient](`StorageError::is_transient`) error are retried:
+/// the delay doubles after every attempt (up to `max_backoff`), with random jitter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// including the first one; `1` disables retries
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// never retry
+    #[must_use]
+    pub const fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    /// the delay before the `retry`th retry (starting at 0), between half and all of the exponential backoff
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// runs `operation` until it succeeds, fails with an error which isn't transient, or runs out of attempts
+    ///
+    /// # Errors
+    /// * the last error returned by `operation`
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T, StorageError>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T, StorageError>> + Send,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Err(error) if error.is_transient() && retry + 1 < self.max_attempts => {
+                    tokio::time::sleep(self.backoff(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        };
+
+        let attempts = AtomicU32::new(0);
+        let result = policy
+            .run(|| async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(StorageError::Throttled("slow down".to_string())),
+                    _ => Ok("done"),
+                }
+            })
+            .await;
+        assert_eq!(result, Ok("done"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = policy
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(StorageError::Network("timed out".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = policy
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(StorageError::NotFound("missing".to_string()))
+            })
+            .await;
+        assert_eq!(result.unwrap_err().status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
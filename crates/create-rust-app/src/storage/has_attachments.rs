@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use diesel::result::Error;
+use diesel::QueryResult;
+
+use crate::storage::{Attachment, AttachmentBlob, AttachmentData, Storage, StorageError, ID};
+use crate::Connection;
+
+/// The database handle [`Attachment::attach`] and [`Attachment::detach`] take with the enabled backend
+#[cfg(feature = "backend_actix-web")]
+pub type AttachmentDb<'a> = &'a mut Connection;
+/// The database handle [`Attachment::attach`] and [`Attachment::detach`] take with the enabled backend
+#[cfg(feature = "backend_poem")]
+pub type AttachmentDb<'a> = &'a crate::database::Pool;
+
+/// A model which files can be attached to, usually implemented with [`has_attachments!`](`crate::has_attachments`)
+pub trait HasAttachments {
+    /// the `record_type` of the model's attachments, e.g. its table name
+    const RECORD_TYPE: &'static str;
+
+    /// the `record_id` of the model's attachments
+    fn attachment_record_id(&self) -> ID;
+}
+
+/// A named attachment a model has at most one of
+pub struct HasOne<M> {
+    name: &'static str,
+    model: PhantomData<fn(&M)>,
+}
+
+/// A named attachment a model can have any number of
+pub struct HasMany<M> {
+    name: &'static str,
+    model: PhantomData<fn(&M)>,
+}
+
+impl<M: HasAttachments> HasOne<M> {
+    #[must_use]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            model: PhantomData,
+        }
+    }
+
+    /// attaches `data` to `record`, replacing the current attachment
+    ///
+    /// # Errors
+    /// * see [`Attachment::attach`]
+    pub async fn attach(
+        &self,
+        db: AttachmentDb<'_>,
+        storage: &Storage,
+        record: &M,
+        data: AttachmentData,
+    ) -> Result<String, StorageError> {
+        Attachment::attach(
+            db,
+            storage,
+            self.name.to_string(),
+            M::RECORD_TYPE.to_string(),
+            record.attachment_record_id(),
+            data,
+            false,
+            true,
+        )
+        .await
+    }
+
+    /// the attachment of `record`, if there is one
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn find(&self, db: &mut Connection, record: &M) -> QueryResult<Option<Attachment>> {
+        match Attachment::find_for_record(
+            db,
+            self.name.to_string(),
+            M::RECORD_TYPE.to_string(),
+            record.attachment_record_id(),
+        ) {
+            Ok(attachment) => Ok(Some(attachment)),
+            Err(Error::NotFound) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// a URI the attachment of `record` can be downloaded from, see [`Storage::blob_download_uri`]
+    ///
+    /// # Errors
+    /// * Diesel error
+    /// * could not build the URI
+    pub async fn url(
+        &self,
+        db: &mut Connection,
+        storage: &Storage,
+        record: &M,
+        expires_in: Option<Duration>,
+    ) -> Result<Option<String>, StorageError> {
+        let Some(attachment) = self.find(db, record)? else {
+            return Ok(None);
+        };
+        let blob = AttachmentBlob::find_by_id(db, attachment.blob_id)?;
+
+        storage.blob_download_uri(&blob, expires_in).await.map(Some)
+    }
+
+    /// removes the attachment of `record`, if there is one
+    ///
+    /// # Errors
+    /// * see [`Attachment::detach`]
+    #[cfg(feature = "backend_actix-web")]
+    pub async fn detach(
+        &self,
+        db: AttachmentDb<'_>,
+        storage: &Storage,
+        record: &M,
+    ) -> Result<(), StorageError> {
+        match self.find(db, record)? {
+            Some(attachment) => Attachment::detach(db, storage, attachment.id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// removes the attachment of `record`, if there is one
+    ///
+    /// # Errors
+    /// * see [`Attachment::detach`]
+    /// * the pool is unable to get a connection
+    #[cfg(feature = "backend_poem")]
+    pub async fn detach(
+        &self,
+        db: AttachmentDb<'_>,
+        storage: &Storage,
+        record: &M,
+    ) -> Result<(), StorageError> {
+        let attachment = self.find(&mut db.get()?, record)?;
+
+        match attachment {
+            Some(attachment) => Attachment::detach(db, storage, attachment.id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// the attachments of all `records` in a single query, by record id
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn preload(
+        &self,
+        db: &mut Connection,
+        records: &[M],
+    ) -> QueryResult<HashMap<ID, Attachment>> {
+        Ok(load_for_records(db, self.name, records)?
+            .into_iter()
+            .map(|attachment| (attachment.record_id, attachment))
+            .collect())
+    }
+}
+
+impl<M: HasAttachments> HasMany<M> {
+    #[must_use]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            model: PhantomData,
+        }
+    }
+
+    /// adds `data` to the attachments of `record`
+    ///
+    /// # Errors
+    /// * see [`Attachment::attach`]
+    pub async fn attach(
+        &self,
+        db: AttachmentDb<'_>,
+        storage: &Storage,
+        record: &M,
+        data: AttachmentData,
+    ) -> Result<String, StorageError> {
+        Attachment::attach(
+            db,
+            storage,
+            self.name.to_string(),
+            M::RECORD_TYPE.to_string(),
+            record.attachment_record_id(),
+            data,
+            true,
+            false,
+        )
+        .await
+    }
+
+    /// the attachments of `record`
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn find_all(&self, db: &mut Connection, record: &M) -> QueryResult<Vec<Attachment>> {
+        Attachment::find_all_for_record(
+            db,
+            self.name.to_string(),
+            M::RECORD_TYPE.to_string(),
+            record.attachment_record_id(),
+        )
+    }
+
+    /// URIs the attachments of `record` can be downloaded from, see [`Storage::blob_download_uri`]
+    ///
+    /// # Errors
+    /// * Diesel error
+    /// * could not build a URI
+    pub async fn urls(
+        &self,
+        db: &mut Connection,
+        storage: &Storage,
+        record: &M,
+        expires_in: Option<Duration>,
+    ) -> Result<Vec<String>, StorageError> {
+        let attachments = self.find_all(db, record)?;
+        let blobs = AttachmentBlob::find_all_by_id(
+            db,
+            attachments
+                .iter()
+                .map(|attachment| attachment.blob_id)
+                .collect(),
+        )?;
+
+        let mut urls = Vec::with_capacity(blobs.len());
+        for blob in &blobs {
+            urls.push(storage.blob_download_uri(blob, expires_in).await?);
+        }
+        Ok(urls)
+    }
+
+    /// removes all attachments of `record`
+    ///
+    /// # Errors
+    /// * see [`Attachment::detach_all`]
+    pub async fn detach_all(
+        &self,
+        db: &mut Connection,
+        storage: &Storage,
+        record: &M,
+    ) -> Result<(), StorageError> {
+        Attachment::detach_all(
+            db,
+            storage,
+            self.name.to_string(),
+            M::RECORD_TYPE.to_string(),
+            record.attachment_record_id(),
+        )
+        .await
+    }
+
+    /// the attachments of all `records` in a single query, by record id
+    ///
+    /// # Errors
+    /// * Diesel error
+    pub fn preload(
+        &self,
+        db: &mut Connection,
+        records: &[M],
+    ) -> QueryResult<HashMap<ID, Vec<Attachment>>> {
+        let mut attachments: HashMap<ID, Vec<Attachment>> = HashMap::new();
+        for attachment in load_for_records(db, self.name, records)? {
+            attachments
+                .entry(attachment.record_id)
+                .or_default()
+                .push(attachment);
+        }
+        Ok(attachments)
+    }
+}
+
+fn load_for_records<M: HasAttachments>(
+    db: &mut Connection,
+    name: &str,
+    records: &[M],
+) -> QueryResult<Vec<Attachment>> {
+    Attachment::find_all_for_records(
+        db,
+        name.to_string(),
+        M::RECORD_TYPE.to_string(),
+        records
+            .iter()
+            .map(HasAttachments::attachment_record_id)
+            .collect(),
+    )
+}
+
+/// Declares the attachments of a model, which must have an `id: i32` field,
+/// and generates typed accessors for them:
+///
+/// ```rust,ignore
+/// create_rust_app::has_attachments! {
+///     User in "users" {
+///         avatar: one,
+///         photos: many,
+///     }
+/// }
+///
+/// user.attach_avatar(&mut db, &storage, data).await?;
+/// let url = user.avatar_url(&mut db, &storage, None).await?;
+/// let photos = user.photos(&mut db)?;
+///
+/// // one query for the avatars of all users, instead of one per user
+/// let avatars = User::preload_avatar(&mut db, &users)?;
+/// ```
+///
+/// * `one` generates `attach_{name}`, `{name}`, `{name}_url`, `detach_{name}` and `preload_{name}`
+/// * `many` generates `attach_{name}`, `{name}`, `{name}_urls`, `detach_{name}` and `preload_{name}`
+///
+/// The `attach_*` and `detach_*` methods of `one` attachments take an [`AttachmentDb`](`crate::AttachmentDb`),
+/// like [`Attachment::attach`]; everything else takes a `&mut Connection`.
+#[macro_export]
+macro_rules! has_attachments {
+    ($model:ident in $record_type:literal { $($name:ident : $kind:ident),* $(,)? }) => {
+        impl $crate::HasAttachments for $model {
+            const RECORD_TYPE: &'static str = $record_type;
+
+            fn attachment_record_id(&self) -> i32 {
+                self.id
+            }
+        }
+
+        impl $model {
+            $($crate::has_attachments!(@$kind $name);)*
+        }
+    };
+
+    (@one $name:ident) => {
+        $crate::__paste::paste! {
+            pub async fn [<attach_ $name>](
+                &self,
+                db: $crate::AttachmentDb<'_>,
+                storage: &$crate::Storage,
+                data: $crate::AttachmentData,
+            ) -> Result<String, $crate::StorageError> {
+                $crate::HasOne::<Self>::new(stringify!($name))
+                    .attach(db, storage, self, data)
+                    .await
+            }
+
+            pub fn $name(
+                &self,
+                db: &mut $crate::Connection,
+            ) -> $crate::__QueryResult<Option<$crate::Attachment>> {
+                $crate::HasOne::<Self>::new(stringify!($name)).find(db, self)
+            }
+
+            pub async fn [<$name _url>](
+                &self,
+                db: &mut $crate::Connection,
+                storage: &$crate::Storage,
+                expires_in: Option<std::time::Duration>,
+            ) -> Result<Option<String>, $crate::StorageError> {
+                $crate::HasOne::<Self>::new(stringify!($name))
+                    .url(db, storage, self, expires_in)
+                    .await
+            }
+
+            pub async fn [<detach_ $name>](
+                &self,
+                db: $crate::AttachmentDb<'_>,
+                storage: &$crate::Storage,
+            ) -> Result<(), $crate::StorageError> {
+                $crate::HasOne::<Self>::new(stringify!($name))
+                    .detach(db, storage, self)
+                    .await
+            }
+
+            pub fn [<preload_ $name>](
+                db: &mut $crate::Connection,
+                records: &[Self],
+            ) -> $crate::__QueryResult<std::collections::HashMap<i32, $crate::Attachment>> {
+                $crate::HasOne::<Self>::new(stringify!($name)).preload(db, records)
+            }
+        }
+    };
+
+    (@many $name:ident) => {
+        $crate::__paste::paste! {
+            pub async fn [<attach_ $name>](
+                &self,
+                db: $crate::AttachmentDb<'_>,
+                storage: &$crate::Storage,
+                data: $crate::AttachmentData,
+            ) -> Result<String, $crate::StorageError> {
+                $crate::HasMany::<Self>::new(stringify!($name))
+                    .attach(db, storage, self, data)
+                    .await
+            }
+
+            pub fn $name(
+                &self,
+                db: &mut $crate::Connection,
+            ) -> $crate::__QueryResult<Vec<$crate::Attachment>> {
+                $crate::HasMany::<Self>::new(stringify!($name)).find_all(db, self)
+            }
+
+            pub async fn [<$name _urls>](
+                &self,
+                db: &mut $crate::Connection,
+                storage: &$crate::Storage,
+                expires_in: Option<std::time::Duration>,
+            ) -> Result<Vec<String>, $crate::StorageError> {
+                $crate::HasMany::<Self>::new(stringify!($name))
+                    .urls(db, storage, self, expires_in)
+                    .await
+            }
+
+            pub async fn [<detach_ $name>](
+                &self,
+                db: &mut $crate::Connection,
+                storage: &$crate::Storage,
+            ) -> Result<(), $crate::StorageError> {
+                $crate::HasMany::<Self>::new(stringify!($name))
+                    .detach_all(db, storage, self)
+                    .await
+            }
+
+            pub fn [<preload_ $name>](
+                db: &mut $crate::Connection,
+                records: &[Self],
+            ) -> $crate::__QueryResult<std::collections::HashMap<i32, Vec<$crate::Attachment>>> {
+                $crate::HasMany::<Self>::new(stringify!($name)).preload(db, records)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+// the generated accessors need a database, the test only checks they compile
+#[allow(dead_code)]
+mod tests {
+    use super::HasAttachments;
+
+    struct User {
+        id: i32,
+    }
+
+    crate::has_attachments! {
+        User in "users" {
+            avatar: one,
+            photos: many,
+        }
+    }
+
+    #[test]
+    fn declares_attachments() {
+        let user = User { id: 7 };
+
+        assert_eq!(User::RECORD_TYPE, "users");
+        assert_eq!(user.attachment_record_id(), 7);
+    }
+}
@@ -1,40 +1,66 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
-use aws_sdk_s3::model::{Delete, ObjectIdentifier};
-use aws_sdk_s3::presigning::config::PresigningConfig;
-use aws_sdk_s3::types::ByteStream;
-//use aws_sdk_s3::types::SdkError::*;
-use aws_sdk_s3::{Client, Config, Endpoint};
-use aws_types::region::Region;
-use aws_types::Credentials;
-//use base64;
 use http::{HeaderMap, Uri};
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::AsyncRead;
 
-pub use attachment::{Attachment, AttachmentData};
+pub use attachment::{Attachment, AttachmentData, DirectUpload};
 pub use attachment_blob::AttachmentBlob;
+pub use backend::{
+    LocalBackend, MemoryBackend, ObjectMetadata, ObjectReader, S3Backend, S3Config, S3Credentials,
+    ServerSideEncryption, StorageBackend, UploadSession, UploadedObject, UPLOAD_CHUNK_SIZE,
+};
+pub use encryption::Encryption;
+pub use error::{RetryPolicy, StorageError};
+pub use has_attachments::{AttachmentDb, HasAttachments, HasMany, HasOne};
+#[cfg(feature = "plugin_tasks")]
+pub use reconcile::ReconcileStorage;
+pub use reconcile::{OrphanedObject, ReconcileOptions, ReconcileReport};
+pub use validation::{sniff_content_type, AttachmentRules, ClamAvScanHook, ScanHook, ScanResult};
+#[cfg(all(feature = "plugin_storage-variants", feature = "plugin_tasks"))]
+pub use variants::ProcessVariants;
+#[cfg(feature = "plugin_storage-variants")]
+pub use variants::{ProcessedVariant, Transformation, Variant, VariantFormat};
 
 mod attachment;
 mod attachment_blob;
+mod backend;
+mod encryption;
+mod error;
+mod has_attachments;
+mod reconcile;
 mod schema;
+mod validation;
+#[cfg(feature = "plugin_storage-variants")]
+mod variants;
 
 #[tsync::tsync]
 type ID = i32;
 
 #[tsync::tsync]
-#[cfg(not(feature = "database_sqlite"))]
+#[cfg(feature = "database_postgres")]
 type Utc = chrono::DateTime<chrono::Utc>;
-#[cfg(feature = "database_sqlite")]
+#[cfg(not(feature = "database_postgres"))]
 type Utc = chrono::NaiveDateTime;
 
 #[derive(Clone)]
+/// Routes storage operations to the registered [`StorageBackend`]s.
+///
+/// New blobs go to the default backend; existing blobs are handled by the backend
+/// whose [`service_name`](`StorageBackend::service_name`) they were stored with.
 pub struct Storage {
-    client: Option<Client>,
-    bucket: String,
-    host: String,
+    default_service: Option<String>,
+    backends: HashMap<String, Arc<dyn StorageBackend>>,
+    attachment_rules: HashMap<String, AttachmentRules>,
+    scan_hook: Option<Arc<dyn ScanHook>>,
+    encryption: Encryption,
+    attachment_encryption: HashMap<String, Encryption>,
+    master_keys: encryption::MasterKeys,
+    #[cfg(feature = "plugin_storage-variants")]
+    variants: HashMap<String, Variant>,
 }
 
 pub struct UploadURI {
@@ -56,37 +82,56 @@ impl Storage {
     /// # Errors
     /// * could not download the object
     /// * could not write the object to the given path
-    pub async fn download(&self, key: String, to_path: PathBuf) -> Result<(), String> {
-        let client = self.client_or_error()?;
-
-        let response = client
-            .get_object()
-            .bucket(&self.bucket)
-            .key(key.clone())
-            .send()
-            .await
-            .map_err(|err| self.error_string("Could not download object", &key, err))?;
+    pub async fn download(&self, key: String, to_path: PathBuf) -> Result<(), StorageError> {
+        let reader = self.download_stream(key.clone()).await?;
+        Self::write_to_file(reader, key, to_path).await
+    }
 
-        let data = response
-            .body
-            .collect()
-            .await
-            .map_err(|err| self.error_string("Could not download object", &key, err))?;
+    /// like [`Storage::download`], but for a blob which may live on any registered backend;
+    /// envelope-encrypted blobs are decrypted
+    ///
+    /// # Errors
+    /// * the blob's backend isn't registered
+    /// * could not download (or decrypt) the object
+    /// * could not write the object to the given path
+    pub async fn blob_download(
+        &self,
+        blob: &AttachmentBlob,
+        to_path: PathBuf,
+    ) -> Result<(), StorageError> {
+        let reader = self.blob_download_stream(blob).await?;
+        Self::write_to_file(reader, &blob.key, to_path).await
+    }
 
+    async fn write_to_file(
+        mut reader: ObjectReader,
+        key: impl std::fmt::Display,
+        to_path: PathBuf,
+    ) -> Result<(), StorageError> {
         let mut file = File::create(to_path)
             .await
-            .map_err(|err| self.error_string("Could not download object", &key, err))?;
+            .map_err(|err| Self::io_error("Could not download object", &key, &err))?;
 
-        file.write_all(&data.into_bytes())
+        tokio::io::copy(&mut reader, &mut file)
             .await
-            .map_err(|err| self.error_string("Could not download object", key, err))?;
+            .map_err(|err| Self::io_error("Could not download object", key, &err))?;
 
         Ok(())
     }
 
+    /// stream an object from the bucket without loading it into memory
+    ///
+    /// # Arguments
+    /// * `key` - the key of the object to download
+    ///
+    /// # Errors
+    /// * could not download the object
+    pub async fn download_stream(&self, key: String) -> Result<ObjectReader, StorageError> {
+        self.default_backend()?.download_stream(&key).await
+    }
+
     /// if `expires_in` is `None`, then we assume the bucket is publicly accessible and return the
     /// public URL. For this to work, you have to make sure the bucket's policy allows public access.
-    /// TODO: validate the uri we return in this case is valid, and if not, return an error
     ///
     /// # Arguments
     /// * `key` - the key of the object to download
@@ -98,35 +143,31 @@ impl Storage {
         &self,
         key: String,
         expires_in: Option<Duration>,
-    ) -> Result<String, String> {
-        let expires_in = match expires_in {
-            None => {
-                let host = self.host.clone();
-                let host = if host.ends_with('/') {
-                    host
-                } else {
-                    format!("{host}/")
-                };
-                let bucket = &self.bucket;
-                return Ok(format!("{host}{bucket}/{key}"));
-            }
-            Some(time) => time,
-        };
-
-        let client = self.client_or_error()?;
+    ) -> Result<String, StorageError> {
+        self.default_backend()?.download_uri(&key, expires_in).await
+    }
 
-        let response =
-            client
-                .get_object()
-                .bucket(&self.bucket)
-                .key(key.clone())
-                .presigned(PresigningConfig::expires_in(expires_in).map_err(|err| {
-                    self.error_string("Could not retrieve download URI", &key, err)
-                })?)
-                .await
-                .map_err(|err| self.error_string("Could not retrieve download URI", key, err))?;
+    /// like [`Storage::download_uri`], but for a blob which may live on any registered backend
+    ///
+    /// # Errors
+    /// * the blob's backend isn't registered
+    /// * the blob is envelope-encrypted, so it can only be downloaded through [`Storage::blob_download_stream`]
+    /// * could not retrieve the download URI
+    pub async fn blob_download_uri(
+        &self,
+        blob: &AttachmentBlob,
+        expires_in: Option<Duration>,
+    ) -> Result<String, StorageError> {
+        if Encryption::of_blob(blob) == Encryption::Envelope {
+            return Err(StorageError::Invalid(format!(
+                "The blob is encrypted by the app and can't be downloaded from the storage directly (key: '{}')",
+                blob.key
+            )));
+        }
 
-        Ok(response.uri().to_string())
+        self.backend(&blob.service_name)?
+            .download_uri(&blob.key, expires_in)
+            .await
     }
 
     /// upload an object to the bucket
@@ -144,25 +185,31 @@ impl Storage {
         key: String,
         bytes: Vec<u8>,
         content_type: String,
-        _content_md5: String,
-    ) -> Result<(), String> {
-        let stream = ByteStream::from(bytes);
-
-        let client = self.client_or_error()?;
-
-        client
-            .put_object()
-            .bucket(&self.bucket)
-            .key(&key)
-            .body(stream)
-            .content_type(content_type)
-            // TODO: Error { code: \"InvalidDigest\", message: \"The Content-Md5 you specified is not valid.\", request_id: \"16DBB0A878146F1A\" }
-            // .content_md5(base64::encode(content_md5))
-            .send()
+        content_md5: String,
+    ) -> Result<(), StorageError> {
+        self.default_backend()?
+            .upload(&key, bytes, &content_type, &content_md5)
             .await
-            .map_err(|err| self.error_string("Could not upload object", key, err))?;
+    }
 
-        Ok(())
+    /// upload everything `reader` yields to the bucket, without loading it all into memory;
+    /// large objects are uploaded in parts of [`UPLOAD_CHUNK_SIZE`] bytes
+    ///
+    /// # Arguments
+    /// * `key` - the key of the object to upload
+    /// * `reader` - the contents of the object to upload
+    /// * `content_type` - the content type of the object to upload
+    ///
+    /// # Errors
+    /// * could not read from `reader`
+    /// * could not upload the object
+    pub async fn upload_stream(
+        &self,
+        key: String,
+        reader: impl AsyncRead + Unpin,
+        content_type: String,
+    ) -> Result<UploadedObject, StorageError> {
+        backend::upload_from_reader(self.default_backend()?, &key, reader, &content_type).await
     }
 
     /// returns a URI that can be used to upload an object to the bucket
@@ -173,26 +220,12 @@ impl Storage {
     ///
     /// # Errors
     /// * could not retrieve the upload URI
-    pub async fn upload_uri(&self, key: String, expires_in: Duration) -> Result<UploadURI, String> {
-        let client = self.client_or_error()?;
-
-        let response = client
-            .put_object()
-            .bucket(&self.bucket)
-            .key(&key)
-            .presigned(
-                PresigningConfig::expires_in(expires_in)
-                    .map_err(|err| self.error_string("Could not retrieve upload URI", &key, err))?,
-            )
-            .await
-            .map_err(|err| self.error_string("Could not retrieve upload URI", key, err))?;
-
-        let upload_uri = UploadURI {
-            uri: response.uri().clone(),
-            headers: response.headers().clone(),
-        };
-
-        Ok(upload_uri)
+    pub async fn upload_uri(
+        &self,
+        key: String,
+        expires_in: Duration,
+    ) -> Result<UploadURI, StorageError> {
+        self.default_backend()?.upload_uri(&key, expires_in).await
     }
 
     /// delete an object from the bucket
@@ -202,18 +235,8 @@ impl Storage {
     ///
     /// # Errors
     /// * could not delete the object
-    pub async fn delete(&self, key: String) -> Result<(), String> {
-        let client = self.client_or_error()?;
-
-        client
-            .delete_object()
-            .bucket(&self.bucket)
-            .key(&key)
-            .send()
-            .await
-            .map_err(|err| self.error_string("Could not delete object", key, err))?;
-
-        Ok(())
+    pub async fn delete(&self, key: String) -> Result<(), StorageError> {
+        self.default_backend()?.delete(&key).await
     }
 
     /// delete many objects from the bucket
@@ -223,113 +246,206 @@ impl Storage {
     ///
     /// # Errors
     /// * could not delete the objects
-    pub async fn delete_many(&self, keys: Vec<String>) -> Result<(), String> {
-        let client = self.client_or_error()?;
-
-        let ids = keys
-            .iter()
-            .map(|k| {
-                ObjectIdentifier::builder()
-                    .set_key(Some(k.to_string()))
-                    .build()
+    pub async fn delete_many(&self, keys: Vec<String>) -> Result<(), StorageError> {
+        self.default_backend()?.delete_many(&keys).await
+    }
+
+    /// check whether an object exists in the bucket
+    ///
+    /// # Errors
+    /// * could not reach the storage
+    pub async fn exists(&self, key: String) -> Result<bool, StorageError> {
+        self.default_backend()?.exists(&key).await
+    }
+
+    /// list the keys of the objects in the bucket, optionally only those starting with `prefix`
+    ///
+    /// # Errors
+    /// * could not list the objects
+    pub async fn list(&self, prefix: Option<String>) -> Result<Vec<String>, StorageError> {
+        self.default_backend()?.list(prefix.as_deref()).await
+    }
+
+    /// the backend new blobs are uploaded to
+    ///
+    /// # Errors
+    /// * no backend is configured
+    pub fn default_backend(&self) -> Result<&dyn StorageBackend, StorageError> {
+        self.default_service
+            .as_ref()
+            .and_then(|service_name| self.backends.get(service_name))
+            .map(AsRef::as_ref)
+            .ok_or_else(|| {
+                StorageError::NotConfigured(
+                    "The storage is not available; did you set the right environment variables?"
+                        .to_string(),
+                )
             })
-            .collect::<Vec<ObjectIdentifier>>();
-        let delete = Delete::builder().set_objects(Some(ids)).build();
-
-        client
-            .delete_objects()
-            .bucket(&self.bucket)
-            .delete(delete)
-            .send()
-            .await
-            .map_err(|err| {
-                self.error_string("Could not delete objects", format!("{keys:#?}"), err)
-            })?;
+    }
 
-        Ok(())
+    /// the backend registered under `service_name`
+    ///
+    /// # Errors
+    /// * no such backend is registered
+    pub fn backend(&self, service_name: &str) -> Result<&dyn StorageBackend, StorageError> {
+        self.backends
+            .get(service_name)
+            .map(AsRef::as_ref)
+            .ok_or_else(|| {
+                StorageError::NotConfigured(format!(
+                    "The storage service '{service_name}' is not configured"
+                ))
+            })
     }
 
-    fn error_string(
-        &self,
+    fn io_error(
         message: &'static str,
         key: impl std::fmt::Display,
-        error: impl std::fmt::Display,
-    ) -> String {
-        let bucket = &self.bucket;
-        format!("{message} (bucket: '{bucket}', key: '{key}', error: '{error}')")
+        error: &std::io::Error,
+    ) -> StorageError {
+        StorageError::io(error)(format!("{message} (key: '{key}', error: '{error}')"))
+    }
+
+    /// a [`Storage`] which uploads to `backend`
+    #[must_use]
+    pub fn from_backend(backend: impl StorageBackend + 'static) -> Self {
+        Self {
+            default_service: Some(backend.service_name().to_string()),
+            ..Self::disabled()
+        }
+        .with_backend(backend)
+    }
+
+    /// registers an additional backend so blobs stored on it can still be read and deleted
+    #[must_use]
+    pub fn with_backend(mut self, backend: impl StorageBackend + 'static) -> Self {
+        self.backends
+            .insert(backend.service_name().to_string(), Arc::new(backend));
+        self
+    }
+
+    /// limits what can be attached under `name`, see [`AttachmentRules`]
+    #[must_use]
+    pub fn with_attachment_rules(
+        mut self,
+        name: impl Into<String>,
+        rules: AttachmentRules,
+    ) -> Self {
+        self.attachment_rules.insert(name.into(), rules);
+        self
     }
 
-    fn client_or_error(&self) -> Result<&Client, String> {
-        self.client.as_ref().ok_or_else(|| {
-            "The storage is not available; did you set the right environment variables?".to_string()
-        })
+    /// the rules registered for attachments named `name`
+    #[must_use]
+    pub fn attachment_rules(&self, name: &str) -> Option<&AttachmentRules> {
+        self.attachment_rules.get(name)
     }
 
-    fn check_environment_variables() {
-        let vars = vec![
-            "S3_HOST",
-            "S3_REGION",
-            "S3_BUCKET",
-            "S3_ACCESS_KEY_ID",
-            "S3_SECRET_ACCESS_KEY",
-        ];
-
-        let unset_vars = vars
-            .into_iter()
-            .filter(|v| std::env::var(v).is_err())
-            .collect::<Vec<_>>();
-
-        if !unset_vars.is_empty() {
-            println!(
-                "Warning: Storage disabled; the following variables must be set: {}",
-                unset_vars.join(", ")
-            );
+    /// scans every attached file with `hook` before its blob is committed, see [`ScanHook`]
+    #[must_use]
+    pub fn with_scan_hook(mut self, hook: impl ScanHook + 'static) -> Self {
+        self.scan_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// runs the [`ScanHook`] (if any) on the contents `object` resolves to; it isn't awaited otherwise
+    ///
+    /// # Errors
+    /// * the file is infected, or could not be scanned
+    pub(crate) async fn scan(
+        &self,
+        file_name: &str,
+        object: impl std::future::Future<Output = Result<ObjectReader, StorageError>>,
+    ) -> Result<(), StorageError> {
+        let Some(scan_hook) = &self.scan_hook else {
+            return Ok(());
+        };
+
+        match scan_hook.scan(file_name, object.await?).await? {
+            ScanResult::Clean => Ok(()),
+            ScanResult::Infected(found) => Err(StorageError::Rejected(format!(
+                "The file was rejected ({found} found)"
+            ))),
         }
     }
 
-    fn init(
-        host: &str,
-        region: Region,
-        access_key_id: String,
-        secret_access_key: String,
-    ) -> Result<Option<Client>, String> {
-        Self::check_environment_variables();
-
-        let s3_config = Config::builder()
-            .region(region)
-            .endpoint_resolver(Endpoint::immutable(Uri::from_str(host).map_err(|err| {
-                let error = err.to_string();
-                format!("Could not initialize storage (error: '{error}')")
-            })?))
-            .credentials_provider(Credentials::new(
-                access_key_id,
-                secret_access_key,
-                None,
-                None,
-                "UNNAMED_PROVIDER",
-            ))
-            .build();
-        let client = Client::from_conf(s3_config);
-
-        Ok(Some(client))
+    /// registers an image variant, see [`Variant`]
+    #[cfg(feature = "plugin_storage-variants")]
+    #[must_use]
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variants.insert(variant.name.clone(), variant);
+        self
+    }
+
+    /// the variant registered under `name`
+    #[cfg(feature = "plugin_storage-variants")]
+    #[must_use]
+    pub fn variant(&self, name: &str) -> Option<&Variant> {
+        self.variants.get(name)
     }
 
+    /// a [`Storage`] without any backend; every operation fails
+    fn disabled() -> Self {
+        Self {
+            default_service: None,
+            backends: HashMap::new(),
+            attachment_rules: HashMap::new(),
+            scan_hook: None,
+            encryption: Encryption::None,
+            attachment_encryption: HashMap::new(),
+            master_keys: encryption::MasterKeys::default(),
+            #[cfg(feature = "plugin_storage-variants")]
+            variants: HashMap::new(),
+        }
+    }
+
+    /// configures the default backend from the `STORAGE_SERVICE` environment variable:
+    ///
+    /// * `s3` (default) - uses the `S3_*` variables, see [`S3Backend::from_env`]
+    /// * `local` - files under `STORAGE_LOCAL_ROOT` (default: `./storage`),
+    ///   served from `STORAGE_LOCAL_PUBLIC_URL` if set
+    /// * `memory` - in-memory, for tests
+    ///
+    /// if `CLAMAV_ADDRESS` is set, attached files are scanned by that clamd daemon, see [`ClamAvScanHook`]
+    ///
+    /// if `STORAGE_MASTER_KEY` is set (a base64-encoded 256-bit key), it is registered as the master key
+    /// for [`Encryption::Envelope`] under the id in `STORAGE_MASTER_KEY_ID` (default: `default`)
     #[must_use]
     pub fn new() -> Self {
-        let host = std::env::var("S3_HOST").unwrap_or_else(|_| String::new());
-        let region = std::env::var("S3_REGION").unwrap_or_else(|_| String::new());
-        let bucket = std::env::var("S3_BUCKET").unwrap_or_else(|_| String::new());
-        let access_key_id = std::env::var("S3_ACCESS_KEY_ID").unwrap_or_else(|_| String::new());
-        let secret_access_key =
-            std::env::var("S3_SECRET_ACCESS_KEY").unwrap_or_else(|_| String::new());
+        let service = std::env::var("STORAGE_SERVICE")
+            .unwrap_or_else(|_| S3Backend::SERVICE_NAME.to_string());
+
+        let storage = match service.as_str() {
+            S3Backend::SERVICE_NAME => match S3Backend::from_env() {
+                Ok(backend) => Self::from_backend(backend),
+                Err(error) => {
+                    println!("Warning: Storage disabled; {error}");
+                    Self::disabled()
+                }
+            },
+            LocalBackend::SERVICE_NAME => Self::from_backend(LocalBackend::new(
+                std::env::var("STORAGE_LOCAL_ROOT").unwrap_or_else(|_| "./storage".to_string()),
+                std::env::var("STORAGE_LOCAL_PUBLIC_URL").ok(),
+            )),
+            MemoryBackend::SERVICE_NAME => Self::from_backend(MemoryBackend::new()),
+            other => {
+                println!("Warning: Storage disabled; unknown STORAGE_SERVICE '{other}'");
+                Self::disabled()
+            }
+        };
 
-        let client = Self::init(&host, Region::new(region), access_key_id, secret_access_key)
-            .unwrap_or(None);
+        let storage = match encryption::MasterKeys::from_env() {
+            Ok(Some((id, key))) => storage.with_master_key(id, key),
+            Ok(None) => storage,
+            Err(error) => {
+                println!("Warning: Envelope encryption disabled; {error}");
+                storage
+            }
+        };
 
-        Self {
-            client,
-            bucket,
-            host,
+        match ClamAvScanHook::from_env() {
+            Some(scan_hook) => storage.with_scan_hook(scan_hook),
+            None => storage,
         }
     }
 }
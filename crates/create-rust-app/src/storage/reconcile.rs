@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use diesel::result::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use crate::storage::{schema, Attachment, AttachmentBlob, Storage, StorageError};
+use crate::Connection;
+
+/// Settings for [`Storage::reconcile`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileOptions {
+    /// only report what is out of sync, without removing anything
+    pub dry_run: bool,
+    /// objects and blobs younger than this are left alone because they may belong to an upload in progress;
+    /// this should be longer than the time an upload (or a direct upload) can take
+    pub grace_period: Duration,
+}
+
+impl Default for ReconcileOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: true,
+            grace_period: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// An object no blob refers to
+#[derive(Debug, Clone)]
+pub struct OrphanedObject {
+    pub service_name: String,
+    pub key: String,
+}
+
+/// What [`Storage::reconcile`] found, and removed unless it was a dry run
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub dry_run: bool,
+    /// objects in the storage backends without a row in `attachment_blobs`
+    pub orphaned_objects: Vec<OrphanedObject>,
+    /// rows in `attachment_blobs` whose object is missing
+    pub dangling_blobs: Vec<AttachmentBlob>,
+    /// attachments which referenced the dangling blobs
+    pub dangling_attachments: Vec<Attachment>,
+    /// services blobs were stored on which aren't registered with the [`Storage`], so they could not be checked
+    pub unchecked_services: Vec<String>,
+}
+
+impl fmt::Display for ReconcileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run { "found" } else { "removed" };
+
+        writeln!(
+            f,
+            "{verb} {} orphaned object(s)",
+            self.orphaned_objects.len()
+        )?;
+        for object in &self.orphaned_objects {
+            writeln!(f, "  {}: {}", object.service_name, object.key)?;
+        }
+
+        writeln!(
+            f,
+            "{verb} {} dangling blob(s) and {} attachment(s) referencing them",
+            self.dangling_blobs.len(),
+            self.dangling_attachments.len()
+        )?;
+        for blob in &self.dangling_blobs {
+            writeln!(f, "  {}: {} (id: {})", blob.service_name, blob.key, blob.id)?;
+        }
+
+        for service_name in &self.unchecked_services {
+            writeln!(
+                f,
+                "skipped blobs on '{service_name}': no such backend is registered"
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage {
+    /// Compares the objects in every registered backend with the `attachment_blobs` table, and removes
+    /// the objects no blob refers to as well as the blobs (and their attachments) whose object is missing.
+    ///
+    /// Nothing is removed if `options.dry_run` is set. Objects are only considered orphaned if the backend
+    /// reports when they were last modified, and that was longer than `options.grace_period` ago.
+    ///
+    /// # Errors
+    /// * could not list, inspect or delete objects; no rows are removed when an object could not be deleted
+    /// * Diesel error
+    pub async fn reconcile(
+        &self,
+        db: &mut Connection,
+        options: &ReconcileOptions,
+    ) -> Result<ReconcileReport, StorageError> {
+        let object_cutoff = SystemTime::now() - options.grace_period;
+        let grace_period = chrono::Duration::from_std(options.grace_period)
+            .map_err(|err| StorageError::Invalid(err.to_string()))?;
+        #[cfg(feature = "database_postgres")]
+        let blob_cutoff = chrono::Utc::now() - grace_period;
+        #[cfg(not(feature = "database_postgres"))]
+        let blob_cutoff = chrono::Utc::now().naive_utc() - grace_period;
+
+        let mut report = ReconcileReport {
+            dry_run: options.dry_run,
+            ..ReconcileReport::default()
+        };
+
+        let mut service_names = self.backends.keys().collect::<Vec<_>>();
+        service_names.sort();
+
+        for service_name in service_names {
+            let backend = self.backend(service_name)?;
+
+            // list the objects first, so blobs created in the meantime are taken into account
+            let keys = backend.list(None).await?;
+            let blobs = schema::attachment_blobs::table
+                .filter(schema::attachment_blobs::service_name.eq(service_name))
+                .load::<AttachmentBlob>(db)?;
+
+            let blob_keys = blobs
+                .iter()
+                .map(|blob| blob.key.as_str())
+                .collect::<HashSet<_>>();
+            for key in keys.iter().filter(|key| !blob_keys.contains(key.as_str())) {
+                let last_modified = backend
+                    .metadata(key)
+                    .await?
+                    .and_then(|metadata| metadata.last_modified);
+                if last_modified.is_some_and(|last_modified| last_modified < object_cutoff) {
+                    report.orphaned_objects.push(OrphanedObject {
+                        service_name: service_name.to_string(),
+                        key: key.to_string(),
+                    });
+                }
+            }
+
+            let keys = keys.iter().map(String::as_str).collect::<HashSet<_>>();
+            report.dangling_blobs.extend(
+                blobs.into_iter().filter(|blob| {
+                    blob.created_at < blob_cutoff && !keys.contains(blob.key.as_str())
+                }),
+            );
+        }
+
+        report.unchecked_services = schema::attachment_blobs::table
+            .select(schema::attachment_blobs::service_name)
+            .distinct()
+            .load::<String>(db)?
+            .into_iter()
+            .filter(|service_name| !self.backends.contains_key(service_name))
+            .collect();
+
+        let dangling_blob_ids = report
+            .dangling_blobs
+            .iter()
+            .map(|blob| blob.id)
+            .collect::<Vec<_>>();
+        report.dangling_attachments = schema::attachments::table
+            .filter(schema::attachments::blob_id.eq_any(dangling_blob_ids.clone()))
+            .load::<Attachment>(db)?;
+
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        let mut orphans_by_service: HashMap<&str, Vec<String>> = HashMap::new();
+        for object in &report.orphaned_objects {
+            orphans_by_service
+                .entry(&object.service_name)
+                .or_default()
+                .push(object.key.clone());
+        }
+        for (service_name, keys) in orphans_by_service {
+            self.backend(service_name)?.delete_many(&keys).await?;
+        }
+
+        // the dangling blobs' own objects are missing, but their variants' are deleted before the rows,
+        // which are kept if that fails
+        let blobs = Attachment::with_derived_blobs(db, report.dangling_blobs.clone())?;
+        let derived = blobs
+            .iter()
+            .filter(|blob| !dangling_blob_ids.contains(&blob.id))
+            .cloned()
+            .collect::<Vec<_>>();
+        Attachment::try_delete_objects(self, &derived).await?;
+
+        diesel::connection::Connection::transaction::<_, Error, _>(db, |db| {
+            diesel::delete(
+                schema::attachments::table
+                    .filter(schema::attachments::blob_id.eq_any(dangling_blob_ids)),
+            )
+            .execute(db)?;
+
+            Attachment::delete_blobs(db, &blobs)
+        })?;
+
+        Ok(report)
+    }
+}
+
+/// Runs [`Storage::reconcile`] on the `async` queue, with the [`Storage`] built from the environment
+/// (see [`Storage::new`]); the report is printed.
+///
+/// ```rust,ignore
+/// create_rust_app::tasks::async_queue()
+///     .lock()
+///     .unwrap()
+///     .insert_task(&ReconcileStorage { options: ReconcileOptions { dry_run: false, ..Default::default() } })
+///     .await?;
+/// ```
+#[cfg(feature = "plugin_tasks")]
+#[derive(Serialize, Deserialize)]
+pub struct ReconcileStorage {
+    pub options: ReconcileOptions,
+}
+
+#[cfg(feature = "plugin_tasks")]
+mod task {
+    use fang::asynk::async_queue::AsyncQueueable;
+    use fang::{async_trait, typetag, AsyncRunnable, FangError};
+
+    use super::{ReconcileStorage, Storage};
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for ReconcileStorage {
+        async fn run(&self, _queueable: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+            let db = crate::Database::new();
+            let mut db = db.get_connection().map_err(|err| FangError {
+                description: err.to_string(),
+            })?;
+
+            let report = Storage::new()
+                .reconcile(&mut db, &self.options)
+                .await
+                .map_err(|err| FangError {
+                    description: err.to_string(),
+                })?;
+            println!("{report}");
+
+            Ok(())
+        }
+
+        fn task_type(&self) -> String {
+            "async".to_string()
+        }
+    }
+}
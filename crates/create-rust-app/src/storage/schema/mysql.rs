@@ -0,0 +1,41 @@
+table! {
+    attachment_blobs (id) {
+        id -> Integer,
+        key -> Text,
+        file_name -> Text,
+        content_type -> Nullable<Text>,
+        byte_size -> BigInt,
+        checksum -> Text,
+        sha256 -> Nullable<Text>,
+        encryption -> Nullable<Text>,
+        encryption_key -> Nullable<Text>,
+        service_name -> Text,
+        created_at -> Datetime,
+    }
+}
+
+table! {
+    attachments (id) {
+        id -> Integer,
+        name -> Text,
+        record_type -> Text,
+        record_id -> Integer,
+        blob_id -> Integer,
+        created_at -> Datetime,
+    }
+}
+
+table! {
+    attachment_blob_variants (id) {
+        id -> Integer,
+        blob_id -> Integer,
+        variant_blob_id -> Integer,
+        name -> Text,
+        digest -> Text,
+        created_at -> Datetime,
+    }
+}
+
+joinable!(attachments -> attachment_blobs (blob_id));
+
+allow_tables_to_appear_in_same_query!(attachment_blobs, attachments, attachment_blob_variants,);
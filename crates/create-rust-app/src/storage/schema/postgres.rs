@@ -6,6 +6,9 @@ table! {
         content_type -> Nullable<Text>,
         byte_size -> Int8,
         checksum -> Text,
+        sha256 -> Nullable<Text>,
+        encryption -> Nullable<Text>,
+        encryption_key -> Nullable<Text>,
         service_name -> Text,
         created_at -> Timestamptz,
     }
@@ -22,6 +25,17 @@ table! {
     }
 }
 
+table! {
+    attachment_blob_variants (id) {
+        id -> Int4,
+        blob_id -> Int4,
+        variant_blob_id -> Int4,
+        name -> Text,
+        digest -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 joinable!(attachments -> attachment_blobs (blob_id));
 
-allow_tables_to_appear_in_same_query!(attachment_blobs, attachments,);
+allow_tables_to_appear_in_same_query!(attachment_blobs, attachments, attachment_blob_variants,);
@@ -0,0 +1,394 @@
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::{ObjectReader, StorageError};
+
+/// how many bytes of an upload are looked at to detect its content type
+pub(super) const SNIFF_LEN: usize = 512;
+
+/// Limits for the files attached under a given name, see [`Storage::with_attachment_rules`](`super::Storage::with_attachment_rules`)
+///
+/// ```rust,ignore
+/// let storage = Storage::new().with_attachment_rules(
+///     "avatar",
+///     AttachmentRules::new()
+///         .max_bytes(2 * 1024 * 1024)
+///         .allow_content_types(["image/png", "image/jpeg"]),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AttachmentRules {
+    pub max_bytes: Option<u64>,
+    /// MIME types (or wildcards like `image/*`) detected from the file's contents, not its extension
+    pub allowed_content_types: Option<Vec<String>>,
+    /// only applies when attaching with `allow_multiple`
+    pub max_count: Option<usize>,
+}
+
+impl AttachmentRules {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    #[must_use]
+    pub fn allow_content_types(
+        mut self,
+        content_types: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.allowed_content_types = Some(content_types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub const fn max_count(mut self, max_count: usize) -> Self {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    /// # Errors
+    /// * the file is larger than `max_bytes`
+    pub(super) fn check_size(&self, byte_size: u64) -> Result<(), StorageError> {
+        match self.max_bytes {
+            Some(max_bytes) if byte_size > max_bytes => Err(StorageError::Rejected(format!(
+                "The file is too large (maximum: {max_bytes} bytes)"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// # Errors
+    /// * the detected content type is not allowed
+    pub(super) fn check_content_type(
+        &self,
+        content_type: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let Some(allowed) = &self.allowed_content_types else {
+            return Ok(());
+        };
+
+        let is_allowed = content_type.is_some_and(|content_type| {
+            allowed.iter().any(|pattern| {
+                pattern == content_type
+                    || pattern.strip_suffix("/*").is_some_and(|prefix| {
+                        content_type
+                            .strip_prefix(prefix)
+                            .is_some_and(|rest| rest.starts_with('/'))
+                    })
+            })
+        });
+
+        if is_allowed {
+            Ok(())
+        } else {
+            Err(StorageError::Rejected(format!(
+                "Files of type '{}' are not allowed",
+                content_type.unwrap_or("unknown")
+            )))
+        }
+    }
+
+    /// # Errors
+    /// * `existing` attachments already reach `max_count`
+    pub(super) fn check_count(&self, existing: usize) -> Result<(), StorageError> {
+        match self.max_count {
+            Some(max_count) if existing >= max_count => Err(StorageError::Conflict(format!(
+                "Only {max_count} attachments are allowed"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The outcome of a [`ScanHook`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanResult {
+    Clean,
+    /// the name of what was found
+    Infected(String),
+}
+
+/// Inspects uploaded files (e.g. with a virus scanner) before their blob is committed;
+/// register it with [`Storage::with_scan_hook`](`super::Storage::with_scan_hook`)
+#[async_trait]
+pub trait ScanHook: Send + Sync {
+    /// scans the contents of the file named `file_name`
+    ///
+    /// # Errors
+    /// * the file could not be scanned; the upload is rejected
+    async fn scan(&self, file_name: &str, reader: ObjectReader)
+        -> Result<ScanResult, StorageError>;
+}
+
+/// Scans uploads with a `clamd` daemon, using its `INSTREAM` command
+pub struct ClamAvScanHook {
+    address: String,
+}
+
+impl ClamAvScanHook {
+    /// `address` of clamd's TCP socket, e.g. `localhost:3310`
+    #[must_use]
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+        }
+    }
+
+    /// uses the `CLAMAV_ADDRESS` environment variable; `None` if it isn't set
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        std::env::var("CLAMAV_ADDRESS").ok().map(Self::new)
+    }
+
+    async fn instream(&self, mut reader: ObjectReader) -> std::io::Result<String> {
+        let mut socket = TcpStream::connect(&self.address).await?;
+        socket.write_all(b"zINSTREAM\0").await?;
+
+        let mut chunk = vec![0; 64 * 1024];
+        loop {
+            let read = reader.read(&mut chunk).await?;
+            #[allow(clippy::cast_possible_truncation)]
+            socket.write_all(&(read as u32).to_be_bytes()).await?;
+            if read == 0 {
+                break;
+            }
+            socket.write_all(&chunk[..read]).await?;
+        }
+
+        let mut response = vec![];
+        socket.read_to_end(&mut response).await?;
+
+        Ok(String::from_utf8_lossy(&response)
+            .trim_end_matches('\0')
+            .trim()
+            .to_string())
+    }
+}
+
+#[async_trait]
+impl ScanHook for ClamAvScanHook {
+    async fn scan(
+        &self,
+        file_name: &str,
+        reader: ObjectReader,
+    ) -> Result<ScanResult, StorageError> {
+        let response = self.instream(reader).await.map_err(|err| {
+            StorageError::Network(format!("Could not scan '{file_name}' (error: '{err}')"))
+        })?;
+
+        // e.g. "stream: OK" or "stream: Eicar-Signature FOUND"
+        let verdict = response
+            .strip_prefix("stream: ")
+            .unwrap_or(&response)
+            .to_string();
+        if verdict == "OK" {
+            Ok(ScanResult::Clean)
+        } else if let Some(found) = verdict.strip_suffix(" FOUND") {
+            Ok(ScanResult::Infected(found.to_string()))
+        } else {
+            Err(StorageError::Other(format!(
+                "Could not scan '{file_name}' (clamd: '{response}')"
+            )))
+        }
+    }
+}
+
+/// reads up to [`SNIFF_LEN`] bytes from the start of `reader`
+pub(super) async fn read_head(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Vec<u8>> {
+    let mut head = vec![];
+    reader.take(SNIFF_LEN as u64).read_to_end(&mut head).await?;
+    Ok(head)
+}
+
+/// the content type of a file, detected from its first bytes;
+/// the file name is only used to tell apart kinds of plain text (e.g. CSV or JSON)
+pub(super) fn detect_content_type(head: &[u8], file_name: Option<&str>) -> Option<String> {
+    let detected = sniff_content_type(head)?;
+    let guessed = file_name.and_then(|file_name| mime_guess::from_path(file_name).first_raw());
+
+    match guessed {
+        Some(guessed)
+            if detected == "text/plain"
+                && (guessed.starts_with("text/") || guessed == "application/json") =>
+        {
+            Some(guessed.to_string())
+        }
+        _ => Some(detected.to_string()),
+    }
+}
+
+/// detects the MIME type of a file from its first bytes
+#[must_use]
+pub fn sniff_content_type(head: &[u8]) -> Option<&'static str> {
+    if head.is_empty() {
+        return None;
+    }
+
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"II*\0", "image/tiff"),
+        (b"MM\0*", "image/tiff"),
+        (b"\0\0\x01\0", "image/x-icon"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"\x7fELF", "application/x-executable"),
+        (b"MZ", "application/x-msdownload"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+
+    if let Some((_, content_type)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+    {
+        return Some(content_type);
+    }
+
+    // BMP, RIFF containers and ISO base media files carry their type around a size field
+    if head.starts_with(b"BM") && head.get(6..10) == Some(&[0; 4]) {
+        return Some("image/bmp");
+    }
+    match (head.get(..4), head.get(8..12)) {
+        (Some(b"RIFF"), Some(b"WEBP")) => return Some("image/webp"),
+        (Some(b"RIFF"), Some(b"WAVE")) => return Some("audio/wav"),
+        (Some(b"RIFF"), Some(b"AVI ")) => return Some("video/x-msvideo"),
+        _ => {}
+    }
+    if head.get(4..8) == Some(b"ftyp") {
+        return match head.get(8..12) {
+            Some(b"avif" | b"avis") => Some("image/avif"),
+            Some(b"heic" | b"heix" | b"mif1") => Some("image/heic"),
+            Some(b"qt  ") => Some("video/quicktime"),
+            Some(b"M4A ") => Some("audio/mp4"),
+            _ => Some("video/mp4"),
+        };
+    }
+
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // the last character was cut off
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&head[..err.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+    let lowercase = trimmed.to_ascii_lowercase();
+    if lowercase.starts_with("<svg")
+        || (lowercase.starts_with("<?xml") && lowercase.contains("<svg"))
+    {
+        Some("image/svg+xml")
+    } else if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+        Some("text/html")
+    } else if lowercase.starts_with("<?xml") {
+        Some("application/xml")
+    } else if text
+        .chars()
+        .all(|c| !c.is_control() || c.is_ascii_whitespace())
+    {
+        Some("text/plain")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_content_type_from_magic_bytes() {
+        assert_eq!(
+            sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some("image/png")
+        );
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(
+            sniff_content_type(b"<?xml version=\"1.0\"?><svg></svg>"),
+            Some("image/svg+xml")
+        );
+        assert_eq!(sniff_content_type(b"hello\n"), Some("text/plain"));
+        assert_eq!(sniff_content_type(b"\0\x01\x02\x03"), None);
+    }
+
+    #[test]
+    fn checks_rules() {
+        let rules = AttachmentRules::new()
+            .max_bytes(10)
+            .allow_content_types(["image/*", "application/pdf"])
+            .max_count(2);
+
+        assert!(rules.check_size(10).is_ok());
+        assert!(rules.check_size(11).is_err());
+        assert!(rules.check_content_type(Some("image/png")).is_ok());
+        assert!(rules.check_content_type(Some("application/pdf")).is_ok());
+        assert!(rules.check_content_type(Some("imagex/png")).is_err());
+        assert!(rules.check_content_type(None).is_err());
+        assert!(rules.check_count(1).is_ok());
+        assert!(rules.check_count(2).is_err());
+    }
+
+    #[tokio::test]
+    async fn clamav_scan_hook_reports_infected_files() {
+        // a stand-in for clamd which flags everything containing "EICAR"
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut command = [0; 10];
+                socket.read_exact(&mut command).await.unwrap();
+                assert_eq!(&command, b"zINSTREAM\0");
+
+                let mut contents = vec![];
+                loop {
+                    let length = socket.read_u32().await.unwrap() as usize;
+                    if length == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0; length];
+                    socket.read_exact(&mut chunk).await.unwrap();
+                    contents.extend(chunk);
+                }
+
+                let infected = contents.windows(5).any(|window| window == b"EICAR");
+                let response: &[u8] = if infected {
+                    b"stream: Eicar-Signature FOUND\0"
+                } else {
+                    b"stream: OK\0"
+                };
+                socket.write_all(response).await.unwrap();
+            }
+        });
+
+        let hook = ClamAvScanHook::new(address);
+
+        assert_eq!(
+            hook.scan("clean.txt", Box::pin(&b"hello"[..])).await,
+            Ok(ScanResult::Clean)
+        );
+        assert_eq!(
+            hook.scan("eicar.txt", Box::pin(&b"X5O!P%@AP EICAR"[..]))
+                .await,
+            Ok(ScanResult::Infected("Eicar-Signature".to_string()))
+        );
+    }
+}
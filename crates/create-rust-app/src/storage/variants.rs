@@ -0,0 +1,506 @@
+use std::io::Cursor;
+
+use diesel::result::Error;
+use image::{imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use crate::diesel::{
+    insert_into, BoolExpressionMethods, ExpressionMethods, Insertable, OptionalExtension, QueryDsl,
+    QueryResult, RunQueryDsl,
+};
+use crate::storage::attachment_blob::AttachmentBlobChangeset;
+use crate::storage::backend::upload_to_session;
+use crate::storage::{
+    schema, schema::attachment_blob_variants, AttachmentBlob, Encryption, Storage, StorageError, ID,
+};
+use crate::Connection;
+
+/// A named transformation of image attachments, like a thumbnail.
+///
+/// Register variants with [`Storage::with_variant`], then use [`Storage::variant_download_uri`]
+/// to serve them. Each variant of a blob is generated the first time it's requested (or ahead of time,
+/// see [`AttachmentBlob::process_variants`]) and stored as a child blob of the original.
+///
+/// Variants never carry the original's metadata, so EXIF data (like the location a photo was taken at)
+/// is stripped. The EXIF orientation is applied before that.
+///
+/// ```rust,ignore
+/// let storage = Storage::new()
+///     .with_variant(Variant::new("thumbnail").resize_to_fill(128, 128).format(VariantFormat::WebP))
+///     .with_variant(Variant::new("large").resize(1600, 1600));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    /// applied in order
+    pub transformations: Vec<Transformation>,
+    /// the format to encode the variant in; defaults to the original's format
+    pub format: Option<VariantFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Transformation {
+    /// scale the image down to fit within the given bounds, preserving its aspect ratio
+    Resize { width: u32, height: u32 },
+    /// scale and center-crop the image to exactly the given size
+    ResizeToFill { width: u32, height: u32 },
+    /// cut out the given region
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VariantFormat {
+    Png,
+    Jpeg,
+    /// lossless
+    WebP,
+    Avif,
+}
+
+impl VariantFormat {
+    fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Jpeg => ImageFormat::Jpeg,
+            Self::WebP => ImageFormat::WebP,
+            Self::Avif => ImageFormat::Avif,
+        }
+    }
+
+    fn from_image_format(format: ImageFormat) -> Option<Self> {
+        match format {
+            ImageFormat::Png => Some(Self::Png),
+            ImageFormat::Jpeg => Some(Self::Jpeg),
+            ImageFormat::WebP => Some(Self::WebP),
+            ImageFormat::Avif => Some(Self::Avif),
+            _ => None,
+        }
+    }
+}
+
+/// The output of [`Variant::apply`]
+pub struct ProcessedVariant {
+    pub data: Vec<u8>,
+    pub content_type: &'static str,
+    pub extension: &'static str,
+}
+
+impl Variant {
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            transformations: vec![],
+            format: None,
+        }
+    }
+
+    /// see [`Transformation::Resize`]
+    #[must_use]
+    pub fn resize(mut self, width: u32, height: u32) -> Self {
+        self.transformations
+            .push(Transformation::Resize { width, height });
+        self
+    }
+
+    /// see [`Transformation::ResizeToFill`]
+    #[must_use]
+    pub fn resize_to_fill(mut self, width: u32, height: u32) -> Self {
+        self.transformations
+            .push(Transformation::ResizeToFill { width, height });
+        self
+    }
+
+    /// see [`Transformation::Crop`]
+    #[must_use]
+    pub fn crop(mut self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.transformations.push(Transformation::Crop {
+            x,
+            y,
+            width,
+            height,
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn format(mut self, format: VariantFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// identifies the variant's definition, so changing it regenerates the variant
+    ///
+    /// # Panics
+    /// * never; a `Variant` always serializes
+    #[must_use]
+    pub fn digest(&self) -> String {
+        format!("{:x}", md5::compute(serde_json::to_vec(self).unwrap()))
+    }
+
+    /// decode `original`, transform it and encode the result
+    ///
+    /// # Errors
+    /// * `original` is not an image in a supported format
+    /// * the result could not be encoded
+    pub fn apply(&self, original: &[u8]) -> Result<ProcessedVariant, String> {
+        let reader = ImageReader::new(Cursor::new(original))
+            .with_guessed_format()
+            .map_err(|err| err.to_string())?;
+        let original_format = reader.format();
+
+        let mut decoder = reader.into_decoder().map_err(|err| err.to_string())?;
+        let orientation = decoder.orientation().map_err(|err| err.to_string())?;
+        let mut image = DynamicImage::from_decoder(decoder).map_err(|err| err.to_string())?;
+        image.apply_orientation(orientation);
+
+        for transformation in &self.transformations {
+            image = match *transformation {
+                Transformation::Resize { width, height } => {
+                    image.resize(width, height, FilterType::Lanczos3)
+                }
+                Transformation::ResizeToFill { width, height } => {
+                    image.resize_to_fill(width, height, FilterType::Lanczos3)
+                }
+                Transformation::Crop {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => image.crop_imm(x, y, width, height),
+            };
+        }
+
+        let format = self
+            .format
+            .or_else(|| original_format.and_then(VariantFormat::from_image_format))
+            .unwrap_or(VariantFormat::Png);
+
+        if format == VariantFormat::Jpeg {
+            // JPEG has no alpha channel
+            image = DynamicImage::ImageRgb8(image.to_rgb8());
+        }
+
+        let mut data = Cursor::new(vec![]);
+        image
+            .write_to(&mut data, format.image_format())
+            .map_err(|err| err.to_string())?;
+
+        let image_format = format.image_format();
+        Ok(ProcessedVariant {
+            data: data.into_inner(),
+            content_type: image_format.to_mime_type(),
+            extension: image_format
+                .extensions_str()
+                .first()
+                .copied()
+                .unwrap_or("bin"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = attachment_blob_variants)]
+struct CreateAttachmentBlobVariant {
+    blob_id: ID,
+    variant_blob_id: ID,
+    name: String,
+    digest: String,
+}
+
+/// the id of the blob holding `blob_id`'s `variant`, if it was generated already
+fn find_variant_blob_id(
+    db: &mut Connection,
+    blob_id: ID,
+    variant: &Variant,
+) -> QueryResult<Option<ID>> {
+    schema::attachment_blob_variants::table
+        .filter(schema::attachment_blob_variants::blob_id.eq(blob_id))
+        .filter(schema::attachment_blob_variants::name.eq(&variant.name))
+        .filter(schema::attachment_blob_variants::digest.eq(variant.digest()))
+        .select(schema::attachment_blob_variants::variant_blob_id)
+        .first::<ID>(db)
+        .optional()
+}
+
+impl AttachmentBlob {
+    /// the blob holding this blob's `variant_name` variant, generating it if it doesn't exist yet
+    ///
+    /// # Errors
+    /// * no variant with that name was registered with [`Storage::with_variant`]
+    /// * this blob isn't an image, or the variant could not be generated
+    /// * Diesel error
+    pub async fn variant(
+        &self,
+        db: &mut Connection,
+        storage: &Storage,
+        variant_name: &str,
+    ) -> Result<Self, StorageError> {
+        let variant = storage
+            .variant(variant_name)
+            .ok_or_else(|| StorageError::Invalid(format!("Unknown variant '{variant_name}'")))?;
+
+        if let Some(variant_blob_id) = find_variant_blob_id(db, self.id, variant)? {
+            return Ok(Self::find_by_id(db, variant_blob_id)?);
+        }
+
+        let mut original = vec![];
+        storage
+            .blob_download_stream(self)
+            .await?
+            .read_to_end(&mut original)
+            .await
+            .map_err(|err| {
+                StorageError::io(&err)(format!(
+                    "Could not download object (key: '{}', error: '{err}')",
+                    self.key
+                ))
+            })?;
+        let processed = {
+            let variant = variant.clone();
+            tokio::task::spawn_blocking(move || variant.apply(&original))
+                .await
+                .map_err(|err| StorageError::Other(err.to_string()))?
+                .map_err(StorageError::Invalid)?
+        };
+
+        // the key only depends on the variant's definition, so generating it twice is harmless
+        let digest = variant.digest();
+        let key = format!(
+            "variants/{key}/{digest}.{extension}",
+            key = self.key,
+            extension = processed.extension
+        );
+        // variants are encrypted like their original
+        let backend = storage.default_backend()?;
+        let (session, recorded) = storage
+            .start_blob_upload(
+                backend,
+                &key,
+                processed.content_type,
+                &Encryption::of_blob(self),
+            )
+            .await?;
+        let uploaded = upload_to_session(session, &key, processed.data.as_slice()).await?;
+        #[allow(clippy::cast_possible_wrap)]
+        let byte_size = uploaded.byte_size as i64;
+
+        let stem = std::path::Path::new(&self.file_name)
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().to_string());
+        let created = diesel::connection::Connection::transaction::<Self, Error, _>(db, |db| {
+            let variant_blob = Self::create(
+                db,
+                &AttachmentBlobChangeset {
+                    key,
+                    file_name: format!("{stem}-{variant_name}.{}", processed.extension),
+                    content_type: Some(processed.content_type.to_string()),
+                    byte_size,
+                    checksum: uploaded.checksum,
+                    sha256: None,
+                    encryption: recorded.encryption,
+                    encryption_key: recorded.encryption_key,
+                    service_name: backend.service_name().to_string(),
+                },
+            )?;
+
+            insert_into(schema::attachment_blob_variants::table)
+                .values(&CreateAttachmentBlobVariant {
+                    blob_id: self.id,
+                    variant_blob_id: variant_blob.id,
+                    name: variant_name.to_string(),
+                    digest: digest.clone(),
+                })
+                .execute(db)?;
+
+            Ok(variant_blob)
+        });
+
+        match created {
+            Ok(variant_blob) => Ok(variant_blob),
+            // someone else generated it concurrently
+            Err(error) => match find_variant_blob_id(db, self.id, variant) {
+                Ok(Some(variant_blob_id)) => Ok(Self::find_by_id(db, variant_blob_id)?),
+                _ => Err(error.into()),
+            },
+        }
+    }
+
+    /// generate the given variants ahead of time, instead of on first request
+    ///
+    /// # Errors
+    /// * see [`AttachmentBlob::variant`]
+    pub async fn process_variants(
+        &self,
+        db: &mut Connection,
+        storage: &Storage,
+        variant_names: &[&str],
+    ) -> Result<(), StorageError> {
+        for variant_name in variant_names {
+            self.variant(db, storage, variant_name).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage {
+    /// the download URI of `blob`'s `variant_name` variant, generating it if needed;
+    /// see [`Storage::download_uri`] regarding `expires_in`
+    ///
+    /// # Errors
+    /// * see [`AttachmentBlob::variant`]
+    /// * could not retrieve the download URI
+    pub async fn variant_download_uri(
+        &self,
+        db: &mut Connection,
+        blob: &AttachmentBlob,
+        variant_name: &str,
+        expires_in: Option<std::time::Duration>,
+    ) -> Result<String, StorageError> {
+        let variant_blob = blob.variant(db, self, variant_name).await?;
+
+        self.blob_download_uri(&variant_blob, expires_in).await
+    }
+}
+
+/// the variant blobs generated from the blobs in `blob_ids`
+pub(super) fn variant_blobs(
+    db: &mut Connection,
+    blob_ids: Vec<ID>,
+) -> QueryResult<Vec<AttachmentBlob>> {
+    let variant_blob_ids = schema::attachment_blob_variants::table
+        .filter(schema::attachment_blob_variants::blob_id.eq_any(blob_ids))
+        .select(schema::attachment_blob_variants::variant_blob_id)
+        .load::<ID>(db)?;
+
+    AttachmentBlob::find_all_by_id(db, variant_blob_ids)
+}
+
+/// removes the links between the blobs in `blob_ids` and their variants, or the blobs they are variants of
+pub(super) fn delete_variant_links(db: &mut Connection, blob_ids: Vec<ID>) -> QueryResult<usize> {
+    diesel::delete(
+        schema::attachment_blob_variants::table.filter(
+            schema::attachment_blob_variants::blob_id
+                .eq_any(blob_ids.clone())
+                .or(schema::attachment_blob_variants::variant_blob_id.eq_any(blob_ids)),
+        ),
+    )
+    .execute(db)
+}
+
+/// Generates variants of a blob in the background (instead of on first request), on the `async` queue
+///
+/// The task builds its [`Storage`] from the environment (see [`Storage::new`]),
+/// which is why it carries the definitions of the variants to generate.
+///
+/// ```rust,ignore
+/// create_rust_app::tasks::async_queue()
+///     .lock()
+///     .unwrap()
+///     .insert_task(&ProcessVariants { blob_id: blob.id, variants: vec![thumbnail] })
+///     .await?;
+/// ```
+#[cfg(feature = "plugin_tasks")]
+#[derive(Serialize, Deserialize)]
+pub struct ProcessVariants {
+    pub blob_id: ID,
+    pub variants: Vec<Variant>,
+}
+
+#[cfg(feature = "plugin_tasks")]
+mod task {
+    use fang::asynk::async_queue::AsyncQueueable;
+    use fang::{async_trait, typetag, AsyncRunnable, FangError};
+
+    use super::{AttachmentBlob, ProcessVariants, Storage};
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for ProcessVariants {
+        async fn run(&self, _queueable: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+            let storage = self
+                .variants
+                .iter()
+                .cloned()
+                .fold(Storage::new(), Storage::with_variant);
+            let variant_names = self
+                .variants
+                .iter()
+                .map(|variant| variant.name.as_str())
+                .collect::<Vec<_>>();
+
+            let db = crate::Database::new();
+            let mut db = db.get_connection().map_err(|err| FangError {
+                description: err.to_string(),
+            })?;
+
+            let blob =
+                AttachmentBlob::find_by_id(&mut db, self.blob_id).map_err(|err| FangError {
+                    description: err.to_string(),
+                })?;
+
+            blob.process_variants(&mut db, &storage, &variant_names)
+                .await
+                .map_err(|err| FangError {
+                    description: err.to_string(),
+                })
+        }
+
+        fn task_type(&self) -> String {
+            "async".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Cursor::new(vec![]);
+        DynamicImage::new_rgba8(width, height)
+            .write_to(&mut data, ImageFormat::Png)
+            .unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn applies_transformations_in_order() {
+        let thumbnail = Variant::new("thumbnail")
+            .resize_to_fill(40, 40)
+            .crop(0, 0, 20, 10)
+            .format(VariantFormat::Jpeg)
+            .apply(&png(200, 100))
+            .unwrap();
+
+        assert_eq!(thumbnail.content_type, "image/jpeg");
+        let image = image::load_from_memory(&thumbnail.data).unwrap();
+        assert_eq!(image.dimensions(), (20, 10));
+
+        let resized = Variant::new("small")
+            .resize(50, 50)
+            .apply(&png(200, 100))
+            .unwrap();
+
+        assert_eq!(resized.content_type, "image/png");
+        let image = image::load_from_memory(&resized.data).unwrap();
+        assert_eq!(image.dimensions(), (50, 25));
+    }
+
+    #[test]
+    fn digest_changes_with_definition() {
+        assert_ne!(
+            Variant::new("thumbnail").resize(10, 10).digest(),
+            Variant::new("thumbnail").resize(20, 20).digest()
+        );
+        assert!(Variant::new("thumbnail").apply(b"not an image").is_err());
+    }
+}
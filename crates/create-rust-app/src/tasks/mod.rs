@@ -16,7 +16,7 @@ pub fn queue() -> &'static Queue {
     static QUEUE: OnceCell<Queue> = OnceCell::new();
 
     QUEUE.get_or_init(|| {
-        // TODO: make the number of connections in the pool configurable
+        // the app's pool; the queue binary can size it with `Database::init`
         let db = Database::new();
 
         Queue::builder().connection_pool(db.pool.clone()).build()
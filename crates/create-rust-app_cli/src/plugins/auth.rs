@@ -180,6 +180,46 @@ import { ResetPage } from './containers/ResetPage'",
         created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
         PRIMARY KEY (role, permission)
       );
+    "},
+                BackendDatabase::Mysql => indoc! {r"
+      CREATE TABLE users (
+        id INTEGER PRIMARY KEY AUTO_INCREMENT,
+        email VARCHAR(255) NOT NULL,
+        hash_password TEXT NOT NULL,
+        activated BOOLEAN NOT NULL DEFAULT FALSE,
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+      );
+
+      CREATE TABLE user_sessions (
+        id INTEGER PRIMARY KEY AUTO_INCREMENT,
+        user_id INTEGER NOT NULL REFERENCES users(id),
+        refresh_token TEXT NOT NULL,
+        device TEXT,
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+      );
+
+      CREATE TABLE user_permissions (
+        user_id INTEGER NOT NULL REFERENCES users(id),
+        permission VARCHAR(255) NOT NULL,
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (user_id, permission)
+      );
+
+      CREATE TABLE user_roles (
+        user_id INTEGER NOT NULL REFERENCES users(id),
+        role VARCHAR(255) NOT NULL,
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (user_id, role)
+      );
+
+      CREATE TABLE role_permissions (
+        role VARCHAR(255) NOT NULL,
+        permission VARCHAR(255) NOT NULL,
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (role, permission)
+      );
     "},
             },
             indoc! {r"
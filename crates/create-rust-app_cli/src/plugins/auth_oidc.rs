@@ -166,6 +166,27 @@ impl Plugin for AuthOIDC {
         created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
       );
 
+    "},
+                BackendDatabase::Mysql => indoc! {r"
+      CREATE TABLE user_oauth2_links (
+        id INTEGER PRIMARY KEY AUTO_INCREMENT,
+        provider TEXT NOT NULL,
+
+        -- all attempts at oauth2 will create a record with these properties
+        csrf_token TEXT NOT NULL,
+        nonce TEXT NOT NULL,
+        pkce_secret TEXT NOT NULL,
+
+        -- when oauth2 attempts succeed, either a user is created or the oauth2 attempt is discarded
+        -- depending on whether or not the user ends up linking the account or not
+        refresh_token TEXT,
+        access_token TEXT,
+        subject_id VARCHAR(255) UNIQUE,
+        user_id INTEGER REFERENCES users(id),
+
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+      );
     "},
             },
             indoc! {r"
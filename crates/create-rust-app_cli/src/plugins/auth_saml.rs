@@ -0,0 +1,184 @@
+use crate::plugins::InstallConfig;
+use crate::plugins::Plugin;
+use crate::utils::{fs, logger};
+use crate::{BackendDatabase, BackendFramework};
+use anyhow::Result;
+use indoc::indoc;
+
+pub struct AuthSAML {}
+
+impl Plugin for AuthSAML {
+    fn name(&self) -> &'static str {
+        "SAML Auth"
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn install(&self, install_config: InstallConfig) -> Result<()> {
+        if !install_config.plugin_auth {
+            logger::exit_code("Cannot install SAML Auth plugin without Auth plugin", 1);
+        }
+        if matches!(install_config.backend_framework, BackendFramework::Poem) {
+            logger::exit_code("The SAML Auth plugin only supports actix-web for now", 1);
+        }
+
+        // ===============================
+        // New env vars
+        // ===============================
+        fs::append(".env.example", "SAML_IDP_METADATA_FILE=idp-metadata.xml\n")?;
+
+        // ===============================
+        // Backend changes
+        // ===============================
+        let saml_providers = r#"saml_providers: vec![create_rust_app::auth::saml::SAMLProvider::new(
+                "sso",
+                &std::fs::read_to_string(std::env::var("SAML_IDP_METADATA_FILE").unwrap())
+                    .unwrap(),
+                format!(
+                    "{app_url}/oauth/success",
+                    app_url = std::env::var("APP_URL").unwrap()
+                ),
+                format!(
+                    "{app_url}/oauth/error",
+                    app_url = std::env::var("APP_URL").unwrap()
+                ),
+            )
+            .unwrap()],"#;
+
+        if install_config.plugin_auth_oidc {
+            // the OIDC plugin already added the `AuthConfig`
+            fs::replace(
+                "backend/main.rs",
+                "app = app.app_data(Data::new(create_rust_app::auth::AuthConfig {",
+                &format!(
+                    "app = app.app_data(Data::new(create_rust_app::auth::AuthConfig {{\n            {saml_providers}"
+                ),
+            )?;
+        } else {
+            fs::replace(
+                "backend/main.rs",
+                r#"app = app.app_data(Data::new(AppConfig {
+            app_url: std::env::var("APP_URL").unwrap(),
+        }));"#,
+                &format!(
+                    r#"app = app.app_data(Data::new(AppConfig {{
+            app_url: std::env::var("APP_URL").unwrap(),
+        }}));
+        app = app.app_data(Data::new(create_rust_app::auth::AuthConfig {{
+            {saml_providers}
+        }}));"#
+                ),
+            )?;
+        }
+
+        // ===============================
+        // MIGRATIONS
+        // ===============================
+
+        // `IF NOT EXISTS`: the migrations `create_rust_app` embeds for the plugin may have run first
+        crate::content::migration::create(
+            "plugin_auth-saml",
+            match install_config.backend_database {
+                BackendDatabase::Postgres => indoc! {r"
+      CREATE TABLE IF NOT EXISTS user_saml_links (
+        id SERIAL PRIMARY KEY,
+        provider TEXT NOT NULL,
+        name_id TEXT NOT NULL,
+        user_id INTEGER NOT NULL REFERENCES users(id),
+        created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        UNIQUE (provider, name_id)
+      );
+
+      SELECT manage_updated_at('user_saml_links');
+
+      -- the AuthnRequests we sent, bound to the browser which started the login
+      CREATE TABLE IF NOT EXISTS saml_requests (
+        provider TEXT NOT NULL,
+        request_id TEXT NOT NULL,
+        browser_token TEXT NOT NULL,
+        expires_at TIMESTAMPTZ NOT NULL,
+        PRIMARY KEY (provider, request_id)
+      );
+
+      -- the assertions already used to log in, kept until they expire
+      CREATE TABLE IF NOT EXISTS saml_assertions (
+        provider TEXT NOT NULL,
+        assertion_id TEXT NOT NULL,
+        expires_at TIMESTAMPTZ NOT NULL,
+        PRIMARY KEY (provider, assertion_id)
+      );
+
+      CREATE INDEX IF NOT EXISTS saml_requests_expires_at ON saml_requests (expires_at);
+      CREATE INDEX IF NOT EXISTS saml_assertions_expires_at ON saml_assertions (expires_at);
+    "},
+                BackendDatabase::Sqlite => indoc! {r"
+      CREATE TABLE IF NOT EXISTS user_saml_links (
+        id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+        provider TEXT NOT NULL,
+        name_id TEXT NOT NULL,
+        user_id INTEGER NOT NULL REFERENCES users(id),
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        UNIQUE (provider, name_id)
+      );
+
+      -- the AuthnRequests we sent, bound to the browser which started the login
+      CREATE TABLE IF NOT EXISTS saml_requests (
+        provider TEXT NOT NULL,
+        request_id TEXT NOT NULL,
+        browser_token TEXT NOT NULL,
+        expires_at DATETIME NOT NULL,
+        PRIMARY KEY (provider, request_id)
+      );
+
+      -- the assertions already used to log in, kept until they expire
+      CREATE TABLE IF NOT EXISTS saml_assertions (
+        provider TEXT NOT NULL,
+        assertion_id TEXT NOT NULL,
+        expires_at DATETIME NOT NULL,
+        PRIMARY KEY (provider, assertion_id)
+      );
+
+      CREATE INDEX IF NOT EXISTS saml_requests_expires_at ON saml_requests (expires_at);
+      CREATE INDEX IF NOT EXISTS saml_assertions_expires_at ON saml_assertions (expires_at);
+    "},
+                BackendDatabase::Mysql => indoc! {r"
+      CREATE TABLE IF NOT EXISTS user_saml_links (
+        id INTEGER PRIMARY KEY AUTO_INCREMENT,
+        provider VARCHAR(255) NOT NULL,
+        name_id VARCHAR(255) NOT NULL,
+        user_id INTEGER NOT NULL REFERENCES users(id),
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+        UNIQUE (provider, name_id)
+      );
+
+      -- the AuthnRequests we sent, bound to the browser which started the login
+      CREATE TABLE IF NOT EXISTS saml_requests (
+        provider VARCHAR(255) NOT NULL,
+        request_id VARCHAR(255) NOT NULL,
+        browser_token VARCHAR(255) NOT NULL,
+        expires_at DATETIME NOT NULL,
+        PRIMARY KEY (provider, request_id),
+        INDEX saml_requests_expires_at (expires_at)
+      );
+
+      -- the assertions already used to log in, kept until they expire
+      CREATE TABLE IF NOT EXISTS saml_assertions (
+        provider VARCHAR(255) NOT NULL,
+        assertion_id VARCHAR(255) NOT NULL,
+        expires_at DATETIME NOT NULL,
+        PRIMARY KEY (provider, assertion_id),
+        INDEX saml_assertions_expires_at (expires_at)
+      );
+    "},
+            },
+            indoc! {r"
+      DROP TABLE saml_assertions;
+      DROP TABLE saml_requests;
+      DROP TABLE user_saml_links;
+    "},
+        )?;
+
+        Ok(())
+    }
+}
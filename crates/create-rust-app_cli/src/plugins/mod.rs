@@ -1,5 +1,6 @@
 pub mod auth;
 pub mod auth_oidc;
+pub mod auth_saml;
 pub mod container;
 pub mod dev;
 pub mod graphql;
@@ -22,6 +23,7 @@ pub struct InstallConfig {
     pub plugin_dev: bool,
     pub plugin_auth: bool,
     pub plugin_auth_oidc: bool,
+    pub plugin_auth_saml: bool,
     pub plugin_container: bool,
     pub plugin_storage: bool,
     pub plugin_tasks: bool,
@@ -1,3 +1,4 @@
+use crate::content::cargo_toml::add_dependency;
 use crate::plugins::InstallConfig;
 use crate::plugins::Plugin;
 use crate::utils::fs;
@@ -39,11 +40,23 @@ impl Plugin for Storage {
         fs::append(
             ".env.example",
             r"
+# s3 | local | memory
+STORAGE_SERVICE=s3
 S3_HOST=http://localhost:9000
 S3_REGION=minio
 S3_BUCKET=bucket
 S3_ACCESS_KEY_ID=access_key
 S3_SECRET_ACCESS_KEY=secret_key
+# S3_SESSION_TOKEN=
+# leave S3_HOST unset for AWS; leave the keys unset to use the default AWS credentials chain
+# S3_FORCE_PATH_STYLE=true
+# S3_CA_BUNDLE=./certs/ca.pem
+# S3_PUBLIC_URL=https://cdn.example.com
+# scan attachments with a clamd daemon
+# CLAMAV_ADDRESS=localhost:3310
+# master key for envelope-encrypted attachments (generate one with `openssl rand -base64 32`)
+# STORAGE_MASTER_KEY=
+# STORAGE_MASTER_KEY_ID=default
 ",
         )?;
 
@@ -80,11 +93,17 @@ CREATE TABLE attachment_blobs(
   content_type TEXT,
   byte_size BIGINT NOT NULL,
   checksum TEXT NOT NULL,
+  sha256 TEXT,
+  encryption TEXT,
+  encryption_key TEXT,
   service_name TEXT NOT NULL,
 
   created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
 
+-- `AttachmentBlob::find_duplicate` looks blobs up by these on every upload
+CREATE INDEX attachment_blobs_sha256 ON attachment_blobs (sha256, byte_size, service_name);
+
 CREATE TABLE attachments(
   id SERIAL PRIMARY KEY,
 
@@ -95,6 +114,17 @@ CREATE TABLE attachments(
 
   created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
+
+-- the variants of image blobs (the `plugin_storage-variants` feature)
+CREATE TABLE attachment_blob_variants(
+  id SERIAL PRIMARY KEY,
+  blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  variant_blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  name TEXT NOT NULL,
+  digest TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (blob_id, name, digest)
+);
 "},
                 BackendDatabase::Sqlite => indoc! {r"
 CREATE TABLE attachment_blobs(
@@ -105,11 +135,17 @@ CREATE TABLE attachment_blobs(
   content_type TEXT,
   byte_size BIGINT NOT NULL,
   checksum TEXT NOT NULL,
+  sha256 TEXT,
+  encryption TEXT,
+  encryption_key TEXT,
   service_name TEXT NOT NULL,
 
   created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
 
+-- `AttachmentBlob::find_duplicate` looks blobs up by these on every upload
+CREATE INDEX attachment_blobs_sha256 ON attachment_blobs (sha256, byte_size, service_name);
+
 CREATE TABLE attachments(
   id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
 
@@ -120,16 +156,77 @@ CREATE TABLE attachments(
 
   created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
+
+-- the variants of image blobs (the `plugin_storage-variants` feature)
+CREATE TABLE attachment_blob_variants(
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  variant_blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  name TEXT NOT NULL,
+  digest TEXT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (blob_id, name, digest)
+);
+"},
+                BackendDatabase::Mysql => indoc! {r"
+CREATE TABLE attachment_blobs(
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+
+  `key` VARCHAR(255) NOT NULL,
+  file_name TEXT NOT NULL,
+  content_type TEXT,
+  byte_size BIGINT NOT NULL,
+  checksum TEXT NOT NULL,
+  sha256 TEXT,
+  encryption TEXT,
+  encryption_key TEXT,
+  service_name TEXT NOT NULL,
+
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- `AttachmentBlob::find_duplicate` looks blobs up by these on every upload
+CREATE INDEX attachment_blobs_sha256 ON attachment_blobs (sha256(64), byte_size, service_name(64));
+
+CREATE TABLE attachments(
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+
+  name TEXT NOT NULL,
+  record_type TEXT NOT NULL,
+  record_id INTEGER NOT NULL,
+  blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- the variants of image blobs (the `plugin_storage-variants` feature)
+CREATE TABLE attachment_blob_variants(
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+  blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  variant_blob_id INTEGER NOT NULL REFERENCES attachment_blobs(id),
+  name VARCHAR(255) NOT NULL,
+  digest VARCHAR(255) NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (blob_id, name, digest)
+);
 "},
             },
             indoc! {r"
-DROP TABLE attachment_blobs;
+DROP TABLE attachment_blob_variants;
 DROP TABLE attachments;
+DROP TABLE attachment_blobs;
 "},
         )?;
 
         match install_config.backend_framework {
             BackendFramework::ActixWeb => {
+                // to stream multipart uploads into storage
+                add_dependency(
+                    &install_config.project_dir,
+                    "tokio-util",
+                    r#"tokio-util = { version = "0.7", features = ["io"] }"#,
+                )?;
+
                 crate::content::service::register_actix(
                     "file",
                     r#"services::file::endpoints(web::scope("/files"))"#,
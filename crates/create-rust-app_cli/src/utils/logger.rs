@@ -102,6 +102,7 @@ pub fn project_created_msg(install_config: crate::plugins::InstallConfig) {
             match install_config.backend_database {
                 BackendDatabase::Postgres => "postgres",
                 BackendDatabase::Sqlite => "sqlite-bundled",
+                BackendDatabase::Mysql => "mysql",
             }
         ));
     }
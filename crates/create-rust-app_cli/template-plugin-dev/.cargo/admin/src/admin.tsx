@@ -1,4 +1,4 @@
-import React, { useState } from 'react'
+import React, { useEffect, useRef, useState } from 'react'
 import ReactDOM from 'react-dom'
 import { QueryClient, QueryClientProvider, useQuery } from 'react-query'
 
@@ -58,6 +58,99 @@ const TableView = (props: {name: string}) => {
   </div>
 }
 
+interface CapturedEmail {
+  id: number,
+  received_at: number,
+  from?: string,
+  to: string[],
+  subject?: string,
+  text?: string,
+  html?: string,
+  attachments: { file_name?: string, content_type?: string, content_id?: string, size: number }[],
+  raw: string
+}
+
+/* the emails the app sent while SEND_MAIL=false, captured by the development server */
+const useCapturedEmails = () => {
+  const [emails, setEmails] = useState<CapturedEmail[]>([])
+  const [connected, setConnected] = useState(false)
+  const wsRef = useRef<WebSocket>()
+
+  useEffect(() => {
+    let closed = false
+
+    const connect = (port: number) => {
+      const ws = new WebSocket(`ws://localhost:${port}/ws`)
+      wsRef.current = ws
+
+      ws.onopen = () => { setConnected(true) }
+      ws.onclose = () => {
+        setConnected(false)
+        if (!closed) setTimeout(() => connect(port), 1000)
+      }
+      ws.onmessage = (payload) => {
+        const data = JSON.parse(payload.data)
+
+        if (data.type === 'mails') {
+          setEmails(data.mails)
+        } else if (data.type === 'mailCaptured') {
+          setEmails(emails => [...emails, data.mail])
+        }
+      }
+    }
+
+    fetch('/api/development/dev-server')
+      .then(r => r.json())
+      .then(({ port }) => { if (port && !closed) connect(port) })
+
+    return () => {
+      closed = true
+      wsRef.current?.close()
+    }
+  }, [])
+
+  const clear = () => wsRef.current?.send('mail:clear')
+
+  return { emails, connected, clear }
+}
+
+/* links in plain text emails, made clickable */
+const Linkified = (props: { text: string }) => <>
+  {props.text.split(/(https?:\/\/[^\s<>"]+)/g).map((part, i) =>
+    i % 2 === 1
+      ? <a href={part} target="_blank" className="text-blue-500 hover:underline hover:text-blue-700">{part}</a>
+      : part
+  )}
+</>
+
+const EmailView = (props: { email: CapturedEmail }) => {
+  const { email } = props
+  const [tab, setTab] = useState<'html' | 'text' | 'raw'>(email.html ? 'html' : 'text')
+
+  return <div className="flex flex-col h-full">
+    <h1 className="font-bold text-xl">{email.subject || '(no subject)'}</h1>
+    <div className="text-sm text-gray-500">
+      <div>From: {email.from}</div>
+      <div>To: {email.to.join(', ')}</div>
+      <div>{new Date(email.received_at).toLocaleString()}</div>
+      {email.attachments.length > 0 && <div>Attachments: {email.attachments.map(a => `${a.file_name || a.content_id || 'unnamed'} (${a.content_type}, ${a.size} bytes)`).join(', ')}</div>}
+    </div>
+    <div className="flex space-x-4 my-2 border-b-2">
+      {(['html', 'text', 'raw'] as const).map(t =>
+        <button onClick={() => setTab(t)} className={tab === t ? 'font-bold' : 'text-blue-500 hover:underline'}>{t}</button>
+      )}
+    </div>
+    {tab === 'html' && (email.html
+      // links open in a new tab, so the activation links and the likes can be followed
+      ? <iframe className="flex-1 w-full border-2" sandbox="allow-popups allow-popups-to-escape-sandbox" srcDoc={`<base target="_blank">${email.html}`} />
+      : <div className="text-gray-500">No html part.</div>)}
+    {tab === 'text' && (email.text
+      ? <pre className="whitespace-pre-wrap"><Linkified text={email.text} /></pre>
+      : <div className="text-gray-500">No text part.</div>)}
+    {tab === 'raw' && <pre className="whitespace-pre-wrap text-xs">{email.raw}</pre>}
+  </div>
+}
+
 const AdminPage = () => {
   /*
     SELECT tablename AS name, (SELECT COUNT(*) FROM `tablename`) AS count FROM (SELECT * FROM pg_catalog.pg_tables WHERE schemaname != 'pg_catalog' AND schemaname != 'information_schema')
@@ -66,6 +159,9 @@ const AdminPage = () => {
   const tableQuery = useQuery<{name: string}[]>('tables', () => fetchQuery(`SELECT tablename AS name FROM pg_catalog.pg_tables WHERE schemaname != 'pg_catalog' AND schemaname != 'information_schema'`))
 
   const [selectedTable, setSelectedTable] = useState<string | undefined>(undefined)
+  const mail = useCapturedEmails()
+  const [selectedEmailId, setSelectedEmailId] = useState<number | undefined>(undefined)
+  const selectedEmail = mail.emails.find(email => email.id === selectedEmailId)
   
   return (
     <div className="flex h-full flex flex-col">
@@ -76,17 +172,31 @@ const AdminPage = () => {
           <ul className="flex-col">
             {tableQuery.data && tableQuery.data.map(table =>
               <li className="flex">
-                <TableLink name={table.name} onClick={() => setSelectedTable(table.name)} />
+                <TableLink name={table.name} onClick={() => { setSelectedTable(table.name); setSelectedEmailId(undefined) }} />
               </li>
             )}
             
           </ul>
+          <h2 className="text-xs mt-4">
+            mail {!mail.connected && <span className="text-gray-500">(not connected to the development server)</span>}
+            {mail.emails.length > 0 && <button onClick={mail.clear} className="ml-2 text-blue-500 hover:underline hover:text-blue-700">clear</button>}
+          </h2>
+          <ul className="flex-col">
+            {[...mail.emails].reverse().map(email =>
+              <li className="flex">
+                <button onClick={() => { setSelectedEmailId(email.id); setSelectedTable(undefined) }} className="flex-1 truncate text-left hover:underline text-blue-500 hover:text-blue-700">
+                  {email.subject || '(no subject)'} <span className="text-xs text-gray-500">{email.to.join(', ')}</span>
+                </button>
+              </li>
+            )}
+          </ul>
         </div>
         <div className="p-4 flex-1">
-          {!selectedTable && <div className="text-gray-500">
-            No table selected.
+          {!selectedTable && !selectedEmail && <div className="text-gray-500">
+            No table or email selected.
           </div>}
           {selectedTable && <TableView name={selectedTable}/>}
+          {selectedEmail && <EmailView key={selectedEmail.id} email={selectedEmail}/>}
         </div>
       </div>
     </div>
@@ -0,0 +1,34 @@
+///
+/// This binary compares the stored files with the `attachment_blobs` table:
+/// it finds files no blob refers to, and blobs whose file is missing
+///
+/// Use `cargo run --bin reconcile_storage` to see what is out of sync
+/// Use `cargo run --bin reconcile_storage -- --delete` to remove it
+///
+
+use create_rust_app::ReconcileOptions;
+
+#[tokio::main]
+pub async fn main() {
+    let dry_run = !std::env::args().any(|arg| arg == "--delete");
+
+    let app_data = create_rust_app::setup();
+    let mut db = app_data
+        .database
+        .get_connection()
+        .expect("Failed to connect to the database");
+
+    let report = app_data
+        .storage
+        .reconcile(
+            &mut db,
+            &ReconcileOptions {
+                dry_run,
+                ..ReconcileOptions::default()
+            },
+        )
+        .await
+        .expect("Failed to reconcile storage");
+
+    print!("{report}");
+}
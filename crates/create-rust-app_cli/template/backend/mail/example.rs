@@ -1,7 +1,7 @@
-use create_rust_app::Mailer;
+use create_rust_app::{EmailAddress, MailError, Mailer};
 
 #[allow(dead_code)]
-pub fn send(mailer: &Mailer, to_email: &str) {
+pub fn send(mailer: &Mailer, to: &EmailAddress) -> Result<(), MailError> {
     let subject = "Example Email";
     let text = format!(
         r#"
@@ -28,5 +28,5 @@ Warmest regards
         link = "https://app.my-domain.com"
     );
 
-    mailer.send(to_email, &subject, &text, &html);
+    mailer.send(to, subject, &text, &html)
 }
@@ -0,0 +1,37 @@
+///
+/// This binary delivers the emails queued with `Mailer::enqueue`,
+/// retrying the ones which fail until they run out of attempts
+///
+/// Use `cargo run --bin mail_outbox` to keep delivering them
+/// Use `cargo run --bin mail_outbox -- --once` to deliver what is due and exit
+///
+
+use std::time::Duration;
+
+use create_rust_app::OutboxOptions;
+
+pub fn main() {
+    let once = std::env::args().any(|arg| arg == "--once");
+
+    let app_data = create_rust_app::setup();
+    simple_logger::init_with_env().unwrap();
+    let options = OutboxOptions::default();
+
+    if !once {
+        app_data
+            .mailer
+            .run_outbox(&app_data.database, &options, Duration::from_secs(10));
+    }
+
+    let mut db = app_data
+        .database
+        .get_connection()
+        .expect("Failed to connect to the database");
+
+    let report = app_data
+        .mailer
+        .deliver_outbox(&mut db, &options)
+        .expect("Failed to deliver the email outbox");
+
+    println!("{report}");
+}